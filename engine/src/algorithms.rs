@@ -4,3 +4,401 @@
 //! Could possibly use the Parry crate for collision detection algorithms?
 
 //---------------------------------------------------------------------------------------------------//
+// Fast Fourier transforms.
+
+/// In-place radix-2 fast Fourier transform of a complex sequence, split into its real and
+/// imaginary parts. The length must be a power of two. The inverse transform is normalized by 1/n.
+pub fn fft(re: &mut [f64], im: &mut [f64], inverse: bool) {
+    let n = re.len();
+    assert!(n.is_power_of_two() && im.len() == n);
+
+    // bit reversal permutation
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    // butterflies
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut len = 2;
+    while len <= n {
+        let angle = sign * 2.0 * core::f64::consts::PI / (len as f64);
+        let (w_im, w_re) = angle.sin_cos();
+        for start in (0..n).step_by(len) {
+            let (mut cur_re, mut cur_im) = (1.0, 0.0);
+            for k in 0..(len / 2) {
+                let (a, b) = (start + k, start + k + len / 2);
+                let t_re = re[b] * cur_re - im[b] * cur_im;
+                let t_im = re[b] * cur_im + im[b] * cur_re;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+
+                let next_re = cur_re * w_re - cur_im * w_im;
+                cur_im = cur_re * w_im + cur_im * w_re;
+                cur_re = next_re;
+            }
+        }
+        len <<= 1;
+    }
+
+    if inverse {
+        for i in 0..n {
+            re[i] /= n as f64;
+            im[i] /= n as f64;
+        }
+    }
+}
+
+/// In-place fast Fourier transform of a 3d grid stored with x varying fastest,
+/// ie: `index = x + nx * (y + ny * z)`. Every dimension must be a power of two.
+pub fn fft_3d(re: &mut [f64], im: &mut [f64], dims: [usize; 3], inverse: bool) {
+    let [nx, ny, nz] = dims;
+    let strides = [1, nx, nx * ny];
+    let mut line_re = Vec::new();
+    let mut line_im = Vec::new();
+
+    for axis in 0..3 {
+        let (len, stride) = (dims[axis], strides[axis]);
+        line_re.resize(len, 0.0);
+        line_im.resize(len, 0.0);
+
+        for start in 0..(nx * ny * nz) {
+            // only visit the first element of each line along this axis
+            if (start / stride) % len != 0 {
+                continue;
+            }
+            for i in 0..len {
+                line_re[i] = re[start + i * stride];
+                line_im[i] = im[start + i * stride];
+            }
+            fft(&mut line_re, &mut line_im, inverse);
+            for i in 0..len {
+                re[start + i * stride] = line_re[i];
+                im[start + i * stride] = line_im[i];
+            }
+        }
+    }
+}
+
+//---------------------------------------------------------------------------------------------------//
+// Random numbers.
+
+/// A small, seedable pseudo-random number generator (xoshiro256**), so that stochastic
+/// interactions can be reproduced exactly from their seed.
+#[derive(Clone, Debug)]
+pub struct Random {
+    state: [u64; 4],
+    // the second of each pair of gaussians drawn by the Box-Muller transform
+    spare: Option<f64>,
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Random {
+        // the seed is spread over the state by SplitMix64, as xoshiro's authors recommend
+        let mut seed = seed;
+        let mut state = [0; 4];
+        for word in &mut state {
+            seed = seed.wrapping_add(0x9e3779b97f4a7c15);
+            let mut z = seed;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+            *word = z ^ (z >> 31);
+        }
+        Random { state, spare: None }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let result = self.state[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let shifted = self.state[1] << 17;
+        self.state[2] ^= self.state[0];
+        self.state[3] ^= self.state[1];
+        self.state[1] ^= self.state[2];
+        self.state[0] ^= self.state[3];
+        self.state[2] ^= shifted;
+        self.state[3] = self.state[3].rotate_left(45);
+        result
+    }
+
+    /// Uniform in [0, 1).
+    pub fn uniform(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Normally distributed, with zero mean and unit variance.
+    pub fn gaussian(&mut self) -> f64 {
+        if let Some(spare) = self.spare.take() {
+            return spare;
+        }
+        // 1 - u keeps the logarithm finite
+        let radius = (-2.0 * (1.0 - self.uniform()).ln()).sqrt();
+        let angle = 2.0 * std::f64::consts::PI * self.uniform();
+        self.spare = Some(radius * angle.sin());
+        radius * angle.cos()
+    }
+
+    /// Gamma distributed with the given shape and unit scale, by Marsaglia & Tsang's method.
+    pub fn gamma(&mut self, shape: f64) -> f64 {
+        if shape < 1.0 {
+            // boosted to a shape above one, then scaled back down by u^(1 / shape)
+            let u = 1.0 - self.uniform();
+            return self.gamma(shape + 1.0) * u.powf(1.0 / shape);
+        }
+        let d = shape - 1.0 / 3.0;
+        let c = 1.0 / (9.0 * d).sqrt();
+        loop {
+            let x = self.gaussian();
+            let v = (1.0 + c * x).powi(3);
+            if v <= 0.0 {
+                continue;
+            }
+            let u = 1.0 - self.uniform();
+            if u.ln() < 0.5 * x * x + d - d * v + d * v.ln() {
+                return d * v;
+            }
+        }
+    }
+}
+
+//---------------------------------------------------------------------------------------------------//
+// Interpolation.
+
+/// A natural cubic spline through a table of samples, with a continuous first and second
+/// derivative. Beyond the table, the end pieces are extended.
+#[derive(Clone, Debug)]
+pub struct CubicSpline {
+    knots: Vec<f64>,
+    values: Vec<f64>,
+    // the second derivative at each knot, zero at both ends
+    curvatures: Vec<f64>,
+}
+
+impl CubicSpline {
+    /// The knots have to be strictly increasing, and there have to be at least two.
+    pub fn new(knots: &[f64], values: &[f64]) -> CubicSpline {
+        assert!(
+            knots.len() == values.len() && knots.len() >= 2,
+            "a spline needs as many values as knots, and at least two"
+        );
+        assert!(
+            knots.windows(2).all(|pair| pair[0] < pair[1]),
+            "a spline's knots have to be strictly increasing"
+        );
+
+        // continuity of the first derivative at each inner knot gives a tridiagonal system for
+        // the curvatures, solved by the Thomas algorithm
+        let n = knots.len();
+        let mut curvatures = vec![0.0; n];
+        let mut diagonal = vec![1.0; n];
+        let mut right = vec![0.0; n];
+        for i in 1..n - 1 {
+            let (before, after) = (knots[i] - knots[i - 1], knots[i + 1] - knots[i]);
+            let slope_change =
+                (values[i + 1] - values[i]) / after - (values[i] - values[i - 1]) / before;
+            diagonal[i] = 2.0 * (before + after);
+            right[i] = 6.0 * slope_change;
+            if i > 1 {
+                let factor = before / diagonal[i - 1];
+                diagonal[i] -= factor * before;
+                right[i] -= factor * right[i - 1];
+            }
+        }
+        for i in (1..n - 1).rev() {
+            let after = knots[i + 1] - knots[i];
+            curvatures[i] = (right[i] - after * curvatures[i + 1]) / diagonal[i];
+        }
+
+        CubicSpline {
+            knots: knots.to_vec(),
+            values: values.to_vec(),
+            curvatures,
+        }
+    }
+
+    /// Sample a function at evenly spaced knots across an interval.
+    pub fn from_function(
+        function: impl Fn(f64) -> f64,
+        start: f64,
+        end: f64,
+        count: usize,
+    ) -> CubicSpline {
+        let knots: Vec<f64> = (0..count)
+            .map(|i| start + (end - start) * i as f64 / (count - 1) as f64)
+            .collect();
+        let values: Vec<f64> = knots.iter().map(|x| function(*x)).collect();
+        CubicSpline::new(&knots, &values)
+    }
+
+    /// The first and last knots.
+    pub fn domain(&self) -> (f64, f64) {
+        (self.knots[0], self.knots[self.knots.len() - 1])
+    }
+
+    pub fn value(&self, x: f64) -> f64 {
+        self.evaluate(x).0
+    }
+
+    pub fn derivative(&self, x: f64) -> f64 {
+        self.evaluate(x).1
+    }
+
+    /// The value and the first derivative.
+    pub fn evaluate(&self, x: f64) -> (f64, f64) {
+        // the piece whose interval holds x, or the nearest end piece
+        let i = self
+            .knots
+            .partition_point(|knot| *knot <= x)
+            .clamp(1, self.knots.len() - 1)
+            - 1;
+        let width = self.knots[i + 1] - self.knots[i];
+        let (a, b) = ((self.knots[i + 1] - x) / width, (x - self.knots[i]) / width);
+        let (m0, m1) = (self.curvatures[i], self.curvatures[i + 1]);
+        let value = a * self.values[i]
+            + b * self.values[i + 1]
+            + ((a * a * a - a) * m0 + (b * b * b - b) * m1) * width * width / 6.0;
+        let derivative = (self.values[i + 1] - self.values[i]) / width
+            + ((1.0 - 3.0 * a * a) * m0 + (3.0 * b * b - 1.0) * m1) * width / 6.0;
+        (value, derivative)
+    }
+}
+
+//---------------------------------------------------------------------------------------------------//
+// Special functions.
+
+/// The complementary error function, erfc(x) = 1 - erf(x), to about 1e-14 relative accuracy.
+pub fn erfc(x: f64) -> f64 {
+    if x < 0.0 {
+        return 2.0 - erfc(-x);
+    }
+
+    let x2 = x * x;
+    if x < 1.5 {
+        // the Maclaurin series of erf, which loses too much to cancellation further out
+        let (mut term, mut sum) = (x, x);
+        let mut n = 0.0;
+        loop {
+            n += 1.0;
+            term *= -x2 / n;
+            let contribution = term / (2.0 * n + 1.0);
+            sum += contribution;
+            if contribution.abs() < 1e-17 * sum.abs() {
+                break;
+            }
+        }
+        1.0 - 2.0 / core::f64::consts::PI.sqrt() * sum
+    } else {
+        // Laplace's continued fraction, x + (1/2)/(x + 1/(x + (3/2)/(x + ...))), from its tail
+        let mut fraction = x;
+        for n in (1..=80).rev() {
+            fraction = x + 0.5 * n as f64 / fraction;
+        }
+        (-x2).exp() / (fraction * core::f64::consts::PI.sqrt())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The discrete Fourier transform of a 3d grid, summed term by term.
+    fn naive_dft(re: &[f64], im: &[f64], dims: [usize; 3]) -> (Vec<f64>, Vec<f64>) {
+        let [nx, ny, nz] = dims;
+        let (mut out_re, mut out_im) = (vec![0.0; re.len()], vec![0.0; re.len()]);
+        for (k, (out_re, out_im)) in out_re.iter_mut().zip(&mut out_im).enumerate() {
+            let (kx, ky, kz) = (k % nx, (k / nx) % ny, k / (nx * ny));
+            for n in 0..re.len() {
+                let (x, y, z) = (n % nx, (n / nx) % ny, n / (nx * ny));
+                let phase = -2.0
+                    * core::f64::consts::PI
+                    * ((kx * x) as f64 / nx as f64
+                        + (ky * y) as f64 / ny as f64
+                        + (kz * z) as f64 / nz as f64);
+                let (sin, cos) = phase.sin_cos();
+                *out_re += re[n] * cos - im[n] * sin;
+                *out_im += re[n] * sin + im[n] * cos;
+            }
+        }
+        (out_re, out_im)
+    }
+
+    #[test]
+    fn fft_matches_the_discrete_fourier_transform() {
+        let mut random = Random::new(9);
+        for dims in [[16, 1, 1], [4, 2, 8]] {
+            let count = dims.iter().product();
+            let re: Vec<f64> = (0..count).map(|_| random.gaussian()).collect();
+            let im: Vec<f64> = (0..count).map(|_| random.gaussian()).collect();
+            let (expected_re, expected_im) = naive_dft(&re, &im, dims);
+
+            let (mut fast_re, mut fast_im) = (re.clone(), im.clone());
+            fft_3d(&mut fast_re, &mut fast_im, dims, false);
+            for k in 0..count {
+                assert!((fast_re[k] - expected_re[k]).abs() < 1e-10);
+                assert!((fast_im[k] - expected_im[k]).abs() < 1e-10);
+            }
+
+            // and the inverse brings the grid back
+            fft_3d(&mut fast_re, &mut fast_im, dims, true);
+            for n in 0..count {
+                assert!((fast_re[n] - re[n]).abs() < 1e-12);
+                assert!((fast_im[n] - im[n]).abs() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn splines_pass_through_their_knots_with_smooth_derivatives() {
+        let knots = [0.0, 0.3, 1.0, 1.2, 2.0, 3.5];
+        let values = [1.0, -0.5, 0.25, 2.0, 0.0, 1.0];
+        let spline = CubicSpline::new(&knots, &values);
+        for (knot, value) in knots.iter().zip(&values) {
+            assert!((spline.value(*knot) - value).abs() < 1e-12);
+        }
+        // the derivative is the value's slope, and it's continuous across the inner knots
+        let step = 1e-6;
+        for x in [0.1, 0.7, 1.1, 1.7, 3.0, 4.0, -0.5] {
+            let slope = (spline.value(x + step) - spline.value(x - step)) / (2.0 * step);
+            assert!((spline.derivative(x) - slope).abs() < 1e-6);
+        }
+        for knot in &knots[1..knots.len() - 1] {
+            let jump = spline.derivative(knot + 1e-12) - spline.derivative(knot - 1e-12);
+            assert!(jump.abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn splines_converge_on_the_function_they_sample() {
+        let error = |count: usize| {
+            let spline = CubicSpline::from_function(|x| x.sin(), 0.0, core::f64::consts::PI, count);
+            (1..100)
+                .map(|i| {
+                    let x = core::f64::consts::PI * i as f64 / 100.0;
+                    (spline.value(x) - x.sin())
+                        .abs()
+                        .max((spline.derivative(x) - x.cos()).abs())
+                })
+                .fold(0.0, f64::max)
+        };
+        // the sine's curvature vanishes at both ends, as a natural spline's does
+        assert!(error(50) < 1e-4);
+        assert!(error(100) < 0.2 * error(50));
+    }
+
+    #[test]
+    #[should_panic]
+    fn splines_reject_knots_out_of_order() {
+        CubicSpline::new(&[0.0, 1.0, 1.0], &[0.0, 1.0, 2.0]);
+    }
+}
+
+//---------------------------------------------------------------------------------------------------//
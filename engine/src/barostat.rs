@@ -0,0 +1,360 @@
+//! Barostats, which hold a periodic system at a pressure by resizing its box, for molecular
+//! dynamics at constant pressure (the isobaric ensembles, NPH on its own or NPT with a
+//! thermostat).
+//!
+//! The pressure of particles in a box of volume V is measured by the virial,
+//! P_a = (Σ m v_a² + Σ r_ij,a F_ij,a) / V along each axis, from the velocities and the virials
+//! the interactions report. Under RATTLE the forces holding the rigid constraints count too, but
+//! XPBD's projections aren't forces, so rigid molecules under XPBD leave out the pressure their
+//! constraints carry. Fixed particles, with no inverse mass, are neither moved nor counted as
+//! degrees of freedom. A barostat acts once per substep, after the constraints.
+//!
+//! - Berendsen's weak coupling stretches the box and the particles with it so that the pressure
+//!   relaxes towards the target over a time τ, at a rate set by the fluid's compressibility β.
+//!   It's robust for equilibrating, but it suppresses the volume's fluctuations.
+//! - The Martyna–Tobias–Klein barostat gives the box a momentum, driven by the excess pressure,
+//!   which stretches the box and the particles' positions and drags their velocities. Its
+//!   volume oscillates over a time around τ, and it samples the isobaric ensembles exactly.
+//!   The energy of the particles plus [`Barostat::energy`] stays constant without a thermostat.
+//!
+//! Either can stretch the box evenly, driven by the mean pressure, or stretch each axis by the
+//! pressure along it.
+
+//---------------------------------------------------------------------------------------------------//
+use crate::{math::Vec3, particle::Particle, simulation_box::SimulationBox};
+
+//---------------------------------------------------------------------------------------------------//
+
+enum Coupling {
+    Berendsen {
+        compressibility: f64,
+    },
+    MartynaTobiasKlein {
+        // the rate the box is stretching at along each axis
+        strain_rate: Vec3,
+        // set from the temperature, or the particles' temperature the first time the barostat acts
+        mass: Option<f64>,
+        temperature: Option<f64>,
+    },
+}
+
+pub struct Barostat {
+    coupling: Coupling,
+    pressure: f64,
+    time_constant: f64,
+    anisotropic: bool,
+}
+
+impl Barostat {
+    /// Berendsen's barostat, relaxing the pressure over a time constant τ in a fluid with an
+    /// isothermal compressibility β.
+    pub fn berendsen(pressure: f64, time_constant: f64, compressibility: f64) -> Barostat {
+        Barostat {
+            coupling: Coupling::Berendsen { compressibility },
+            pressure,
+            time_constant,
+            anisotropic: false,
+        }
+    }
+
+    /// The Martyna–Tobias–Klein barostat, whose mass (N_f + d) k T τ² is set by the time
+    /// constant τ of the volume's oscillations.
+    pub fn martyna_tobias_klein(pressure: f64, time_constant: f64) -> Barostat {
+        Barostat {
+            coupling: Coupling::MartynaTobiasKlein {
+                strain_rate: Vec3::zero(),
+                mass: None,
+                temperature: None,
+            },
+            pressure,
+            time_constant,
+            anisotropic: false,
+        }
+    }
+
+    /// Stretch each axis of the box by the pressure along it, rather than the whole box by the
+    /// mean pressure.
+    pub fn anisotropic(mut self) -> Barostat {
+        self.anisotropic = true;
+        self
+    }
+
+    /// The temperature k T that sizes the Martyna–Tobias–Klein barostat's mass, usually a
+    /// thermostat's. Defaults to the particles' temperature the first time the barostat acts,
+    /// which is too small for particles that start at rest.
+    pub fn temperature(mut self, temperature: f64) -> Barostat {
+        if let Coupling::MartynaTobiasKlein {
+            temperature: target,
+            ..
+        } = &mut self.coupling
+        {
+            *target = Some(temperature);
+        }
+        self
+    }
+
+    //--------------------------------------------------------------------//
+
+    pub fn target_pressure(&self) -> f64 {
+        self.pressure
+    }
+
+    /// The barostat's share of the conserved energy, P V plus the kinetic energy of the box.
+    pub fn energy(&self, simulation_box: &SimulationBox) -> f64 {
+        let work = self.pressure * simulation_box.volume();
+        match &self.coupling {
+            Coupling::Berendsen { .. } => work,
+            Coupling::MartynaTobiasKlein {
+                strain_rate, mass, ..
+            } => {
+                // each axis carries a share of the mass
+                let axis_mass = mass.unwrap_or(0.0) / simulation_box.dimension.count() as f64;
+                work + 0.5 * axis_mass * strain_rate.mag_squared()
+            }
+        }
+    }
+
+    //--------------------------------------------------------------------//
+
+    /// Stretch the box and the particles in it towards the target pressure, given the virial
+    /// of the forces between them.
+    pub(crate) fn apply(
+        &mut self,
+        simulation_box: &mut SimulationBox,
+        particle_source: &mut [Particle],
+        virial: Vec3,
+        dt: f64,
+    ) {
+        let (target, time_constant, anisotropic) =
+            (self.pressure, self.time_constant, self.anisotropic);
+        let d = simulation_box.dimension.count() as usize;
+        let periodic = |axis: usize| axis < d;
+        let pressures = simulation_box.pressure(particle_source, virial);
+        let pressures = [pressures.x, pressures.y, pressures.z];
+        let mean = pressures[..d].iter().sum::<f64>() / d as f64;
+        let pressure_along = |axis: usize| {
+            if anisotropic {
+                pressures[axis]
+            } else {
+                mean
+            }
+        };
+
+        let mobile = particle_source
+            .iter()
+            .filter(|particle| particle.inverse_mass > 0.0);
+        let degrees_of_freedom = (d * mobile.clone().count()) as f64;
+        // twice the kinetic energy along the periodic axes
+        let kinetic: f64 = mobile
+            .map(|particle| {
+                let vel = particle.vel;
+                [vel.x, vel.y, vel.z][..d]
+                    .iter()
+                    .map(|v| particle.mass * v * v)
+                    .sum::<f64>()
+            })
+            .sum();
+        if degrees_of_freedom == 0.0 {
+            return;
+        }
+
+        let (stretch, drag) = match &mut self.coupling {
+            Coupling::Berendsen { compressibility } => {
+                let rate = *compressibility * dt / time_constant;
+                // the whole box changes its volume by the mean pressure, while each axis alone
+                // is stretched by its own pressure through the linear compressibility β / d
+                let stretch = |axis: usize| {
+                    if !periodic(axis) {
+                        1.0
+                    } else if anisotropic {
+                        (1.0 - rate / d as f64 * (target - pressures[axis])).max(0.0)
+                    } else {
+                        (1.0 - rate * (target - mean)).max(0.0).powf(1.0 / d as f64)
+                    }
+                };
+                (
+                    Vec3::new(stretch(0), stretch(1), stretch(2)),
+                    Vec3::new(1.0, 1.0, 1.0),
+                )
+            }
+            Coupling::MartynaTobiasKlein {
+                strain_rate,
+                mass,
+                temperature,
+            } => {
+                // particles at rest don't set a temperature to size the box's mass by
+                let temperature = temperature.unwrap_or(kinetic / degrees_of_freedom);
+                if mass.is_none() && temperature > 0.0 {
+                    *mass =
+                        Some((degrees_of_freedom + d as f64) * temperature * time_constant.powi(2));
+                }
+                let mass = match *mass {
+                    Some(mass) => mass,
+                    None => return,
+                };
+
+                // each axis is driven by its excess pressure and by the kinetic energy, and
+                // carries a share of the mass
+                let volume = simulation_box.volume();
+                let axis_mass = mass / d as f64;
+                let mut rates = [strain_rate.x, strain_rate.y, strain_rate.z];
+                for (axis, rate) in rates.iter_mut().enumerate().take(d) {
+                    let force =
+                        volume * (pressure_along(axis) - target) + kinetic / degrees_of_freedom;
+                    *rate += force / axis_mass * dt;
+                }
+                *strain_rate = Vec3::new(rates[0], rates[1], rates[2]);
+
+                // the particles' velocities are dragged by the stretching, and by the change
+                // in volume shared over every degree of freedom
+                let shared = rates.iter().sum::<f64>() / degrees_of_freedom;
+                let drag = |axis: usize| {
+                    if periodic(axis) {
+                        (-(rates[axis] + shared) * dt).exp()
+                    } else {
+                        1.0
+                    }
+                };
+                (
+                    Vec3::new(
+                        (rates[0] * dt).exp(),
+                        (rates[1] * dt).exp(),
+                        (rates[2] * dt).exp(),
+                    ),
+                    Vec3::new(drag(0), drag(1), drag(2)),
+                )
+            }
+        };
+
+        for particle in particle_source.iter_mut() {
+            if particle.inverse_mass == 0.0 {
+                continue;
+            }
+            particle.pos = simulation_box.scale_point(particle.pos, stretch);
+            particle.vel = Vec3::new(
+                particle.vel.x * drag.x,
+                particle.vel.y * drag.y,
+                particle.vel.z * drag.z,
+            );
+        }
+        simulation_box.scale(stretch);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        algorithms::Random,
+        constraint::{rattle::Rattle, ConstraintSolver},
+        system::System,
+    };
+
+    fn cube(side: f64) -> SimulationBox {
+        SimulationBox::new(Vec3::zero(), Vec3::new(side, side, side))
+    }
+
+    /// Free particles scattered through a cube, moving with random velocities.
+    fn gas(count: usize, side: f64) -> System {
+        let mut random = Random::new(4);
+        let mut system = System::new();
+        system.substeps = 1;
+        system.set_simulation_box(cube(side));
+        for _ in 0..count {
+            system.add_particle(
+                Particle::new()
+                    .mass(1.0)
+                    .pos(side * Vec3::new(random.uniform(), random.uniform(), random.uniform()))
+                    .vel(Vec3::new(
+                        random.gaussian(),
+                        random.gaussian(),
+                        random.gaussian(),
+                    )),
+            );
+        }
+        system
+    }
+
+    #[test]
+    fn berendsen_stretches_by_the_mean_pressure_or_each_axis_by_its_own() {
+        // moving only along x, so that P_x = 100 * 4 / 1000 and the other pressures are zero
+        let particles = |count: usize| {
+            (0..count)
+                .map(|id| {
+                    Particle::new()
+                        .id(id as u32)
+                        .mass(1.0)
+                        .pos_xyz(5.0, 5.0, 5.0)
+                        .vel_xyz(2.0, 0.0, 0.0)
+                })
+                .collect::<Vec<Particle>>()
+        };
+        let (target, dt, pressure) = (0.1, 0.1, 0.4);
+
+        let mut simulation_box = cube(10.0);
+        let mut barostat = Barostat::berendsen(target, 1.0, 1.0);
+        barostat.apply(&mut simulation_box, &mut particles(100), Vec3::zero(), dt);
+        let volume = 1000.0 * (1.0 - dt * (target - pressure / 3.0));
+        assert!((simulation_box.volume() - volume).abs() < 1e-9);
+        let lengths = simulation_box.lengths();
+        assert!((lengths.x - lengths.y).abs() < 1e-12 && (lengths.x - lengths.z).abs() < 1e-12);
+
+        // with the linear compressibility β / 3 along each axis
+        let mut simulation_box = cube(10.0);
+        let mut barostat = Barostat::berendsen(target, 1.0, 1.0).anisotropic();
+        barostat.apply(&mut simulation_box, &mut particles(100), Vec3::zero(), dt);
+        let lengths = simulation_box.lengths();
+        assert!((lengths.x - 10.0 * (1.0 - dt / 3.0 * (target - pressure))).abs() < 1e-12);
+        assert!((lengths.y - 10.0 * (1.0 - dt / 3.0 * target)).abs() < 1e-12);
+        assert!((lengths.z - lengths.y).abs() < 1e-12);
+    }
+
+    #[test]
+    fn berendsen_takes_an_ideal_gas_to_its_pressure() {
+        let mut system = gas(100, 10.0);
+        system.set_barostat(Barostat::berendsen(0.2, 0.1, 1.0));
+        assert!(system.pressure().unwrap() < 0.15);
+        for _ in 0..1000 {
+            system.step_forward(0.01);
+        }
+        // free particles keep their kinetic energy, so the volume settles at N_f k T / (d P)
+        assert!((system.pressure().unwrap() - 0.2).abs() < 1e-6);
+        let kinetic: f64 = system
+            .particles
+            .iter()
+            .map(|particle| particle.mass * particle.vel.mag_squared())
+            .sum();
+        let volume = system.simulation_box.unwrap().volume();
+        assert!((volume - kinetic / (3.0 * 0.2)).abs() < 1e-4 * volume);
+    }
+
+    #[test]
+    fn martyna_tobias_klein_conserves_the_energy_of_an_ideal_gas() {
+        let mut system = gas(100, 10.0);
+        system.set_constraint_solver(ConstraintSolver::Rattle(Rattle::new()));
+        system.set_barostat(Barostat::martyna_tobias_klein(0.2, 1.0).temperature(1.0));
+        let energy = |system: &System| {
+            let kinetic: f64 = system
+                .particles
+                .iter()
+                .map(|particle| 0.5 * particle.mass * particle.vel.mag_squared())
+                .sum();
+            let barostat = system.barostat.as_ref().unwrap();
+            kinetic + barostat.energy(&system.simulation_box.unwrap())
+        };
+        let start = energy(&system);
+        let start_volume = system.simulation_box.unwrap().volume();
+
+        let (mut drift, mut smallest): (f64, f64) = (0.0, f64::MAX);
+        for _ in 0..5000 {
+            system.step_forward(0.01);
+            drift = drift.max((energy(&system) - start).abs());
+            smallest = smallest.min(system.simulation_box.unwrap().volume());
+        }
+        // the gas starts at about half the pressure, so the box has to shrink
+        assert!(smallest < 0.8 * start_volume);
+        assert!(drift < 1e-3 * start);
+    }
+}
+
+//---------------------------------------------------------------------------------------------------//
@@ -0,0 +1,533 @@
+//! A bounding volume hierarchy (AABB tree) for broadphase collision detection and spatial queries.
+//!
+//! The tree is built top-down by median splits along the longest axis. Between rebuilds it is
+//! refit bottom-up, which is cheap and keeps the tree valid as primitives move, but slowly
+//! degrades its quality. Unlike a uniform grid it adapts to strongly clustered scenes.
+//!
+//! In a periodic [`SimulationBox`], particles are bounded wrapped into the box, and contacts are
+//! also found across its faces.
+
+//---------------------------------------------------------------------------------------------------//
+use crate::{
+    collision::{periodic_images, Aabb, CollisionDetector},
+    math::{Point3, Vec3},
+    particle::{Particle, ParticleReference},
+    simulation_box::{displacement, SimulationBox},
+};
+
+//---------------------------------------------------------------------------------------------------//
+
+#[derive(Copy, Clone)]
+enum NodeKind {
+    Leaf(usize),
+    Branch(usize, usize),
+}
+
+#[derive(Copy, Clone)]
+struct Node {
+    bounds: Aabb,
+    kind: NodeKind,
+}
+
+pub struct Bvh {
+    collision_distance: f64,
+    rebuild_interval: u32,
+    refits_since_build: u32,
+
+    nodes: Vec<Node>,
+    primitives: Vec<Aabb>,
+
+    simulation_box: Option<SimulationBox>,
+}
+
+impl Bvh {
+    /// Create an empty tree. Particles are bounded as spheres whose diameter is the collision distance.
+    pub fn new(collision_distance: f64) -> Bvh {
+        Bvh {
+            collision_distance,
+            rebuild_interval: 30,
+            refits_since_build: 0,
+            nodes: Vec::new(),
+            primitives: Vec::new(),
+            simulation_box: None,
+        }
+    }
+
+    /// How many refits to perform before the tree is rebuilt from scratch.
+    pub fn rebuild_interval(mut self, refits: u32) -> Bvh {
+        self.rebuild_interval = refits;
+        self
+    }
+
+    //--------------------------------------------------------------------//
+    // construction
+
+    /// Build a fresh tree over a set of primitive bounding boxes.
+    /// Indices into `boxes` are what the queries return.
+    pub fn build(&mut self, boxes: &[Aabb]) {
+        self.nodes.clear();
+        self.primitives.clear();
+        self.primitives.extend_from_slice(boxes);
+        self.refits_since_build = 0;
+
+        if !boxes.is_empty() {
+            let mut items: Vec<usize> = (0..boxes.len()).collect();
+            self.build_node(&mut items);
+        }
+    }
+
+    fn build_node(&mut self, items: &mut [usize]) -> usize {
+        let index = self.nodes.len();
+
+        if items.len() == 1 {
+            self.nodes.push(Node {
+                bounds: self.primitives[items[0]],
+                kind: NodeKind::Leaf(items[0]),
+            });
+            return index;
+        }
+
+        let mut centroids = Aabb::new(
+            self.primitives[items[0]].center(),
+            self.primitives[items[0]].center(),
+        );
+        for item in items.iter() {
+            let center = self.primitives[*item].center();
+            centroids = centroids.union(Aabb::new(center, center));
+        }
+        let extent = centroids.extent();
+        let axis: fn(Vec3) -> f64 = if extent.x >= extent.y && extent.x >= extent.z {
+            |v| v.x
+        } else if extent.y >= extent.z {
+            |v| v.y
+        } else {
+            |v| v.z
+        };
+
+        let mid = items.len() / 2;
+        let primitives = &self.primitives;
+        items.select_nth_unstable_by(mid, |a, b| {
+            axis(primitives[*a].center()).total_cmp(&axis(primitives[*b].center()))
+        });
+
+        // reserve the parent so that children always come after it
+        self.nodes.push(Node {
+            bounds: centroids,
+            kind: NodeKind::Leaf(0),
+        });
+        let (left_items, right_items) = items.split_at_mut(mid);
+        let left = self.build_node(left_items);
+        let right = self.build_node(right_items);
+
+        self.nodes[index] = Node {
+            bounds: self.nodes[left].bounds.union(self.nodes[right].bounds),
+            kind: NodeKind::Branch(left, right),
+        };
+        index
+    }
+
+    /// Update the primitive boxes without changing the tree's topology.
+    /// Falls back to a full build if the number of primitives changed.
+    pub fn refit(&mut self, boxes: &[Aabb]) {
+        if boxes.len() != self.primitives.len() {
+            self.build(boxes);
+            return;
+        }
+
+        self.primitives.copy_from_slice(boxes);
+        self.refits_since_build += 1;
+
+        // children are stored after their parents, so a reverse sweep visits them first
+        for index in (0..self.nodes.len()).rev() {
+            self.nodes[index].bounds = match self.nodes[index].kind {
+                NodeKind::Leaf(primitive) => self.primitives[primitive],
+                NodeKind::Branch(left, right) => {
+                    self.nodes[left].bounds.union(self.nodes[right].bounds)
+                }
+            };
+        }
+    }
+
+    /// Refit the tree, or rebuild it if it has been refit too many times.
+    pub fn update(&mut self, boxes: &[Aabb]) {
+        if self.refits_since_build >= self.rebuild_interval {
+            self.build(boxes);
+        } else {
+            self.refit(boxes);
+        }
+    }
+
+    //--------------------------------------------------------------------//
+    // queries
+
+    /// Every pair of primitives `(i, j)` with `i < j` whose boxes overlap.
+    pub fn overlap_pairs(&self) -> Vec<(usize, usize)> {
+        let mut pairs = Vec::new();
+        if !self.nodes.is_empty() {
+            self.self_pairs(0, &mut pairs);
+        }
+        pairs
+    }
+
+    fn self_pairs(&self, node: usize, pairs: &mut Vec<(usize, usize)>) {
+        if let NodeKind::Branch(left, right) = self.nodes[node].kind {
+            self.self_pairs(left, pairs);
+            self.self_pairs(right, pairs);
+            self.cross_pairs(left, right, pairs);
+        }
+    }
+
+    fn cross_pairs(&self, a: usize, b: usize, pairs: &mut Vec<(usize, usize)>) {
+        let (node_a, node_b) = (self.nodes[a], self.nodes[b]);
+        if !node_a.bounds.overlaps(node_b.bounds) {
+            return;
+        }
+
+        match (node_a.kind, node_b.kind) {
+            (NodeKind::Leaf(i), NodeKind::Leaf(j)) => pairs.push((i.min(j), i.max(j))),
+            (NodeKind::Branch(left, right), NodeKind::Leaf(_)) => {
+                self.cross_pairs(left, b, pairs);
+                self.cross_pairs(right, b, pairs);
+            }
+            (NodeKind::Leaf(_), NodeKind::Branch(left, right)) => {
+                self.cross_pairs(a, left, pairs);
+                self.cross_pairs(a, right, pairs);
+            }
+            (NodeKind::Branch(a_left, a_right), NodeKind::Branch(b_left, b_right)) => {
+                // descend into the larger of the two nodes
+                if node_a.bounds.extent().mag_squared() >= node_b.bounds.extent().mag_squared() {
+                    self.cross_pairs(a_left, b, pairs);
+                    self.cross_pairs(a_right, b, pairs);
+                } else {
+                    self.cross_pairs(a, b_left, pairs);
+                    self.cross_pairs(a, b_right, pairs);
+                }
+            }
+        }
+    }
+
+    /// Every primitive whose box is within `radius` of `center`.
+    pub fn query_radius(&self, center: Point3, radius: f64) -> Vec<usize> {
+        let mut found = Vec::new();
+        let mut stack = Vec::new();
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+
+        while let Some(node) = stack.pop() {
+            let node = self.nodes[node];
+            if node.bounds.intersects_sphere(center, radius) {
+                match node.kind {
+                    NodeKind::Leaf(primitive) => found.push(primitive),
+                    NodeKind::Branch(left, right) => {
+                        stack.push(left);
+                        stack.push(right);
+                    }
+                }
+            }
+        }
+        found
+    }
+
+    /// The closest primitive hit by a ray, and the distance to it. `narrow_phase` is handed
+    /// each candidate whose box is hit, and returns the distance to the primitive itself.
+    pub fn ray_cast(
+        &self,
+        origin: Point3,
+        direction: Vec3,
+        max_distance: f64,
+        mut narrow_phase: impl FnMut(usize) -> Option<f64>,
+    ) -> Option<(usize, f64)> {
+        let direction = direction.norm();
+        let inverse = Vec3::new(1.0 / direction.x, 1.0 / direction.y, 1.0 / direction.z);
+        let mut closest: Option<(usize, f64)> = None;
+        let mut stack = Vec::new();
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+
+        while let Some(node) = stack.pop() {
+            let limit = closest.map_or(max_distance, |(_, t)| t);
+            let node = self.nodes[node];
+            if node.bounds.ray_entry(origin, inverse, limit).is_none() {
+                continue;
+            }
+
+            match node.kind {
+                NodeKind::Leaf(primitive) => {
+                    if let Some(t) = narrow_phase(primitive) {
+                        if t >= 0.0 && t <= limit {
+                            closest = Some((primitive, t));
+                        }
+                    }
+                }
+                NodeKind::Branch(left, right) => {
+                    stack.push(left);
+                    stack.push(right);
+                }
+            }
+        }
+        closest
+    }
+
+    /// The closest primitive box hit by a ray, and the distance to it.
+    pub fn ray_cast_boxes(
+        &self,
+        origin: Point3,
+        direction: Vec3,
+        max_distance: f64,
+    ) -> Option<(usize, f64)> {
+        let norm = direction.norm();
+        let inverse = Vec3::new(1.0 / norm.x, 1.0 / norm.y, 1.0 / norm.z);
+        self.ray_cast(origin, direction, max_distance, |primitive| {
+            self.primitives[primitive].ray_entry(origin, inverse, max_distance)
+        })
+    }
+
+    //--------------------------------------------------------------------//
+    // particle helpers
+
+    /// The particle positions, wrapped into the periodic box if there is one.
+    fn particle_points(&self, particles: &[Particle]) -> Vec<Point3> {
+        particles
+            .iter()
+            .map(|particle| match &self.simulation_box {
+                Some(simulation_box) => simulation_box.wrap(particle.pos),
+                None => particle.pos,
+            })
+            .collect()
+    }
+
+    fn particle_boxes(&self, particles: &[Particle]) -> Vec<Aabb> {
+        self.particle_points(particles)
+            .into_iter()
+            .map(|point| Aabb::from_sphere(point, self.collision_distance / 2.0))
+            .collect()
+    }
+
+    /// Refit (or rebuild) the tree around the current particle positions.
+    pub fn update_particles(&mut self, particles: &[Particle]) {
+        let boxes = self.particle_boxes(particles);
+        self.update(&boxes);
+    }
+
+    /// The first particle hit by a ray, treating particles as spheres of half the collision distance.
+    /// The tree must have been built from the same particles, and in a periodic box they are hit
+    /// where it holds them, wrapped into the box.
+    pub fn ray_cast_particles(
+        &self,
+        particles: &[Particle],
+        origin: Point3,
+        direction: Vec3,
+        max_distance: f64,
+    ) -> Option<(ParticleReference, f64)> {
+        let norm = direction.norm();
+        let radius = self.collision_distance / 2.0;
+
+        self.ray_cast(origin, direction, max_distance, |index| {
+            // solve |origin + t * norm - center| = radius for the nearest t
+            let offset = origin - self.primitives[index].center();
+            let b = offset.dot(norm);
+            let discriminant = b * b - (offset.mag_squared() - radius * radius);
+            if discriminant < 0.0 || -b + discriminant.sqrt() < 0.0 {
+                None
+            } else {
+                Some((-b - discriminant.sqrt()).max(0.0))
+            }
+        })
+        .map(|(index, t)| (ParticleReference::new(particles[index].id, index), t))
+    }
+}
+
+impl CollisionDetector for Bvh {
+    fn contact_pairs(
+        &mut self,
+        particles: &[Particle],
+    ) -> Vec<(ParticleReference, ParticleReference, Point3)> {
+        self.update_particles(particles);
+        let points = self.particle_points(particles);
+        let reach = self.collision_distance;
+
+        let mut pairs: Vec<(usize, usize)> = self
+            .overlap_pairs()
+            .into_iter()
+            .filter(|(i, j)| (points[*j] - points[*i]).mag_squared() < reach * reach)
+            .collect();
+        // the box has to be more than twice as wide as the collision distance, so that a pair
+        // is only ever in reach through one copy
+        if let Some(simulation_box) = &self.simulation_box {
+            for (i, point) in points.iter().enumerate() {
+                for image in periodic_images(*point, simulation_box, reach)
+                    .into_iter()
+                    .skip(1)
+                {
+                    for j in self.query_radius(image, reach / 2.0) {
+                        if j > i && (points[j] - image).mag_squared() < reach * reach {
+                            pairs.push((i, j));
+                        }
+                    }
+                }
+            }
+        }
+
+        let simulation_box = self.simulation_box;
+        pairs
+            .into_iter()
+            .map(|(i, j)| {
+                let (pos1, pos2) = (particles[i].pos, particles[j].pos);
+                (
+                    ParticleReference::new(particles[i].id, i),
+                    ParticleReference::new(particles[j].id, j),
+                    pos1 + 0.5 * displacement(simulation_box.as_ref(), pos1, pos2),
+                )
+            })
+            .collect()
+    }
+
+    fn collision_distance(&self) -> f64 {
+        self.collision_distance
+    }
+
+    fn set_simulation_box(&mut self, simulation_box: &SimulationBox) {
+        assert!(
+            simulation_box.fits_reach(self.collision_distance),
+            "the periodic box has to be more than twice as wide as the collision distance"
+        );
+        self.simulation_box = Some(*simulation_box);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::Random;
+
+    /// Particles bunched into a few tight clumps scattered through a box.
+    fn clumps(side: f64) -> Vec<Particle> {
+        let mut random = Random::new(11);
+        let mut point = || side * Vec3::new(random.uniform(), random.uniform(), random.uniform());
+        let centers: Vec<Point3> = (0..6).map(|_| point()).collect();
+        (0..300)
+            .map(|id| {
+                let offset = 0.15 * (point() - 0.5 * Vec3::new(side, side, side));
+                Particle::new()
+                    .id(id)
+                    .pos(centers[id as usize % centers.len()] + offset)
+            })
+            .collect()
+    }
+
+    fn brute_force_pairs(
+        particles: &[Particle],
+        distance: f64,
+        simulation_box: Option<&SimulationBox>,
+    ) -> Vec<(usize, usize)> {
+        let mut pairs = Vec::new();
+        for i in 0..particles.len() {
+            for j in (i + 1)..particles.len() {
+                let d = displacement(simulation_box, particles[i].pos, particles[j].pos);
+                if d.mag() < distance {
+                    pairs.push((i, j));
+                }
+            }
+        }
+        pairs
+    }
+
+    fn found_pairs(detector: &mut Bvh, particles: &[Particle]) -> Vec<(usize, usize)> {
+        let mut pairs: Vec<(usize, usize)> = detector
+            .contact_pairs(particles)
+            .into_iter()
+            .map(|(first, second, _)| (first.index, second.index))
+            .collect();
+        pairs.sort_unstable();
+        pairs
+    }
+
+    #[test]
+    fn contact_pairs_match_brute_force_as_the_tree_is_refit() {
+        let mut particles = clumps(10.0);
+        let mut random = Random::new(2);
+        let mut bvh = Bvh::new(0.8).rebuild_interval(3);
+        for _ in 0..8 {
+            let expected = brute_force_pairs(&particles, 0.8, None);
+            assert!(!expected.is_empty());
+            assert_eq!(found_pairs(&mut bvh, &particles), expected);
+            for particle in &mut particles {
+                particle.pos += 0.5 * Vec3::new(random.gaussian(), random.gaussian(), 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn contact_pairs_match_brute_force_across_faces() {
+        let simulation_box = SimulationBox::new(Vec3::zero(), Vec3::new(10.0, 10.0, 10.0));
+        // clumps straddling the faces, which the box wraps
+        let particles = clumps(10.0)
+            .into_iter()
+            .map(|particle| {
+                let pos = particle.pos + Vec3::new(5.0, 5.0, 0.0);
+                particle.pos(pos)
+            })
+            .collect::<Vec<_>>();
+        let expected = brute_force_pairs(&particles, 0.8, Some(&simulation_box));
+        let wrapped: Vec<Point3> = particles
+            .iter()
+            .map(|particle| simulation_box.wrap(particle.pos))
+            .collect();
+        let across_faces = expected
+            .iter()
+            .filter(|(i, j)| (wrapped[*i] - wrapped[*j]).mag() >= 0.8)
+            .count();
+        assert!(across_faces > 0);
+
+        let mut bvh = Bvh::new(0.8);
+        bvh.set_simulation_box(&simulation_box);
+        assert_eq!(found_pairs(&mut bvh, &particles), expected);
+    }
+
+    #[test]
+    fn query_radius_finds_every_box_in_range() {
+        let particles = clumps(10.0);
+        let mut bvh = Bvh::new(0.5);
+        bvh.update_particles(&particles);
+        let center = particles[17].pos;
+        let mut found = bvh.query_radius(center, 2.0);
+        found.sort_unstable();
+        let expected: Vec<usize> = (0..particles.len())
+            .filter(|index| {
+                Aabb::from_sphere(particles[*index].pos, 0.25).intersects_sphere(center, 2.0)
+            })
+            .collect();
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn rays_hit_the_nearest_particle_where_the_tree_holds_it() {
+        let simulation_box = SimulationBox::new(Vec3::zero(), Vec3::new(10.0, 10.0, 10.0));
+        let particles = vec![
+            Particle::new().id(0).pos_xyz(4.0, 5.0, 5.0),
+            Particle::new().id(1).pos_xyz(7.0, 5.0, 5.0),
+            // outside the box, wrapped to (2, 5, 5), in front of the others
+            Particle::new().id(2).pos_xyz(12.0, 5.0, 5.0),
+        ];
+        let mut bvh = Bvh::new(1.0);
+        let hit = |bvh: &mut Bvh| {
+            bvh.update_particles(&particles);
+            let origin = Point3::new(0.0, 5.0, 5.0);
+            bvh.ray_cast_particles(&particles, origin, Vec3::x_hat(), 20.0)
+                .map(|(reference, t)| (reference.index, t))
+        };
+
+        let (index, t) = hit(&mut bvh).unwrap();
+        assert_eq!(index, 0);
+        assert!((t - 3.5).abs() < 1e-12);
+
+        bvh.set_simulation_box(&simulation_box);
+        let (index, t) = hit(&mut bvh).unwrap();
+        assert_eq!(index, 2);
+        assert!((t - 1.5).abs() < 1e-12);
+    }
+}
+
+//---------------------------------------------------------------------------------------------------//
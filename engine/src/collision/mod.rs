@@ -0,0 +1,156 @@
+pub mod bvh;
+pub mod neighbor_list;
+pub mod spatial_hash;
+
+//---------------------------------------------------------------------------------------------------//
+use crate::{
+    math::{Point3, Vec3},
+    particle::{Particle, ParticleReference},
+    simulation_box::SimulationBox,
+};
+
+//---------------------------------------------------------------------------------------------------//
+
+pub trait CollisionDetector {
+    fn contact_pairs(
+        &mut self,
+        particles: &[Particle],
+    ) -> Vec<(ParticleReference, ParticleReference, Point3)>;
+
+    /// How close two particles' centers come before they're in contact.
+    fn collision_distance(&self) -> f64;
+
+    /// Find contacts across the faces of a periodic box. Detectors that don't override this
+    /// ignore the box.
+    fn set_simulation_box(&mut self, _simulation_box: &SimulationBox) {}
+}
+
+/// The copies of a point, wrapped into a periodic box, that could be within reach of a point
+/// across the box's faces: the point itself, and its shifts by a box length along each periodic
+/// axis whose faces it is within reach of. Only one copy of a point is ever within reach of
+/// another if the box is more than twice as wide as the reach. The point itself comes first.
+pub(crate) fn periodic_images(
+    point: Point3,
+    simulation_box: &SimulationBox,
+    reach: f64,
+) -> Vec<Point3> {
+    let lengths = simulation_box.lengths();
+    let (lower, upper) = (simulation_box.lower, simulation_box.upper);
+    let d = simulation_box.dimension.count();
+    let shifts = |position: f64, lower: f64, upper: f64, periodic: bool| {
+        let mut shifts = vec![0.0];
+        if periodic && position - lower < reach {
+            shifts.push(1.0);
+        }
+        if periodic && upper - position < reach {
+            shifts.push(-1.0);
+        }
+        shifts
+    };
+
+    let mut images = Vec::new();
+    for x in shifts(point.x, lower.x, upper.x, true) {
+        for y in shifts(point.y, lower.y, upper.y, d > 1) {
+            for z in shifts(point.z, lower.z, upper.z, d > 2) {
+                images.push(point + Vec3::new(x * lengths.x, y * lengths.y, z * lengths.z));
+            }
+        }
+    }
+    images
+}
+
+//---------------------------------------------------------------------------------------------------//
+
+/// An axis-aligned bounding box
+#[derive(Copy, Clone, Debug)]
+pub struct Aabb {
+    pub min: Point3,
+    pub max: Point3,
+}
+
+impl Aabb {
+    pub fn new(min: Point3, max: Point3) -> Aabb {
+        Aabb { min, max }
+    }
+
+    /// The tightest box around a sphere
+    pub fn from_sphere(center: Point3, radius: f64) -> Aabb {
+        let extent = Vec3::new(radius, radius, radius);
+        Aabb::new(center - extent, center + extent)
+    }
+
+    /// The smallest box containing both boxes
+    pub fn union(self, other: Aabb) -> Aabb {
+        Aabb::new(
+            Point3::new(
+                self.min.x.min(other.min.x),
+                self.min.y.min(other.min.y),
+                self.min.z.min(other.min.z),
+            ),
+            Point3::new(
+                self.max.x.max(other.max.x),
+                self.max.y.max(other.max.y),
+                self.max.z.max(other.max.z),
+            ),
+        )
+    }
+
+    pub fn center(self) -> Point3 {
+        0.5 * (self.min + self.max)
+    }
+
+    pub fn extent(self) -> Vec3 {
+        self.max - self.min
+    }
+
+    pub fn overlaps(self, other: Aabb) -> bool {
+        self.min.x <= other.max.x
+            && self.max.x >= other.min.x
+            && self.min.y <= other.max.y
+            && self.max.y >= other.min.y
+            && self.min.z <= other.max.z
+            && self.max.z >= other.min.z
+    }
+
+    /// Whether any part of the box is within `radius` of `center`
+    pub fn intersects_sphere(self, center: Point3, radius: f64) -> bool {
+        let closest = Point3::new(
+            center.x.clamp(self.min.x, self.max.x),
+            center.y.clamp(self.min.y, self.max.y),
+            center.z.clamp(self.min.z, self.max.z),
+        );
+        (closest - center).mag_squared() <= radius * radius
+    }
+
+    /// Distance along the ray at which it enters the box, using the slab method.
+    /// `inverse_direction` is the componentwise reciprocal of the ray direction.
+    pub fn ray_entry(
+        self,
+        origin: Point3,
+        inverse_direction: Vec3,
+        max_distance: f64,
+    ) -> Option<f64> {
+        let mut t_min = 0.0_f64;
+        let mut t_max = max_distance;
+
+        for (min, max, origin, inverse) in [
+            (self.min.x, self.max.x, origin.x, inverse_direction.x),
+            (self.min.y, self.max.y, origin.y, inverse_direction.y),
+            (self.min.z, self.max.z, origin.z, inverse_direction.z),
+        ] {
+            let t1 = (min - origin) * inverse;
+            let t2 = (max - origin) * inverse;
+            // f64::min/max skip NaN, which appears when the ray runs along a slab face
+            t_min = t_min.max(t1.min(t2));
+            t_max = t_max.min(t1.max(t2));
+        }
+
+        if t_min <= t_max {
+            Some(t_min)
+        } else {
+            None
+        }
+    }
+}
+
+//---------------------------------------------------------------------------------------------------//
@@ -0,0 +1,199 @@
+//! Verlet neighbor lists for short-range interactions.
+//!
+//! Pairs are collected out to the cutoff plus a skin distance using a cell list, and the
+//! list is reused until some particle has moved further than half the skin since it was
+//! built. Until then no pair inside the cutoff can be missing from the list.
+//!
+//! In a periodic [`SimulationBox`], pairs are also found across the box's faces, and
+//! displacements are measured to the nearest periodic copy.
+
+//---------------------------------------------------------------------------------------------------//
+use crate::{
+    collision::spatial_hash::SpatialHash,
+    math::Point3,
+    simulation_box::{displacement, SimulationBox},
+};
+
+//---------------------------------------------------------------------------------------------------//
+
+pub struct NeighborList {
+    cutoff: f64,
+    skin: f64,
+
+    pairs: Vec<(usize, usize)>,
+    built_positions: Vec<Point3>,
+    cells: SpatialHash,
+    simulation_box: Option<SimulationBox>,
+}
+
+impl NeighborList {
+    pub fn new(cutoff: f64, skin: f64) -> NeighborList {
+        NeighborList {
+            cutoff,
+            skin,
+            pairs: Vec::new(),
+            built_positions: Vec::new(),
+            cells: SpatialHash::new(cutoff + skin),
+            simulation_box: None,
+        }
+    }
+
+    /// Find pairs across the faces of a periodic box, which has to be more than twice as wide as
+    /// the cutoff plus the skin. The box may change between updates, as a barostat resizes it.
+    pub fn set_simulation_box(&mut self, simulation_box: SimulationBox) {
+        assert!(
+            simulation_box.fits_reach(self.cutoff + self.skin),
+            "the periodic box has to be more than twice as wide as the cutoff plus the skin"
+        );
+        self.simulation_box = Some(simulation_box);
+    }
+
+    pub fn cutoff(&self) -> f64 {
+        self.cutoff
+    }
+
+    /// Whether the list has to be rebuilt for these positions.
+    pub fn stale(&self, positions: &[Point3]) -> bool {
+        if positions.len() != self.built_positions.len() {
+            return true;
+        }
+
+        let limit = (0.5 * self.skin).powi(2);
+        positions
+            .iter()
+            .zip(&self.built_positions)
+            .any(|(pos, built)| {
+                displacement(self.simulation_box.as_ref(), *built, *pos).mag_squared() > limit
+            })
+    }
+
+    /// Rebuild the list from scratch.
+    pub fn build(&mut self, positions: &[Point3]) {
+        match self.simulation_box {
+            None => {
+                self.cells.build(positions);
+                self.pairs = self.cells.pairs();
+            }
+            Some(simulation_box) => {
+                let wrapped: Vec<Point3> = positions
+                    .iter()
+                    .map(|pos| simulation_box.wrap(*pos))
+                    .collect();
+                self.cells.build(&wrapped);
+                self.pairs = self.cells.periodic_pairs(&simulation_box);
+            }
+        }
+        self.built_positions.clear();
+        self.built_positions.extend_from_slice(positions);
+    }
+
+    /// Rebuild the list only if it has gone stale. Returns whether it was rebuilt.
+    pub fn update(&mut self, positions: &[Point3]) -> bool {
+        let stale = self.stale(positions);
+        if stale {
+            self.build(positions);
+        }
+        stale
+    }
+
+    /// Every pair of indices `(i, j)` with `i < j` that might be within the cutoff.
+    pub fn pairs(&self) -> &[(usize, usize)] {
+        &self.pairs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{algorithms::Random, math::Vec3};
+
+    fn scattered(count: usize, side: f64) -> Vec<Point3> {
+        let mut random = Random::new(13);
+        (0..count)
+            .map(|_| side * Vec3::new(random.uniform(), random.uniform(), random.uniform()))
+            .collect()
+    }
+
+    /// Every pair within the cutoff, by brute force.
+    fn within(
+        positions: &[Point3],
+        cutoff: f64,
+        simulation_box: Option<&SimulationBox>,
+    ) -> Vec<(usize, usize)> {
+        let mut pairs = Vec::new();
+        for i in 0..positions.len() {
+            for j in (i + 1)..positions.len() {
+                if displacement(simulation_box, positions[i], positions[j]).mag() < cutoff {
+                    pairs.push((i, j));
+                }
+            }
+        }
+        pairs
+    }
+
+    fn missing(list: &NeighborList, pairs: &[(usize, usize)]) -> usize {
+        pairs
+            .iter()
+            .filter(|pair| !list.pairs().contains(pair))
+            .count()
+    }
+
+    #[test]
+    fn the_list_holds_every_pair_in_reach_until_it_goes_stale() {
+        for simulation_box in [
+            None,
+            Some(SimulationBox::new(Vec3::zero(), Vec3::new(8.0, 8.0, 8.0))),
+        ] {
+            let mut positions = scattered(300, 8.0);
+            let mut list = NeighborList::new(1.5, 0.4);
+            if let Some(simulation_box) = simulation_box {
+                list.set_simulation_box(simulation_box);
+            }
+            assert!(list.update(&positions));
+
+            let mut random = Random::new(1);
+            let mut rebuilds = 0;
+            for _ in 0..40 {
+                for pos in &mut positions {
+                    *pos +=
+                        0.03 * Vec3::new(random.gaussian(), random.gaussian(), random.gaussian());
+                }
+                if list.update(&positions) {
+                    rebuilds += 1;
+                }
+                let expected = within(&positions, 1.5, simulation_box.as_ref());
+                assert_eq!(missing(&list, &expected), 0);
+            }
+            // the skin saves rebuilds, but not all of them
+            assert!(rebuilds > 0 && rebuilds < 20);
+        }
+    }
+
+    #[test]
+    fn no_pair_is_listed_twice_across_the_faces() {
+        let simulation_box = SimulationBox::new(Vec3::zero(), Vec3::new(5.0, 5.0, 5.0));
+        let positions = scattered(200, 5.0);
+        let mut list = NeighborList::new(2.0, 0.4);
+        list.set_simulation_box(simulation_box);
+        list.build(&positions);
+
+        let mut pairs = list.pairs().to_vec();
+        let count = pairs.len();
+        pairs.sort_unstable();
+        pairs.dedup();
+        assert_eq!(pairs.len(), count);
+        assert_eq!(
+            missing(&list, &within(&positions, 2.0, Some(&simulation_box))),
+            0
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "twice as wide as the cutoff plus the skin")]
+    fn a_box_too_small_for_the_cutoff_is_refused() {
+        let simulation_box = SimulationBox::new(Vec3::zero(), Vec3::new(5.0, 5.0, 5.0));
+        NeighborList::new(2.4, 0.24).set_simulation_box(simulation_box);
+    }
+}
+
+//---------------------------------------------------------------------------------------------------//
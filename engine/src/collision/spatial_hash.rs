@@ -0,0 +1,287 @@
+//! A uniform-grid spatial hash for broadphase collision detection and neighbor finding.
+//!
+//! Space is divided into cubic cells that are at least as large as the collision distance,
+//! so that every candidate pair lives in the same or an adjacent cell.
+//!
+//! In a periodic [`SimulationBox`], points are hashed wrapped into the box, and pairs are also
+//! found across its faces.
+
+//---------------------------------------------------------------------------------------------------//
+use std::collections::HashMap;
+
+use crate::{
+    collision::{periodic_images, CollisionDetector},
+    math::Point3,
+    particle::{Particle, ParticleReference},
+    simulation_box::{displacement, SimulationBox},
+};
+
+//---------------------------------------------------------------------------------------------------//
+
+pub struct SpatialHash {
+    collision_distance: f64,
+    cell_size: f64,
+    cells: HashMap<[i64; 3], Vec<usize>>,
+    points: Vec<Point3>,
+    simulation_box: Option<SimulationBox>,
+}
+
+impl SpatialHash {
+    /// Create a spatial hash whose cells are sized to the given collision distance.
+    pub fn new(collision_distance: f64) -> SpatialHash {
+        assert!(
+            collision_distance.is_finite() && collision_distance > 0.0,
+            "the collision distance must be positive and finite"
+        );
+        SpatialHash {
+            collision_distance,
+            cell_size: collision_distance,
+            cells: HashMap::new(),
+            points: Vec::new(),
+            simulation_box: None,
+        }
+    }
+
+    /// Override the cell size. Cells smaller than the collision distance are searched
+    /// over a wider stencil, so this only trades memory for the number of candidates.
+    pub fn cell_size(mut self, cell_size: f64) -> SpatialHash {
+        assert!(
+            cell_size.is_finite() && cell_size > 0.0,
+            "the cell size must be positive and finite"
+        );
+        self.cell_size = cell_size;
+        self
+    }
+
+    //--------------------------------------------------------------------//
+
+    fn cell(&self, point: Point3) -> [i64; 3] {
+        [
+            (point.x / self.cell_size).floor() as i64,
+            (point.y / self.cell_size).floor() as i64,
+            (point.z / self.cell_size).floor() as i64,
+        ]
+    }
+
+    /// Hash a fresh set of points. Indices into `points` are what the queries return.
+    pub fn build(&mut self, points: &[Point3]) {
+        self.cells.clear();
+        self.points.clear();
+        self.points.extend_from_slice(points);
+
+        for (index, point) in points.iter().enumerate() {
+            let cell = self.cell(*point);
+            self.cells.entry(cell).or_default().push(index);
+        }
+    }
+
+    /// Indices of every hashed point within `radius` of `center`.
+    pub fn query(&self, center: Point3, radius: f64) -> Vec<usize> {
+        let mut found = Vec::new();
+        self.for_each_candidate(center, radius, |index| {
+            if (self.points[index] - center).mag_squared() <= radius * radius {
+                found.push(index);
+            }
+        });
+        found
+    }
+
+    /// Every pair of hashed points `(i, j)` with `i < j` that is closer than the collision distance.
+    pub fn pairs(&self) -> Vec<(usize, usize)> {
+        let mut pairs = Vec::new();
+        let dist_squared = self.collision_distance * self.collision_distance;
+
+        for (i, point) in self.points.iter().enumerate() {
+            self.for_each_candidate(*point, self.collision_distance, |j| {
+                if j > i && (self.points[j] - *point).mag_squared() < dist_squared {
+                    pairs.push((i, j));
+                }
+            });
+        }
+        pairs
+    }
+
+    /// Every pair of hashed points `(i, j)` with `i < j` whose nearest periodic copies are closer
+    /// than the collision distance. The points should have been hashed wrapped into the box,
+    /// which has to be more than twice as wide as the collision distance.
+    pub fn periodic_pairs(&self, simulation_box: &SimulationBox) -> Vec<(usize, usize)> {
+        let reach = self.collision_distance;
+        let mut pairs = Vec::new();
+        for (i, point) in self.points.iter().enumerate() {
+            for image in periodic_images(*point, simulation_box, reach) {
+                self.for_each_candidate(image, reach, |j| {
+                    if j > i && (self.points[j] - image).mag_squared() < reach * reach {
+                        pairs.push((i, j));
+                    }
+                });
+            }
+        }
+        pairs
+    }
+
+    fn for_each_candidate(&self, center: Point3, radius: f64, mut visit: impl FnMut(usize)) {
+        let reach = (radius / self.cell_size).ceil().max(1.0) as i64;
+        let [x, y, z] = self.cell(center);
+
+        for i in (x - reach)..=(x + reach) {
+            for j in (y - reach)..=(y + reach) {
+                for k in (z - reach)..=(z + reach) {
+                    if let Some(cell) = self.cells.get(&[i, j, k]) {
+                        for index in cell {
+                            visit(*index);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl CollisionDetector for SpatialHash {
+    fn contact_pairs(
+        &mut self,
+        particles: &[Particle],
+    ) -> Vec<(ParticleReference, ParticleReference, Point3)> {
+        let simulation_box = self.simulation_box;
+        let points: Vec<Point3> = particles
+            .iter()
+            .map(|particle| match &simulation_box {
+                Some(simulation_box) => simulation_box.wrap(particle.pos),
+                None => particle.pos,
+            })
+            .collect();
+        self.build(&points);
+
+        let pairs = match &simulation_box {
+            Some(simulation_box) => self.periodic_pairs(simulation_box),
+            None => self.pairs(),
+        };
+        pairs
+            .into_iter()
+            .map(|(i, j)| {
+                let (pos1, pos2) = (particles[i].pos, particles[j].pos);
+                (
+                    ParticleReference::new(particles[i].id, i),
+                    ParticleReference::new(particles[j].id, j),
+                    pos1 + 0.5 * displacement(simulation_box.as_ref(), pos1, pos2),
+                )
+            })
+            .collect()
+    }
+
+    fn collision_distance(&self) -> f64 {
+        self.collision_distance
+    }
+
+    fn set_simulation_box(&mut self, simulation_box: &SimulationBox) {
+        assert!(
+            simulation_box.fits_reach(self.collision_distance),
+            "the periodic box has to be more than twice as wide as the collision distance"
+        );
+        self.simulation_box = Some(*simulation_box);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{algorithms::Random, math::Vec3};
+
+    fn scattered(count: u32, side: f64) -> Vec<Particle> {
+        let mut random = Random::new(3);
+        (0..count)
+            .map(|id| {
+                Particle::new()
+                    .id(id)
+                    .pos(side * Vec3::new(random.uniform(), random.uniform(), random.uniform()))
+            })
+            .collect()
+    }
+
+    fn brute_force_pairs(
+        particles: &[Particle],
+        distance: f64,
+        simulation_box: Option<&SimulationBox>,
+    ) -> Vec<(usize, usize)> {
+        let mut pairs = Vec::new();
+        for i in 0..particles.len() {
+            for j in (i + 1)..particles.len() {
+                let d = displacement(simulation_box, particles[i].pos, particles[j].pos);
+                if d.mag() < distance {
+                    pairs.push((i, j));
+                }
+            }
+        }
+        pairs
+    }
+
+    fn found_pairs(detector: &mut SpatialHash, particles: &[Particle]) -> Vec<(usize, usize)> {
+        let mut pairs: Vec<(usize, usize)> = detector
+            .contact_pairs(particles)
+            .into_iter()
+            .map(|(first, second, _)| (first.index, second.index))
+            .collect();
+        pairs.sort_unstable();
+        pairs
+    }
+
+    #[test]
+    fn pairs_match_brute_force() {
+        let particles = scattered(400, 10.0);
+        let expected = brute_force_pairs(&particles, 1.3, None);
+        assert!(!expected.is_empty());
+        assert_eq!(
+            found_pairs(&mut SpatialHash::new(1.3), &particles),
+            expected
+        );
+        // smaller cells search a wider stencil and find the same pairs
+        let mut fine = SpatialHash::new(1.3).cell_size(0.4);
+        assert_eq!(found_pairs(&mut fine, &particles), expected);
+    }
+
+    #[test]
+    fn periodic_pairs_match_brute_force_across_faces() {
+        let simulation_box = SimulationBox::new(Vec3::zero(), Vec3::new(10.0, 10.0, 10.0));
+        let mut particles = scattered(400, 10.0);
+        // a few particles that have drifted out through the faces, but are hashed wrapped
+        particles[0].pos += Vec3::new(10.0, 0.0, 0.0);
+        particles[1].pos -= Vec3::new(0.0, 20.0, 10.0);
+        let expected = brute_force_pairs(&particles, 1.3, Some(&simulation_box));
+        let across_faces = expected
+            .iter()
+            .filter(|(i, j)| (particles[*i].pos - particles[*j].pos).mag() >= 1.3)
+            .count();
+        assert!(across_faces > 0);
+
+        let mut hash = SpatialHash::new(1.3);
+        hash.set_simulation_box(&simulation_box);
+        assert_eq!(found_pairs(&mut hash, &particles), expected);
+    }
+
+    #[test]
+    fn query_finds_every_point_in_range() {
+        let points: Vec<Point3> = scattered(300, 10.0)
+            .iter()
+            .map(|particle| particle.pos)
+            .collect();
+        let mut hash = SpatialHash::new(1.0);
+        hash.build(&points);
+        let center = Point3::new(5.0, 5.0, 5.0);
+        for radius in [0.5, 2.5] {
+            let mut found = hash.query(center, radius);
+            found.sort_unstable();
+            let expected: Vec<usize> = (0..points.len())
+                .filter(|index| (points[*index] - center).mag() <= radius)
+                .collect();
+            assert_eq!(found, expected);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "positive and finite")]
+    fn rejects_an_empty_cell_size() {
+        SpatialHash::new(1.0).cell_size(0.0);
+    }
+}
+
+//---------------------------------------------------------------------------------------------------//
@@ -1,16 +1,27 @@
+pub use crate::constraint::position_based_fluid::PositionBasedFluid;
+
+use std::rc::Rc;
+
 use crate::{
-    constraint::xpbd::{Xpbd, XpbdParameters},
+    collision::CollisionDetector,
+    constraint::{
+        xpbd::{Response, Xpbd, XpbdParameters},
+        Constraint,
+    },
     math::{Point3, Vec3},
     particle::{Particle, ParticleReference},
+    simulation_box::{displacement, SimulationBox},
+    sph::boundary::SignedDistance,
 };
 
 //---------------------------------------------------------------------------------------------------//
 
-pub struct Distance([ParticleReference; 2], f64);
+pub struct Distance([ParticleReference; 2], f64, Option<SimulationBox>);
 
 impl Distance {
+    #[allow(clippy::new_ret_no_self)]
     pub fn new(particles: [ParticleReference; 2], dist: f64) -> XpbdParameters {
-        XpbdParameters::new(Distance(particles, dist))
+        XpbdParameters::new(Distance(particles, dist, None))
     }
 }
 
@@ -20,22 +31,28 @@ impl Xpbd for Distance {
     }
 
     fn constraint(&self, particles: &[&Particle]) -> f64 {
-        self.1 - (particles[1].pos - particles[0].pos).mag()
+        self.1 - displacement(self.2.as_ref(), particles[0].pos, particles[1].pos).mag()
     }
 
     fn gradients(&self, particles: &[&Particle]) -> Vec<Vec3> {
-        let norm = (particles[1].pos - particles[0].pos).norm();
+        let norm = displacement(self.2.as_ref(), particles[0].pos, particles[1].pos).norm();
         vec![norm, -norm]
     }
+
+    fn set_simulation_box(&mut self, simulation_box: &SimulationBox) {
+        self.2 = Some(*simulation_box);
+    }
 }
 
 //--------------------------------------------------------------------//
 
-pub struct NonPenetrate([ParticleReference; 2], f64);
+#[derive(Copy, Clone)]
+pub struct NonPenetrate([ParticleReference; 2], f64, Option<SimulationBox>);
 
 impl NonPenetrate {
+    #[allow(clippy::new_ret_no_self)]
     pub fn new(particles: [ParticleReference; 2], collision_distance: f64) -> XpbdParameters {
-        XpbdParameters::new(NonPenetrate(particles, collision_distance)).as_inequality()
+        XpbdParameters::new(NonPenetrate(particles, collision_distance, None)).as_inequality()
     }
 }
 
@@ -45,13 +62,17 @@ impl Xpbd for NonPenetrate {
     }
 
     fn constraint(&self, particles: &[&Particle]) -> f64 {
-        (particles[1].pos - particles[0].pos).mag() - self.1
+        displacement(self.2.as_ref(), particles[0].pos, particles[1].pos).mag() - self.1
     }
 
     fn gradients(&self, particles: &[&Particle]) -> Vec<Vec3> {
-        let norm = (particles[1].pos - particles[0].pos).norm();
+        let norm = displacement(self.2.as_ref(), particles[0].pos, particles[1].pos).norm();
         vec![-norm, norm]
     }
+
+    fn set_simulation_box(&mut self, simulation_box: &SimulationBox) {
+        self.2 = Some(*simulation_box);
+    }
 }
 
 //--------------------------------------------------------------------//
@@ -64,6 +85,7 @@ pub struct ContactPlane {
 }
 
 impl ContactPlane {
+    #[allow(clippy::new_ret_no_self)]
     pub fn new(
         particle: ParticleReference,
         collision_distance: f64,
@@ -94,4 +116,249 @@ impl Xpbd for ContactPlane {
     }
 }
 
+//--------------------------------------------------------------------//
+
+/// Keeps a particle out of a solid described by its signed distance, such as the walls of an
+/// [`crate::sph::boundary::SphBoundary`]. Walls are shared between the contacts of every particle
+/// they hold back.
+pub struct WallContact {
+    particle: [ParticleReference; 1],
+    collision_distance: f64,
+    wall: Rc<dyn SignedDistance>,
+}
+
+impl WallContact {
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(
+        particle: ParticleReference,
+        collision_distance: f64,
+        wall: Rc<dyn SignedDistance>,
+    ) -> XpbdParameters {
+        XpbdParameters::new(WallContact {
+            particle: [particle],
+            collision_distance,
+            wall,
+        })
+        .as_inequality()
+    }
+}
+
+impl Xpbd for WallContact {
+    fn particles(&self) -> &[ParticleReference] {
+        &self.particle
+    }
+
+    fn constraint(&self, particles: &[&Particle]) -> f64 {
+        self.wall.distance(particles[0].pos) - self.collision_distance
+    }
+
+    fn gradients(&self, particles: &[&Particle]) -> Vec<Vec3> {
+        vec![self.wall.normal(particles[0].pos)]
+    }
+}
+
+//--------------------------------------------------------------------//
+
+/// Transient non-penetration constraints between whichever particles a broadphase
+/// reports as touching. The detector is queried once before each substep's projections, so
+/// pairs that are further apart than the collision distance cost nothing.
+pub struct Collisions {
+    detector: Box<dyn CollisionDetector>,
+    collision_distance: f64,
+    simulation_box: Option<SimulationBox>,
+
+    response: Response,
+    contacts: Vec<NonPenetrate>,
+}
+
+impl Collisions {
+    /// Particles collide at the detector's collision distance.
+    pub fn new(detector: impl CollisionDetector + 'static) -> Collisions {
+        Collisions {
+            collision_distance: detector.collision_distance(),
+            detector: Box::new(detector),
+            simulation_box: None,
+            response: Response {
+                as_inequality: true,
+                ..Default::default()
+            },
+            contacts: Vec::new(),
+        }
+    }
+
+    pub fn compliance(mut self, compliance: f64) -> Collisions {
+        self.response.compliance = compliance;
+        self
+    }
+
+    pub fn dissipation(mut self, dissipation: f64) -> Collisions {
+        self.response.dissipation = dissipation;
+        self
+    }
+
+    pub fn as_force(mut self) -> Collisions {
+        self.response.as_force = true;
+        self
+    }
+}
+
+impl Constraint for Collisions {
+    fn project(&mut self, particle_source: &mut [Particle], dt: f64, static_pass: bool) {
+        for contact in &self.contacts {
+            self.response
+                .project(contact, particle_source, dt, static_pass);
+        }
+    }
+
+    fn set_simulation_box(&mut self, simulation_box: &SimulationBox) {
+        self.simulation_box = Some(*simulation_box);
+        self.detector.set_simulation_box(simulation_box);
+    }
+
+    fn prepare(&mut self, particle_source: &[Particle]) {
+        self.contacts.clear();
+        for (ref1, ref2, _) in self.detector.contact_pairs(particle_source) {
+            if ref1.get(particle_source).inverse_mass == 0.0
+                && ref2.get(particle_source).inverse_mass == 0.0
+            {
+                continue;
+            }
+            self.contacts.push(NonPenetrate(
+                [ref1, ref2],
+                self.collision_distance,
+                self.simulation_box,
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{algorithms::Random, collision::spatial_hash::SpatialHash, system::System};
+
+    fn smallest_separation(particles: &[Particle]) -> f64 {
+        let mut smallest = f64::MAX;
+        for i in 0..particles.len() {
+            for j in (i + 1)..particles.len() {
+                smallest = smallest.min((particles[i].pos - particles[j].pos).mag());
+            }
+        }
+        smallest
+    }
+
+    #[test]
+    fn static_passes_separate_a_crowd() {
+        let mut random = Random::new(5);
+        let mut particles: Vec<Particle> = (0..60)
+            .map(|id| {
+                Particle::new()
+                    .id(id)
+                    .pos(2.0 * Vec3::new(random.uniform(), random.uniform(), random.uniform()))
+            })
+            .collect();
+        let center = |particles: &[Particle]| {
+            particles
+                .iter()
+                .fold(Vec3::zero(), |total, particle| total + particle.pos)
+                / particles.len() as f64
+        };
+        let start = center(&particles);
+        assert!(smallest_separation(&particles) < 0.5);
+
+        let mut collisions = Collisions::new(SpatialHash::new(1.0));
+        for _ in 0..500 {
+            collisions.prepare(&particles);
+            collisions.project(&mut particles, f64::MAX, true);
+        }
+        assert!(smallest_separation(&particles) > 0.99);
+        // equal masses push each other apart symmetrically
+        assert!((center(&particles) - start).mag() < 1e-10);
+    }
+
+    #[test]
+    fn a_head_on_collision_stops_at_the_collision_distance() {
+        let mut system = System::new();
+        system.substeps = 10;
+        system.add_particle(
+            Particle::new()
+                .pos_xyz(-2.0, 0.0, 0.0)
+                .vel_xyz(1.0, 0.0, 0.0),
+        );
+        system.add_particle(
+            Particle::new()
+                .mass(30.0)
+                .pos_xyz(2.0, 0.1, 0.0)
+                .vel_xyz(-1.0, 0.0, 0.0),
+        );
+        system.add_constraint(Collisions::new(SpatialHash::new(1.0)));
+        let momentum = |system: &System| {
+            system
+                .particles
+                .iter()
+                .fold(Vec3::zero(), |total, particle| {
+                    total + particle.mass * particle.vel
+                })
+        };
+        let start = momentum(&system);
+
+        let mut closest = f64::MAX;
+        for _ in 0..300 {
+            system.step_forward(0.01);
+            closest = closest.min(smallest_separation(&system.particles));
+        }
+        assert!(closest > 0.999 && closest < 1.01);
+        assert!((momentum(&system) - start).mag() < 1e-9);
+    }
+
+    #[test]
+    fn distance_and_non_penetration_reach_across_the_faces() {
+        let simulation_box = SimulationBox::new(Vec3::zero(), Vec3::new(10.0, 10.0, 10.0));
+        // 0.6 apart through the face at x = 0
+        let pair = || {
+            vec![
+                Particle::new().id(0).pos_xyz(0.2, 5.0, 5.0),
+                Particle::new().id(1).pos_xyz(9.6, 5.0, 5.0),
+            ]
+        };
+        let references = [ParticleReference::new(0, 0), ParticleReference::new(1, 1)];
+        for mut constraint in [
+            Distance::new(references, 1.0),
+            NonPenetrate::new(references, 1.0),
+        ] {
+            let mut particles = pair();
+            constraint.set_simulation_box(&simulation_box);
+            constraint.prepare(&particles);
+            constraint.project(&mut particles, f64::MAX, true);
+
+            // pushed apart through the face, rather than pulled together through the box
+            let separation =
+                displacement(Some(&simulation_box), particles[0].pos, particles[1].pos);
+            assert!((separation.mag() - 1.0).abs() < 1e-12);
+            assert!((particles[0].pos.x - 0.4).abs() < 1e-12);
+            assert!((particles[1].pos.x - 9.4).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn contacts_between_immovable_particles_are_skipped() {
+        let particles = vec![
+            Particle::new().id(0).inverse_mass(0.0),
+            Particle::new()
+                .id(1)
+                .inverse_mass(0.0)
+                .pos_xyz(0.5, 0.0, 0.0),
+            Particle::new().id(2).pos_xyz(1.2, 0.0, 0.0),
+        ];
+        let mut collisions = Collisions::new(SpatialHash::new(1.0));
+        collisions.prepare(&particles);
+        let pairs: Vec<[usize; 2]> = collisions
+            .contacts
+            .iter()
+            .map(|contact| [contact.0[0].index, contact.0[1].index])
+            .collect();
+        assert_eq!(pairs, vec![[1, 2]]);
+    }
+}
+
 //---------------------------------------------------------------------------------------------------//
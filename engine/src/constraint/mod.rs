@@ -1,11 +1,47 @@
 pub mod constraints;
+pub mod position_based_fluid;
+pub mod rattle;
 pub mod xpbd;
 
 //---------------------------------------------------------------------------------------------------//
-use crate::particle::Particle;
+use crate::{
+    constraint::{rattle::Rattle, xpbd::Xpbd},
+    particle::Particle,
+    simulation_box::SimulationBox,
+};
 
 pub trait Constraint {
     fn project(&mut self, particle_source: &mut [Particle], dt: f64, static_pass: bool);
+
+    /// Called with the system's periodic box before every projection, for constraints that
+    /// measure displacements to the nearest periodic copy.
+    fn set_simulation_box(&mut self, _simulation_box: &SimulationBox) {}
+
+    /// Called once before a substep's projections, or a static pass's iterations, for
+    /// constraints that work out what they'll project from where the particles are.
+    fn prepare(&mut self, _particle_source: &[Particle]) {}
+
+    /// Called once the velocities have been updated from the projected positions, for
+    /// constraints that also act on the velocities directly.
+    fn correct_velocities(&mut self, _particle_source: &mut [Particle], _dt: f64) {}
+
+    /// The rigid equality constraint this holds, for the RATTLE solver, if it is one. Any other
+    /// constraint is projected as it is under XPBD.
+    fn holonomic(&self) -> Option<&dyn Xpbd> {
+        None
+    }
+}
+
+/// How a system integrates its particles and holds its constraints.
+#[derive(Default)]
+pub enum ConstraintSolver {
+    /// Symplectic Euler with the constraints projected onto the positions, the velocities
+    /// following from the change in position over the step.
+    #[default]
+    Xpbd,
+    /// Velocity Verlet with the rigid equality constraints held by RATTLE, which conserves the
+    /// energy and angular momentum of rigid molecules.
+    Rattle(Rattle),
 }
 
 //---------------------------------------------------------------------------------------------------//
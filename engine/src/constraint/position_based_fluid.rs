@@ -0,0 +1,500 @@
+//! Position based fluids (Macklin & Müller, 2013).
+//!
+//! Incompressibility is enforced by one density constraint per particle, C = 1 - ρ/ρ0,
+//! over the neighbors found by a Verlet neighbor list. The constraints are projected by
+//! the XPBD solver like any other, so fluids share the constraint loop with cloth, chains
+//! and contacts. An artificial pressure counters the clumping of particles at free surfaces,
+//! and vorticity confinement puts back some of the rotation that the solver damps out.
+
+//---------------------------------------------------------------------------------------------------//
+use std::rc::Rc;
+
+use crate::{
+    collision::neighbor_list::NeighborList,
+    constraint::{
+        xpbd::{Xpbd, XpbdParameters},
+        Constraint,
+    },
+    math::{Point3, Vec3},
+    particle::{Particle, ParticleReference},
+    sph::{
+        kernels::{Poly6Kernel, SpikyKernel},
+        SphKernel,
+    },
+};
+
+//---------------------------------------------------------------------------------------------------//
+
+/// The density constraint of a single particle, `particles[0]`, over its neighbors.
+pub struct FluidDensity {
+    particles: Vec<ParticleReference>,
+    rest_density: f64,
+    smoothing_radius: f64,
+    kernel: Rc<dyn SphKernel>,
+    gradient_kernel: Rc<dyn SphKernel>,
+}
+
+impl Xpbd for FluidDensity {
+    fn particles(&self) -> &[ParticleReference] {
+        &self.particles
+    }
+
+    fn constraint(&self, particles: &[&Particle]) -> f64 {
+        let center = particles[0].pos;
+        let density: f64 = particles
+            .iter()
+            .map(|particle| {
+                particle.mass * self.kernel.w(center - particle.pos, self.smoothing_radius)
+            })
+            .sum();
+        1.0 - density / self.rest_density
+    }
+
+    fn gradients(&self, particles: &[&Particle]) -> Vec<Vec3> {
+        let center = particles[0].pos;
+        let mut gradients = vec![Vec3::zero(); particles.len()];
+        for (index, particle) in particles.iter().enumerate().skip(1) {
+            let gradient = particle.mass / self.rest_density
+                * self
+                    .gradient_kernel
+                    .grad_w(center - particle.pos, self.smoothing_radius);
+            gradients[0] -= gradient;
+            gradients[index] = gradient;
+        }
+        gradients
+    }
+}
+
+//--------------------------------------------------------------------//
+
+pub struct PositionBasedFluid {
+    coupled_particles: Vec<ParticleReference>,
+    rest_density: f64,
+    smoothing_radius: f64,
+    kernel: Rc<dyn SphKernel>,
+    gradient_kernel: Rc<dyn SphKernel>,
+
+    compliance: f64,
+    unilateral: bool,
+    // (k, n, Δq as a fraction of the smoothing radius)
+    tensile_correction: Option<(f64, i32, f64)>,
+    vorticity_confinement: Option<f64>,
+
+    neighbor_list: Option<NeighborList>,
+    // from the last projection, for the velocity corrections that follow it
+    last_neighborhoods: Vec<Vec<usize>>,
+}
+
+impl PositionBasedFluid {
+    /// Defaults to the poly6 kernel for densities and the spiky kernel for gradients, as in the paper.
+    pub fn new(rest_density: f64, smoothing_radius: f64) -> PositionBasedFluid {
+        PositionBasedFluid {
+            coupled_particles: Vec::new(),
+            rest_density,
+            smoothing_radius,
+            kernel: Rc::new(Poly6Kernel),
+            gradient_kernel: Rc::new(SpikyKernel),
+            compliance: 0.0,
+            unilateral: false,
+            tensile_correction: None,
+            vorticity_confinement: None,
+            neighbor_list: None,
+            last_neighborhoods: Vec::new(),
+        }
+    }
+
+    pub fn kernel(mut self, kernel: impl SphKernel + 'static) -> PositionBasedFluid {
+        self.kernel = Rc::new(kernel);
+        self.neighbor_list = None;
+        self
+    }
+
+    pub fn gradient_kernel(mut self, kernel: impl SphKernel + 'static) -> PositionBasedFluid {
+        self.gradient_kernel = Rc::new(kernel);
+        self.neighbor_list = None;
+        self
+    }
+
+    /// Softens the density constraints, which plays the part of the paper's relaxation parameter.
+    pub fn compliance(mut self, compliance: f64) -> PositionBasedFluid {
+        self.compliance = compliance;
+        self
+    }
+
+    /// Only correct particles that are compressed, so that under-dense regions such as the free
+    /// surface are never pulled together. An alternative to the tensile correction.
+    pub fn unilateral(mut self) -> PositionBasedFluid {
+        self.unilateral = true;
+        self
+    }
+
+    /// The artificial pressure s_corr = -k (W(r) / W(Δq))^n, as a compression relative to the
+    /// rest density. The paper suggests k = 0.1, n = 4, and Δq between 0.1 and 0.3 of the
+    /// smoothing radius.
+    pub fn tensile_correction(mut self, k: f64, n: i32, delta_q: f64) -> PositionBasedFluid {
+        self.tensile_correction = Some((k, n, delta_q));
+        self
+    }
+
+    /// Amplifies existing vortices by accelerating the particles around them, once their
+    /// velocities have been updated each substep. `epsilon` has units of speed.
+    pub fn vorticity_confinement(mut self, epsilon: f64) -> PositionBasedFluid {
+        self.vorticity_confinement = Some(epsilon);
+        self
+    }
+
+    pub fn with_particle(mut self, reference: ParticleReference) -> PositionBasedFluid {
+        self.coupled_particles.push(reference);
+        self
+    }
+
+    pub fn with_particles(mut self, references: &[ParticleReference]) -> PositionBasedFluid {
+        for reference in references {
+            self.coupled_particles.push(*reference);
+        }
+        self
+    }
+
+    //--------------------------------------------------------------------//
+
+    /// The neighbors of every coupled particle, as indices into the coupled particles.
+    fn neighborhoods(&mut self, particle_source: &[Particle]) -> Vec<Vec<usize>> {
+        let support = self
+            .kernel
+            .support(self.smoothing_radius)
+            .max(self.gradient_kernel.support(self.smoothing_radius));
+        let positions: Vec<Point3> = self
+            .coupled_particles
+            .iter()
+            .map(|reference| reference.get(particle_source).pos)
+            .collect();
+        let list = self
+            .neighbor_list
+            .get_or_insert_with(|| NeighborList::new(support, 0.1 * support));
+        list.update(&positions);
+
+        let mut neighborhoods = vec![Vec::new(); positions.len()];
+        for (i, j) in list.pairs() {
+            if (positions[*i] - positions[*j]).mag_squared() < support * support {
+                neighborhoods[*i].push(*j);
+                neighborhoods[*j].push(*i);
+            }
+        }
+        neighborhoods
+    }
+
+    fn apply_tensile_correction(
+        &self,
+        particle_source: &mut [Particle],
+        neighborhoods: &[Vec<usize>],
+        (k, n, delta_q): (f64, i32, f64),
+        dt: f64,
+    ) {
+        let h = self.smoothing_radius;
+        let reference_weight = self.kernel.w(delta_q * h * Vec3::x_hat(), h);
+        if reference_weight == 0.0 {
+            return;
+        }
+
+        // the denominator of each particle's density constraint, Σ w |∇C|²
+        let scales: Vec<f64> = neighborhoods
+            .iter()
+            .enumerate()
+            .map(|(i, neighbors)| {
+                let particle = self.coupled_particles[i].get(particle_source);
+                let mut own_gradient = Vec3::zero();
+                let mut scale = 0.0;
+                for j in neighbors {
+                    let neighbor = self.coupled_particles[*j].get(particle_source);
+                    let gradient = neighbor.mass / self.rest_density
+                        * self.gradient_kernel.grad_w(particle.pos - neighbor.pos, h);
+                    own_gradient += gradient;
+                    scale += neighbor.inverse_mass * gradient.mag_squared();
+                }
+                scale + particle.inverse_mass * own_gradient.mag_squared()
+            })
+            .collect();
+
+        for (i, neighbors) in neighborhoods.iter().enumerate() {
+            for j in neighbors.iter().filter(|j| **j > i) {
+                let (ref1, ref2) = (self.coupled_particles[i], self.coupled_particles[*j]);
+                let (particle1, particle2) = (ref1.get(particle_source), ref2.get(particle_source));
+                let scale = 0.5 * (scales[i] + scales[*j]);
+                if scale == 0.0 {
+                    continue;
+                }
+
+                let radial = particle1.pos - particle2.pos;
+                let compression = k * (self.kernel.w(radial, h) / reference_weight).powi(n);
+                let lagrange = compression / scale;
+                let gradient =
+                    lagrange / self.rest_density * self.gradient_kernel.grad_w(radial, h);
+                let (displacement1, displacement2) = (
+                    -particle1.inverse_mass * particle2.mass * gradient,
+                    particle2.inverse_mass * particle2.mass * gradient,
+                );
+                let (pos1, pos2) = (particle1.pos, particle2.pos);
+
+                ref1.get_mut(particle_source)
+                    .add_displacement(displacement1, pos1, false, dt);
+                ref2.get_mut(particle_source)
+                    .add_displacement(displacement2, pos2, false, dt);
+            }
+        }
+    }
+
+    fn apply_vorticity_confinement(
+        &self,
+        particle_source: &mut [Particle],
+        neighborhoods: &[Vec<usize>],
+        epsilon: f64,
+        dt: f64,
+    ) {
+        let h = self.smoothing_radius;
+        let vorticities: Vec<Vec3> = neighborhoods
+            .iter()
+            .enumerate()
+            .map(|(i, neighbors)| {
+                let particle = self.coupled_particles[i].get(particle_source);
+                // the SPH estimate of the curl of the velocity
+                let mut vorticity = Vec3::zero();
+                for j in neighbors {
+                    let neighbor = self.coupled_particles[*j].get(particle_source);
+                    if neighbor.density != 0.0 {
+                        let gradient = self.gradient_kernel.grad_w(particle.pos - neighbor.pos, h);
+                        vorticity += neighbor.mass / neighbor.density
+                            * (particle.vel - neighbor.vel).cross(gradient);
+                    }
+                }
+                vorticity
+            })
+            .collect();
+
+        for (i, neighbors) in neighborhoods.iter().enumerate() {
+            let particle = self.coupled_particles[i].get(particle_source);
+            // the gradient of the vorticity's magnitude points towards the vortex's center
+            let mut towards_center = Vec3::zero();
+            for j in neighbors {
+                let neighbor = self.coupled_particles[*j].get(particle_source);
+                if neighbor.density != 0.0 {
+                    let gradient = self.gradient_kernel.grad_w(particle.pos - neighbor.pos, h);
+                    towards_center += neighbor.mass / neighbor.density
+                        * (vorticities[*j].mag() - vorticities[i].mag())
+                        * gradient;
+                }
+            }
+
+            if towards_center.mag_squared() > 0.0 {
+                let acceleration = epsilon * towards_center.norm().cross(vorticities[i]);
+                self.coupled_particles[i].get_mut(particle_source).vel += acceleration * dt;
+            }
+        }
+    }
+}
+
+impl Constraint for PositionBasedFluid {
+    fn project(&mut self, particle_source: &mut [Particle], dt: f64, static_pass: bool) {
+        let neighborhoods = self.neighborhoods(particle_source);
+
+        for (i, neighbors) in neighborhoods.iter().enumerate() {
+            if neighbors.is_empty() {
+                continue;
+            }
+
+            let mut particles = Vec::with_capacity(neighbors.len() + 1);
+            particles.push(self.coupled_particles[i]);
+            particles.extend(neighbors.iter().map(|j| self.coupled_particles[*j]));
+
+            let mut density = XpbdParameters::new(FluidDensity {
+                particles,
+                rest_density: self.rest_density,
+                smoothing_radius: self.smoothing_radius,
+                kernel: self.kernel.clone(),
+                gradient_kernel: self.gradient_kernel.clone(),
+            })
+            .compliance(self.compliance);
+            if self.unilateral {
+                density = density.as_inequality();
+            }
+
+            density.project(particle_source, dt, static_pass);
+        }
+
+        if let Some(correction) = self.tensile_correction {
+            self.apply_tensile_correction(particle_source, &neighborhoods, correction, dt);
+        }
+
+        // record the densities, which the vorticity estimate needs and renderers can use
+        let h = self.smoothing_radius;
+        for (i, neighbors) in neighborhoods.iter().enumerate() {
+            let particle = self.coupled_particles[i].get(particle_source);
+            let mut density = particle.mass * self.kernel.w(Vec3::zero(), h);
+            for j in neighbors {
+                let neighbor = self.coupled_particles[*j].get(particle_source);
+                density += neighbor.mass * self.kernel.w(particle.pos - neighbor.pos, h);
+            }
+            self.coupled_particles[i].get_mut(particle_source).density = density;
+        }
+        self.last_neighborhoods = neighborhoods;
+    }
+
+    fn correct_velocities(&mut self, particle_source: &mut [Particle], dt: f64) {
+        if let Some(epsilon) = self.vorticity_confinement {
+            self.apply_vorticity_confinement(
+                particle_source,
+                &self.last_neighborhoods,
+                epsilon,
+                dt,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{algorithms::Random, math::Dimension, sph::kernels::CubicSplineKernel};
+
+    /// A cube of particles of unit mass on a lattice, and references to them.
+    fn lattice(side: usize, spacing: f64) -> (Vec<Particle>, Vec<ParticleReference>) {
+        let mut particles = Vec::new();
+        for x in 0..side {
+            for y in 0..side {
+                for z in 0..side {
+                    let pos = spacing * Vec3::new(x as f64, y as f64, z as f64);
+                    let particle = Particle::new()
+                        .id(particles.len() as u32)
+                        .pos(pos)
+                        .mass(1.0);
+                    particles.push(particle);
+                }
+            }
+        }
+        for particle in particles.iter_mut() {
+            particle.prev_pos = particle.pos;
+        }
+        let references = (0..particles.len())
+            .map(|index| ParticleReference::new(particles[index].id, index))
+            .collect();
+        (particles, references)
+    }
+
+    #[test]
+    fn density_gradients_match_finite_differences() {
+        let mut random = Random::new(5);
+        let particles: Vec<Particle> = (0..12)
+            .map(|id| {
+                let offset = Vec3::new(random.gaussian(), random.gaussian(), random.gaussian());
+                Particle::new()
+                    .id(id)
+                    .pos(0.1 * offset)
+                    .mass(0.5 + random.uniform())
+            })
+            .collect();
+        let references = (0..particles.len())
+            .map(|index| ParticleReference::new(particles[index].id, index))
+            .collect();
+        let kernel: Rc<dyn SphKernel> = Rc::new(CubicSplineKernel::new(Dimension::Three));
+        let density = FluidDensity {
+            particles: references,
+            rest_density: 1000.0,
+            smoothing_radius: 0.15,
+            kernel: kernel.clone(),
+            gradient_kernel: kernel,
+        };
+
+        let borrowed: Vec<&Particle> = particles.iter().collect();
+        let gradients = density.gradients(&borrowed);
+        let step = 1e-7;
+        for (index, gradient) in gradients.iter().enumerate() {
+            for axis in [Vec3::x_hat(), Vec3::y_hat(), Vec3::z_hat()] {
+                let shifted = |sign: f64| {
+                    let moved: Vec<Point3> = particles
+                        .iter()
+                        .enumerate()
+                        .map(|(other, particle)| {
+                            if other == index {
+                                particle.pos + sign * step * axis
+                            } else {
+                                particle.pos
+                            }
+                        })
+                        .collect();
+                    let moved: Vec<Particle> = particles
+                        .iter()
+                        .zip(moved)
+                        .map(|(particle, pos)| Particle::new().pos(pos).mass(particle.mass))
+                        .collect();
+                    density.constraint(&moved.iter().collect::<Vec<&Particle>>())
+                };
+                let expected = (shifted(1.0) - shifted(-1.0)) / (2.0 * step);
+                assert!((gradient.dot(axis) - expected).abs() < 1e-5 * (1.0 + expected.abs()));
+            }
+        }
+    }
+
+    #[test]
+    fn projection_relaxes_a_compressed_block() {
+        // unit masses a tenth apart make a rest density of 1000
+        let (mut particles, references) = lattice(8, 0.075);
+        let mut fluid = PositionBasedFluid::new(1000.0, 0.2)
+            .unilateral()
+            .with_particles(&references);
+
+        fluid.project(&mut particles, 1.0 / 60.0, true);
+        let densest = |particles: &[Particle]| {
+            particles
+                .iter()
+                .map(|particle| particle.density)
+                .fold(0.0, f64::max)
+        };
+        assert!(densest(&particles) > 1.5 * 1000.0);
+        for _ in 0..100 {
+            fluid.project(&mut particles, 1.0 / 60.0, true);
+        }
+        assert!(
+            densest(&particles) < 1.05 * 1000.0,
+            "{}",
+            densest(&particles)
+        );
+    }
+
+    #[test]
+    fn tensile_correction_pushes_close_pairs_apart() {
+        let mut particles = vec![
+            Particle::new().id(0).pos(Vec3::zero()).mass(1.0),
+            Particle::new().id(1).pos(0.02 * Vec3::x_hat()).mass(3.0),
+        ];
+        let references = [ParticleReference::new(0, 0), ParticleReference::new(1, 1)];
+        let fluid = PositionBasedFluid::new(1000.0, 0.1)
+            .tensile_correction(0.1, 4, 0.2)
+            .with_particles(&references);
+        let neighborhoods = vec![vec![1], vec![0]];
+        fluid.apply_tensile_correction(&mut particles, &neighborhoods, (0.1, 4, 0.2), 1.0 / 60.0);
+
+        assert!(particles[0].pos.x < 0.0);
+        assert!(particles[1].pos.x > 0.02);
+        // and the pair's center of mass stays put
+        let center = particles[0].mass * particles[0].pos + particles[1].mass * particles[1].pos;
+        assert!((center - 3.0 * 0.02 * Vec3::x_hat()).mag() < 1e-15);
+    }
+
+    #[test]
+    fn vorticity_confinement_leaves_uniform_flow_alone() {
+        let (mut particles, references) = lattice(6, 0.1);
+        let drift = Vec3::new(0.3, -0.2, 0.1);
+        for particle in particles.iter_mut() {
+            particle.vel = drift;
+        }
+        let mut fluid = PositionBasedFluid::new(1000.0, 0.2)
+            .vorticity_confinement(1.0)
+            .with_particles(&references);
+        fluid.project(&mut particles, 1.0 / 60.0, true);
+        fluid.correct_velocities(&mut particles, 1.0 / 60.0);
+        for particle in &particles {
+            assert!((particle.vel - drift).mag() < 1e-12);
+        }
+    }
+}
+
+//---------------------------------------------------------------------------------------------------//
@@ -0,0 +1,168 @@
+//! RATTLE, the velocity Verlet form of SHAKE (Andersen, 1983), which holds rigid equality
+//! constraints like `Distance` exactly. Where XPBD projects the positions onto the constraints
+//! along their gradients at the projected positions, SHAKE moves them along the gradients at the
+//! start of the step, as the constraint forces acting through the step would, and RATTLE then
+//! removes the velocity along the constraints' gradients at the end of the step. The result is
+//! time reversible and symplectic, so rigid molecules conserve their energy and angular
+//! momentum, which XPBD's projection doesn't.
+//!
+//! Coupled constraints, like the three sides of a rigid triangle, are iterated in turn until all
+//! of them hold.
+
+//---------------------------------------------------------------------------------------------------//
+use crate::{
+    constraint::xpbd::Xpbd,
+    math::Vec3,
+    particle::{Particle, ParticleReference},
+    simulation_box::{displacement, SimulationBox},
+};
+
+//---------------------------------------------------------------------------------------------------//
+
+pub struct Rattle {
+    tolerance: f64,
+    max_iterations: u32,
+}
+
+impl Rattle {
+    pub fn new() -> Rattle {
+        Rattle {
+            tolerance: 1e-10,
+            max_iterations: 1000,
+        }
+    }
+
+    /// The largest violation of a constraint, in its own units, or of its rate of change, that
+    /// counts as satisfied.
+    pub fn tolerance(mut self, tolerance: f64) -> Rattle {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// How many sweeps through the constraints to make before giving up on them all holding.
+    pub fn max_iterations(mut self, max_iterations: u32) -> Rattle {
+        self.max_iterations = max_iterations;
+        self
+    }
+
+    /// The gradients of the constraints at the start of a step, which SHAKE moves along.
+    pub(crate) fn gradients(
+        &self,
+        constraints: &[&dyn Xpbd],
+        particle_source: &[Particle],
+    ) -> Vec<Vec<Vec3>> {
+        constraints
+            .iter()
+            .map(|constraint| {
+                constraint.gradients(&gather(constraint.particles(), particle_source))
+            })
+            .collect()
+    }
+
+    /// SHAKE: move the particles back onto the constraints after an unconstrained drift, along
+    /// the gradients from the start of the step.
+    pub(crate) fn project_positions(
+        &self,
+        constraints: &[&dyn Xpbd],
+        start_gradients: &[Vec<Vec3>],
+        particle_source: &mut [Particle],
+    ) {
+        for _ in 0..self.max_iterations {
+            let mut satisfied = true;
+            for (constraint, start) in constraints.iter().zip(start_gradients) {
+                let particles = gather(constraint.particles(), particle_source);
+                let evaluated = constraint.constraint(&particles);
+                if evaluated.abs() <= self.tolerance {
+                    continue;
+                }
+                satisfied = false;
+
+                // a Newton step along the start gradients, using the current ones
+                let gradients = constraint.gradients(&particles);
+                let denominator: f64 = particles
+                    .iter()
+                    .zip(&gradients)
+                    .zip(start)
+                    .map(|((part, gradient), start)| part.inverse_mass * gradient.dot(*start))
+                    .sum();
+                if denominator == 0.0 {
+                    continue;
+                }
+                let lagrange = -evaluated / denominator;
+                for (reference, start) in constraint.particles().iter().zip(start) {
+                    let part = reference.get_mut(particle_source);
+                    part.pos += lagrange * part.inverse_mass * *start;
+                }
+            }
+            if satisfied {
+                return;
+            }
+        }
+    }
+
+    /// RATTLE: remove the velocities along the gradients of the constraints, so that the
+    /// constraints stay satisfied as the particles move on. Returns the diagonal of
+    /// Σ r_i ⊗ Δp_i, with Δp_i the momentum given to each particle and r_i its displacement from
+    /// the first particle of its constraint, which the constraint forces' virial is made from.
+    pub(crate) fn project_velocities(
+        &self,
+        constraints: &[&dyn Xpbd],
+        particle_source: &mut [Particle],
+        simulation_box: Option<&SimulationBox>,
+    ) -> Vec3 {
+        let gradients = self.gradients(constraints, particle_source);
+        let mut virial = Vec3::zero();
+        for _ in 0..self.max_iterations {
+            let mut satisfied = true;
+            for (constraint, gradients) in constraints.iter().zip(&gradients) {
+                let particles = gather(constraint.particles(), particle_source);
+                let (rate, scale) = particles.iter().zip(gradients).fold(
+                    (0.0, 0.0),
+                    |(rate, scale), (part, gradient)| {
+                        (
+                            rate + gradient.dot(part.vel),
+                            scale + part.inverse_mass * gradient.mag_squared(),
+                        )
+                    },
+                );
+                if rate.abs() <= self.tolerance || scale == 0.0 {
+                    continue;
+                }
+                satisfied = false;
+
+                let lagrange = -rate / scale;
+                let origin = particles[0].pos;
+                for (reference, gradient) in constraint.particles().iter().zip(gradients) {
+                    let part = reference.get_mut(particle_source);
+                    part.vel += lagrange * part.inverse_mass * *gradient;
+
+                    let arm = displacement(simulation_box, origin, part.pos);
+                    let impulse = lagrange * *gradient;
+                    virial += Vec3::new(arm.x * impulse.x, arm.y * impulse.y, arm.z * impulse.z);
+                }
+            }
+            if satisfied {
+                break;
+            }
+        }
+        virial
+    }
+}
+
+impl Default for Rattle {
+    fn default() -> Rattle {
+        Rattle::new()
+    }
+}
+
+fn gather<'a>(
+    particles: &[ParticleReference],
+    particle_source: &'a [Particle],
+) -> Vec<&'a Particle> {
+    particles
+        .iter()
+        .map(|reference| reference.get(particle_source))
+        .collect()
+}
+
+//---------------------------------------------------------------------------------------------------//
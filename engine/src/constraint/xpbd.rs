@@ -2,51 +2,56 @@ use crate::{
     constraint::Constraint,
     math::Vec3,
     particle::{Particle, ParticleReference},
+    simulation_box::SimulationBox,
 };
 
 //---------------------------------------------------------------------------------------------------//
 
 pub struct XpbdParameters {
     xpbd: Box<dyn Xpbd>,
-
-    compliance: f64,
-    dissipation: f64,
+    response: Response,
 
     force: f64,
     max_force: Option<f64>,
     broken: bool,
+}
 
-    as_force: bool,
-    as_inequality: bool,
+/// How a constraint gives way when it's projected.
+#[derive(Copy, Clone, Default)]
+pub(crate) struct Response {
+    pub compliance: f64,
+    pub dissipation: f64,
+
+    pub as_force: bool,
+    pub as_inequality: bool,
 }
 
 pub trait Xpbd {
     fn particles(&self) -> &[ParticleReference];
     fn constraint(&self, particles: &[&Particle]) -> f64;
     fn gradients(&self, particles: &[&Particle]) -> Vec<Vec3>;
+
+    fn set_simulation_box(&mut self, _simulation_box: &SimulationBox) {}
 }
 
 impl XpbdParameters {
     pub fn new(xpbd: impl Xpbd + 'static) -> XpbdParameters {
         XpbdParameters {
             xpbd: Box::new(xpbd),
-            compliance: 0.0,
-            dissipation: 0.0,
+            response: Response::default(),
             force: 0.0,
             max_force: None,
             broken: false,
-            as_force: false,
-            as_inequality: false,
         }
     }
 
     pub fn compliance(mut self, compliance: f64) -> XpbdParameters {
-        self.compliance = compliance;
+        self.response.compliance = compliance;
         self
     }
 
     pub fn dissipation(mut self, dissipation: f64) -> XpbdParameters {
-        self.dissipation = dissipation;
+        self.response.dissipation = dissipation;
         self
     }
 
@@ -56,12 +61,12 @@ impl XpbdParameters {
     }
 
     pub fn as_force(mut self) -> XpbdParameters {
-        self.as_force = true;
+        self.response.as_force = true;
         self
     }
 
     pub fn as_inequality(mut self) -> XpbdParameters {
-        self.as_inequality = true;
+        self.response.as_inequality = true;
         self
     }
 
@@ -70,6 +75,68 @@ impl XpbdParameters {
     }
 }
 
+impl Response {
+    /// Move the constraint's particles towards satisfying it, returning the force that took if
+    /// it wasn't already satisfied.
+    pub(crate) fn project(
+        &self,
+        xpbd: &dyn Xpbd,
+        particle_source: &mut [Particle],
+        dt: f64,
+        static_pass: bool,
+    ) -> Option<f64> {
+        let particles: Vec<&Particle> = xpbd
+            .particles()
+            .iter()
+            .map(|p| p.get(particle_source))
+            .collect();
+
+        let evaluated = xpbd.constraint(&particles);
+
+        let satisfied = match self.as_inequality {
+            false => evaluated == 0.0,
+            true => evaluated >= 0.0,
+        };
+        if satisfied {
+            return None;
+        }
+
+        let dt = if static_pass { f64::MAX } else { dt };
+        let alpha = self.compliance / dt.powi(2);
+        let gamma = self.compliance * self.dissipation / dt;
+        let gradients = xpbd.gradients(&particles);
+
+        let mut damp = 0.0;
+        let mut scale = 0.0;
+
+        for (i, part) in particles.iter().enumerate() {
+            damp += gradients[i].dot(part.pos - part.prev_pos);
+            scale += part.inverse_mass * gradients[i].mag_squared();
+        }
+
+        // no particle can move to satisfy the constraint
+        let denominator = (1.0 + gamma) * scale + alpha;
+        if denominator == 0.0 {
+            return None;
+        }
+        let lagrange = (-evaluated - gamma * damp) / denominator;
+
+        for (i, part) in xpbd.particles().iter().enumerate() {
+            let displacement = lagrange * part.get(particle_source).inverse_mass * gradients[i];
+            let pos = part.get(particle_source).pos;
+
+            part.get_mut(particle_source).add_displacement(
+                displacement,
+                pos,
+                self.as_force && !static_pass,
+                dt,
+            );
+        }
+
+        Some(lagrange / dt.powi(2))
+    }
+}
+
 impl Constraint for XpbdParameters {
     fn project(&mut self, particle_source: &mut [Particle], dt: f64, static_pass: bool) {
         let (breakable, max_force) = match self.max_force {
@@ -78,50 +145,10 @@ impl Constraint for XpbdParameters {
         };
 
         if !breakable || !self.broken {
-            let particles: Vec<&Particle> = self
-                .xpbd
-                .particles()
-                .iter()
-                .map(|p| p.get(particle_source))
-                .collect();
-
-            let evaluated = self.xpbd.constraint(&particles);
-
-            let satisfied = match self.as_inequality {
-                false => evaluated == 0.0,
-                true => evaluated >= 0.0,
-            };
-
-            if !satisfied {
-                let dt = if static_pass { core::f64::MAX } else { dt };
-                let alpha = self.compliance / dt.powi(2);
-                let gamma = self.compliance * self.dissipation / dt;
-                let gradients = self.xpbd.gradients(&particles);
-
-                let mut damp = 0.0;
-                let mut scale = 0.0;
-
-                for (i, part) in particles.iter().enumerate() {
-                    damp += gradients[i].dot(part.pos - part.prev_pos);
-                    scale += part.inverse_mass * gradients[i].mag_squared();
-                }
-
-                let lagrange = (-evaluated - gamma * damp) / ((1.0 + gamma) * scale + alpha);
-
-                for (i, part) in self.xpbd.particles().iter().enumerate() {
-                    let displacement =
-                        lagrange * part.get(particle_source).inverse_mass * gradients[i];
-                    let pos = part.get(particle_source).pos;
-
-                    part.get_mut(particle_source).add_displacement(
-                        displacement,
-                        pos,
-                        self.as_force && !static_pass,
-                        dt,
-                    );
-                }
-
-                let force = lagrange / dt.powi(2);
+            let projected =
+                self.response
+                    .project(self.xpbd.as_ref(), particle_source, dt, static_pass);
+            if let Some(force) = projected {
                 self.force = force;
                 if breakable {
                     self.broken = force > max_force;
@@ -129,6 +156,16 @@ impl Constraint for XpbdParameters {
             }
         }
     }
+
+    fn set_simulation_box(&mut self, simulation_box: &SimulationBox) {
+        self.xpbd.set_simulation_box(simulation_box);
+    }
+
+    fn holonomic(&self) -> Option<&dyn Xpbd> {
+        let rigid = self.response.compliance == 0.0 && self.max_force.is_none();
+        (rigid && !self.response.as_force && !self.response.as_inequality)
+            .then_some(self.xpbd.as_ref())
+    }
 }
 
 //---------------------------------------------------------------------------------------------------//
@@ -0,0 +1,587 @@
+//! Barnes–Hut tree gravity.
+//!
+//! Particles are sorted into an octree, and distant cells are approximated by their
+//! multipole expansion, which reduces the cost of gravity from O(N²) to O(N log N).
+//!
+//! Close encounters can be softened either by a single Plummer length, or by each particle's
+//! own smoothing length with the gravity of the cubic spline kernel (Price & Monaghan, 2007),
+//! which makes SPH particles gravitate as the smoothed mass distributions they stand for.
+
+//---------------------------------------------------------------------------------------------------//
+use crate::{
+    interaction::Interaction,
+    math::{Matrix3, Point3, Vec3},
+    particle::{Particle, ParticleReference},
+};
+
+//---------------------------------------------------------------------------------------------------//
+
+/// Cells deeper than this keep all of their particles in a single bucket,
+/// which guards against infinite subdivision of coincident particles.
+const MAX_DEPTH: u32 = 32;
+
+struct Cell {
+    center: Point3,
+    half_size: f64,
+
+    mass: f64,
+    center_of_mass: Point3,
+    quadrupole: Matrix3,
+    // the largest smoothing length of any particle within
+    smoothing_length: f64,
+
+    children: Vec<usize>,
+    bodies: Vec<usize>,
+}
+
+pub struct BarnesHutGravity {
+    coupled_particles: Vec<ParticleReference>,
+    gravitational_constant: f64,
+    opening_angle: f64,
+    softening: f64,
+    quadrupole: bool,
+    kernel_softening: bool,
+
+    cells: Vec<Cell>,
+    bodies: Vec<(Point3, f64)>,
+    smoothing_lengths: Vec<f64>,
+}
+
+impl BarnesHutGravity {
+    pub fn new(gravitational_constant: f64) -> BarnesHutGravity {
+        BarnesHutGravity {
+            coupled_particles: Vec::new(),
+            gravitational_constant,
+            opening_angle: 0.5,
+            softening: 0.0,
+            quadrupole: false,
+            kernel_softening: false,
+            cells: Vec::new(),
+            bodies: Vec::new(),
+            smoothing_lengths: Vec::new(),
+        }
+    }
+
+    /// A cell of width `s` at distance `d` is opened when `s / d` exceeds this angle.
+    /// Zero reproduces direct summation.
+    pub fn opening_angle(mut self, opening_angle: f64) -> BarnesHutGravity {
+        self.opening_angle = opening_angle;
+        self
+    }
+
+    /// Plummer softening length.
+    pub fn softening(mut self, softening: f64) -> BarnesHutGravity {
+        self.softening = softening;
+        self
+    }
+
+    /// Include quadrupole moments in the cell expansions.
+    pub fn quadrupole(mut self) -> BarnesHutGravity {
+        self.quadrupole = true;
+        self
+    }
+
+    /// Soften gravity with the cubic spline kernel over the particles' smoothing lengths,
+    /// averaging the force over the two lengths of each pair, in place of Plummer softening,
+    /// whose length is then ignored. Beyond twice the smoothing length it is exactly Newtonian,
+    /// and cells are only approximated when they are that far away. Particles without a
+    /// smoothing length are unsoftened.
+    ///
+    /// Price & Monaghan's gravitational grad-h terms are left out, so with adaptive smoothing
+    /// lengths energy is only conserved to the order of the softening.
+    pub fn kernel_softening(mut self) -> BarnesHutGravity {
+        self.kernel_softening = true;
+        self
+    }
+
+    pub fn with_particle(mut self, reference: ParticleReference) -> BarnesHutGravity {
+        self.coupled_particles.push(reference);
+        self
+    }
+
+    pub fn with_particles(mut self, references: &[ParticleReference]) -> BarnesHutGravity {
+        for reference in references {
+            self.coupled_particles.push(*reference);
+        }
+        self
+    }
+
+    //--------------------------------------------------------------------//
+    // tree construction
+
+    fn build(&mut self) {
+        self.cells.clear();
+        if self.bodies.is_empty() {
+            return;
+        }
+
+        let (mut min, mut max) = (self.bodies[0].0, self.bodies[0].0);
+        for (pos, _) in &self.bodies {
+            min = Point3::new(min.x.min(pos.x), min.y.min(pos.y), min.z.min(pos.z));
+            max = Point3::new(max.x.max(pos.x), max.y.max(pos.y), max.z.max(pos.z));
+        }
+        let extent = max - min;
+        let half_size = 0.5 * extent.x.max(extent.y).max(extent.z) * (1.0 + 1e-9) + 1e-12;
+
+        let bodies: Vec<usize> = (0..self.bodies.len()).collect();
+        self.build_cell(bodies, 0.5 * (min + max), half_size, 0);
+    }
+
+    fn build_cell(
+        &mut self,
+        bodies: Vec<usize>,
+        center: Point3,
+        half_size: f64,
+        depth: u32,
+    ) -> usize {
+        let index = self.cells.len();
+        self.cells.push(Cell {
+            center,
+            half_size,
+            mass: 0.0,
+            center_of_mass: Point3::zero(),
+            quadrupole: Matrix3::zero(),
+            smoothing_length: 0.0,
+            children: Vec::new(),
+            bodies: Vec::new(),
+        });
+
+        if bodies.len() <= 1 || depth >= MAX_DEPTH {
+            self.cells[index].bodies = bodies;
+        } else {
+            let mut octants: [Vec<usize>; 8] = Default::default();
+            for body in bodies {
+                let pos = self.bodies[body].0;
+                let octant = (pos.x >= center.x) as usize
+                    | ((pos.y >= center.y) as usize) << 1
+                    | ((pos.z >= center.z) as usize) << 2;
+                octants[octant].push(body);
+            }
+
+            let quarter = 0.5 * half_size;
+            for (octant, bodies) in octants.into_iter().enumerate() {
+                if !bodies.is_empty() {
+                    let offset = Vec3::new(
+                        if octant & 1 != 0 { quarter } else { -quarter },
+                        if octant & 2 != 0 { quarter } else { -quarter },
+                        if octant & 4 != 0 { quarter } else { -quarter },
+                    );
+                    let child = self.build_cell(bodies, center + offset, quarter, depth + 1);
+                    self.cells[index].children.push(child);
+                }
+            }
+        }
+
+        self.compute_moments(index);
+        index
+    }
+
+    fn compute_moments(&mut self, index: usize) {
+        let mut mass = 0.0;
+        let mut weighted = Vec3::zero();
+
+        // point masses in buckets, or the already computed moments of the children
+        let mut members: Vec<(Point3, f64, Matrix3)> = Vec::new();
+        let mut smoothing_length = 0.0_f64;
+        for body in &self.cells[index].bodies {
+            let (pos, m) = self.bodies[*body];
+            members.push((pos, m, Matrix3::zero()));
+            smoothing_length = smoothing_length.max(self.smoothing_lengths[*body]);
+        }
+        for child in &self.cells[index].children {
+            let child = &self.cells[*child];
+            members.push((child.center_of_mass, child.mass, child.quadrupole));
+            smoothing_length = smoothing_length.max(child.smoothing_length);
+        }
+
+        for (pos, m, _) in &members {
+            mass += m;
+            weighted += *m * *pos;
+        }
+        let center_of_mass = if mass != 0.0 {
+            weighted / mass
+        } else {
+            self.cells[index].center
+        };
+
+        let mut quadrupole = Matrix3::zero();
+        if self.quadrupole {
+            // parallel axis theorem for traceless quadrupole moments
+            for (pos, m, q) in &members {
+                let d = *pos - center_of_mass;
+                quadrupole += *q
+                    + *m * (3.0 * Matrix3::outer_product(d, d)
+                        - d.mag_squared() * Matrix3::identity());
+            }
+        }
+
+        let cell = &mut self.cells[index];
+        cell.mass = mass;
+        cell.center_of_mass = center_of_mass;
+        cell.quadrupole = quadrupole;
+        cell.smoothing_length = smoothing_length;
+    }
+
+    //--------------------------------------------------------------------//
+    // force evaluation
+
+    /// The gravitational acceleration and potential at a body.
+    fn field(&self, body: usize) -> (Vec3, f64) {
+        let pos = self.bodies[body].0;
+        // the kernel takes the place of the Plummer length
+        let epsilon_squared = if self.kernel_softening {
+            0.0
+        } else {
+            self.softening * self.softening
+        };
+        let theta_squared = self.opening_angle * self.opening_angle;
+        let mut acceleration = Vec3::zero();
+        let mut potential = 0.0;
+
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let cell = &self.cells[index];
+
+            if cell.children.is_empty() {
+                for other in &cell.bodies {
+                    if *other != body {
+                        let (other_pos, other_mass) = self.bodies[*other];
+                        let d = pos - other_pos;
+                        if self.kernel_softening {
+                            let r = d.mag();
+                            if r > 0.0 {
+                                let (h1, h2) =
+                                    (self.smoothing_lengths[body], self.smoothing_lengths[*other]);
+                                let force = 0.5 * (softened_force(r, h1) + softened_force(r, h2));
+                                acceleration -= (other_mass * force / r) * d;
+                                potential += 0.5
+                                    * other_mass
+                                    * (softened_potential(r, h1) + softened_potential(r, h2));
+                            }
+                        } else {
+                            let r_squared = d.mag_squared() + epsilon_squared;
+                            let r = r_squared.sqrt();
+                            acceleration -= (other_mass / (r_squared * r)) * d;
+                            potential -= other_mass / r;
+                        }
+                    }
+                }
+                continue;
+            }
+
+            let d = pos - cell.center_of_mass;
+            let dist_squared = d.mag_squared();
+            let width = 2.0 * cell.half_size;
+            let inside = (pos - cell.center).x.abs() <= cell.half_size
+                && (pos - cell.center).y.abs() <= cell.half_size
+                && (pos - cell.center).z.abs() <= cell.half_size;
+
+            // a cell within any of the softening kernels involved has to be opened
+            let clear = !self.kernel_softening || {
+                let reach = 2.0 * self.smoothing_lengths[body].max(cell.smoothing_length)
+                    + 3.0_f64.sqrt() * cell.half_size;
+                dist_squared > reach * reach
+            };
+
+            if !inside && clear && width * width < theta_squared * dist_squared {
+                let r_squared = dist_squared + epsilon_squared;
+                let r = r_squared.sqrt();
+                acceleration -= (cell.mass / (r_squared * r)) * d;
+                potential -= cell.mass / r;
+
+                if self.quadrupole {
+                    let r5 = r_squared * r_squared * r;
+                    let qd = cell.quadrupole * d;
+                    acceleration += qd / r5 - (2.5 * d.dot(qd) / (r5 * r_squared)) * d;
+                    potential -= 0.5 * d.dot(qd) / r5;
+                }
+            } else {
+                stack.extend_from_slice(&cell.children);
+            }
+        }
+
+        (
+            self.gravitational_constant * acceleration,
+            self.gravitational_constant * potential,
+        )
+    }
+
+    /// Sort the coupled particles into a fresh tree.
+    fn build_from(&mut self, particle_source: &[Particle]) {
+        self.bodies.clear();
+        self.smoothing_lengths.clear();
+        for reference in &self.coupled_particles {
+            let particle = reference.get(particle_source);
+            self.bodies.push((particle.pos, particle.mass));
+            self.smoothing_lengths.push(if self.kernel_softening {
+                particle.smoothing_length
+            } else {
+                0.0
+            });
+        }
+
+        self.build();
+    }
+
+    /// The total potential energy of the coupled particles, evaluated through the tree with the
+    /// same approximations as the forces.
+    pub fn potential_energy(&mut self, particle_source: &[Particle]) -> f64 {
+        self.build_from(particle_source);
+        (0..self.bodies.len())
+            .map(|body| 0.5 * self.bodies[body].1 * self.field(body).1)
+            .sum()
+    }
+}
+
+impl Interaction for BarnesHutGravity {
+    fn handle(&mut self, particle_source: &mut [Particle], _dt: f64) {
+        self.build_from(particle_source);
+
+        for (body, reference) in self.coupled_particles.iter().enumerate() {
+            let (acceleration, _) = self.field(body);
+            let particle = reference.get_mut(particle_source);
+            particle.add_force(particle.mass * acceleration);
+        }
+    }
+}
+
+/// The magnitude of the gravity, per unit mass and gravitational constant, between two points a
+/// distance r apart when one is smeared out by the cubic spline kernel of smoothing length h.
+fn softened_force(r: f64, h: f64) -> f64 {
+    let q = r / h;
+    if h == 0.0 || q >= 2.0 {
+        1.0 / (r * r)
+    } else if q < 1.0 {
+        (4.0 / 3.0 * q - 1.2 * q.powi(3) + 0.5 * q.powi(4)) / (h * h)
+    } else {
+        (8.0 / 3.0 * q - 3.0 * q.powi(2) + 1.2 * q.powi(3) - q.powi(4) / 6.0 - 1.0 / (15.0 * q * q))
+            / (h * h)
+    }
+}
+
+/// The potential, per unit mass and gravitational constant, that goes with [`softened_force`].
+fn softened_potential(r: f64, h: f64) -> f64 {
+    let q = r / h;
+    if h == 0.0 || q >= 2.0 {
+        -1.0 / r
+    } else if q < 1.0 {
+        (2.0 / 3.0 * q.powi(2) - 0.3 * q.powi(4) + 0.1 * q.powi(5) - 1.4) / h
+    } else {
+        (4.0 / 3.0 * q.powi(2) - q.powi(3) + 0.3 * q.powi(4) - q.powi(5) / 30.0 - 1.6
+            + 1.0 / (15.0 * q))
+            / h
+    }
+}
+
+//---------------------------------------------------------------------------------------------------//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::Random;
+
+    /// A centrally concentrated cluster, so that the tree has to cope with a wide range of cell
+    /// sizes.
+    fn cluster() -> Vec<Particle> {
+        let mut random = Random::new(7);
+        (0..500)
+            .map(|id| {
+                let direction = Vec3::new(random.gaussian(), random.gaussian(), random.gaussian());
+                let radius = 100.0 * random.uniform().powi(2);
+                Particle::new()
+                    .id(id)
+                    .pos(radius * direction.norm())
+                    .mass(1.0 + 9.0 * random.uniform())
+            })
+            .collect()
+    }
+
+    fn direct_forces(particles: &[Particle]) -> Vec<Vec3> {
+        particles
+            .iter()
+            .map(|particle| {
+                particles
+                    .iter()
+                    .filter(|other| other.id != particle.id)
+                    .fold(Vec3::zero(), |force, other| {
+                        let d = other.pos - particle.pos;
+                        force + (particle.mass * other.mass / d.mag().powi(3)) * d
+                    })
+            })
+            .collect()
+    }
+
+    fn tree_forces(gravity: BarnesHutGravity) -> Vec<Vec3> {
+        let mut particles = cluster();
+        let references: Vec<ParticleReference> = (0..particles.len())
+            .map(|index| ParticleReference::new(particles[index].id, index))
+            .collect();
+        gravity
+            .with_particles(&references)
+            .handle(&mut particles, 0.0);
+        particles
+            .iter()
+            .map(|particle| {
+                particle
+                    .forces
+                    .iter()
+                    .fold(Vec3::zero(), |total, force| total + *force)
+            })
+            .collect()
+    }
+
+    fn rms_relative_error(approximate: &[Vec3], exact: &[Vec3]) -> f64 {
+        let sum: f64 = approximate
+            .iter()
+            .zip(exact)
+            .map(|(a, e)| (*a - *e).mag_squared() / e.mag_squared())
+            .sum();
+        (sum / exact.len() as f64).sqrt()
+    }
+
+    #[test]
+    fn opening_angle_of_zero_is_direct_summation() {
+        let forces = tree_forces(BarnesHutGravity::new(1.0).opening_angle(0.0));
+        assert!(rms_relative_error(&forces, &direct_forces(&cluster())) < 1e-10);
+    }
+
+    #[test]
+    fn force_error_against_direct_summation() {
+        let exact = direct_forces(&cluster());
+        let mut previous = f64::MAX;
+        for opening_angle in [0.8, 0.5, 0.3] {
+            let gravity = || BarnesHutGravity::new(1.0).opening_angle(opening_angle);
+            let monopole = rms_relative_error(&tree_forces(gravity()), &exact);
+            let quadrupole = rms_relative_error(&tree_forces(gravity().quadrupole()), &exact);
+            assert!(quadrupole < monopole, "θ = {}", opening_angle);
+            assert!(monopole < previous, "θ = {}", opening_angle);
+            previous = monopole;
+        }
+        assert!(previous < 5e-3);
+    }
+    #[test]
+    fn potential_energy_matches_direct_summation() {
+        let particles = cluster();
+        let references: Vec<ParticleReference> = (0..particles.len())
+            .map(|index| ParticleReference::new(particles[index].id, index))
+            .collect();
+        let mut direct = 0.0;
+        for i in 0..particles.len() {
+            for j in (i + 1)..particles.len() {
+                let r = (particles[i].pos - particles[j].pos).mag();
+                direct -= particles[i].mass * particles[j].mass / r;
+            }
+        }
+
+        let energy = |gravity: BarnesHutGravity| {
+            gravity
+                .with_particles(&references)
+                .potential_energy(&particles)
+        };
+        let exact = energy(BarnesHutGravity::new(1.0).opening_angle(0.0));
+        assert!((exact - direct).abs() < 1e-10 * direct.abs());
+        let approximate = energy(BarnesHutGravity::new(1.0).quadrupole());
+        assert!((approximate - direct).abs() < 1e-3 * direct.abs());
+    }
+
+    #[test]
+    fn kernel_softening_ignores_the_plummer_length() {
+        let with_lengths = |gravity: BarnesHutGravity| {
+            let mut particles: Vec<Particle> = cluster()
+                .into_iter()
+                .map(|particle| particle.smoothing_length(2.0))
+                .collect();
+            let references: Vec<ParticleReference> = (0..particles.len())
+                .map(|index| ParticleReference::new(particles[index].id, index))
+                .collect();
+            gravity
+                .with_particles(&references)
+                .handle(&mut particles, 0.0);
+            particles
+                .iter()
+                .map(|particle| particle.forces[0])
+                .collect::<Vec<Vec3>>()
+        };
+        let kernel = with_lengths(BarnesHutGravity::new(1.0).kernel_softening());
+        let both = with_lengths(BarnesHutGravity::new(1.0).softening(5.0).kernel_softening());
+        assert!(rms_relative_error(&both, &kernel) < 1e-14);
+    }
+    #[test]
+    fn softened_forces_with_mixed_smoothing_lengths_are_the_energy_gradient() {
+        let mut random = Random::new(11);
+        let particles: Vec<Particle> = cluster()
+            .into_iter()
+            .map(|particle| particle.smoothing_length(1.0 + 9.0 * random.uniform()))
+            .collect();
+        let references: Vec<ParticleReference> = (0..particles.len())
+            .map(|index| ParticleReference::new(particles[index].id, index))
+            .collect();
+        let gravity = || {
+            BarnesHutGravity::new(1.0)
+                .opening_angle(0.0)
+                .kernel_softening()
+                .with_particles(&references)
+        };
+        let moved = |index: usize, offset: Vec3| {
+            let mut moved: Vec<Particle> = particles
+                .iter()
+                .map(|particle| {
+                    Particle::new()
+                        .id(particle.id)
+                        .pos(particle.pos)
+                        .mass(particle.mass)
+                        .smoothing_length(particle.smoothing_length)
+                })
+                .collect();
+            moved[index].pos += offset;
+            moved
+        };
+
+        let mut forced = moved(0, Vec3::zero());
+        gravity().handle(&mut forced, 0.0);
+        let forces: Vec<Vec3> = forced.iter().map(|particle| particle.forces[0]).collect();
+        let (net, scale) = forces
+            .iter()
+            .fold((Vec3::zero(), 0.0), |(net, scale), force| {
+                (net + *force, scale + force.mag())
+            });
+        assert!(net.mag() < 1e-12 * scale);
+
+        let step = 1e-5;
+        // the innermost bodies, whose neighbors lie within their kernels
+        let mut by_radius: Vec<usize> = (0..particles.len()).collect();
+        by_radius.sort_by(|a, b| {
+            particles[*a]
+                .pos
+                .mag()
+                .partial_cmp(&particles[*b].pos.mag())
+                .unwrap()
+        });
+        for index in by_radius.into_iter().take(5) {
+            for axis in [Vec3::x_hat(), Vec3::y_hat(), Vec3::z_hat()] {
+                let energy =
+                    |sign: f64| gravity().potential_energy(&moved(index, sign * step * axis));
+                let expected = -(energy(1.0) - energy(-1.0)) / (2.0 * step);
+                let force = forces[index].dot(axis);
+                assert!(
+                    (force - expected).abs() < 1e-5 * forces[index].mag(),
+                    "{} against {}",
+                    force,
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn softened_force_is_the_potential_gradient() {
+        let (h, step) = (1.5, 1e-6);
+        for r in [0.1, 0.9, 1.6, 2.4, 3.5] {
+            let expected =
+                (softened_potential(r + step, h) - softened_potential(r - step, h)) / (2.0 * step);
+            assert!((softened_force(r, h) - expected).abs() < 1e-7, "r = {}", r);
+        }
+    }
+}
+
+//---------------------------------------------------------------------------------------------------//
@@ -0,0 +1,737 @@
+//! Bonded terms of molecular mechanics force fields, between particles listed in a topology:
+//! bonds between two particles, angles between three, and dihedral and improper torsions
+//! between four.
+//!
+//! Each term is a potential energy of the term's geometry, which the forces are minus the
+//! gradient of, so that a molecule held together by them conserves its energy. Each kind of
+//! term is an interaction of its own, which lists every term of that kind with its own style and
+//! parameters, and reports its energy. In a periodic box, the particles of a term are followed
+//! from one to the next to their nearest periodic copies, so molecules may straddle the box.
+//!
+//! Unlike the XPBD `Distance` constraint, which holds a bond at its length exactly, a stiff
+//! bond vibrates, and needs a timestep short enough to resolve it.
+
+//---------------------------------------------------------------------------------------------------//
+use std::f64::consts::PI;
+
+use crate::{
+    interaction::{many_body::cosine_gradients, Interaction},
+    math::Vec3,
+    particle::{Particle, ParticleReference},
+    simulation_box::{displacement, SimulationBox},
+};
+
+//---------------------------------------------------------------------------------------------------//
+
+/// The positions of a term's particles relative to its first, following each to the nearest
+/// periodic copy of the one before.
+fn chain<const N: usize>(
+    particles: &[ParticleReference; N],
+    particle_source: &[Particle],
+    simulation_box: Option<&SimulationBox>,
+) -> [Vec3; N] {
+    let mut positions = [Vec3::zero(); N];
+    for index in 1..N {
+        positions[index] = positions[index - 1]
+            + displacement(
+                simulation_box,
+                particles[index - 1].get(particle_source).pos,
+                particles[index].get(particle_source).pos,
+            );
+    }
+    positions
+}
+
+/// Apply the forces of a term to its particles, adding their virial.
+fn apply<const N: usize>(
+    particles: &[ParticleReference; N],
+    particle_source: &mut [Particle],
+    positions: &[Vec3; N],
+    forces: &[Vec3; N],
+    virial: &mut Vec3,
+) {
+    for ((reference, pos), force) in particles.iter().zip(positions).zip(forces) {
+        reference.get_mut(particle_source).add_force(*force);
+        // the forces add up to zero, so the virial doesn't depend on the origin
+        *virial += Vec3::new(pos.x * force.x, pos.y * force.y, pos.z * force.z);
+    }
+}
+
+/// The dihedral angle φ between the plane of the first three positions and the plane of the
+/// last three, zero when the ends are eclipsed (cis) and ±π when they're opposite (trans),
+/// with the gradient of the angle with respect to each position.
+fn dihedral(positions: &[Vec3; 4]) -> (f64, [Vec3; 4]) {
+    let first = positions[1] - positions[0];
+    let axis = positions[2] - positions[1];
+    let last = positions[3] - positions[2];
+    let (normal1, normal2) = (first.cross(axis), axis.cross(last));
+    let length = axis.mag();
+    let angle = (length * first.dot(normal2)).atan2(normal1.dot(normal2));
+
+    // Blondel & Karplus (1996), free of singularities away from collinear bonds
+    let start = -(length / normal1.mag_squared()) * normal1;
+    let end = (length / normal2.mag_squared()) * normal2;
+    let (lead, trail) = (
+        first.dot(axis) / (length * length),
+        last.dot(axis) / (length * length),
+    );
+    (
+        angle,
+        [
+            start,
+            -(1.0 + lead) * start + trail * end,
+            lead * start - (1.0 + trail) * end,
+            end,
+        ],
+    )
+}
+
+/// The difference between two angles, wrapped into [-π, π).
+fn angle_difference(angle: f64, reference: f64) -> f64 {
+    (angle - reference + PI).rem_euclid(2.0 * PI) - PI
+}
+
+//---------------------------------------------------------------------------------------------------//
+
+// how near 1 - (r/R0)² may come to zero before a fene bond carries on as a stiff spring
+const FENE_SLACK: f64 = 0.01;
+
+#[derive(Copy, Clone)]
+enum BondStyle {
+    Harmonic { stiffness: f64, length: f64 },
+    Fene { stiffness: f64, maximum_length: f64 },
+}
+
+impl BondStyle {
+    /// The energy at a length, and its derivative.
+    fn energy(self, r: f64) -> (f64, f64) {
+        match self {
+            BondStyle::Harmonic { stiffness, length } => {
+                let stretch = r - length;
+                (0.5 * stiffness * stretch * stretch, stiffness * stretch)
+            }
+            BondStyle::Fene {
+                stiffness,
+                maximum_length,
+            } => {
+                let slack = 1.0 - (r / maximum_length).powi(2);
+                let squared = maximum_length * maximum_length;
+                if slack > FENE_SLACK {
+                    (
+                        -0.5 * stiffness * squared * slack.ln(),
+                        stiffness * r / slack,
+                    )
+                } else {
+                    // continued with the slope it had, so the force stays the energy's derivative
+                    (
+                        -0.5 * stiffness
+                            * squared
+                            * (FENE_SLACK.ln() + (slack - FENE_SLACK) / FENE_SLACK),
+                        stiffness * r / FENE_SLACK,
+                    )
+                }
+            }
+        }
+    }
+}
+
+/// Bonds between pairs of particles.
+#[derive(Default)]
+pub struct Bonds {
+    bonds: Vec<([ParticleReference; 2], BondStyle)>,
+    simulation_box: Option<SimulationBox>,
+    virial: Vec3,
+}
+
+impl Bonds {
+    pub fn new() -> Bonds {
+        Bonds::default()
+    }
+
+    /// A spring, U = ½ k (r - r0)².
+    pub fn harmonic(
+        mut self,
+        particles: [ParticleReference; 2],
+        stiffness: f64,
+        length: f64,
+    ) -> Bonds {
+        self.bonds
+            .push((particles, BondStyle::Harmonic { stiffness, length }));
+        self
+    }
+
+    /// The finitely extensible nonlinear elastic bond of bead-spring polymers,
+    /// U = -½ k R0² ln(1 - (r/R0)²), which can't stretch past R0. In the Kremer–Grest model it's
+    /// paired with the Weeks–Chandler–Andersen repulsion between every pair of beads.
+    ///
+    /// A step too long can still carry a bond up to R0, where the force diverges. From 99.5% of
+    /// R0 on, the bond is a stiff spring of stiffness 100 k instead, which pulls it back.
+    pub fn fene(
+        mut self,
+        particles: [ParticleReference; 2],
+        stiffness: f64,
+        maximum_length: f64,
+    ) -> Bonds {
+        self.bonds.push((
+            particles,
+            BondStyle::Fene {
+                stiffness,
+                maximum_length,
+            },
+        ));
+        self
+    }
+
+    pub fn potential_energy(&self, particle_source: &[Particle]) -> f64 {
+        self.bonds
+            .iter()
+            .map(|(particles, style)| {
+                let positions = chain(particles, particle_source, self.simulation_box.as_ref());
+                style.energy(positions[1].mag()).0
+            })
+            .sum()
+    }
+}
+
+impl Interaction for Bonds {
+    fn handle(&mut self, particle_source: &mut [Particle], _dt: f64) {
+        let mut virial = Vec3::zero();
+        for (particles, style) in &self.bonds {
+            let positions = chain(particles, particle_source, self.simulation_box.as_ref());
+            let r = positions[1].mag();
+            let force = (style.energy(r).1 / r) * positions[1];
+            apply(
+                particles,
+                particle_source,
+                &positions,
+                &[force, -force],
+                &mut virial,
+            );
+        }
+        self.virial = virial;
+    }
+
+    fn set_simulation_box(&mut self, simulation_box: &SimulationBox) {
+        self.simulation_box = Some(*simulation_box);
+    }
+
+    fn virial(&self) -> Vec3 {
+        self.virial
+    }
+}
+
+//---------------------------------------------------------------------------------------------------//
+
+#[derive(Copy, Clone)]
+enum AngleStyle {
+    Harmonic { stiffness: f64, angle: f64 },
+    Cosine { stiffness: f64 },
+    CosineSquared { stiffness: f64, angle: f64 },
+}
+
+impl AngleStyle {
+    /// The energy at an angle's cosine, and its derivative with respect to the cosine.
+    fn energy(self, cosine: f64) -> (f64, f64) {
+        match self {
+            AngleStyle::Harmonic { stiffness, angle } => {
+                let cosine = cosine.clamp(-1.0, 1.0);
+                let bend = cosine.acos() - angle;
+                // dθ/d(cos θ) = -1/sin θ, which stays finite in the limit of a straight angle
+                let sine = (1.0 - cosine * cosine).sqrt().max(1e-12);
+                (0.5 * stiffness * bend * bend, -stiffness * bend / sine)
+            }
+            AngleStyle::Cosine { stiffness } => (stiffness * (1.0 + cosine), stiffness),
+            AngleStyle::CosineSquared { stiffness, angle } => {
+                let bend = cosine - angle.cos();
+                (0.5 * stiffness * bend * bend, stiffness * bend)
+            }
+        }
+    }
+}
+
+/// Angles θ between the bonds from a middle particle, the second of each three, to the other
+/// two.
+#[derive(Default)]
+pub struct Angles {
+    angles: Vec<([ParticleReference; 3], AngleStyle)>,
+    simulation_box: Option<SimulationBox>,
+    virial: Vec3,
+}
+
+impl Angles {
+    pub fn new() -> Angles {
+        Angles::default()
+    }
+
+    /// U = ½ k (θ - θ0)², with the angles in radians.
+    pub fn harmonic(
+        mut self,
+        particles: [ParticleReference; 3],
+        stiffness: f64,
+        angle: f64,
+    ) -> Angles {
+        self.angles
+            .push((particles, AngleStyle::Harmonic { stiffness, angle }));
+        self
+    }
+
+    /// U = k (1 + cos θ), which is least for a straight angle, the bending stiffness of
+    /// semiflexible polymers.
+    pub fn cosine(mut self, particles: [ParticleReference; 3], stiffness: f64) -> Angles {
+        self.angles
+            .push((particles, AngleStyle::Cosine { stiffness }));
+        self
+    }
+
+    /// U = ½ k (cos θ - cos θ0)², as in GROMOS.
+    pub fn cosine_squared(
+        mut self,
+        particles: [ParticleReference; 3],
+        stiffness: f64,
+        angle: f64,
+    ) -> Angles {
+        self.angles
+            .push((particles, AngleStyle::CosineSquared { stiffness, angle }));
+        self
+    }
+
+    pub fn potential_energy(&self, particle_source: &[Particle]) -> f64 {
+        self.angles
+            .iter()
+            .map(|(particles, style)| {
+                let positions = chain(particles, particle_source, self.simulation_box.as_ref());
+                let (cosine, _, _) =
+                    cosine_gradients(positions[0] - positions[1], positions[2] - positions[1]);
+                style.energy(cosine).0
+            })
+            .sum()
+    }
+}
+
+impl Interaction for Angles {
+    fn handle(&mut self, particle_source: &mut [Particle], _dt: f64) {
+        let mut virial = Vec3::zero();
+        for (particles, style) in &self.angles {
+            let positions = chain(particles, particle_source, self.simulation_box.as_ref());
+            let (cosine, first, last) =
+                cosine_gradients(positions[0] - positions[1], positions[2] - positions[1]);
+            let slope = style.energy(cosine).1;
+            let (first, last) = (-slope * first, -slope * last);
+            apply(
+                particles,
+                particle_source,
+                &positions,
+                &[first, -(first + last), last],
+                &mut virial,
+            );
+        }
+        self.virial = virial;
+    }
+
+    fn set_simulation_box(&mut self, simulation_box: &SimulationBox) {
+        self.simulation_box = Some(*simulation_box);
+    }
+
+    fn virial(&self) -> Vec3 {
+        self.virial
+    }
+}
+
+//---------------------------------------------------------------------------------------------------//
+
+#[derive(Copy, Clone)]
+enum TorsionStyle {
+    Periodic {
+        stiffness: f64,
+        multiplicity: f64,
+        phase: f64,
+    },
+    RyckaertBellemans {
+        coefficients: [f64; 6],
+    },
+    Harmonic {
+        stiffness: f64,
+        angle: f64,
+    },
+}
+
+impl TorsionStyle {
+    /// The energy at a dihedral angle, and its derivative.
+    fn energy(self, angle: f64) -> (f64, f64) {
+        match self {
+            TorsionStyle::Periodic {
+                stiffness,
+                multiplicity,
+                phase,
+            } => {
+                let argument = multiplicity * angle - phase;
+                (
+                    stiffness * (1.0 + argument.cos()),
+                    -stiffness * multiplicity * argument.sin(),
+                )
+            }
+            TorsionStyle::RyckaertBellemans { coefficients } => {
+                // in the polymer convention ψ = φ - π, so cos ψ = -cos φ
+                let cosine = -angle.cos();
+                let (mut energy, mut slope, mut power) = (coefficients[0], 0.0, 1.0);
+                for (n, coefficient) in coefficients.iter().enumerate().skip(1) {
+                    // d(cos^n ψ)/dφ = n cos^(n-1) ψ sin φ
+                    slope += n as f64 * coefficient * power * angle.sin();
+                    power *= cosine;
+                    energy += coefficient * power;
+                }
+                (energy, slope)
+            }
+            TorsionStyle::Harmonic {
+                stiffness,
+                angle: rest,
+            } => {
+                let twist = angle_difference(angle, rest);
+                (0.5 * stiffness * twist * twist, stiffness * twist)
+            }
+        }
+    }
+}
+
+fn torsion_energy(
+    torsions: &[([ParticleReference; 4], TorsionStyle)],
+    particle_source: &[Particle],
+    simulation_box: Option<&SimulationBox>,
+) -> f64 {
+    torsions
+        .iter()
+        .map(|(particles, style)| {
+            let positions = chain(particles, particle_source, simulation_box);
+            style.energy(dihedral(&positions).0).0
+        })
+        .sum()
+}
+
+fn apply_torsions(
+    torsions: &[([ParticleReference; 4], TorsionStyle)],
+    particle_source: &mut [Particle],
+    simulation_box: Option<&SimulationBox>,
+) -> Vec3 {
+    let mut virial = Vec3::zero();
+    for (particles, style) in torsions {
+        let positions = chain(particles, particle_source, simulation_box);
+        let (angle, gradients) = dihedral(&positions);
+        let slope = style.energy(angle).1;
+        let forces = gradients.map(|gradient| -slope * gradient);
+        apply(particles, particle_source, &positions, &forces, &mut virial);
+    }
+    virial
+}
+
+/// Dihedral torsions about the bond between the middle two of each four particles, by the angle
+/// φ between the planes of the first three and the last three, zero for cis and π for trans.
+#[derive(Default)]
+pub struct Dihedrals {
+    dihedrals: Vec<([ParticleReference; 4], TorsionStyle)>,
+    simulation_box: Option<SimulationBox>,
+    virial: Vec3,
+}
+
+impl Dihedrals {
+    pub fn new() -> Dihedrals {
+        Dihedrals::default()
+    }
+
+    /// U = k (1 + cos(n φ - δ)), as in CHARMM and AMBER. A torsion with several terms lists
+    /// the same particles once for each.
+    pub fn periodic(
+        mut self,
+        particles: [ParticleReference; 4],
+        stiffness: f64,
+        multiplicity: u32,
+        phase: f64,
+    ) -> Dihedrals {
+        self.dihedrals.push((
+            particles,
+            TorsionStyle::Periodic {
+                stiffness,
+                multiplicity: multiplicity as f64,
+                phase,
+            },
+        ));
+        self
+    }
+
+    /// The Ryckaert–Bellemans polynomial of alkanes, U = Σ C_n cos^n ψ for n from 0 to 5, with
+    /// ψ = φ - π measured from trans as in polymer chemistry.
+    pub fn ryckaert_bellemans(
+        mut self,
+        particles: [ParticleReference; 4],
+        coefficients: [f64; 6],
+    ) -> Dihedrals {
+        self.dihedrals
+            .push((particles, TorsionStyle::RyckaertBellemans { coefficients }));
+        self
+    }
+
+    pub fn potential_energy(&self, particle_source: &[Particle]) -> f64 {
+        torsion_energy(
+            &self.dihedrals,
+            particle_source,
+            self.simulation_box.as_ref(),
+        )
+    }
+}
+
+impl Interaction for Dihedrals {
+    fn handle(&mut self, particle_source: &mut [Particle], _dt: f64) {
+        self.virial = apply_torsions(
+            &self.dihedrals,
+            particle_source,
+            self.simulation_box.as_ref(),
+        );
+    }
+
+    fn set_simulation_box(&mut self, simulation_box: &SimulationBox) {
+        self.simulation_box = Some(*simulation_box);
+    }
+
+    fn virial(&self) -> Vec3 {
+        self.virial
+    }
+}
+
+//---------------------------------------------------------------------------------------------------//
+
+/// Improper torsions, which keep planar groups flat and chiral centers from inverting. The
+/// angle is the dihedral angle of the four particles in the order given, which for CHARMM's
+/// impropers starts with the central particle.
+#[derive(Default)]
+pub struct Impropers {
+    impropers: Vec<([ParticleReference; 4], TorsionStyle)>,
+    simulation_box: Option<SimulationBox>,
+    virial: Vec3,
+}
+
+impl Impropers {
+    pub fn new() -> Impropers {
+        Impropers::default()
+    }
+
+    /// U = ½ k (ψ - ψ0)², as in CHARMM, with the difference wrapped into [-π, π).
+    pub fn harmonic(
+        mut self,
+        particles: [ParticleReference; 4],
+        stiffness: f64,
+        angle: f64,
+    ) -> Impropers {
+        self.impropers
+            .push((particles, TorsionStyle::Harmonic { stiffness, angle }));
+        self
+    }
+
+    /// U = k (1 + cos(n ψ - δ)), as in AMBER.
+    pub fn periodic(
+        mut self,
+        particles: [ParticleReference; 4],
+        stiffness: f64,
+        multiplicity: u32,
+        phase: f64,
+    ) -> Impropers {
+        self.impropers.push((
+            particles,
+            TorsionStyle::Periodic {
+                stiffness,
+                multiplicity: multiplicity as f64,
+                phase,
+            },
+        ));
+        self
+    }
+
+    pub fn potential_energy(&self, particle_source: &[Particle]) -> f64 {
+        torsion_energy(
+            &self.impropers,
+            particle_source,
+            self.simulation_box.as_ref(),
+        )
+    }
+}
+
+impl Interaction for Impropers {
+    fn handle(&mut self, particle_source: &mut [Particle], _dt: f64) {
+        self.virial = apply_torsions(
+            &self.impropers,
+            particle_source,
+            self.simulation_box.as_ref(),
+        );
+    }
+
+    fn set_simulation_box(&mut self, simulation_box: &SimulationBox) {
+        self.simulation_box = Some(*simulation_box);
+    }
+
+    fn virial(&self) -> Vec3 {
+        self.virial
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn simulation_box() -> SimulationBox {
+        SimulationBox::new(Vec3::zero(), Vec3::new(10.0, 10.0, 10.0))
+    }
+
+    /// A bent, twisted chain of five particles, straddling the box's face at x = 0.
+    fn molecule() -> (Vec<Particle>, [ParticleReference; 5]) {
+        let positions = [
+            Vec3::new(9.4, 5.0, 5.0),
+            Vec3::new(0.3, 5.3, 5.1),
+            Vec3::new(0.8, 6.1, 4.8),
+            Vec3::new(1.7, 6.4, 5.3),
+            Vec3::new(0.9, 5.2, 6.2),
+        ];
+        let particles = positions
+            .iter()
+            .enumerate()
+            .map(|(id, pos)| Particle::new().id(id as u32).pos(*pos))
+            .collect();
+        (
+            particles,
+            [0, 1, 2, 3, 4].map(|id| ParticleReference::new(id, id as usize)),
+        )
+    }
+
+    /// Checks the forces against the derivatives of the energy with each particle's position,
+    /// and the virial against the derivative of the energy with the box's length along each
+    /// axis.
+    fn check_gradients<T: Interaction>(mut term: T, energy: impl Fn(&T, &[Particle]) -> f64) {
+        let (mut particles, _) = molecule();
+        let simulation_box = simulation_box();
+        term.set_simulation_box(&simulation_box);
+        term.handle(&mut particles, 0.0);
+        let virial = term.virial();
+        let forces: Vec<Vec3> = particles
+            .iter()
+            .map(|particle| {
+                particle
+                    .forces
+                    .iter()
+                    .fold(Vec3::zero(), |total, force| total + *force)
+            })
+            .collect();
+
+        let step = 1e-6;
+        for index in 0..particles.len() {
+            for axis in [Vec3::x_hat(), Vec3::y_hat(), Vec3::z_hat()] {
+                particles[index].pos += step * axis;
+                let ahead = energy(&term, &particles);
+                particles[index].pos -= 2.0 * step * axis;
+                let behind = energy(&term, &particles);
+                particles[index].pos += step * axis;
+
+                let expected = -(ahead - behind) / (2.0 * step);
+                assert!((forces[index].dot(axis) - expected).abs() < 1e-5 * (1.0 + expected.abs()));
+            }
+        }
+
+        let virial = [virial.x, virial.y, virial.z];
+        for (axis, virial) in virial.iter().enumerate() {
+            let mut energy_at = |factor: f64| {
+                let mut factors = [1.0; 3];
+                factors[axis] = factor;
+                let factors = Vec3::new(factors[0], factors[1], factors[2]);
+                let mut stretched = simulation_box;
+                stretched.scale(factors);
+                let (mut moved, _) = molecule();
+                for particle in moved.iter_mut() {
+                    particle.pos = simulation_box.scale_point(particle.pos, factors);
+                }
+                term.set_simulation_box(&stretched);
+                energy(&term, &moved)
+            };
+            // the length changes by ±L h, so -L dU/dL is -(U(+) - U(-)) / 2h
+            let expected = -(energy_at(1.0 + step) - energy_at(1.0 - step)) / (2.0 * step);
+            assert!((virial - expected).abs() < 1e-5 * (1.0 + expected.abs()));
+        }
+    }
+
+    #[test]
+    fn bond_forces_and_virial_are_the_energy_gradient() {
+        let (_, [a, b, c, d, _]) = molecule();
+        let bonds = Bonds::new()
+            .harmonic([a, b], 50.0, 1.0)
+            .fene([b, c], 30.0, 1.5)
+            // stretched past its maximum, onto the stiff spring that carries it on
+            .fene([c, d], 30.0, 1.06);
+        check_gradients(bonds, Bonds::potential_energy);
+    }
+
+    #[test]
+    fn angle_forces_and_virial_are_the_energy_gradient() {
+        let (_, [a, b, c, d, e]) = molecule();
+        let angles = Angles::new()
+            .harmonic([a, b, c], 20.0, 1.9)
+            .cosine([b, c, d], 5.0)
+            .cosine_squared([c, d, e], 8.0, 2.0);
+        check_gradients(angles, Angles::potential_energy);
+    }
+
+    #[test]
+    fn dihedral_forces_and_virial_are_the_energy_gradient() {
+        let (_, [a, b, c, d, e]) = molecule();
+        let dihedrals = Dihedrals::new()
+            .periodic([a, b, c, d], 2.0, 3, 0.3)
+            .ryckaert_bellemans([b, c, d, e], [9.28, 12.16, -13.12, -3.06, 26.24, -31.5]);
+        check_gradients(dihedrals, Dihedrals::potential_energy);
+    }
+
+    #[test]
+    fn improper_forces_and_virial_are_the_energy_gradient() {
+        let (_, [a, b, c, d, e]) = molecule();
+        let impropers =
+            Impropers::new()
+                .harmonic([b, a, c, e], 10.0, 0.2)
+                .periodic([c, b, d, e], 1.5, 2, PI);
+        check_gradients(impropers, Impropers::potential_energy);
+    }
+
+    #[test]
+    fn dihedral_angles_are_zero_for_cis_and_pi_for_trans() {
+        let at = |last: Vec3| {
+            dihedral(&[
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::zero(),
+                Vec3::new(0.0, 0.0, 1.0),
+                last,
+            ])
+            .0
+        };
+        assert!(at(Vec3::new(1.0, 0.0, 1.0)).abs() < 1e-12);
+        assert!((at(Vec3::new(-1.0, 0.0, 1.0)).abs() - PI).abs() < 1e-12);
+        // clockwise, looking down the middle bond, is positive
+        assert!((at(Vec3::new(0.0, 1.0, 1.0)) - 0.5 * PI).abs() < 1e-12);
+        assert!((at(Vec3::new(0.0, -1.0, 1.0)) + 0.5 * PI).abs() < 1e-12);
+
+        assert!((angle_difference(3.0, -3.0) - (6.0 - 2.0 * PI)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn an_overstretched_fene_bond_pulls_back_continuously() {
+        let style = BondStyle::Fene {
+            stiffness: 30.0,
+            maximum_length: 1.5,
+        };
+        // where the slack reaches its limit, both branches agree
+        let threshold = 1.5 * (1.0 - FENE_SLACK).sqrt();
+        let (below, above) = (
+            style.energy(threshold - 1e-9),
+            style.energy(threshold + 1e-9),
+        );
+        assert!((below.0 - above.0).abs() < 1e-6 * below.0);
+        assert!((below.1 - above.1).abs() < 1e-6 * below.1);
+        // and past the maximum length the bond still pulls back, harder the further it goes
+        let (_, slope) = style.energy(1.6);
+        assert!(slope > 0.0 && slope.is_finite());
+        assert!(style.energy(1.7).1 > slope);
+    }
+}
+
+//---------------------------------------------------------------------------------------------------//
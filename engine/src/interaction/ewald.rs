@@ -0,0 +1,659 @@
+//! Long-range electrostatics in a periodic box, by Ewald summation or by the particle-particle
+//! particle-mesh (PPPM) method.
+//!
+//! The Coulomb sum over every periodic copy of the charges converges too slowly to be summed
+//! directly. Ewald's method splits each charge's potential in two with a gaussian of width
+//! 1/α: the short-range part, erfc(α r)/r, is summed between nearby pairs with a neighbor list,
+//! and the smooth long-range part is summed over the wave vectors of the box. A larger α moves
+//! work from the pairs to the wave vectors.
+//!
+//! - Ewald summation sums the structure factor of the charges at every wave vector up to a
+//!   cutoff, which costs O(N^(3/2)) at the best split.
+//! - PPPM spreads the charges over a mesh, solves Poisson's equation there with fast Fourier
+//!   transforms, and interpolates the field back onto the charges (Hockney & Eastwood), which
+//!   costs O(N log N). The optimal influence function for ik-differentiation corrects for the
+//!   mesh, so that the forces are as accurate as the mesh allows. It's recomputed whenever the
+//!   box changes size.
+//!
+//! Given an accuracy δ, the split is chosen so that the short-range part has fallen to about δ
+//! at the cutoff, α = √(-ln δ) / r_c, and Ewald summation keeps the wave vectors whose gaussian
+//! weight exp(-k²/4α²) is above δ. Any net charge is neutralized by a uniform background.
+
+//---------------------------------------------------------------------------------------------------//
+use crate::{
+    algorithms::{erfc, fft_3d},
+    collision::neighbor_list::NeighborList,
+    interaction::{
+        particle_mesh::{MassAssignment, Mesh},
+        Interaction,
+    },
+    math::{Dimension, Point3, Vec3, PI},
+    particle::{Particle, ParticleReference},
+    simulation_box::{displacement, SimulationBox},
+};
+
+//---------------------------------------------------------------------------------------------------//
+
+enum Reciprocal {
+    Summation {
+        // the wave vectors in one half of reciprocal space, each with 4π exp(-k²/4α²) / k²
+        wave_vectors: Vec<(Vec3, f64)>,
+    },
+    ParticleMesh {
+        grid_size: usize,
+        assignment: MassAssignment,
+        // the optimal influence function at each node of the transformed mesh
+        influence: Vec<f64>,
+    },
+}
+
+pub struct Ewald {
+    coupled_particles: Vec<ParticleReference>,
+    electrostatic_constant: f64,
+    cutoff: f64,
+    accuracy: f64,
+    splitting: Option<f64>,
+    reciprocal: Reciprocal,
+
+    skin: Option<f64>,
+    neighbor_list: Option<NeighborList>,
+    simulation_box: SimulationBox,
+    // the box lengths and splitting parameter the reciprocal tables were built for
+    tabulated: Option<(Vec3, f64)>,
+    virial: Vec3,
+}
+
+impl Ewald {
+    /// Ewald summation, with the short-range part cut off at a distance, in a box periodic along
+    /// all three axes. The system's box replaces it at each step.
+    pub fn summation(
+        electrostatic_constant: f64,
+        cutoff: f64,
+        simulation_box: SimulationBox,
+    ) -> Ewald {
+        Ewald::new(
+            electrostatic_constant,
+            cutoff,
+            simulation_box,
+            Reciprocal::Summation {
+                wave_vectors: Vec::new(),
+            },
+        )
+    }
+
+    /// PPPM, with the short-range part cut off at a distance and the long-range part solved on
+    /// a mesh of `grid_size` (a power of two) nodes along each side of a box periodic along all
+    /// three axes. The system's box replaces it at each step. Defaults to triangular-shaped-cloud
+    /// assignment.
+    pub fn particle_mesh(
+        electrostatic_constant: f64,
+        cutoff: f64,
+        grid_size: usize,
+        simulation_box: SimulationBox,
+    ) -> Ewald {
+        assert!(
+            grid_size.is_power_of_two(),
+            "the grid size must be a power of two"
+        );
+        Ewald::new(
+            electrostatic_constant,
+            cutoff,
+            simulation_box,
+            Reciprocal::ParticleMesh {
+                grid_size,
+                assignment: MassAssignment::TriangularShapedCloud,
+                influence: Vec::new(),
+            },
+        )
+    }
+
+    fn new(
+        electrostatic_constant: f64,
+        cutoff: f64,
+        simulation_box: SimulationBox,
+        reciprocal: Reciprocal,
+    ) -> Ewald {
+        assert!(
+            matches!(simulation_box.dimension, Dimension::Three),
+            "ewald summation needs a box periodic along all three axes"
+        );
+        Ewald {
+            coupled_particles: Vec::new(),
+            electrostatic_constant,
+            cutoff,
+            accuracy: 1e-5,
+            splitting: None,
+            reciprocal,
+            skin: None,
+            neighbor_list: None,
+            simulation_box,
+            tabulated: None,
+            virial: Vec3::zero(),
+        }
+    }
+
+    pub fn with_particle(mut self, reference: ParticleReference) -> Ewald {
+        self.coupled_particles.push(reference);
+        self
+    }
+
+    pub fn with_particles(mut self, references: &[ParticleReference]) -> Ewald {
+        for reference in references {
+            self.coupled_particles.push(*reference);
+        }
+        self
+    }
+
+    /// The relative accuracy δ that chooses the split and the wave vectors. Defaults to 1e-5.
+    pub fn accuracy(mut self, accuracy: f64) -> Ewald {
+        self.accuracy = accuracy;
+        self
+    }
+
+    /// Set the splitting parameter α directly, rather than from the accuracy.
+    pub fn splitting(mut self, splitting: f64) -> Ewald {
+        self.splitting = Some(splitting);
+        self
+    }
+
+    /// The extra distance the neighbor list looks past the cutoff. Defaults to a tenth of the cutoff.
+    pub fn skin(mut self, skin: f64) -> Ewald {
+        self.skin = Some(skin);
+        self
+    }
+
+    /// How PPPM spreads the charges over its mesh.
+    pub fn charge_assignment(mut self, assignment: MassAssignment) -> Ewald {
+        if let Reciprocal::ParticleMesh {
+            assignment: scheme, ..
+        } = &mut self.reciprocal
+        {
+            *scheme = assignment;
+        }
+        self
+    }
+
+    //--------------------------------------------------------------------//
+
+    /// The splitting parameter α, the inverse width of the gaussians.
+    pub fn splitting_parameter(&self) -> f64 {
+        self.splitting
+            .unwrap_or_else(|| (-self.accuracy.ln()).sqrt() / self.cutoff)
+    }
+
+    /// The total electrostatic energy of the coupled particles and all their periodic copies.
+    pub fn potential_energy(&mut self, particle_source: &[Particle]) -> f64 {
+        self.evaluate(particle_source).0
+    }
+
+    //--------------------------------------------------------------------//
+
+    /// The energy, the force on each coupled particle and the virial.
+    fn evaluate(&mut self, particle_source: &[Particle]) -> (f64, Vec<Vec3>, Vec3) {
+        let simulation_box = self.simulation_box;
+        let positions: Vec<Point3> = self
+            .coupled_particles
+            .iter()
+            .map(|reference| reference.get(particle_source).pos)
+            .collect();
+        let charges: Vec<f64> = self
+            .coupled_particles
+            .iter()
+            .map(|reference| reference.get(particle_source).charge)
+            .collect();
+
+        let alpha = self.splitting_parameter();
+        let lengths = simulation_box.lengths();
+        let stale = !self.tabulated.is_some_and(|(tabulated, splitting)| {
+            (tabulated - lengths).mag_squared() == 0.0 && splitting == alpha
+        });
+        if stale {
+            self.tabulate(lengths, alpha);
+            self.tabulated = Some((lengths, alpha));
+        }
+
+        let mut forces = vec![Vec3::zero(); positions.len()];
+        let mut virial = Vec3::zero();
+        let mut energy = self.short_range(&positions, &charges, &mut forces, &mut virial);
+        energy += match &self.reciprocal {
+            Reciprocal::Summation { .. } => {
+                self.reciprocal_sum(&positions, &charges, &mut forces, &mut virial)
+            }
+            Reciprocal::ParticleMesh { .. } => {
+                self.reciprocal_mesh(&positions, &charges, &mut forces, &mut virial)
+            }
+        };
+
+        // each charge's own gaussian is counted in the reciprocal sum, but isn't felt; and the
+        // background neutralizing any net charge scales with the inverse of the volume
+        let k = self.electrostatic_constant;
+        let squares: f64 = charges.iter().map(|charge| charge * charge).sum();
+        let net: f64 = charges.iter().sum();
+        let background = -k * PI * net * net / (2.0 * simulation_box.volume() * alpha * alpha);
+        energy += -k * alpha / PI.sqrt() * squares + background;
+        virial += Vec3::new(background, background, background);
+
+        (energy, forces, virial)
+    }
+
+    /// The pairs' share, k q_i q_j erfc(α r)/r, within the cutoff.
+    fn short_range(
+        &mut self,
+        positions: &[Point3],
+        charges: &[f64],
+        forces: &mut [Vec3],
+        virial: &mut Vec3,
+    ) -> f64 {
+        let (alpha, cutoff) = (self.splitting_parameter(), self.cutoff);
+        let skin = self.skin.unwrap_or(0.1 * cutoff);
+        let neighbor_list = self
+            .neighbor_list
+            .get_or_insert_with(|| NeighborList::new(cutoff, skin));
+        neighbor_list.set_simulation_box(self.simulation_box);
+        neighbor_list.update(positions);
+
+        let mut energy = 0.0;
+        for (i, j) in neighbor_list.pairs() {
+            let strength = self.electrostatic_constant * charges[*i] * charges[*j];
+            if strength == 0.0 {
+                continue;
+            }
+            let radial = displacement(Some(&self.simulation_box), positions[*i], positions[*j]);
+            let r = radial.mag();
+            if r >= cutoff {
+                continue;
+            }
+
+            let screened = erfc(alpha * r) / r;
+            energy += strength * screened;
+            let slope = -strength
+                * (screened + 2.0 * alpha / PI.sqrt() * (-alpha * alpha * r * r).exp())
+                / r;
+            // the force on particle i, which the displacement points away from
+            let force = (slope / r) * radial;
+            forces[*i] += force;
+            forces[*j] -= force;
+            *virial -= Vec3::new(radial.x * force.x, radial.y * force.y, radial.z * force.z);
+        }
+        energy
+    }
+
+    /// Rebuild the wave vectors or the influence function for a box and splitting parameter.
+    fn tabulate(&mut self, lengths: Vec3, alpha: f64) {
+        let weight =
+            |k_squared: f64| 4.0 * PI * (-k_squared / (4.0 * alpha * alpha)).exp() / k_squared;
+        let unit = Vec3::new(
+            2.0 * PI / lengths.x,
+            2.0 * PI / lengths.y,
+            2.0 * PI / lengths.z,
+        );
+
+        match &mut self.reciprocal {
+            Reciprocal::Summation { wave_vectors } => {
+                let k_max = 2.0 * alpha * (-self.accuracy.ln()).sqrt();
+                let extent = |unit: f64| (k_max / unit).ceil() as i64;
+                let (nx, ny, nz) = (extent(unit.x), extent(unit.y), extent(unit.z));
+                wave_vectors.clear();
+                for x in 0..=nx {
+                    for y in -ny..=ny {
+                        for z in -nz..=nz {
+                            // one of each pair k and -k
+                            if x == 0 && (y < 0 || (y == 0 && z <= 0)) {
+                                continue;
+                            }
+                            let k =
+                                Vec3::new(x as f64 * unit.x, y as f64 * unit.y, z as f64 * unit.z);
+                            let k_squared = k.mag_squared();
+                            if k_squared <= k_max * k_max {
+                                wave_vectors.push((k, weight(k_squared)));
+                            }
+                        }
+                    }
+                }
+            }
+            Reciprocal::ParticleMesh {
+                grid_size,
+                assignment,
+                influence,
+            } => {
+                let n = *grid_size;
+                let order = match assignment {
+                    MassAssignment::CloudInCell => 2,
+                    MassAssignment::TriangularShapedCloud => 3,
+                };
+                let spacing = lengths / n as f64;
+                // the transform of the assignment function along one axis
+                let window = |k: f64, h: f64| {
+                    let x = 0.5 * k * h;
+                    if x == 0.0 {
+                        1.0
+                    } else {
+                        (x.sin() / x).powi(order)
+                    }
+                };
+                let wave_number = |i: usize| {
+                    if i <= n / 2 {
+                        i as f64
+                    } else {
+                        i as f64 - n as f64
+                    }
+                };
+
+                influence.clear();
+                influence.resize(n * n * n, 0.0);
+                for z in 0..n {
+                    for y in 0..n {
+                        for x in 0..n {
+                            let k = Vec3::new(
+                                wave_number(x) * unit.x,
+                                wave_number(y) * unit.y,
+                                wave_number(z) * unit.z,
+                            );
+                            let k_squared = k.mag_squared();
+                            if k_squared == 0.0 {
+                                continue;
+                            }
+
+                            // the aliases of k that the mesh can't tell apart from it
+                            let (mut numerator, mut windows) = (0.0, 0.0);
+                            for mz in -2..=2 {
+                                for my in -2..=2 {
+                                    for mx in -2..=2 {
+                                        let alias = k + Vec3::new(
+                                            mx as f64 * n as f64 * unit.x,
+                                            my as f64 * n as f64 * unit.y,
+                                            mz as f64 * n as f64 * unit.z,
+                                        );
+                                        let u = (window(alias.x, spacing.x)
+                                            * window(alias.y, spacing.y)
+                                            * window(alias.z, spacing.z))
+                                        .powi(2);
+                                        numerator += u * k.dot(alias) * weight(alias.mag_squared());
+                                        windows += u;
+                                    }
+                                }
+                            }
+                            influence[x + n * (y + n * z)] =
+                                numerator / (k_squared * windows * windows);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// The smooth share summed over the wave vectors, (k / V) Σ 4π exp(-k²/4α²)/k² |S(k)|² over
+    /// one half of reciprocal space, with S(k) = Σ q_j exp(i k·r_j) the structure factor.
+    fn reciprocal_sum(
+        &self,
+        positions: &[Point3],
+        charges: &[f64],
+        forces: &mut [Vec3],
+        virial: &mut Vec3,
+    ) -> f64 {
+        let wave_vectors = match &self.reciprocal {
+            Reciprocal::Summation { wave_vectors } => wave_vectors,
+            Reciprocal::ParticleMesh { .. } => return 0.0,
+        };
+        let volume = self.simulation_box.volume();
+        let alpha = self.splitting_parameter();
+        let prefactor = self.electrostatic_constant / volume;
+
+        let mut energy = 0.0;
+        let mut phases = vec![(0.0, 0.0); positions.len()];
+        for (k, weight) in wave_vectors {
+            let (mut real, mut imaginary) = (0.0, 0.0);
+            for ((pos, charge), phase) in positions.iter().zip(charges).zip(&mut phases) {
+                *phase = k.dot(*pos).sin_cos();
+                real += charge * phase.1;
+                imaginary += charge * phase.0;
+            }
+
+            let share = prefactor * weight * (real * real + imaginary * imaginary);
+            energy += share;
+            let k_squared = k.mag_squared();
+            let stretch = 2.0 * (1.0 / k_squared + 1.0 / (4.0 * alpha * alpha));
+            *virial += share
+                * Vec3::new(
+                    1.0 - stretch * k.x * k.x,
+                    1.0 - stretch * k.y * k.y,
+                    1.0 - stretch * k.z * k.z,
+                );
+            for ((force, charge), (sin, cos)) in forces.iter_mut().zip(charges).zip(&phases) {
+                *force += (2.0 * prefactor * weight * charge * (sin * real - cos * imaginary)) * *k;
+            }
+        }
+        energy
+    }
+
+    /// The smooth share solved on the mesh.
+    fn reciprocal_mesh(
+        &self,
+        positions: &[Point3],
+        charges: &[f64],
+        forces: &mut [Vec3],
+        virial: &mut Vec3,
+    ) -> f64 {
+        let (n, assignment, influence) = match &self.reciprocal {
+            Reciprocal::ParticleMesh {
+                grid_size,
+                assignment,
+                influence,
+            } => (*grid_size, *assignment, influence),
+            Reciprocal::Summation { .. } => return 0.0,
+        };
+        let simulation_box = self.simulation_box;
+        let (volume, lengths) = (simulation_box.volume(), simulation_box.lengths());
+        let cell_volume = volume / (n * n * n) as f64;
+        let alpha = self.splitting_parameter();
+        // the mesh works in units of its cells, which needn't be cubes in a stretched box
+        let mesh = Mesh {
+            grid_size: n,
+            cell_size: 1.0,
+            origin: Point3::zero(),
+            periodic: true,
+            assignment,
+        };
+        let in_cells = |pos: Point3| {
+            let offset = pos - simulation_box.lower;
+            n as f64
+                * Vec3::new(
+                    offset.x / lengths.x,
+                    offset.y / lengths.y,
+                    offset.z / lengths.z,
+                )
+        };
+
+        let mut re = vec![0.0; n * n * n];
+        let mut im = vec![0.0; n * n * n];
+        for (pos, charge) in positions.iter().zip(charges) {
+            mesh.for_each_node(in_cells(*pos), |node, weight| {
+                re[node] += weight * charge / cell_volume;
+            });
+        }
+        fft_3d(&mut re, &mut im, [n, n, n], false);
+
+        let wave_number = |i: usize| {
+            if i <= n / 2 {
+                i as f64
+            } else {
+                i as f64 - n as f64
+            }
+        };
+        let unit = Vec3::new(
+            2.0 * PI / lengths.x,
+            2.0 * PI / lengths.y,
+            2.0 * PI / lengths.z,
+        );
+        let prefactor = self.electrostatic_constant * cell_volume * cell_volume / (2.0 * volume);
+        let mut energy = 0.0;
+        // the transformed field along each axis, -i k times the potential
+        let mut fields = [
+            (vec![0.0; n * n * n], vec![0.0; n * n * n]),
+            (vec![0.0; n * n * n], vec![0.0; n * n * n]),
+            (vec![0.0; n * n * n], vec![0.0; n * n * n]),
+        ];
+        for z in 0..n {
+            for y in 0..n {
+                for x in 0..n {
+                    let node = mesh.index(x, y, z);
+                    let green = influence[node];
+                    if green == 0.0 {
+                        continue;
+                    }
+                    let k = Vec3::new(
+                        wave_number(x) * unit.x,
+                        wave_number(y) * unit.y,
+                        wave_number(z) * unit.z,
+                    );
+                    let share = prefactor * green * (re[node] * re[node] + im[node] * im[node]);
+                    energy += share;
+                    let stretch = 2.0 * (1.0 / k.mag_squared() + 1.0 / (4.0 * alpha * alpha));
+                    *virial += share
+                        * Vec3::new(
+                            1.0 - stretch * k.x * k.x,
+                            1.0 - stretch * k.y * k.y,
+                            1.0 - stretch * k.z * k.z,
+                        );
+
+                    let potential = self.electrostatic_constant * green;
+                    for (axis, component) in [k.x, k.y, k.z].into_iter().enumerate() {
+                        fields[axis].0[node] = component * potential * im[node];
+                        fields[axis].1[node] = -component * potential * re[node];
+                    }
+                }
+            }
+        }
+        for (field_re, field_im) in &mut fields {
+            fft_3d(field_re, field_im, [n, n, n], true);
+        }
+
+        for ((pos, charge), force) in positions.iter().zip(charges).zip(forces.iter_mut()) {
+            let mut field = Vec3::zero();
+            mesh.for_each_node(in_cells(*pos), |node, weight| {
+                field +=
+                    weight * Vec3::new(fields[0].0[node], fields[1].0[node], fields[2].0[node]);
+            });
+            *force += *charge * field;
+        }
+        energy
+    }
+}
+
+impl Interaction for Ewald {
+    fn handle(&mut self, particle_source: &mut [Particle], _dt: f64) {
+        let (_, forces, virial) = self.evaluate(particle_source);
+        for (reference, force) in self.coupled_particles.iter().zip(forces) {
+            reference.get_mut(particle_source).add_force(force);
+        }
+        self.virial = virial;
+    }
+
+    fn set_simulation_box(&mut self, simulation_box: &SimulationBox) {
+        assert!(
+            matches!(simulation_box.dimension, Dimension::Three),
+            "ewald summation needs a box periodic along all three axes"
+        );
+        self.simulation_box = *simulation_box;
+    }
+
+    fn virial(&self) -> Vec3 {
+        self.virial
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::Random;
+
+    const MADELUNG: f64 = 1.747_564_594_633;
+    const SIDE: usize = 6;
+
+    /// Unit charges alternating on a cubic lattice of unit spacing, the rock-salt crystal.
+    fn rock_salt() -> Vec<Particle> {
+        let mut particles = Vec::new();
+        for x in 0..SIDE {
+            for y in 0..SIDE {
+                for z in 0..SIDE {
+                    particles.push(
+                        Particle::new()
+                            .id(particles.len() as u32)
+                            .pos(Vec3::new(x as f64 + 0.25, y as f64 + 0.25, z as f64 + 0.25))
+                            .charge(if (x + y + z) % 2 == 0 { 1.0 } else { -1.0 }),
+                    );
+                }
+            }
+        }
+        particles
+    }
+
+    fn cube() -> SimulationBox {
+        let side = SIDE as f64;
+        SimulationBox::new(Vec3::zero(), Vec3::new(side, side, side))
+    }
+
+    /// The crystal's energy per ion pair, relative to Madelung's constant.
+    fn madelung_error(solver: Ewald) -> f64 {
+        let particles = rock_salt();
+        let references: Vec<ParticleReference> = (0..particles.len())
+            .map(|index| ParticleReference::new(particles[index].id, index))
+            .collect();
+        let energy = solver
+            .with_particles(&references)
+            .potential_energy(&particles);
+        let per_pair = energy / (0.5 * particles.len() as f64);
+        (per_pair + MADELUNG).abs() / MADELUNG
+    }
+
+    #[test]
+    fn summation_finds_the_madelung_constant() {
+        let summation = || Ewald::summation(1.0, 2.5, cube()).accuracy(1e-10);
+        assert!(madelung_error(summation()) < 1e-8);
+        // the split moves work between the sums, but not the total
+        for splitting in [1.6, 2.8] {
+            assert!(madelung_error(summation().splitting(splitting)) < 1e-6);
+        }
+    }
+
+    #[test]
+    fn particle_mesh_finds_the_madelung_constant() {
+        assert!(madelung_error(Ewald::particle_mesh(1.0, 2.5, 32, cube())) < 1e-4);
+    }
+
+    #[test]
+    fn forces_are_the_energy_gradient() {
+        let mut random = Random::new(1);
+        let mut particles = rock_salt();
+        for particle in &mut particles {
+            particle.pos += 0.2
+                * Vec3::new(
+                    random.uniform() - 0.5,
+                    random.uniform() - 0.5,
+                    random.uniform() - 0.5,
+                );
+        }
+        let references: Vec<ParticleReference> = (0..particles.len())
+            .map(|index| ParticleReference::new(particles[index].id, index))
+            .collect();
+        let mut summation = Ewald::summation(1.0, 2.5, cube()).with_particles(&references);
+        let (_, forces, _) = summation.evaluate(&particles);
+        let largest_force = forces.iter().map(|force| force.mag()).fold(0.0, f64::max);
+
+        let step = 1e-5;
+        for index in [0, 7, particles.len() / 2, particles.len() - 1] {
+            for axis in [Vec3::x_hat(), Vec3::y_hat(), Vec3::z_hat()] {
+                let mut energy = |offset: f64| {
+                    particles[index].pos += offset * axis;
+                    let energy = summation.potential_energy(&particles);
+                    particles[index].pos -= offset * axis;
+                    energy
+                };
+                let expected = -(energy(step) - energy(-step)) / (2.0 * step);
+                assert!((forces[index].dot(axis) - expected).abs() < 1e-5 * largest_force);
+            }
+        }
+    }
+}
+
+//---------------------------------------------------------------------------------------------------//
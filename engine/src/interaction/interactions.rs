@@ -1,4 +1,13 @@
+pub use crate::interaction::{
+    barnes_hut::BarnesHutGravity,
+    bonded::{Angles, Bonds, Dihedrals, Impropers},
+    ewald::Ewald,
+    many_body::{EmbeddedAtom, StillingerWeber, Tersoff},
+    particle_mesh::ParticleMeshGravity,
+};
+
 use crate::{
+    algorithms::CubicSpline,
     interaction::{
         pair_wise::{PairWiseForce, PairWiseForceParameters},
         simple::{SimpleForce, SimpleForceParameters},
@@ -12,6 +21,7 @@ use crate::{
 pub struct ConstantForce(Vec3);
 
 impl ConstantForce {
+    #[allow(clippy::new_ret_no_self)]
     pub fn new(force: Vec3) -> SimpleForceParameters {
         SimpleForceParameters::new(ConstantForce(force))
     }
@@ -28,6 +38,7 @@ impl SimpleForce for ConstantForce {
 pub struct Falling(f64);
 
 impl Falling {
+    #[allow(clippy::new_ret_no_self)]
     pub fn new(g: f64) -> SimpleForceParameters {
         SimpleForceParameters::new(Falling(g))
     }
@@ -44,37 +55,57 @@ impl SimpleForce for Falling {
 pub struct Gravity(f64);
 
 impl Gravity {
+    #[allow(clippy::new_ret_no_self)]
     pub fn new(gravitational_constant: f64) -> PairWiseForceParameters {
         PairWiseForceParameters::new(Gravity(gravitational_constant))
     }
 }
 
 impl PairWiseForce for Gravity {
-    fn force(&self, particle1: &Particle, particle2: &Particle) -> Option<Vec3> {
-        let radial = particle2.pos - particle1.pos;
+    fn force(&self, particle1: &Particle, particle2: &Particle, radial: Vec3) -> Option<Vec3> {
         let dist = radial.mag();
 
         Some(((self.0 * particle1.mass * particle2.mass) / dist.powi(3)) * radial)
     }
+
+    fn potential_energy(
+        &self,
+        particle1: &Particle,
+        particle2: &Particle,
+        radial: Vec3,
+    ) -> Option<f64> {
+        Some(-(self.0 * particle1.mass * particle2.mass) / radial.mag())
+    }
 }
 
 //--------------------------------------------------------------------//
 
+/// Coulomb's law between every pair of charges. In a periodic box, [`Ewald`] sums over the
+/// periodic copies of the charges as well.
 pub struct ElectroStatic(f64);
 
 impl ElectroStatic {
+    #[allow(clippy::new_ret_no_self)]
     pub fn new(electrostatic_constant: f64) -> PairWiseForceParameters {
         PairWiseForceParameters::new(ElectroStatic(electrostatic_constant))
     }
 }
 
 impl PairWiseForce for ElectroStatic {
-    fn force(&self, particle1: &Particle, particle2: &Particle) -> Option<Vec3> {
-        let radial = particle2.pos - particle1.pos;
+    fn force(&self, particle1: &Particle, particle2: &Particle, radial: Vec3) -> Option<Vec3> {
         let dist = radial.mag();
 
         Some(-((self.0 * particle1.charge * particle2.charge) / dist.powi(3)) * radial)
     }
+
+    fn potential_energy(
+        &self,
+        particle1: &Particle,
+        particle2: &Particle,
+        radial: Vec3,
+    ) -> Option<f64> {
+        Some((self.0 * particle1.charge * particle2.charge) / radial.mag())
+    }
 }
 
 //--------------------------------------------------------------------//
@@ -82,18 +113,27 @@ impl PairWiseForce for ElectroStatic {
 pub struct CoulombAttraction(f64);
 
 impl CoulombAttraction {
+    #[allow(clippy::new_ret_no_self)]
     pub fn new(attraction_constant: f64) -> PairWiseForceParameters {
         PairWiseForceParameters::new(CoulombAttraction(attraction_constant))
     }
 }
 
 impl PairWiseForce for CoulombAttraction {
-    fn force(&self, particle1: &Particle, particle2: &Particle) -> Option<Vec3> {
-        let radial = particle2.pos - particle1.pos;
+    fn force(&self, _particle1: &Particle, _particle2: &Particle, radial: Vec3) -> Option<Vec3> {
         let dist = radial.mag();
 
         Some(-(self.0 / dist.powi(3)) * radial)
     }
+
+    fn potential_energy(
+        &self,
+        _particle1: &Particle,
+        _particle2: &Particle,
+        radial: Vec3,
+    ) -> Option<f64> {
+        Some(self.0 / radial.mag())
+    }
 }
 
 //--------------------------------------------------------------------//
@@ -126,11 +166,17 @@ impl LennardJones {
     }
 }
 
+impl LennardJones {
+    fn prefactor(&self) -> f64 {
+        let (n, m) = (self.repulsion, self.attraction);
+        (n / (n - m)) * ((n / m).powf(m / (n - m))) * self.dispersion_energy
+    }
+}
+
 impl PairWiseForce for LennardJones {
-    fn force(&self, particle1: &Particle, particle2: &Particle) -> Option<Vec3> {
+    fn force(&self, _particle1: &Particle, _particle2: &Particle, radial: Vec3) -> Option<Vec3> {
         let (n, m) = (self.repulsion, self.attraction);
-        let c = (n / (n - m)) * ((n / m).powf(m / (n - m))) * self.dispersion_energy;
-        let radial = particle2.pos - particle1.pos;
+        let c = self.prefactor();
         let sigma = self.collision_radius;
         let r = radial.mag();
 
@@ -139,6 +185,517 @@ impl PairWiseForce for LennardJones {
                 * radial,
         )
     }
+
+    fn potential_energy(
+        &self,
+        _particle1: &Particle,
+        _particle2: &Particle,
+        radial: Vec3,
+    ) -> Option<f64> {
+        let (n, m) = (self.repulsion, self.attraction);
+        let ratio = self.collision_radius / radial.mag();
+
+        Some(self.prefactor() * (ratio.powf(n) - ratio.powf(m)))
+    }
+}
+
+//--------------------------------------------------------------------//
+
+/// Morse's potential U = D (e^(-2a (r - r0)) - 2 e^(-a (r - r0))), a well of depth D at the
+/// equilibrium distance r0, whose width is set by a. It models covalent bonds, which can break.
+pub struct Morse {
+    well_depth: f64,
+    width: f64,
+    equilibrium_distance: f64,
+}
+
+impl Morse {
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(well_depth: f64, width: f64, equilibrium_distance: f64) -> PairWiseForceParameters {
+        PairWiseForceParameters::new(Morse {
+            well_depth,
+            width,
+            equilibrium_distance,
+        })
+    }
+}
+
+impl PairWiseForce for Morse {
+    fn force(&self, _particle1: &Particle, _particle2: &Particle, radial: Vec3) -> Option<Vec3> {
+        let r = radial.mag();
+        let decay = (-self.width * (r - self.equilibrium_distance)).exp();
+        let slope = 2.0 * self.width * self.well_depth * (decay - decay * decay);
+
+        Some((slope / r) * radial)
+    }
+
+    fn potential_energy(
+        &self,
+        _particle1: &Particle,
+        _particle2: &Particle,
+        radial: Vec3,
+    ) -> Option<f64> {
+        let decay = (-self.width * (radial.mag() - self.equilibrium_distance)).exp();
+
+        Some(self.well_depth * (decay * decay - 2.0 * decay))
+    }
+}
+
+//--------------------------------------------------------------------//
+
+/// Buckingham's exp-6 potential U = A e^(-r / ρ) - C / r⁶, an exponential repulsion between
+/// overlapping electron clouds and a dispersion attraction, as in ionic solids. The attraction
+/// wins at very short range, so particles must not be pushed too close together.
+pub struct Buckingham {
+    repulsion: f64,
+    repulsion_length: f64,
+    dispersion: f64,
+}
+
+impl Buckingham {
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(repulsion: f64, repulsion_length: f64, dispersion: f64) -> PairWiseForceParameters {
+        PairWiseForceParameters::new(Buckingham {
+            repulsion,
+            repulsion_length,
+            dispersion,
+        })
+    }
+}
+
+impl PairWiseForce for Buckingham {
+    fn force(&self, _particle1: &Particle, _particle2: &Particle, radial: Vec3) -> Option<Vec3> {
+        let r = radial.mag();
+        let slope = -self.repulsion / self.repulsion_length * (-r / self.repulsion_length).exp()
+            + 6.0 * self.dispersion / r.powi(7);
+
+        Some((slope / r) * radial)
+    }
+
+    fn potential_energy(
+        &self,
+        _particle1: &Particle,
+        _particle2: &Particle,
+        radial: Vec3,
+    ) -> Option<f64> {
+        let r = radial.mag();
+
+        Some(self.repulsion * (-r / self.repulsion_length).exp() - self.dispersion / r.powi(6))
+    }
+}
+
+//--------------------------------------------------------------------//
+
+/// The screened Coulomb, or Yukawa, potential U = k q1 q2 e^(-r / λ) / r between charges in an
+/// electrolyte or a plasma, whose ions screen them beyond the Debye length λ.
+pub struct Yukawa {
+    electrostatic_constant: f64,
+    screening_length: f64,
+}
+
+impl Yukawa {
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(electrostatic_constant: f64, screening_length: f64) -> PairWiseForceParameters {
+        PairWiseForceParameters::new(Yukawa {
+            electrostatic_constant,
+            screening_length,
+        })
+    }
+}
+
+impl PairWiseForce for Yukawa {
+    fn force(&self, particle1: &Particle, particle2: &Particle, radial: Vec3) -> Option<Vec3> {
+        let r = radial.mag();
+        let coupling = self.electrostatic_constant * particle1.charge * particle2.charge;
+        let slope = -coupling
+            * (-r / self.screening_length).exp()
+            * (1.0 / (r * r) + 1.0 / (self.screening_length * r));
+
+        Some((slope / r) * radial)
+    }
+
+    fn potential_energy(
+        &self,
+        particle1: &Particle,
+        particle2: &Particle,
+        radial: Vec3,
+    ) -> Option<f64> {
+        let r = radial.mag();
+        let coupling = self.electrostatic_constant * particle1.charge * particle2.charge;
+
+        Some(coupling * (-r / self.screening_length).exp() / r)
+    }
+}
+
+//--------------------------------------------------------------------//
+
+/// The Weeks–Chandler–Andersen potential, the repulsive part of the 12-6 Lennard-Jones
+/// potential: cut off at its minimum, 2^(1/6) σ, and raised by ε to meet zero there. It's the
+/// usual model of hard but smooth spheres, such as beads in a polymer.
+pub struct WeeksChandlerAndersen {
+    dispersion_energy: f64,
+    collision_radius: f64,
+}
+
+impl WeeksChandlerAndersen {
+    /// The cutoff is set, so the pairs are found by a neighbor list.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(dispersion_energy: f64, collision_radius: f64) -> PairWiseForceParameters {
+        let potential = WeeksChandlerAndersen {
+            dispersion_energy,
+            collision_radius,
+        };
+        let cutoff = potential.cutoff();
+        PairWiseForceParameters::new(potential).cutoff(cutoff)
+    }
+
+    fn cutoff(&self) -> f64 {
+        2_f64.powf(1.0 / 6.0) * self.collision_radius
+    }
+}
+
+impl PairWiseForce for WeeksChandlerAndersen {
+    fn force(&self, _particle1: &Particle, _particle2: &Particle, radial: Vec3) -> Option<Vec3> {
+        let r = radial.mag();
+        if r >= self.cutoff() {
+            return None;
+        }
+        let ratio = (self.collision_radius / r).powi(6);
+        let slope = -24.0 * self.dispersion_energy * (2.0 * ratio * ratio - ratio) / r;
+
+        Some((slope / r) * radial)
+    }
+
+    fn potential_energy(
+        &self,
+        _particle1: &Particle,
+        _particle2: &Particle,
+        radial: Vec3,
+    ) -> Option<f64> {
+        let r = radial.mag();
+        if r >= self.cutoff() {
+            return Some(0.0);
+        }
+        let ratio = (self.collision_radius / r).powi(6);
+
+        Some(4.0 * self.dispersion_energy * (ratio * ratio - ratio) + self.dispersion_energy)
+    }
+}
+
+//--------------------------------------------------------------------//
+
+/// Harmonic soft spheres, U = ε/2 (1 - r / σ)² while they overlap within their diameter σ, and
+/// nothing beyond. They can be pushed through each other, as in models of foams, emulsions and
+/// jammed grains, or of dissipative particle dynamics.
+pub struct SoftSphere {
+    stiffness: f64,
+    diameter: f64,
+}
+
+impl SoftSphere {
+    /// The cutoff is set, so the pairs are found by a neighbor list.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(stiffness: f64, diameter: f64) -> PairWiseForceParameters {
+        PairWiseForceParameters::new(SoftSphere {
+            stiffness,
+            diameter,
+        })
+        .cutoff(diameter)
+    }
+}
+
+impl PairWiseForce for SoftSphere {
+    fn force(&self, _particle1: &Particle, _particle2: &Particle, radial: Vec3) -> Option<Vec3> {
+        let r = radial.mag();
+        if r >= self.diameter {
+            return None;
+        }
+        let slope = -self.stiffness / self.diameter * (1.0 - r / self.diameter);
+
+        Some((slope / r) * radial)
+    }
+
+    fn potential_energy(
+        &self,
+        _particle1: &Particle,
+        _particle2: &Particle,
+        radial: Vec3,
+    ) -> Option<f64> {
+        let overlap = (1.0 - radial.mag() / self.diameter).max(0.0);
+
+        Some(0.5 * self.stiffness * overlap * overlap)
+    }
+}
+
+//--------------------------------------------------------------------//
+
+/// A potential tabulated against distance, interpolated by a cubic spline so that the forces,
+/// its derivative, are continuous. There's no force beyond the table, and the potential is
+/// shifted to meet zero at its end. Below the table, the first piece of the spline is extended.
+pub struct Tabulated(CubicSpline);
+
+impl Tabulated {
+    /// The distances have to be strictly increasing.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(distances: &[f64], energies: &[f64]) -> PairWiseForceParameters {
+        Tabulated::from_spline(CubicSpline::new(distances, energies))
+    }
+
+    /// Tabulate a potential at evenly spaced distances, between an inner and an outer distance.
+    pub fn from_function(
+        potential: impl Fn(f64) -> f64,
+        inner: f64,
+        outer: f64,
+        count: usize,
+    ) -> PairWiseForceParameters {
+        Tabulated::from_spline(CubicSpline::from_function(potential, inner, outer, count))
+    }
+
+    fn from_spline(spline: CubicSpline) -> PairWiseForceParameters {
+        let outer = spline.domain().1;
+        PairWiseForceParameters::new(Tabulated(spline)).cutoff(outer)
+    }
+}
+
+impl PairWiseForce for Tabulated {
+    fn force(&self, _particle1: &Particle, _particle2: &Particle, radial: Vec3) -> Option<Vec3> {
+        let r = radial.mag();
+        if r >= self.0.domain().1 {
+            return None;
+        }
+
+        Some((self.0.derivative(r) / r) * radial)
+    }
+
+    fn potential_energy(
+        &self,
+        _particle1: &Particle,
+        _particle2: &Particle,
+        radial: Vec3,
+    ) -> Option<f64> {
+        Some(self.0.value(radial.mag()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::Random;
+
+    fn charged() -> (Particle, Particle) {
+        (Particle::new().charge(1.5), Particle::new().charge(-0.5))
+    }
+
+    /// Whether the force on the first particle is minus the gradient of the energy with its
+    /// position, at a few distances in random directions.
+    fn force_is_the_energy_gradient(potential: &dyn PairWiseForce, distances: &[f64]) -> bool {
+        let (first, second) = charged();
+        let energy = |radial: Vec3| potential.potential_energy(&first, &second, radial).unwrap();
+        let mut random = Random::new(6);
+        let step = 1e-6;
+        distances.iter().all(|r| {
+            let direction =
+                Vec3::new(random.gaussian(), random.gaussian(), random.gaussian()).norm();
+            let radial = *r * direction;
+            let force = potential
+                .force(&first, &second, radial)
+                .unwrap_or(Vec3::zero());
+            // moving the first particle by h moves the radial by -h
+            let gradient = |axis: Vec3| {
+                (energy(radial - step * axis) - energy(radial + step * axis)) / (2.0 * step)
+            };
+            let expected = Vec3::new(
+                gradient(Vec3::x_hat()),
+                gradient(Vec3::y_hat()),
+                gradient(Vec3::z_hat()),
+            );
+            (force + expected).mag() < 1e-6 * (1.0 + force.mag())
+        })
+    }
+
+    #[test]
+    fn forces_are_the_gradients_of_the_potentials() {
+        let distances = [0.85, 1.0, 1.1, 1.3, 1.7, 2.4];
+        let potentials: Vec<(&str, Box<dyn PairWiseForce>)> = vec![
+            ("lennard-jones", Box::new(LennardJones::new(1.0, 1.0))),
+            (
+                "mie",
+                Box::new(LennardJones::new(1.0, 1.0).mie_potential(10.0, 5.0)),
+            ),
+            ("electrostatic", Box::new(ElectroStatic(2.0))),
+            (
+                "morse",
+                Box::new(Morse {
+                    well_depth: 1.5,
+                    width: 2.0,
+                    equilibrium_distance: 1.2,
+                }),
+            ),
+            (
+                "buckingham",
+                Box::new(Buckingham {
+                    repulsion: 1000.0,
+                    repulsion_length: 0.2,
+                    dispersion: 2.0,
+                }),
+            ),
+            (
+                "yukawa",
+                Box::new(Yukawa {
+                    electrostatic_constant: 2.0,
+                    screening_length: 0.7,
+                }),
+            ),
+            (
+                "weeks-chandler-andersen",
+                Box::new(WeeksChandlerAndersen {
+                    dispersion_energy: 1.0,
+                    collision_radius: 1.0,
+                }),
+            ),
+            (
+                "soft sphere",
+                Box::new(SoftSphere {
+                    stiffness: 3.0,
+                    diameter: 1.5,
+                }),
+            ),
+            (
+                "tabulated",
+                Box::new(Tabulated(CubicSpline::from_function(
+                    |r| (-r).exp() / r,
+                    0.5,
+                    3.0,
+                    40,
+                ))),
+            ),
+        ];
+        for (name, potential) in &potentials {
+            assert!(
+                force_is_the_energy_gradient(potential.as_ref(), &distances),
+                "the {} force isn't the gradient of its potential",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn morse_has_its_well_at_the_equilibrium_distance() {
+        let morse = Morse {
+            well_depth: 1.5,
+            width: 2.0,
+            equilibrium_distance: 1.2,
+        };
+        let (first, second) = charged();
+        let at = |r: f64| {
+            morse
+                .potential_energy(&first, &second, r * Vec3::x_hat())
+                .unwrap()
+        };
+        assert!((at(1.2) + 1.5).abs() < 1e-12);
+        assert!(
+            morse
+                .force(&first, &second, 1.2 * Vec3::x_hat())
+                .unwrap()
+                .mag()
+                .abs()
+                < 1e-12
+        );
+        assert!(at(1.1) > at(1.2) && at(1.3) > at(1.2));
+        // the bond breaks, leaving nothing far away
+        assert!(at(20.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn weeks_chandler_andersen_is_the_shifted_repulsive_lennard_jones() {
+        let wca = WeeksChandlerAndersen {
+            dispersion_energy: 1.3,
+            collision_radius: 0.9,
+        };
+        let lennard_jones = LennardJones::new(1.3, 0.9);
+        let (first, second) = charged();
+        let cutoff = 2_f64.powf(1.0 / 6.0) * 0.9;
+        for r in [0.8, 0.9, 0.95, 1.0] {
+            let radial = r * Vec3::x_hat();
+            let shifted = lennard_jones
+                .potential_energy(&first, &second, radial)
+                .unwrap()
+                + 1.3;
+            let energy = wca.potential_energy(&first, &second, radial).unwrap();
+            assert!((energy - shifted).abs() < 1e-12);
+            let force = wca.force(&first, &second, radial).unwrap();
+            let expected = lennard_jones.force(&first, &second, radial).unwrap();
+            assert!((force - expected).mag() < 1e-9 * expected.mag());
+        }
+        // both the energy and the force meet zero at the cutoff, and stay there
+        let inside = cutoff * (1.0 - 1e-9) * Vec3::x_hat();
+        assert!(wca.potential_energy(&first, &second, inside).unwrap() < 1e-12);
+        assert!(wca.force(&first, &second, inside).unwrap().mag() < 1e-6);
+        let outside = 1.5 * Vec3::x_hat();
+        assert!(wca.force(&first, &second, outside).is_none());
+        assert!(wca.potential_energy(&first, &second, outside) == Some(0.0));
+    }
+
+    #[test]
+    fn soft_spheres_only_push_while_they_overlap() {
+        let soft = SoftSphere {
+            stiffness: 3.0,
+            diameter: 1.5,
+        };
+        let (first, second) = charged();
+        // pushed apart, away from the second particle
+        let force = soft.force(&first, &second, Vec3::x_hat()).unwrap();
+        assert!(force.x < 0.0);
+        let energy = soft
+            .potential_energy(&first, &second, Vec3::x_hat())
+            .unwrap();
+        assert!((energy - 0.5 * 3.0 / 9.0).abs() < 1e-12);
+        assert!(soft.force(&first, &second, 1.6 * Vec3::x_hat()).is_none());
+        assert!(soft.potential_energy(&first, &second, 1.6 * Vec3::x_hat()) == Some(0.0));
+    }
+
+    #[test]
+    fn yukawa_screens_coulomb() {
+        let coulomb = ElectroStatic(2.0);
+        let yukawa = |screening_length| Yukawa {
+            electrostatic_constant: 2.0,
+            screening_length,
+        };
+        let (first, second) = charged();
+        let radial = 1.3 * Vec3::x_hat();
+        let bare = coulomb.potential_energy(&first, &second, radial).unwrap();
+        // unscreened at short range, and screened by e^(-r / λ)
+        let far = yukawa(1e9)
+            .potential_energy(&first, &second, radial)
+            .unwrap();
+        assert!((far - bare).abs() < 1e-8 * bare.abs());
+        let screened = yukawa(0.5)
+            .potential_energy(&first, &second, radial)
+            .unwrap();
+        assert!((screened - bare * (-1.3 / 0.5_f64).exp()).abs() < 1e-12);
+        let force = yukawa(1e9).force(&first, &second, radial).unwrap();
+        let expected = coulomb.force(&first, &second, radial).unwrap();
+        assert!((force - expected).mag() < 1e-8 * expected.mag());
+    }
+
+    #[test]
+    fn tabulated_potentials_follow_their_function() {
+        let function = |r: f64| 4.0 * (r.powi(-12) - r.powi(-6));
+        let tabulated = Tabulated(CubicSpline::from_function(function, 0.9, 2.5, 400));
+        let lennard_jones = LennardJones::new(1.0, 1.0);
+        let (first, second) = charged();
+        for r in [0.95, 1.05, 1.12, 1.4, 2.0] {
+            let radial = r * Vec3::x_hat();
+            let energy = tabulated.potential_energy(&first, &second, radial).unwrap();
+            assert!((energy - function(r)).abs() < 1e-5);
+            let force = tabulated.force(&first, &second, radial).unwrap();
+            let expected = lennard_jones.force(&first, &second, radial).unwrap();
+            assert!((force - expected).mag() < 1e-3 * (1.0 + expected.mag()));
+        }
+        assert!(tabulated
+            .force(&first, &second, 2.6 * Vec3::x_hat())
+            .is_none());
+    }
 }
 
 //---------------------------------------------------------------------------------------------------//
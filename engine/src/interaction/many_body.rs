@@ -0,0 +1,954 @@
+//! Many-body potentials, whose energy isn't a sum over pairs: the embedded-atom method for
+//! metals, and the Stillinger–Weber and Tersoff potentials for covalent solids like silicon.
+//!
+//! A [`ManyBodyPotential`] is handed the neighbors of every particle within its cutoff, found by
+//! a Verlet neighbor list and measured to their nearest periodic copies. It returns its energy,
+//! and reports the energy's gradient with respect to each displacement between neighbors. The
+//! forces, and the virial that feeds the pressure of a periodic system, both follow from those
+//! gradients, so that a potential only has to differentiate its energy.
+//!
+//! Particles of different elements are told apart by their `group`, which indexes the elements
+//! of an embedded-atom potential. Stillinger–Weber and Tersoff are for a single element.
+
+//---------------------------------------------------------------------------------------------------//
+use std::{f64::consts::PI, path::Path, str::FromStr};
+
+use crate::{
+    algorithms::CubicSpline,
+    collision::neighbor_list::NeighborList,
+    interaction::Interaction,
+    math::{Point3, Vec3},
+    particle::{Particle, ParticleReference},
+    simulation_box::{displacement, SimulationBox},
+};
+
+//---------------------------------------------------------------------------------------------------//
+
+/// A potential energy of the particles as a whole, which depends on each particle's neighbors
+/// within a cutoff.
+pub trait ManyBodyPotential {
+    fn cutoff(&self) -> f64;
+
+    /// The potential energy, adding its gradient with respect to each displacement between
+    /// neighbors to `gradients`.
+    fn evaluate(&self, neighborhood: &Neighborhood, gradients: &mut EnergyGradients) -> f64;
+}
+
+/// A neighbor of a particle, within the potential's cutoff.
+#[derive(Copy, Clone, Debug)]
+pub struct Neighbor {
+    /// The neighbor's index among the coupled particles.
+    pub index: usize,
+    /// The displacement from the particle to the neighbor.
+    pub displacement: Vec3,
+    pub distance: f64,
+}
+
+/// The neighbors of each coupled particle, with every pair listed from both ends.
+pub struct Neighborhood {
+    species: Vec<usize>,
+    neighbors: Vec<Vec<Neighbor>>,
+}
+
+impl Neighborhood {
+    pub fn particle_count(&self) -> usize {
+        self.neighbors.len()
+    }
+
+    /// The group of a particle, as the index of its element.
+    pub fn species(&self, particle: usize) -> usize {
+        self.species[particle]
+    }
+
+    pub fn neighbors(&self, particle: usize) -> &[Neighbor] {
+        &self.neighbors[particle]
+    }
+}
+
+/// The forces and virial that follow from the gradients of a potential's energy.
+pub struct EnergyGradients {
+    forces: Vec<Vec3>,
+    virial: Vec3,
+}
+
+impl EnergyGradients {
+    fn new(count: usize) -> EnergyGradients {
+        EnergyGradients {
+            forces: vec![Vec3::zero(); count],
+            virial: Vec3::zero(),
+        }
+    }
+
+    /// Add the gradient of the energy with respect to the displacement from a particle to one
+    /// of its neighbors. Stretching the displacement pushes the particle one way and its
+    /// neighbor the other.
+    pub fn add(&mut self, particle: usize, neighbor: &Neighbor, gradient: Vec3) {
+        self.forces[particle] += gradient;
+        self.forces[neighbor.index] -= gradient;
+        let radial = neighbor.displacement;
+        self.virial -= Vec3::new(
+            radial.x * gradient.x,
+            radial.y * gradient.y,
+            radial.z * gradient.z,
+        );
+    }
+}
+
+//---------------------------------------------------------------------------------------------------//
+
+pub struct ManyBodyParameters {
+    coupled_particles: Vec<ParticleReference>,
+    potential: Box<dyn ManyBodyPotential>,
+
+    skin: Option<f64>,
+    neighbor_list: Option<NeighborList>,
+    simulation_box: Option<SimulationBox>,
+    virial: Vec3,
+}
+
+impl ManyBodyParameters {
+    pub fn new(potential: impl ManyBodyPotential + 'static) -> ManyBodyParameters {
+        ManyBodyParameters {
+            coupled_particles: Vec::new(),
+            potential: Box::new(potential),
+            skin: None,
+            neighbor_list: None,
+            simulation_box: None,
+            virial: Vec3::zero(),
+        }
+    }
+
+    pub fn with_particle(mut self, reference: ParticleReference) -> ManyBodyParameters {
+        self.coupled_particles.push(reference);
+        self
+    }
+
+    pub fn with_particles(mut self, references: &[ParticleReference]) -> ManyBodyParameters {
+        for reference in references {
+            self.coupled_particles.push(*reference);
+        }
+        self
+    }
+
+    /// The extra distance the neighbor list looks past the cutoff. Defaults to a tenth of the cutoff.
+    pub fn skin(mut self, skin: f64) -> ManyBodyParameters {
+        self.skin = Some(skin);
+        self
+    }
+
+    //--------------------------------------------------------------------//
+
+    fn neighborhood(&mut self, particle_source: &[Particle]) -> Neighborhood {
+        let cutoff = self.potential.cutoff();
+        let skin = self.skin.unwrap_or(0.1 * cutoff);
+        let positions: Vec<Point3> = self
+            .coupled_particles
+            .iter()
+            .map(|reference| reference.get(particle_source).pos)
+            .collect();
+
+        let neighbor_list = self
+            .neighbor_list
+            .get_or_insert_with(|| NeighborList::new(cutoff, skin));
+        if let Some(simulation_box) = self.simulation_box {
+            neighbor_list.set_simulation_box(simulation_box);
+        }
+        neighbor_list.update(&positions);
+
+        let mut neighbors = vec![Vec::new(); positions.len()];
+        for (i, j) in neighbor_list.pairs() {
+            let radial = displacement(self.simulation_box.as_ref(), positions[*i], positions[*j]);
+            let distance = radial.mag();
+            if distance < cutoff {
+                neighbors[*i].push(Neighbor {
+                    index: *j,
+                    displacement: radial,
+                    distance,
+                });
+                neighbors[*j].push(Neighbor {
+                    index: *i,
+                    displacement: -radial,
+                    distance,
+                });
+            }
+        }
+        let species = self
+            .coupled_particles
+            .iter()
+            .map(|reference| reference.get(particle_source).group as usize)
+            .collect();
+
+        Neighborhood { species, neighbors }
+    }
+
+    /// The total potential energy of the coupled particles.
+    pub fn potential_energy(&mut self, particle_source: &[Particle]) -> f64 {
+        let neighborhood = self.neighborhood(particle_source);
+        let mut gradients = EnergyGradients::new(neighborhood.particle_count());
+        self.potential.evaluate(&neighborhood, &mut gradients)
+    }
+}
+
+impl Interaction for ManyBodyParameters {
+    fn handle(&mut self, particle_source: &mut [Particle], _dt: f64) {
+        let neighborhood = self.neighborhood(particle_source);
+        let mut gradients = EnergyGradients::new(neighborhood.particle_count());
+        self.potential.evaluate(&neighborhood, &mut gradients);
+
+        for (reference, force) in self.coupled_particles.iter().zip(gradients.forces) {
+            reference.get_mut(particle_source).add_force(force);
+        }
+        self.virial = gradients.virial;
+    }
+
+    fn set_simulation_box(&mut self, simulation_box: &SimulationBox) {
+        self.simulation_box = Some(*simulation_box);
+    }
+
+    fn virial(&self) -> Vec3 {
+        self.virial
+    }
+}
+
+//---------------------------------------------------------------------------------------------------//
+
+/// The cosine of the angle between two displacements, and its gradients with respect to each.
+pub(crate) fn cosine_gradients(first: Vec3, second: Vec3) -> (f64, Vec3, Vec3) {
+    let (r1, r2) = (first.mag(), second.mag());
+    let cosine = first.dot(second) / (r1 * r2);
+    (
+        cosine,
+        second / (r1 * r2) - (cosine / (r1 * r1)) * first,
+        first / (r1 * r2) - (cosine / (r2 * r2)) * second,
+    )
+}
+
+//--------------------------------------------------------------------//
+
+struct Element {
+    mass: f64,
+    // the embedding energy F(ρ), against the density it's embedded in
+    embedding: CubicSpline,
+    // the density ρ(r) an atom of the element contributes at a distance
+    density: CubicSpline,
+}
+
+/// The embedded-atom method (Daw & Baskes, 1984) for metals. Each atom sits in the electron
+/// density its neighbors contribute, ρ_i = Σ_j ρ_j(r_ij), and costs an embedding energy F_i(ρ_i)
+/// on top of a pair potential, E = Σ_i F_i(ρ_i) + ½ Σ_i Σ_j φ_ij(r_ij).
+///
+/// The functions are read from DYNAMO's setfl files, as distributed with LAMMPS and by the
+/// NIST interatomic potentials repository, and interpolated by cubic splines. The particles'
+/// groups index the file's elements, in order.
+pub struct EmbeddedAtom {
+    elements: Vec<Element>,
+    names: Vec<String>,
+    // r φ(r) for each pair of elements, in the file's lower triangular order
+    pair_potentials: Vec<CubicSpline>,
+    cutoff: f64,
+}
+
+impl EmbeddedAtom {
+    /// Read a setfl file: three lines of comments, the number of elements and their names, then
+    /// N_ρ, Δρ, N_r, Δr and the cutoff. Each element follows with its atomic number, mass,
+    /// lattice constant and lattice type, then F at N_ρ densities and ρ at N_r distances, both
+    /// from zero. Last come r φ(r) at N_r distances, for every pair of elements i and j ≤ i.
+    pub fn from_setfl(contents: &str) -> Result<EmbeddedAtom, String> {
+        let mut lines = contents.lines().skip(3);
+        let mut header = lines
+            .next()
+            .ok_or("the file ends before its list of elements")?
+            .split_whitespace();
+        let count: usize = parse(header.next(), "the number of elements")?;
+        let names: Vec<String> = header.take(count).map(String::from).collect();
+        if names.len() != count {
+            return Err(format!(
+                "the file names {} elements rather than {}",
+                names.len(),
+                count
+            ));
+        }
+
+        let mut tokens = lines.flat_map(str::split_whitespace);
+        let density_count: usize = parse(tokens.next(), "the number of densities")?;
+        let density_step: f64 = parse(tokens.next(), "the density spacing")?;
+        let distance_count: usize = parse(tokens.next(), "the number of distances")?;
+        let distance_step: f64 = parse(tokens.next(), "the distance spacing")?;
+        let cutoff: f64 = parse(tokens.next(), "the cutoff")?;
+
+        let mut elements = Vec::new();
+        for name in &names {
+            let _atomic_number: f64 = parse(tokens.next(), "an atomic number")?;
+            let mass = parse(tokens.next(), "a mass")?;
+            let _lattice_constant: f64 = parse(tokens.next(), "a lattice constant")?;
+            let _lattice_type: String = parse(tokens.next(), "a lattice type")?;
+            elements.push(Element {
+                mass,
+                embedding: table(
+                    &mut tokens,
+                    density_count,
+                    density_step,
+                    &format!("the embedding energy of {}", name),
+                )?,
+                density: table(
+                    &mut tokens,
+                    distance_count,
+                    distance_step,
+                    &format!("the density of {}", name),
+                )?,
+            });
+        }
+
+        let mut pair_potentials = Vec::new();
+        for i in 0..count {
+            for j in 0..=i {
+                pair_potentials.push(table(
+                    &mut tokens,
+                    distance_count,
+                    distance_step,
+                    &format!("the pair potential of {} and {}", names[i], names[j]),
+                )?);
+            }
+        }
+
+        Ok(EmbeddedAtom {
+            elements,
+            names,
+            pair_potentials,
+            cutoff,
+        })
+    }
+
+    /// Read a setfl file from disk.
+    pub fn read_setfl(path: impl AsRef<Path>) -> Result<EmbeddedAtom, String> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .map_err(|error| format!("couldn't read {}: {}", path.display(), error))?;
+        EmbeddedAtom::from_setfl(&contents)
+    }
+
+    /// The group of the particles of an element.
+    pub fn group(&self, element: &str) -> Option<u32> {
+        self.names
+            .iter()
+            .position(|name| name == element)
+            .map(|index| index as u32)
+    }
+
+    /// The mass of the element a group stands for.
+    pub fn mass(&self, group: u32) -> f64 {
+        self.elements[group as usize].mass
+    }
+
+    pub fn build(self) -> ManyBodyParameters {
+        ManyBodyParameters::new(self)
+    }
+
+    //--------------------------------------------------------------------//
+
+    /// φ(r) and its derivative, from the tabulated r φ(r).
+    fn pair_potential(&self, first: usize, second: usize, r: f64) -> (f64, f64) {
+        let (i, j) = (first.max(second), first.min(second));
+        let (scaled, slope) = self.pair_potentials[i * (i + 1) / 2 + j].evaluate(r);
+        let potential = scaled / r;
+        (potential, (slope - potential) / r)
+    }
+}
+
+/// The next token, parsed.
+fn parse<T: FromStr>(token: Option<&str>, what: &str) -> Result<T, String> {
+    let token = token.ok_or_else(|| format!("the file ends before {}", what))?;
+    token
+        .parse()
+        .map_err(|_| format!("couldn't read {} from \"{}\"", what, token))
+}
+
+/// A function tabulated at evenly spaced points from zero.
+fn table<'a>(
+    tokens: &mut impl Iterator<Item = &'a str>,
+    count: usize,
+    step: f64,
+    what: &str,
+) -> Result<CubicSpline, String> {
+    let mut knots = Vec::with_capacity(count);
+    let mut values = Vec::with_capacity(count);
+    for index in 0..count {
+        knots.push(index as f64 * step);
+        values.push(parse(tokens.next(), what)?);
+    }
+    if count < 2 || step <= 0.0 {
+        return Err(format!(
+            "{} needs at least two points a positive distance apart",
+            what
+        ));
+    }
+    Ok(CubicSpline::new(&knots, &values))
+}
+
+impl ManyBodyPotential for EmbeddedAtom {
+    fn cutoff(&self) -> f64 {
+        self.cutoff
+    }
+
+    fn evaluate(&self, neighborhood: &Neighborhood, gradients: &mut EnergyGradients) -> f64 {
+        let count = neighborhood.particle_count();
+        assert!(
+            (0..count).all(|i| neighborhood.species(i) < self.elements.len()),
+            "a particle's group isn't one of the potential's elements"
+        );
+
+        let mut energy = 0.0;
+        // the slope of each particle's embedding energy
+        let mut embedding_slopes = Vec::with_capacity(count);
+        for i in 0..count {
+            let species = neighborhood.species(i);
+            let mut density = 0.0;
+            for neighbor in neighborhood.neighbors(i) {
+                let other = neighborhood.species(neighbor.index);
+                density += self.elements[other].density.value(neighbor.distance);
+                // each pair is met from both ends
+                energy += 0.5 * self.pair_potential(species, other, neighbor.distance).0;
+            }
+            let (embedding, slope) = self.elements[species].embedding.evaluate(density);
+            energy += embedding;
+            embedding_slopes.push(slope);
+        }
+
+        // from this end, a pair changes the particle's density and half the pair potential
+        for (i, embedding_slope) in embedding_slopes.into_iter().enumerate() {
+            let species = neighborhood.species(i);
+            for neighbor in neighborhood.neighbors(i) {
+                let other = neighborhood.species(neighbor.index);
+                let density_slope = self.elements[other].density.derivative(neighbor.distance);
+                let pair_slope = self.pair_potential(species, other, neighbor.distance).1;
+                let slope = embedding_slope * density_slope + 0.5 * pair_slope;
+                gradients.add(
+                    i,
+                    neighbor,
+                    (slope / neighbor.distance) * neighbor.displacement,
+                );
+            }
+        }
+        energy
+    }
+}
+
+//--------------------------------------------------------------------//
+
+/// The Stillinger–Weber potential (1985), for tetrahedral covalent solids. A pair potential
+/// binds the atoms, and a three-body term penalizes the angle θ_jik between any two bonds of an
+/// atom for straying from the tetrahedral angle,
+/// E = Σ_i<j φ2(r_ij) + Σ_i Σ_j<k λ ε (cos θ_jik - cos θ0)² exp(γσ/(r_ij - aσ)) exp(γσ/(r_ik - aσ)),
+/// with φ2(r) = A ε (B (σ/r)^p - (σ/r)^q) exp(σ/(r - aσ)). Both vanish smoothly at a σ.
+pub struct StillingerWeber {
+    energy: f64,
+    length: f64,
+    pair_strength: f64,
+    repulsion: f64,
+    repulsion_power: f64,
+    attraction_power: f64,
+    cutoff: f64,
+    three_body_strength: f64,
+    three_body_decay: f64,
+    ideal_cosine: f64,
+}
+
+impl StillingerWeber {
+    /// The potential with an energy scale ε and a length scale σ, and the original reduced
+    /// parameters fitted for silicon: A = 7.049556277, B = 0.6022245584, p = 4, q = 0,
+    /// a = 1.8, λ = 21, γ = 1.2 and cos θ0 = -1/3.
+    pub fn new(energy: f64, length: f64) -> StillingerWeber {
+        StillingerWeber {
+            energy,
+            length,
+            pair_strength: 7.049556277,
+            repulsion: 0.6022245584,
+            repulsion_power: 4.0,
+            attraction_power: 0.0,
+            cutoff: 1.8,
+            three_body_strength: 21.0,
+            three_body_decay: 1.2,
+            ideal_cosine: -1.0 / 3.0,
+        }
+    }
+
+    /// Silicon, in electronvolts and ångströms: ε = 2.1683 eV and σ = 2.0951 Å.
+    pub fn silicon() -> StillingerWeber {
+        StillingerWeber::new(2.1683, 2.0951)
+    }
+
+    /// The strength λ of the three-body term, which other elements refit, such as germanium
+    /// with λ = 31 (Ding & Andersen, 1986).
+    pub fn three_body_strength(mut self, three_body_strength: f64) -> StillingerWeber {
+        self.three_body_strength = three_body_strength;
+        self
+    }
+
+    pub fn build(self) -> ManyBodyParameters {
+        ManyBodyParameters::new(self)
+    }
+
+    //--------------------------------------------------------------------//
+
+    /// φ2(r) and its derivative.
+    fn pair(&self, r: f64) -> (f64, f64) {
+        let (p, q) = (self.repulsion_power, self.attraction_power);
+        let ratio = self.length / r;
+        let gap = r - self.cutoff * self.length;
+        let decay = (self.length / gap).exp();
+        let decay_slope = -decay * self.length / (gap * gap);
+        let power = self.repulsion * ratio.powf(p) - ratio.powf(q);
+        let power_slope = (q * ratio.powf(q) - p * self.repulsion * ratio.powf(p)) / r;
+        let strength = self.pair_strength * self.energy;
+
+        (
+            strength * power * decay,
+            strength * (power_slope * decay + power * decay_slope),
+        )
+    }
+
+    /// exp(γσ/(r - aσ)) and its derivative.
+    fn bond_decay(&self, r: f64) -> (f64, f64) {
+        let gap = r - self.cutoff * self.length;
+        let decay = (self.three_body_decay * self.length / gap).exp();
+        (
+            decay,
+            -decay * self.three_body_decay * self.length / (gap * gap),
+        )
+    }
+}
+
+impl ManyBodyPotential for StillingerWeber {
+    fn cutoff(&self) -> f64 {
+        self.cutoff * self.length
+    }
+
+    fn evaluate(&self, neighborhood: &Neighborhood, gradients: &mut EnergyGradients) -> f64 {
+        let strength = self.three_body_strength * self.energy;
+        let mut energy = 0.0;
+        for i in 0..neighborhood.particle_count() {
+            let neighbors = neighborhood.neighbors(i);
+            for (a, first) in neighbors.iter().enumerate() {
+                // each pair is met from both ends
+                let (pair, pair_slope) = self.pair(first.distance);
+                energy += 0.5 * pair;
+                gradients.add(
+                    i,
+                    first,
+                    (0.5 * pair_slope / first.distance) * first.displacement,
+                );
+
+                let (first_decay, first_slope) = self.bond_decay(first.distance);
+                for second in &neighbors[a + 1..] {
+                    let (second_decay, second_slope) = self.bond_decay(second.distance);
+                    let (cosine, first_cosine, second_cosine) =
+                        cosine_gradients(first.displacement, second.displacement);
+                    let deviation = cosine - self.ideal_cosine;
+                    let angular = strength * deviation * deviation;
+                    energy += angular * first_decay * second_decay;
+
+                    let bend = 2.0 * strength * deviation * first_decay * second_decay;
+                    gradients.add(
+                        i,
+                        first,
+                        bend * first_cosine
+                            + (angular * first_slope * second_decay / first.distance)
+                                * first.displacement,
+                    );
+                    gradients.add(
+                        i,
+                        second,
+                        bend * second_cosine
+                            + (angular * first_decay * second_slope / second.distance)
+                                * second.displacement,
+                    );
+                }
+            }
+        }
+        energy
+    }
+}
+
+//--------------------------------------------------------------------//
+
+/// Tersoff's bond-order potential (1988), for covalent solids of a single element. Each bond
+/// is a repulsion and an attraction weakened by the atom's other bonds,
+/// E = ½ Σ_i Σ_j f_C(r_ij) (A exp(-λ1 r_ij) - b_ij B exp(-λ2 r_ij)), where the bond order
+/// b_ij = (1 + β^n ζ_ij^n)^(-1/2n) counts the other bonds k of atom i by
+/// ζ_ij = Σ_k f_C(r_ik) g(θ_ijk) exp(λ3³ (r_ij - r_ik)³), with the angular weight
+/// g(θ) = 1 + c²/d² - c²/(d² + (h - cos θ)²). The cutoff function f_C falls smoothly from one
+/// to zero between R - D and R + D.
+///
+/// The parameters are public, so that other elements can be set by struct update syntax on
+/// [`Tersoff::silicon`].
+#[derive(Copy, Clone, Debug)]
+pub struct Tersoff {
+    /// A
+    pub repulsion: f64,
+    /// B
+    pub attraction: f64,
+    /// λ1
+    pub repulsion_decay: f64,
+    /// λ2
+    pub attraction_decay: f64,
+    /// λ3
+    pub three_body_decay: f64,
+    /// β
+    pub bond_order_strength: f64,
+    /// n
+    pub bond_order_power: f64,
+    /// c
+    pub angular_strength: f64,
+    /// d
+    pub angular_width: f64,
+    /// h
+    pub ideal_cosine: f64,
+    /// R
+    pub cutoff_radius: f64,
+    /// D
+    pub cutoff_width: f64,
+}
+
+impl Tersoff {
+    /// Tersoff's third parametrization of silicon (Phys. Rev. B 38, 9902), in electronvolts
+    /// and ångströms.
+    pub fn silicon() -> Tersoff {
+        Tersoff {
+            repulsion: 1830.8,
+            attraction: 471.18,
+            repulsion_decay: 2.4799,
+            attraction_decay: 1.7322,
+            three_body_decay: 0.0,
+            bond_order_strength: 1.1e-6,
+            bond_order_power: 0.78734,
+            angular_strength: 1.0039e5,
+            angular_width: 16.217,
+            ideal_cosine: -0.59825,
+            cutoff_radius: 2.85,
+            cutoff_width: 0.15,
+        }
+    }
+
+    pub fn build(self) -> ManyBodyParameters {
+        ManyBodyParameters::new(self)
+    }
+
+    //--------------------------------------------------------------------//
+
+    /// f_C(r) and its derivative.
+    fn cutoff_function(&self, r: f64) -> (f64, f64) {
+        let (radius, width) = (self.cutoff_radius, self.cutoff_width);
+        if r < radius - width {
+            (1.0, 0.0)
+        } else if r >= radius + width {
+            (0.0, 0.0)
+        } else {
+            let phase = 0.5 * PI * (r - radius) / width;
+            (0.5 - 0.5 * phase.sin(), -0.25 * PI / width * phase.cos())
+        }
+    }
+
+    /// g(θ) and its derivative with respect to cos θ.
+    fn angular(&self, cosine: f64) -> (f64, f64) {
+        let (c2, d2) = (
+            self.angular_strength * self.angular_strength,
+            self.angular_width * self.angular_width,
+        );
+        let offset = self.ideal_cosine - cosine;
+        let denominator = d2 + offset * offset;
+        (
+            1.0 + c2 / d2 - c2 / denominator,
+            -2.0 * c2 * offset / (denominator * denominator),
+        )
+    }
+
+    /// b_ij and its derivative with respect to ζ_ij.
+    fn bond_order(&self, zeta: f64) -> (f64, f64) {
+        if zeta <= 0.0 {
+            return (1.0, 0.0);
+        }
+        let (beta, n) = (self.bond_order_strength, self.bond_order_power);
+        let term = (beta * zeta).powf(n);
+        let bond_order = (1.0 + term).powf(-0.5 / n);
+        (bond_order, -0.5 * bond_order * term / ((1.0 + term) * zeta))
+    }
+}
+
+impl ManyBodyPotential for Tersoff {
+    fn cutoff(&self) -> f64 {
+        self.cutoff_radius + self.cutoff_width
+    }
+
+    fn evaluate(&self, neighborhood: &Neighborhood, gradients: &mut EnergyGradients) -> f64 {
+        let cube = self.three_body_decay.powi(3);
+        let mut energy = 0.0;
+        // the gradient of ζ_ij with respect to each other bond of atom i
+        let mut bond_gradients: Vec<(usize, Vec3)> = Vec::new();
+        for i in 0..neighborhood.particle_count() {
+            let neighbors = neighborhood.neighbors(i);
+            for (a, first) in neighbors.iter().enumerate() {
+                let r = first.distance;
+                let (cutoff, cutoff_slope) = self.cutoff_function(r);
+                if cutoff == 0.0 {
+                    continue;
+                }
+                let direction = first.displacement / r;
+
+                let mut zeta = 0.0;
+                let mut zeta_gradient = Vec3::zero();
+                bond_gradients.clear();
+                for (b, second) in neighbors.iter().enumerate() {
+                    if b == a {
+                        continue;
+                    }
+                    let (weight, weight_slope) = self.cutoff_function(second.distance);
+                    if weight == 0.0 && weight_slope == 0.0 {
+                        continue;
+                    }
+                    let (cosine, first_cosine, second_cosine) =
+                        cosine_gradients(first.displacement, second.displacement);
+                    let (angular, angular_slope) = self.angular(cosine);
+                    let difference = r - second.distance;
+                    let exponential = (cube * difference.powi(3)).exp();
+                    let exponential_slope = 3.0 * cube * difference * difference * exponential;
+
+                    zeta += weight * angular * exponential;
+                    zeta_gradient += weight
+                        * (angular_slope * exponential * first_cosine
+                            + (angular * exponential_slope) * direction);
+                    let second_direction = second.displacement / second.distance;
+                    bond_gradients.push((
+                        b,
+                        (weight_slope * angular * exponential) * second_direction
+                            + weight
+                                * (angular_slope * exponential * second_cosine
+                                    - (angular * exponential_slope) * second_direction),
+                    ));
+                }
+                let (bond_order, bond_order_slope) = self.bond_order(zeta);
+
+                let repulsive = self.repulsion * (-self.repulsion_decay * r).exp();
+                let attractive = -self.attraction * (-self.attraction_decay * r).exp();
+                let bond = repulsive + bond_order * attractive;
+                let bond_slope = -self.repulsion_decay * repulsive
+                    - bond_order * self.attraction_decay * attractive;
+                energy += 0.5 * cutoff * bond;
+
+                let radial = 0.5 * (cutoff_slope * bond + cutoff * bond_slope);
+                let order = 0.5 * cutoff * attractive * bond_order_slope;
+                gradients.add(i, first, radial * direction + order * zeta_gradient);
+                for (b, gradient) in &bond_gradients {
+                    gradients.add(i, &neighbors[*b], order * *gradient);
+                }
+            }
+        }
+        energy
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::Random;
+
+    /// Copies of a cubic cell's sites, jittered, in the periodic box they tile.
+    fn crystal(
+        sites: &[Vec3],
+        lattice_constant: f64,
+        cells: usize,
+        jitter: f64,
+    ) -> (Vec<Particle>, SimulationBox) {
+        let mut random = Random::new(8);
+        let mut particles = Vec::new();
+        for x in 0..cells {
+            for y in 0..cells {
+                for z in 0..cells {
+                    for site in sites {
+                        let cell = Vec3::new(x as f64, y as f64, z as f64);
+                        let noise =
+                            Vec3::new(random.gaussian(), random.gaussian(), random.gaussian());
+                        particles.push(
+                            Particle::new()
+                                .id(particles.len() as u32)
+                                .group(particles.len() as u32 % 2)
+                                .pos(lattice_constant * (cell + *site) + jitter * noise),
+                        );
+                    }
+                }
+            }
+        }
+        let side = lattice_constant * cells as f64;
+        let simulation_box = SimulationBox::new(Vec3::zero(), Vec3::new(side, side, side));
+        (particles, simulation_box)
+    }
+
+    fn fcc() -> Vec<Vec3> {
+        vec![
+            Vec3::zero(),
+            Vec3::new(0.5, 0.5, 0.0),
+            Vec3::new(0.5, 0.0, 0.5),
+            Vec3::new(0.0, 0.5, 0.5),
+        ]
+    }
+
+    fn diamond() -> Vec<Vec3> {
+        let mut sites = fcc();
+        for site in fcc() {
+            sites.push(site + Vec3::new(0.25, 0.25, 0.25));
+        }
+        sites
+    }
+
+    /// A smooth step from one to zero across the last half unit before 4.
+    fn taper(r: f64) -> f64 {
+        let x = ((r - 3.5) / 0.5).clamp(0.0, 1.0);
+        1.0 - x * x * x * (10.0 - 15.0 * x + 6.0 * x * x)
+    }
+
+    fn write_table(text: &mut String, function: impl Fn(f64) -> f64, count: usize, step: f64) {
+        for index in 0..count {
+            *text += &format!("{:.16e}\n", function(index as f64 * step));
+        }
+    }
+
+    /// A setfl file for two made-up elements, out to a cutoff of 4.
+    fn setfl() -> String {
+        let (density_count, density_step) = (1000, 0.02);
+        let (distance_count, distance_step) = (801, 0.005);
+        let mut text = String::from("two made-up metals\n\n\n2 A B\n");
+        text += &format!(
+            "{} {} {} {} 4.0\n",
+            density_count, density_step, distance_count, distance_step
+        );
+        for (strength, reach) in [(1.0, 1.5), (0.7, 1.8)] {
+            text += "1 1.0 3.6 fcc\n";
+            write_table(
+                &mut text,
+                |rho| strength * (0.1 * (rho - 4.0).powi(2) - 2.0),
+                density_count,
+                density_step,
+            );
+            write_table(
+                &mut text,
+                |r| (-reach * (r - 2.5)).exp() * taper(r),
+                distance_count,
+                distance_step,
+            );
+        }
+        for scale in [1.0, 1.2, 0.9] {
+            write_table(
+                &mut text,
+                |r| scale * r * 0.3 * (-3.0 * (r - 2.5)).exp() * taper(r),
+                distance_count,
+                distance_step,
+            );
+        }
+        text
+    }
+
+    /// Checks the forces against the derivatives of the energy with each particle's position,
+    /// and the virial against the derivative of the energy with the box's length along each
+    /// axis, W_a = -L_a dU/dL_a.
+    fn check_gradients(
+        mut potential: ManyBodyParameters,
+        mut particles: Vec<Particle>,
+        simulation_box: SimulationBox,
+    ) {
+        let references: Vec<ParticleReference> = (0..particles.len())
+            .map(|index| ParticleReference::new(index as u32, index))
+            .collect();
+        potential = potential.with_particles(&references);
+        potential.set_simulation_box(&simulation_box);
+        potential.handle(&mut particles, 0.0);
+        let virial = potential.virial();
+        let forces: Vec<Vec3> = particles
+            .iter()
+            .map(|particle| {
+                particle
+                    .forces
+                    .iter()
+                    .fold(Vec3::zero(), |total, force| total + *force)
+            })
+            .collect();
+
+        let step = 1e-5;
+        for index in 0..particles.len() {
+            for axis in [Vec3::x_hat(), Vec3::y_hat(), Vec3::z_hat()] {
+                particles[index].pos += step * axis;
+                let ahead = potential.potential_energy(&particles);
+                particles[index].pos -= 2.0 * step * axis;
+                let behind = potential.potential_energy(&particles);
+                particles[index].pos += step * axis;
+
+                let expected = -(ahead - behind) / (2.0 * step);
+                assert!((forces[index].dot(axis) - expected).abs() < 1e-6);
+            }
+        }
+
+        let mut expected = [0.0; 3];
+        for (axis, expected) in expected.iter_mut().enumerate() {
+            let energy_at =
+                |factor: f64, particles: &mut [Particle], potential: &mut ManyBodyParameters| {
+                    let mut factors = [1.0; 3];
+                    factors[axis] = factor;
+                    let factors = Vec3::new(factors[0], factors[1], factors[2]);
+                    let mut stretched = simulation_box;
+                    stretched.scale(factors);
+                    let originals: Vec<Point3> =
+                        particles.iter().map(|particle| particle.pos).collect();
+                    for particle in particles.iter_mut() {
+                        particle.pos = simulation_box.scale_point(particle.pos, factors);
+                    }
+                    potential.set_simulation_box(&stretched);
+                    let energy = potential.potential_energy(particles);
+                    for (particle, original) in particles.iter_mut().zip(originals) {
+                        particle.pos = original;
+                    }
+                    energy
+                };
+            let ahead = energy_at(1.0 + step, &mut particles, &mut potential);
+            let behind = energy_at(1.0 - step, &mut particles, &mut potential);
+            // the length changes by ±L h, so -L dU/dL is -(U(+) - U(-)) / 2h
+            *expected = -(ahead - behind) / (2.0 * step);
+        }
+        let virial = [virial.x, virial.y, virial.z];
+        for (virial, expected) in virial.iter().zip(expected) {
+            assert!((virial - expected).abs() < 1e-5 * (1.0 + expected.abs()));
+        }
+    }
+
+    #[test]
+    fn embedded_atom_forces_and_virial_are_the_energy_gradient() {
+        let potential = EmbeddedAtom::from_setfl(&setfl()).unwrap();
+        assert_eq!(potential.group("B"), Some(1));
+        // both elements, alternating, so that the cross terms are used
+        let (particles, simulation_box) = crystal(&fcc(), 3.6, 3, 0.1);
+        check_gradients(potential.build(), particles, simulation_box);
+    }
+
+    #[test]
+    fn stillinger_weber_forces_and_virial_are_the_energy_gradient() {
+        let (particles, simulation_box) = crystal(&diamond(), 5.431, 2, 0.1);
+        check_gradients(
+            StillingerWeber::silicon().build(),
+            particles,
+            simulation_box,
+        );
+    }
+
+    #[test]
+    fn tersoff_forces_and_virial_are_the_energy_gradient() {
+        let (particles, simulation_box) = crystal(&diamond(), 5.431, 2, 0.1);
+        check_gradients(Tersoff::silicon().build(), particles, simulation_box);
+    }
+
+    #[test]
+    fn truncated_setfl_files_are_refused() {
+        let text = setfl();
+        let truncated = &text[..text.len() / 2];
+        let error = EmbeddedAtom::from_setfl(truncated).err().unwrap();
+        assert!(error.starts_with("the file ends before"));
+    }
+}
+
+//---------------------------------------------------------------------------------------------------//
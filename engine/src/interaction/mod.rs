@@ -1,14 +1,39 @@
+pub mod barnes_hut;
+pub mod bonded;
+pub mod ewald;
 pub mod field;
 pub mod interactions;
+pub mod many_body;
 pub mod pair_wise;
+pub mod particle_mesh;
 pub mod simple;
 pub mod sph;
+pub mod surface_tension;
 
 //---------------------------------------------------------------------------------------------------//
-use crate::particle::Particle;
+use crate::{math::Vec3, particle::Particle, simulation_box::SimulationBox};
 
 pub trait Interaction {
     fn handle(&mut self, particle_source: &mut [Particle], dt: f64);
+
+    /// Add the forces at the particles' current positions, without the rest of what `handle`
+    /// does over a step, like heating the particles or smoothing their velocities. Velocity
+    /// Verlet refreshes the forces it starts a step from this way. Defaults to `handle` over no
+    /// time.
+    fn add_forces(&mut self, particle_source: &mut [Particle]) {
+        self.handle(particle_source, 0.0);
+    }
+
+    /// Called with the system's periodic box before every `handle`, for interactions that
+    /// measure displacements to the nearest periodic copy.
+    fn set_simulation_box(&mut self, _simulation_box: &SimulationBox) {}
+
+    /// The diagonal of the virial Σ r_ij ⊗ F_ij of the forces the last `handle` applied, with
+    /// r_ij the displacement from particle j to particle i and F_ij the force on i. It feeds the
+    /// pressure of a periodic system.
+    fn virial(&self) -> Vec3 {
+        Vec3::zero()
+    }
 }
 
 //---------------------------------------------------------------------------------------------------//
@@ -1,7 +1,9 @@
 use crate::{
+    collision::neighbor_list::NeighborList,
     interaction::Interaction,
-    math::Vec3,
+    math::{Point3, Vec3},
     particle::{Particle, ParticleReference},
+    simulation_box::{displacement, SimulationBox},
 };
 
 //---------------------------------------------------------------------------------------------------//
@@ -9,10 +11,37 @@ use crate::{
 pub struct PairWiseForceParameters {
     coupled_particles: Vec<ParticleReference>,
     force: Box<dyn PairWiseForce>,
+
+    cutoff: Option<f64>,
+    skin: Option<f64>,
+    truncation: Truncation,
+    neighbor_list: Option<NeighborList>,
+    simulation_box: Option<SimulationBox>,
+    virial: Vec3,
 }
 
+/// A force between two particles. `radial` is the displacement from particle1 to particle2,
+/// and the returned force acts on particle1 (particle2 feels the opposite).
 pub trait PairWiseForce {
-    fn force(&self, particle1: &Particle, particle2: &Particle) -> Option<Vec3>;
+    fn force(&self, particle1: &Particle, particle2: &Particle, radial: Vec3) -> Option<Vec3>;
+
+    fn potential_energy(
+        &self,
+        _particle1: &Particle,
+        _particle2: &Particle,
+        _radial: Vec3,
+    ) -> Option<f64> {
+        None
+    }
+}
+
+/// How a force is modified at its cutoff distance.
+#[derive(Copy, Clone)]
+pub enum Truncation {
+    /// The force is cut off, and the potential is shifted to be zero at the cutoff.
+    EnergyShifted,
+    /// The force is shifted to be zero at the cutoff, so that it is continuous.
+    ForceShifted,
 }
 
 impl PairWiseForceParameters {
@@ -20,6 +49,12 @@ impl PairWiseForceParameters {
         PairWiseForceParameters {
             coupled_particles: Vec::new(),
             force: Box::new(force),
+            cutoff: None,
+            skin: None,
+            truncation: Truncation::EnergyShifted,
+            neighbor_list: None,
+            simulation_box: None,
+            virial: Vec3::zero(),
         }
     }
 
@@ -34,23 +69,290 @@ impl PairWiseForceParameters {
         }
         self
     }
+
+    /// Ignore pairs further apart than the cutoff, and find the rest with a Verlet neighbor list.
+    pub fn cutoff(mut self, cutoff: f64) -> PairWiseForceParameters {
+        self.cutoff = Some(cutoff);
+        self
+    }
+
+    /// The extra distance the neighbor list looks past the cutoff. Defaults to a tenth of the cutoff.
+    pub fn skin(mut self, skin: f64) -> PairWiseForceParameters {
+        self.skin = Some(skin);
+        self
+    }
+
+    pub fn truncation(mut self, truncation: Truncation) -> PairWiseForceParameters {
+        self.truncation = truncation;
+        self
+    }
+
+    //--------------------------------------------------------------------//
+
+    fn truncated_force(
+        &self,
+        particle1: &Particle,
+        particle2: &Particle,
+        radial: Vec3,
+    ) -> Option<Vec3> {
+        match self.cutoff {
+            None => self.force.force(particle1, particle2, radial),
+            Some(cutoff) => {
+                if radial.mag_squared() >= cutoff * cutoff {
+                    return None;
+                }
+
+                let force = self.force.force(particle1, particle2, radial)?;
+                match self.truncation {
+                    Truncation::EnergyShifted => Some(force),
+                    Truncation::ForceShifted => {
+                        let at_cutoff = self
+                            .force
+                            .force(particle1, particle2, cutoff * radial.norm())
+                            .unwrap_or_else(Vec3::zero);
+                        Some(force - at_cutoff)
+                    }
+                }
+            }
+        }
+    }
+
+    fn truncated_potential(&self, particle1: &Particle, particle2: &Particle, radial: Vec3) -> f64 {
+        let potential = |radial| {
+            self.force
+                .potential_energy(particle1, particle2, radial)
+                .unwrap_or(0.0)
+        };
+
+        match self.cutoff {
+            None => potential(radial),
+            Some(cutoff) => {
+                let r = radial.mag();
+                if r >= cutoff {
+                    return 0.0;
+                }
+
+                let at_cutoff = cutoff * radial.norm();
+                let shifted = potential(radial) - potential(at_cutoff);
+                match self.truncation {
+                    Truncation::EnergyShifted => shifted,
+                    Truncation::ForceShifted => {
+                        // the force on particle1 points along the gradient of the potential
+                        let slope = self
+                            .force
+                            .force(particle1, particle2, at_cutoff)
+                            .map_or(0.0, |force| force.dot(radial.norm()));
+                        shifted - (r - cutoff) * slope
+                    }
+                }
+            }
+        }
+    }
+
+    /// The displacement from particle1 to particle2, to its nearest periodic copy in a box.
+    fn radial(&self, particle1: &Particle, particle2: &Particle) -> Vec3 {
+        displacement(self.simulation_box.as_ref(), particle1.pos, particle2.pos)
+    }
+
+    fn update_neighbor_list(&mut self, particle_source: &[Particle]) {
+        if let Some(cutoff) = self.cutoff {
+            let skin = self.skin.unwrap_or(0.1 * cutoff);
+            let positions: Vec<Point3> = self
+                .coupled_particles
+                .iter()
+                .map(|reference| reference.get(particle_source).pos)
+                .collect();
+
+            let neighbor_list = self
+                .neighbor_list
+                .get_or_insert_with(|| NeighborList::new(cutoff, skin));
+            if let Some(simulation_box) = self.simulation_box {
+                neighbor_list.set_simulation_box(simulation_box);
+            }
+            neighbor_list.update(&positions);
+        }
+    }
+
+    /// Calls `visit(i, j)` for every pair of indices into the coupled particles that needs evaluating.
+    fn for_each_pair(&self, mut visit: impl FnMut(usize, usize)) {
+        match (&self.neighbor_list, self.cutoff) {
+            (Some(list), Some(_)) => {
+                for (i, j) in list.pairs() {
+                    visit(*i, *j);
+                }
+            }
+            _ => {
+                let count = self.coupled_particles.len();
+                for i in 0..count {
+                    for j in (i + 1)..count {
+                        visit(i, j);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The total potential energy of the coupled particles, including any truncation shift.
+    /// Forces that don't provide a potential contribute nothing.
+    pub fn potential_energy(&mut self, particle_source: &[Particle]) -> f64 {
+        self.update_neighbor_list(particle_source);
+
+        let mut energy = 0.0;
+        self.for_each_pair(|i, j| {
+            let particle1 = self.coupled_particles[i].get(particle_source);
+            let particle2 = self.coupled_particles[j].get(particle_source);
+            energy +=
+                self.truncated_potential(particle1, particle2, self.radial(particle1, particle2));
+        });
+        energy
+    }
 }
 
 impl Interaction for PairWiseForceParameters {
     fn handle(&mut self, particle_source: &mut [Particle], _dt: f64) {
-        for (index, ref1) in self.coupled_particles.iter().enumerate() {
-            for ref2 in &self.coupled_particles[(index + 1)..] {
-                if let Some(force) = self
-                    .force
-                    .force(ref1.get(particle_source), ref2.get(particle_source))
-                {
-                    let p1 = ref1.get_mut(particle_source);
-                    p1.add_force(force);
-                    let p2 = ref2.get_mut(particle_source);
-                    p2.add_force(-force);
+        self.update_neighbor_list(particle_source);
+
+        let mut virial = Vec3::zero();
+        self.for_each_pair(|i, j| {
+            let (ref1, ref2) = (self.coupled_particles[i], self.coupled_particles[j]);
+            let (particle1, particle2) = (ref1.get(particle_source), ref2.get(particle_source));
+
+            let radial = self.radial(particle1, particle2);
+            if let Some(force) = self.truncated_force(particle1, particle2, radial) {
+                // the displacement from particle2 to particle1, against the force on particle1
+                virial -= Vec3::new(radial.x * force.x, radial.y * force.y, radial.z * force.z);
+
+                let p1 = ref1.get_mut(particle_source);
+                p1.add_force(force);
+                let p2 = ref2.get_mut(particle_source);
+                p2.add_force(-force);
+            }
+        });
+        self.virial = virial;
+    }
+
+    fn set_simulation_box(&mut self, simulation_box: &SimulationBox) {
+        self.simulation_box = Some(*simulation_box);
+    }
+
+    fn virial(&self) -> Vec3 {
+        self.virial
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{algorithms::Random, interaction::interactions::LennardJones};
+
+    fn gas() -> Vec<Particle> {
+        let mut random = Random::new(21);
+        let mut particles: Vec<Particle> = Vec::new();
+        // no closer than a collision radius, where the forces are tame
+        while particles.len() < 150 {
+            let pos = 7.0 * Vec3::new(random.uniform(), random.uniform(), random.uniform());
+            if particles.iter().all(|other| (other.pos - pos).mag() > 1.0) {
+                particles.push(Particle::new().id(particles.len() as u32).pos(pos));
+            }
+        }
+        particles
+    }
+
+    fn total_forces(
+        interaction: PairWiseForceParameters,
+        particles: &mut [Particle],
+        simulation_box: Option<&SimulationBox>,
+    ) -> Vec<Vec3> {
+        let references: Vec<ParticleReference> = (0..particles.len())
+            .map(|index| ParticleReference::new(particles[index].id, index))
+            .collect();
+        let mut interaction = interaction.with_particles(&references);
+        if let Some(simulation_box) = simulation_box {
+            interaction.set_simulation_box(simulation_box);
+        }
+        for particle in particles.iter_mut() {
+            particle.forces.clear();
+        }
+        interaction.handle(particles, 0.0);
+        particles
+            .iter()
+            .map(|particle| {
+                particle
+                    .forces
+                    .iter()
+                    .fold(Vec3::zero(), |total, force| total + *force)
+            })
+            .collect()
+    }
+
+    /// The forces summed over every pair, and every periodic copy, within the cutoff.
+    fn brute_force(
+        particles: &[Particle],
+        cutoff: f64,
+        simulation_box: Option<&SimulationBox>,
+    ) -> Vec<Vec3> {
+        let lennard_jones = LennardJones::new(1.0, 1.0);
+        let mut forces = vec![Vec3::zero(); particles.len()];
+        for i in 0..particles.len() {
+            for j in 0..particles.len() {
+                let radial = displacement(simulation_box, particles[i].pos, particles[j].pos);
+                if i != j && radial.mag() < cutoff {
+                    forces[i] += lennard_jones
+                        .force(&particles[i], &particles[j], radial)
+                        .unwrap();
                 }
             }
         }
+        forces
+    }
+
+    #[test]
+    fn cut_off_forces_match_brute_force() {
+        let simulation_box = SimulationBox::new(Vec3::zero(), Vec3::new(7.0, 7.0, 7.0));
+        for simulation_box in [None, Some(&simulation_box)] {
+            let mut particles = gas();
+            let forces = total_forces(
+                LennardJones::new(1.0, 1.0).build().cutoff(2.5),
+                &mut particles,
+                simulation_box,
+            );
+            let expected = brute_force(&particles, 2.5, simulation_box);
+            for (force, expected) in forces.iter().zip(&expected) {
+                assert!((*force - *expected).mag() < 1e-12 * (1.0 + expected.mag()));
+            }
+        }
+    }
+
+    #[test]
+    fn truncation_is_continuous_at_the_cutoff() {
+        let (first, second) = (Particle::new(), Particle::new());
+        let interaction = |truncation| {
+            LennardJones::new(1.0, 1.0)
+                .build()
+                .cutoff(2.5)
+                .truncation(truncation)
+        };
+        let just_inside = 2.5 * (1.0 - 1e-9) * Vec3::x_hat();
+
+        let shifted = interaction(Truncation::EnergyShifted);
+        let energy = shifted.truncated_potential(&first, &second, just_inside);
+        assert!(energy.abs() < 1e-9);
+
+        let force_shifted = interaction(Truncation::ForceShifted);
+        let energy = force_shifted.truncated_potential(&first, &second, just_inside);
+        let force = force_shifted
+            .truncated_force(&first, &second, just_inside)
+            .unwrap();
+        assert!(energy.abs() < 1e-9 && force.mag() < 1e-9);
+
+        // and the force is still the energy's gradient inside
+        let (r, step) = (1.7, 1e-6);
+        let energy = |r: f64| force_shifted.truncated_potential(&first, &second, r * Vec3::x_hat());
+        let expected = (energy(r + step) - energy(r - step)) / (2.0 * step);
+        let force = force_shifted
+            .truncated_force(&first, &second, r * Vec3::x_hat())
+            .unwrap();
+        assert!((force.x - expected).abs() < 1e-7);
     }
 }
 
@@ -0,0 +1,488 @@
+//! Particle-mesh (PM) gravity.
+//!
+//! Mass is deposited onto a regular 3d grid, Poisson's equation is solved on the grid with
+//! fast Fourier transforms, and the gradient of the potential is interpolated back onto the
+//! particles with the same assignment scheme, which keeps the method free of self-forces.
+
+//---------------------------------------------------------------------------------------------------//
+use crate::{
+    algorithms::fft_3d,
+    interaction::field::{FieldForce, FieldForceParameters},
+    math::{Point3, Vec3, PI},
+    particle::Particle,
+};
+
+//---------------------------------------------------------------------------------------------------//
+
+/// How a particle's mass is spread over the surrounding grid nodes.
+#[derive(Copy, Clone)]
+pub enum MassAssignment {
+    /// Cloud-in-cell: linear weights over the 2 nearest nodes along each axis
+    CloudInCell,
+    /// Triangular-shaped-cloud: quadratic weights over the 3 nearest nodes along each axis
+    TriangularShapedCloud,
+}
+
+impl MassAssignment {
+    /// Node offsets and weights along one axis, for a coordinate `u` measured in cells
+    /// from the center of node 0. Returns how many of the three slots are used.
+    fn stencil(self, u: f64) -> ([i64; 3], [f64; 3], usize) {
+        match self {
+            MassAssignment::CloudInCell => {
+                let i = u.floor();
+                let f = u - i;
+                ([i as i64, i as i64 + 1, 0], [1.0 - f, f, 0.0], 2)
+            }
+            MassAssignment::TriangularShapedCloud => {
+                let i = u.round();
+                let d = u - i;
+                (
+                    [i as i64 - 1, i as i64, i as i64 + 1],
+                    [
+                        0.5 * (0.5 - d).powi(2),
+                        0.75 - d * d,
+                        0.5 * (0.5 + d).powi(2),
+                    ],
+                    3,
+                )
+            }
+        }
+    }
+}
+
+/// The geometry of a cubic grid and the scheme used to move quantities between it and particles.
+#[derive(Copy, Clone)]
+pub struct Mesh {
+    pub grid_size: usize,
+    pub cell_size: f64,
+    pub origin: Point3,
+    pub periodic: bool,
+    pub assignment: MassAssignment,
+}
+
+impl Mesh {
+    pub fn node_count(&self) -> usize {
+        self.grid_size.pow(3)
+    }
+
+    pub fn index(&self, x: usize, y: usize, z: usize) -> usize {
+        x + self.grid_size * (y + self.grid_size * z)
+    }
+
+    /// The physical length of one side of the grid.
+    pub fn length(&self) -> f64 {
+        self.grid_size as f64 * self.cell_size
+    }
+
+    /// Calls `visit(node, weight)` for every grid node that a point at `pos` is assigned to.
+    /// Nodes off the edge of the grid wrap around when periodic, and are skipped otherwise.
+    pub fn for_each_node(&self, pos: Point3, mut visit: impl FnMut(usize, f64)) {
+        let n = self.grid_size as i64;
+        let u = (pos - self.origin) / self.cell_size - Vec3::new(0.5, 0.5, 0.5);
+        let (ix, wx, cx) = self.assignment.stencil(u.x);
+        let (iy, wy, cy) = self.assignment.stencil(u.y);
+        let (iz, wz, cz) = self.assignment.stencil(u.z);
+
+        let wrap = |i: i64| -> Option<usize> {
+            if self.periodic {
+                Some(i.rem_euclid(n) as usize)
+            } else if (0..n).contains(&i) {
+                Some(i as usize)
+            } else {
+                None
+            }
+        };
+
+        for c in 0..cz {
+            for b in 0..cy {
+                for a in 0..cx {
+                    if let (Some(x), Some(y), Some(z)) = (wrap(ix[a]), wrap(iy[b]), wrap(iz[c])) {
+                        visit(self.index(x, y, z), wx[a] * wy[b] * wz[c]);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Whether every node a point at `pos` is assigned to is on the grid, as it always is when
+    /// the grid is periodic.
+    pub fn covers(&self, pos: Point3) -> bool {
+        let mut nodes = 0;
+        self.for_each_node(pos, |_, _| nodes += 1);
+        nodes == self.assignment.stencil(0.0).2.pow(3)
+    }
+
+    /// Central difference gradient of a scalar grid, one-sided at the edges of a non-periodic grid.
+    pub fn gradient(&self, field: &[f64]) -> Vec<Vec3> {
+        let n = self.grid_size;
+        let mut gradient = vec![Vec3::zero(); self.node_count()];
+
+        let neighbors = |i: usize| -> (usize, usize, f64) {
+            if self.periodic {
+                ((i + n - 1) % n, (i + 1) % n, 2.0)
+            } else if i == 0 {
+                (0, 1.min(n - 1), 1.0)
+            } else if i == n - 1 {
+                (n - 2, n - 1, 1.0)
+            } else {
+                (i - 1, i + 1, 2.0)
+            }
+        };
+
+        for z in 0..n {
+            let (z0, z1, dz) = neighbors(z);
+            for y in 0..n {
+                let (y0, y1, dy) = neighbors(y);
+                for x in 0..n {
+                    let (x0, x1, dx) = neighbors(x);
+                    gradient[self.index(x, y, z)] = Vec3::new(
+                        (field[self.index(x1, y, z)] - field[self.index(x0, y, z)]) / dx,
+                        (field[self.index(x, y1, z)] - field[self.index(x, y0, z)]) / dy,
+                        (field[self.index(x, y, z1)] - field[self.index(x, y, z0)]) / dz,
+                    ) / self.cell_size;
+                }
+            }
+        }
+        gradient
+    }
+}
+
+//---------------------------------------------------------------------------------------------------//
+
+pub struct ParticleMeshGravity {
+    gravitational_constant: f64,
+    mesh: Mesh,
+
+    density: Vec<f64>,
+    acceleration: Vec<Vec3>,
+    // fourier transform of the zero-padded green's function, for isolated boundaries
+    green: Option<(Vec<f64>, Vec<f64>)>,
+}
+
+impl ParticleMeshGravity {
+    /// A PM solver on a cubic grid of `grid_size` (a power of two) nodes per side, centered on the origin.
+    /// Defaults to cloud-in-cell assignment and isolated boundaries, in which case the grid has
+    /// to hold every particle's assignment stencil: it panics on a particle too close to its
+    /// edge or outside it, rather than lose the particle's mass.
+    pub fn new(
+        gravitational_constant: f64,
+        grid_size: usize,
+        cell_size: f64,
+    ) -> ParticleMeshGravity {
+        assert!(
+            grid_size.is_power_of_two(),
+            "the grid size must be a power of two"
+        );
+        let half = 0.5 * grid_size as f64 * cell_size;
+
+        ParticleMeshGravity {
+            gravitational_constant,
+            mesh: Mesh {
+                grid_size,
+                cell_size,
+                origin: Point3::new(-half, -half, -half),
+                periodic: false,
+                assignment: MassAssignment::CloudInCell,
+            },
+            density: vec![0.0; grid_size.pow(3)],
+            acceleration: vec![Vec3::zero(); grid_size.pow(3)],
+            green: None,
+        }
+    }
+
+    /// Place the lower corner of the grid.
+    pub fn origin(mut self, origin: Point3) -> ParticleMeshGravity {
+        self.mesh.origin = origin;
+        self
+    }
+
+    pub fn mass_assignment(mut self, assignment: MassAssignment) -> ParticleMeshGravity {
+        self.mesh.assignment = assignment;
+        self
+    }
+
+    /// Treat the grid as one cell of an infinite periodic lattice, as in cosmological boxes.
+    pub fn periodic(mut self) -> ParticleMeshGravity {
+        self.mesh.periodic = true;
+        self
+    }
+
+    pub fn build(self) -> FieldForceParameters {
+        FieldForceParameters::new(self)
+    }
+
+    //--------------------------------------------------------------------//
+
+    fn solve_periodic(&self, re: &mut [f64], im: &mut [f64]) {
+        let n = self.mesh.grid_size;
+        let k_unit = 2.0 * PI / self.mesh.length();
+        let wave_number = |i: usize| -> f64 {
+            if i <= n / 2 {
+                i as f64 * k_unit
+            } else {
+                (i as f64 - n as f64) * k_unit
+            }
+        };
+
+        fft_3d(re, im, [n, n, n], false);
+        for z in 0..n {
+            for y in 0..n {
+                for x in 0..n {
+                    let index = self.mesh.index(x, y, z);
+                    let k_squared =
+                        wave_number(x).powi(2) + wave_number(y).powi(2) + wave_number(z).powi(2);
+                    // the mean density does not source any force in a periodic universe
+                    let green = if k_squared == 0.0 {
+                        0.0
+                    } else {
+                        -4.0 * PI * self.gravitational_constant / k_squared
+                    };
+                    re[index] *= green;
+                    im[index] *= green;
+                }
+            }
+        }
+        fft_3d(re, im, [n, n, n], true);
+    }
+
+    fn isolated_green(&self) -> (Vec<f64>, Vec<f64>) {
+        let n = self.mesh.grid_size;
+        let padded = 2 * n;
+        let h = self.mesh.cell_size;
+        let mut re = vec![0.0; padded.pow(3)];
+        let mut im = vec![0.0; padded.pow(3)];
+
+        let distance = |i: usize| -> f64 {
+            if i <= n {
+                i as f64
+            } else {
+                i as f64 - padded as f64
+            }
+        };
+
+        for z in 0..padded {
+            for y in 0..padded {
+                for x in 0..padded {
+                    let r =
+                        (distance(x).powi(2) + distance(y).powi(2) + distance(z).powi(2)).sqrt();
+                    // the mean of 1/r over a unit cube stands in for the singular self term
+                    let inverse_r = if r == 0.0 { 2.380_077 } else { 1.0 / r };
+                    re[x + padded * (y + padded * z)] =
+                        -self.gravitational_constant * inverse_r / h * h.powi(3);
+                }
+            }
+        }
+        fft_3d(&mut re, &mut im, [padded; 3], false);
+        (re, im)
+    }
+
+    fn solve_isolated(&mut self) -> Vec<f64> {
+        let n = self.mesh.grid_size;
+        let padded = 2 * n;
+        if self.green.is_none() {
+            self.green = Some(self.isolated_green());
+        }
+        let (green_re, green_im) = self.green.as_ref().unwrap();
+
+        let mut re = vec![0.0; padded.pow(3)];
+        let mut im = vec![0.0; padded.pow(3)];
+        for z in 0..n {
+            for y in 0..n {
+                for x in 0..n {
+                    re[x + padded * (y + padded * z)] = self.density[self.mesh.index(x, y, z)];
+                }
+            }
+        }
+
+        // convolution with the green's function is a product in fourier space
+        fft_3d(&mut re, &mut im, [padded; 3], false);
+        for i in 0..re.len() {
+            let (a, b) = (re[i], im[i]);
+            re[i] = a * green_re[i] - b * green_im[i];
+            im[i] = a * green_im[i] + b * green_re[i];
+        }
+        fft_3d(&mut re, &mut im, [padded; 3], true);
+
+        let mut potential = vec![0.0; self.mesh.node_count()];
+        for z in 0..n {
+            for y in 0..n {
+                for x in 0..n {
+                    potential[self.mesh.index(x, y, z)] = re[x + padded * (y + padded * z)];
+                }
+            }
+        }
+        potential
+    }
+}
+
+impl FieldForce for ParticleMeshGravity {
+    fn particle_to_field(&mut self, particle: &Particle) {
+        assert!(
+            self.mesh.covers(particle.pos),
+            "a particle at {:?} is off the edge of the isolated grid",
+            particle.pos
+        );
+        let density = particle.mass / self.mesh.cell_size.powi(3);
+        let density_grid = &mut self.density;
+        self.mesh.for_each_node(particle.pos, |node, weight| {
+            density_grid[node] += weight * density;
+        });
+    }
+
+    fn integrate(&mut self, _dt: f64) {
+        let potential = if self.mesh.periodic {
+            let mut re = self.density.clone();
+            let mut im = vec![0.0; re.len()];
+            self.solve_periodic(&mut re, &mut im);
+            re
+        } else {
+            self.solve_isolated()
+        };
+
+        self.acceleration = self
+            .mesh
+            .gradient(&potential)
+            .into_iter()
+            .map(|gradient| -gradient)
+            .collect();
+    }
+
+    fn force_on_particle(&self, particle: &Particle) -> Option<Vec3> {
+        let mut acceleration = Vec3::zero();
+        let mut total_weight = 0.0;
+        self.mesh.for_each_node(particle.pos, |node, weight| {
+            acceleration += weight * self.acceleration[node];
+            total_weight += weight;
+        });
+
+        if total_weight > 0.0 {
+            Some(particle.mass * acceleration)
+        } else {
+            None
+        }
+    }
+
+    fn clear(&mut self) {
+        for density in &mut self.density {
+            *density = 0.0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{algorithms::Random, interaction::Interaction, particle::ParticleReference};
+
+    fn mesh_forces(gravity: ParticleMeshGravity, particles: &mut [Particle]) -> Vec<Vec3> {
+        let references: Vec<ParticleReference> = (0..particles.len())
+            .map(|index| ParticleReference::new(particles[index].id, index))
+            .collect();
+        gravity
+            .build()
+            .with_particles(&references)
+            .handle(particles, 0.0);
+        particles
+            .iter()
+            .map(|particle| {
+                particle
+                    .forces
+                    .iter()
+                    .fold(Vec3::zero(), |total, force| total + *force)
+            })
+            .collect()
+    }
+
+    /// Plummer softened direct summation.
+    fn direct_forces(particles: &[Particle], softening: f64) -> Vec<Vec3> {
+        particles
+            .iter()
+            .map(|particle| {
+                particles
+                    .iter()
+                    .filter(|other| other.id != particle.id)
+                    .fold(Vec3::zero(), |force, other| {
+                        let d = other.pos - particle.pos;
+                        let r = (d.mag_squared() + softening * softening).sqrt();
+                        force + (particle.mass * other.mass / r.powi(3)) * d
+                    })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn a_distant_pair_attracts_as_newton_says() {
+        for assignment in [
+            MassAssignment::CloudInCell,
+            MassAssignment::TriangularShapedCloud,
+        ] {
+            let mut particles = vec![
+                Particle::new().id(0).pos_xyz(-4.3, 0.2, 0.1),
+                Particle::new().id(1).pos_xyz(4.1, -0.3, 0.4),
+            ];
+            let gravity = ParticleMeshGravity::new(1.0, 32, 1.0).mass_assignment(assignment);
+            let forces = mesh_forces(gravity, &mut particles);
+            let exact = direct_forces(&particles, 0.0);
+            for (force, exact) in forces.iter().zip(&exact) {
+                assert!((*force - *exact).mag() < 0.02 * exact.mag());
+            }
+        }
+    }
+
+    #[test]
+    fn cluster_forces_match_direct_summation() {
+        let mut random = Random::new(4);
+        let mut particles: Vec<Particle> = (0..300)
+            .map(|id| {
+                let direction = Vec3::new(random.gaussian(), random.gaussian(), random.gaussian());
+                Particle::new()
+                    .id(id)
+                    .pos(8.0 * random.uniform().sqrt() * direction.norm())
+            })
+            .collect();
+        let gravity = ParticleMeshGravity::new(1.0, 32, 1.0)
+            .mass_assignment(MassAssignment::TriangularShapedCloud);
+        let forces = mesh_forces(gravity, &mut particles);
+        // the mesh can't resolve structure below a cell or so
+        let exact = direct_forces(&particles, 1.0);
+
+        let mean_force = exact.iter().map(|force| force.mag()).sum::<f64>() / exact.len() as f64;
+        let squared_error: f64 = forces
+            .iter()
+            .zip(&exact)
+            .map(|(force, exact)| (*force - *exact).mag_squared())
+            .sum();
+        assert!((squared_error / exact.len() as f64).sqrt() < 0.1 * mean_force);
+    }
+
+    #[test]
+    fn a_uniform_periodic_lattice_feels_no_force() {
+        let mut particles = Vec::new();
+        for x in 0..8 {
+            for y in 0..8 {
+                for z in 0..8 {
+                    let pos = Point3::new(x as f64 + 0.5, y as f64 + 0.5, z as f64 + 0.5);
+                    particles.push(Particle::new().id(particles.len() as u32).pos(pos));
+                }
+            }
+        }
+        let gravity = ParticleMeshGravity::new(1.0, 8, 1.0)
+            .origin(Point3::zero())
+            .periodic();
+        for force in mesh_forces(gravity, &mut particles) {
+            assert!(force.mag() < 1e-9);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "off the edge of the isolated grid")]
+    fn particles_off_an_isolated_grid_are_refused() {
+        let mut particles = vec![
+            Particle::new().id(0),
+            Particle::new().id(1).pos_xyz(20.0, 0.0, 0.0),
+        ];
+        mesh_forces(ParticleMeshGravity::new(1.0, 32, 1.0), &mut particles);
+    }
+}
+
+//---------------------------------------------------------------------------------------------------//
@@ -1,146 +1,1520 @@
+//! Weakly-compressible smoothed-particle hydrodynamics.
+//!
+//! Each step the density of every particle is found by summing the kernel over its
+//! neighbors, a state equation turns that density into a pressure, and the symmetric
+//! pressure-gradient force is applied between every pair of neighbors, so that
+//! momentum is conserved exactly. Artificial and laminar viscosity, and XSPH velocity
+//! smoothing, can be switched on to damp the noise that an inviscid fluid accumulates.
+//!
+//! The temperatures of the particles can be evolved too, by an internal energy equation
+//! with the PdV work and viscous heating that balance the forces exactly, and Cleary &
+//! Monaghan's heat conduction. The temperatures then feed back through the state equation.
+//!
+//! The smoothing length can also be adapted to each particle, so that every particle keeps
+//! about the same number of neighbors however far the fluid is compressed or expanded, as
+//! in astrophysical codes. The smoothing lengths are then solved for together with the
+//! densities, and the forces carry the grad-h terms of Springel & Hernquist and Price &
+//! Monaghan, which keep momentum and energy conserved as the smoothing lengths change.
+//!
+//! Fluids of several phases, or with shear-dependent viscosities, are described by
+//! [`Material`]s. For phases whose densities differ a lot, such as water and air, the
+//! multiphase formulation of Hu & Adams sums each particle's density over its neighbors'
+//! numbers rather than their masses, which keeps the densities sharp across interfaces.
+//!
+//! Solid tanks and obstacles are described by a [`SphBoundary`].
+
+//---------------------------------------------------------------------------------------------------//
+use std::collections::HashMap;
+
 use crate::{
+    collision::neighbor_list::NeighborList,
     interaction::{pair_wise::PairWiseForce, Interaction},
-    math::{Point3, Vec3, PI},
-    particle::Particle,
+    math::{Matrix3, Point3, Vec3, PI},
+    particle::{Particle, ParticleReference},
+    sph::{
+        boundary::SphBoundary,
+        kernels::{Dimension, GaussianKernel},
+        materials::{Material, ViscosityLaw},
+    },
 };
 
+pub use crate::sph::{SphKernel, StateEquation};
+
 //---------------------------------------------------------------------------------------------------//
 
+/// The relative change in a smoothing length at which its solve has converged.
+const SMOOTHING_TOLERANCE: f64 = 1e-4;
+const MAX_SMOOTHING_ITERATIONS: u32 = 30;
+
 pub struct SphParameters {
+    coupled_particles: Vec<ParticleReference>,
     kernel: Box<dyn SphKernel>,
-    state_equation: Option<Box<dyn StateEquation>>,
-    smoothing_radius: Option<f64>,
-    compact: bool,
+    gradient_kernel: Box<dyn SphKernel>,
+    state_equation: Box<dyn StateEquation>,
+    smoothing_radius: f64,
+
+    artificial_viscosity: Option<(f64, f64)>,
+    balsara: bool,
+    laminar_viscosity: Option<ViscosityLaw>,
+    xsph: Option<f64>,
+
+    specific_heat: Option<f64>,
+    conductivity: Option<f64>,
+    // group -> conductivity, for groups that differ from the rest
+    group_conductivities: HashMap<u32, f64>,
+
+    // the number of neighbors to keep, and the dimension to count them in
+    adaptive_smoothing: Option<(f64, Dimension)>,
+
+    // material id -> material, for particles that differ from the rest
+    materials: HashMap<u32, Material>,
+    multiphase: bool,
+
+    boundary: Option<SphBoundary>,
+
+    neighbor_list: Option<NeighborList>,
 }
 
 impl SphParameters {
+    /// Defaults to the gaussian kernel for both densities and pressure gradients. Using the same
+    /// kernel for both keeps the scheme energy conserving.
+    pub fn new(
+        smoothing_radius: f64,
+        state_equation: impl StateEquation + 'static,
+    ) -> SphParameters {
+        SphParameters {
+            coupled_particles: Vec::new(),
+            kernel: Box::new(GaussianKernel),
+            gradient_kernel: Box::new(GaussianKernel),
+            state_equation: Box::new(state_equation),
+            smoothing_radius,
+            artificial_viscosity: None,
+            balsara: false,
+            laminar_viscosity: None,
+            xsph: None,
+            specific_heat: None,
+            conductivity: None,
+            group_conductivities: HashMap::new(),
+            adaptive_smoothing: None,
+            materials: HashMap::new(),
+            multiphase: false,
+            boundary: None,
+            neighbor_list: None,
+        }
+    }
+
+    /// The kernel used for density summation and interpolation.
+    pub fn kernel(mut self, kernel: impl SphKernel + 'static) -> SphParameters {
+        self.kernel = Box::new(kernel);
+        self.neighbor_list = None;
+        self
+    }
+
+    /// The kernel whose gradient is used for the pressure force.
+    pub fn gradient_kernel(mut self, kernel: impl SphKernel + 'static) -> SphParameters {
+        self.gradient_kernel = Box::new(kernel);
+        self.neighbor_list = None;
+        self
+    }
+
+    /// Monaghan's artificial viscosity, which acts between approaching particles to spread
+    /// shocks over a few smoothing lengths. α = 1 and β = 2 suit shocks, and α around 0.01 to
+    /// 0.1 with β = 0 is enough to settle a liquid.
+    pub fn artificial_viscosity(mut self, alpha: f64, beta: f64) -> SphParameters {
+        self.artificial_viscosity = Some((alpha, beta));
+        self
+    }
+
+    /// Scale the artificial viscosity by Balsara's switch, which turns it off in shearing flows
+    /// (where the curl of the velocity dominates its divergence) such as rotating disks.
+    pub fn balsara(mut self) -> SphParameters {
+        self.balsara = true;
+        self
+    }
+
+    /// Morris' laminar viscosity, for a physical dynamic viscosity μ.
+    pub fn laminar_viscosity(mut self, dynamic_viscosity: f64) -> SphParameters {
+        self.laminar_viscosity = Some(ViscosityLaw::Newtonian(dynamic_viscosity));
+        self
+    }
+
+    /// Morris' laminar viscosity, with a viscosity that depends on each particle's shear rate.
+    /// The viscosity between two particles is the harmonic mean of theirs.
+    pub fn viscosity_law(mut self, law: ViscosityLaw) -> SphParameters {
+        self.laminar_viscosity = Some(law);
+        self
+    }
+
+    /// XSPH, which nudges each particle's velocity towards the average of its neighbors' by a
+    /// fraction ε, keeping the particles more orderly. Velocities are carried from one substep to
+    /// the next, so this also acts as a viscosity, and ε should be small (around 0.01).
+    pub fn xsph(mut self, epsilon: f64) -> SphParameters {
+        self.xsph = Some(epsilon);
+        self
+    }
+
+    /// Evolve the temperatures by the internal energy equation, where the specific internal
+    /// energy is u = c_v T for the given specific heat c_v. Temperatures are kept non-negative.
+    pub fn energy_equation(mut self, specific_heat: f64) -> SphParameters {
+        self.specific_heat = Some(specific_heat);
+        self
+    }
+
+    /// Cleary & Monaghan's heat conduction with a thermal conductivity k, which conserves
+    /// energy and handles jumps in conductivity. Needs the energy equation.
+    pub fn heat_conduction(mut self, conductivity: f64) -> SphParameters {
+        self.conductivity = Some(conductivity);
+        self
+    }
+
+    /// Give particles of one group their own thermal conductivity. The conductivity between
+    /// two particles is the harmonic mean of theirs.
+    pub fn group_conductivity(mut self, group: u32, conductivity: f64) -> SphParameters {
+        self.group_conductivities.insert(group, conductivity);
+        self
+    }
+
+    /// Give every particle its own smoothing length, solved each step so that about the given
+    /// number of neighbors falls within its kernel, counted in the given dimension. Around 60
+    /// neighbors suit the cubic spline in three dimensions, 20 in two and 5 in one.
+    ///
+    /// Particles start from their stored smoothing length, or the smoothing radius if they have
+    /// none, and no smoothing length grows beyond ten times the smoothing radius. That keeps a
+    /// particle flung away on its own from taking the whole fluid as its neighbors.
+    pub fn adaptive_smoothing(
+        mut self,
+        neighbor_count: f64,
+        dimension: Dimension,
+    ) -> SphParameters {
+        self.adaptive_smoothing = Some((neighbor_count, dimension));
+        self
+    }
+
+    /// Make the particles with the given material id, or in the group of that number if they
+    /// have no material id, out of the given material. Other particles keep the state equation
+    /// and laminar viscosity given to the interaction itself.
+    pub fn material(mut self, id: u32, material: Material) -> SphParameters {
+        self.materials.insert(id, material);
+        self
+    }
+
+    /// Hu & Adams' multiphase formulation, where each particle's density is its own mass times
+    /// the number density of its neighbors, and the pressure force between two particles is
+    /// -(p1 / n1² + p2 / n2²) ∇W for number densities n. Densities then stay sharp at the
+    /// interfaces between phases, even for density ratios of a thousand. Every phase should be
+    /// sampled at about the same spacing.
+    pub fn multiphase(mut self) -> SphParameters {
+        self.multiphase = true;
+        self
+    }
+
+    /// Contain the fluid, or obstruct it, with boundary particles and walls. Boundary particles
+    /// are sampled with the smoothing radius, even when the fluid's smoothing is adaptive.
+    pub fn boundary(mut self, boundary: SphBoundary) -> SphParameters {
+        self.boundary = Some(boundary);
+        self
+    }
+
+    pub fn with_particle(mut self, reference: ParticleReference) -> SphParameters {
+        self.coupled_particles.push(reference);
+        self
+    }
+
+    pub fn with_particles(mut self, references: &[ParticleReference]) -> SphParameters {
+        for reference in references {
+            self.coupled_particles.push(*reference);
+        }
+        self
+    }
+
+    //--------------------------------------------------------------------//
+    // field estimates
+
+    /// The density at a point, summed over the given particles.
     pub fn density(&self, point: Point3, neighbors: &[Particle]) -> f64 {
-        todo!()
+        neighbors
+            .iter()
+            .map(|neighbor| {
+                neighbor.mass * self.kernel.w(point - neighbor.pos, self.smoothing_radius)
+            })
+            .sum()
     }
 
+    /// The SPH estimate of a field at a point, using the stored densities of the given particles.
     pub fn interpolate<T>(
         &self,
         point: Point3,
         neighbors: &[Particle],
         retrieve: impl Fn(&Particle) -> T,
-    ) -> T {
-        todo!()
+    ) -> T
+    where
+        T: Default + core::ops::Add<T, Output = T> + core::ops::Mul<f64, Output = T>,
+    {
+        neighbors
+            .iter()
+            .filter(|neighbor| neighbor.density != 0.0)
+            .fold(T::default(), |sum, neighbor| {
+                let weight = neighbor.mass / neighbor.density
+                    * self.kernel.w(point - neighbor.pos, self.smoothing_radius);
+                sum + retrieve(neighbor) * weight
+            })
     }
 
+    /// The interpolant of the constant field 1, which is close to 1 inside the fluid
+    /// and falls off at free surfaces.
     pub fn one(&self, point: Point3, neighbors: &[Particle]) -> f64 {
-        todo!()
+        self.interpolate(point, neighbors, |_| 1.0)
     }
 
-    pub fn zero(&self, point: Point3, neighbors: &[Particle]) -> f64 {
-        todo!()
+    /// The gradient of the interpolant of the constant field 1, which is close to zero inside
+    /// the fluid and points inwards at free surfaces.
+    pub fn zero(&self, point: Point3, neighbors: &[Particle]) -> Vec3 {
+        neighbors
+            .iter()
+            .filter(|neighbor| neighbor.density != 0.0)
+            .fold(Vec3::zero(), |sum, neighbor| {
+                sum + neighbor.mass / neighbor.density
+                    * self
+                        .gradient_kernel
+                        .grad_w(point - neighbor.pos, self.smoothing_radius)
+            })
     }
 
+    /// The pressure force on particle1 from particle2, using their stored densities.
     pub fn pressure_force(&self, particle1: &Particle, particle2: &Particle) -> Vec3 {
-        todo!()
+        if particle1.density == 0.0 || particle2.density == 0.0 {
+            return Vec3::zero();
+        }
+
+        let pressure1 = self.state_equation_of(particle1).pressure(particle1);
+        let pressure2 = self.state_equation_of(particle2).pressure(particle2);
+        self.symmetric_pressure_force(particle1, particle2, (pressure1, pressure2), (1.0, 1.0))
     }
-}
 
-pub trait SphKernel {
-    fn w(&self, r: Point3, h: f64) -> f64;
-    fn grad_w(&self, r: Point3, h: f64) -> Vec3;
-    fn div_w(&self, r: Point3, h: f64) -> f64;
-    fn laplace_w(&self, r: Point3, h: f64) -> f64;
-}
+    /// The smoothing length of a particle: its own if the smoothing is adaptive and it has
+    /// one, and otherwise the smoothing radius.
+    pub fn smoothing_length(&self, particle: &Particle) -> f64 {
+        if self.adaptive_smoothing.is_some() && particle.smoothing_length > 0.0 {
+            particle.smoothing_length
+        } else {
+            self.smoothing_radius
+        }
+    }
+
+    /// The largest stable timestep by the Courant condition, dt = C min(h / (c + |v|)), using
+    /// the stored densities. Courant numbers between 0.1 and 0.4 are typical.
+    pub fn cfl_timestep(&self, particle_source: &[Particle], courant_number: f64) -> f64 {
+        self.coupled_particles
+            .iter()
+            .map(|reference| {
+                let particle = reference.get(particle_source);
+                let signal_speed =
+                    self.state_equation_of(particle).sound_speed(particle) + particle.vel.mag();
+                if signal_speed > 0.0 {
+                    courant_number * self.smoothing_length(particle) / signal_speed
+                } else {
+                    f64::INFINITY
+                }
+            })
+            .fold(f64::INFINITY, f64::min)
+    }
+
+    /// The state equation of a particle's material, or the interaction's own.
+    pub fn state_equation_of(&self, particle: &Particle) -> &dyn StateEquation {
+        match self.material_of(particle) {
+            Some(material) => &*material.state_equation,
+            None => &*self.state_equation,
+        }
+    }
+
+    //--------------------------------------------------------------------//
+
+    fn material_of(&self, particle: &Particle) -> Option<&Material> {
+        if self.materials.is_empty() {
+            return None;
+        }
+        self.materials
+            .get(&particle.material.unwrap_or(particle.group))
+    }
+
+    fn viscosity_law_of(&self, particle: &Particle) -> Option<&ViscosityLaw> {
+        match self.material_of(particle) {
+            Some(material) => material.viscosity.as_ref(),
+            None => self.laminar_viscosity.as_ref(),
+        }
+    }
+
+    /// The pressure force on particle1 from particle2. Each particle's term is taken with its
+    /// own smoothing length and divided by its grad-h correction Ω.
+    fn symmetric_pressure_force(
+        &self,
+        particle1: &Particle,
+        particle2: &Particle,
+        pressures: (f64, f64),
+        corrections: (f64, f64),
+    ) -> Vec3 {
+        let radial = particle1.pos - particle2.pos;
+        let gradient1 = self
+            .gradient_kernel
+            .grad_w(radial, self.smoothing_length(particle1));
+        let gradient2 = self
+            .gradient_kernel
+            .grad_w(radial, self.smoothing_length(particle2));
+        // with number densities n = ρ / m, p / n² = m² p / ρ²
+        let (weight1, weight2) = if self.multiphase {
+            (particle1.mass.powi(2), particle2.mass.powi(2))
+        } else {
+            let weight = particle1.mass * particle2.mass;
+            (weight, weight)
+        };
+        -(weight1 * pressures.0 / (corrections.0 * particle1.density.powi(2)) * gradient1
+            + weight2 * pressures.1 / (corrections.1 * particle2.density.powi(2)) * gradient2)
+    }
+
+    /// ∇W between two particles, averaged over their two smoothing lengths.
+    fn pair_gradient(&self, particle1: &Particle, particle2: &Particle) -> Vec3 {
+        let radial = particle1.pos - particle2.pos;
+        let (h1, h2) = (
+            self.smoothing_length(particle1),
+            self.smoothing_length(particle2),
+        );
+        if h1 == h2 {
+            self.gradient_kernel.grad_w(radial, h1)
+        } else {
+            0.5 * (self.gradient_kernel.grad_w(radial, h1)
+                + self.gradient_kernel.grad_w(radial, h2))
+        }
+    }
+
+    /// The artificial and laminar viscous force on particle1 from particle2. The sound speeds,
+    /// Balsara factors and laminar viscosities are passed in, since they depend on more than
+    /// the pair.
+    fn viscous_force(
+        &self,
+        particle1: &Particle,
+        particle2: &Particle,
+        sound_speeds: (f64, f64),
+        switches: (f64, f64),
+        viscosities: (f64, f64),
+    ) -> Vec3 {
+        let h = 0.5 * (self.smoothing_length(particle1) + self.smoothing_length(particle2));
+        let radial = particle1.pos - particle2.pos;
+        let relative_velocity = particle1.vel - particle2.vel;
+        let gradient = self.pair_gradient(particle1, particle2);
+        // keeps the viscosity finite as particles pass close to each other
+        let softened = radial.mag_squared() + 0.01 * h * h;
+        let mut force = Vec3::zero();
+
+        if let Some((alpha, beta)) = self.artificial_viscosity {
+            let approach = relative_velocity.dot(radial);
+            if approach < 0.0 {
+                let mu = h * approach / softened;
+                let sound_speed = 0.5 * (sound_speeds.0 + sound_speeds.1);
+                let density = 0.5 * (particle1.density + particle2.density);
+                let switch = 0.5 * (switches.0 + switches.1);
+                let pi = switch * (-alpha * sound_speed * mu + beta * mu * mu) / density;
+                force -= particle1.mass * particle2.mass * pi * gradient;
+            }
+        }
+
+        if viscosities.0 + viscosities.1 > 0.0 {
+            let viscosity = 2.0 * viscosities.0 * viscosities.1 / (viscosities.0 + viscosities.1);
+            force += particle1.mass * particle2.mass * 2.0 * viscosity * radial.dot(gradient)
+                / (particle1.density * particle2.density * softened)
+                * relative_velocity;
+        }
+        force
+    }
 
-pub trait StateEquation {
-    fn pressure(&self, particle: &Particle) -> f64;
+    /// Balsara's factor |∇·v| / (|∇·v| + |∇×v| + 0.0001 c / h) for every coupled particle,
+    /// which is near 1 in compressive flows and near 0 in shearing ones.
+    fn balsara_switches(
+        &self,
+        particle_source: &[Particle],
+        pairs: &[(usize, usize)],
+        sound_speeds: &[f64],
+    ) -> Vec<f64> {
+        let count = self.coupled_particles.len();
+        let mut divergence = vec![0.0; count];
+        let mut curl = vec![Vec3::zero(); count];
+        for (i, j) in pairs {
+            let particle1 = self.coupled_particles[*i].get(particle_source);
+            let particle2 = self.coupled_particles[*j].get(particle_source);
+            let gradient = self.pair_gradient(particle1, particle2);
+            let relative_velocity = particle2.vel - particle1.vel;
+            // ∇W is antisymmetric, as is the relative velocity, so both particles see the same sign
+            divergence[*i] += particle2.mass * relative_velocity.dot(gradient);
+            divergence[*j] += particle1.mass * relative_velocity.dot(gradient);
+            curl[*i] -= particle2.mass * relative_velocity.cross(gradient);
+            curl[*j] -= particle1.mass * relative_velocity.cross(gradient);
+        }
+
+        (0..count)
+            .map(|index| {
+                let particle = self.coupled_particles[index].get(particle_source);
+                if particle.density == 0.0 {
+                    return 1.0;
+                }
+                let divergence = (divergence[index] / particle.density).abs();
+                let curl = curl[index].mag() / particle.density;
+                let floor = 0.0001 * sound_speeds[index] / self.smoothing_length(particle);
+                if divergence + curl + floor > 0.0 {
+                    divergence / (divergence + curl + floor)
+                } else {
+                    1.0
+                }
+            })
+            .collect()
+    }
+
+    /// The rate of heat flow into particle1 from particle2 per unit mass of particle1, using
+    /// their stored densities.
+    fn conduction(&self, particle1: &Particle, particle2: &Particle) -> f64 {
+        let conductivity = match self.conductivity {
+            Some(conductivity) => conductivity,
+            None => return 0.0,
+        };
+        let conductivity1 = *self
+            .group_conductivities
+            .get(&particle1.group)
+            .unwrap_or(&conductivity);
+        let conductivity2 = *self
+            .group_conductivities
+            .get(&particle2.group)
+            .unwrap_or(&conductivity);
+        if conductivity1 + conductivity2 == 0.0 {
+            return 0.0;
+        }
+
+        let h = 0.5 * (self.smoothing_length(particle1) + self.smoothing_length(particle2));
+        let radial = particle1.pos - particle2.pos;
+        let gradient = self.pair_gradient(particle1, particle2);
+        // r·∇W is negative, so heat flows from hot to cold
+        4.0 * conductivity1 * conductivity2 / (conductivity1 + conductivity2) * particle2.mass
+            / (particle1.density * particle2.density)
+            * (particle1.temperature - particle2.temperature)
+            * radial.dot(gradient)
+            / (radial.mag_squared() + 0.01 * h * h)
+    }
+
+    fn viscous(&self) -> bool {
+        self.artificial_viscosity.is_some()
+            || self.laminar_viscosity.is_some()
+            || self
+                .materials
+                .values()
+                .any(|material| material.viscosity.is_some())
+    }
+
+    /// Whether any particle's viscosity depends on its shear rate.
+    fn shear_dependent(&self) -> bool {
+        let law = self.laminar_viscosity.iter();
+        let materials = self
+            .materials
+            .values()
+            .filter_map(|material| material.viscosity.as_ref());
+        law.chain(materials).any(|law| !law.is_newtonian())
+    }
+
+    /// The shear rate √(2 D:D) of every coupled particle, from the strain rate D, the symmetric
+    /// part of the SPH estimate ∇v = Σ V_j (v_j - v_i) ⊗ ∇W of the velocity gradient.
+    fn shear_rates(&self, particle_source: &[Particle], pairs: &[(usize, usize)]) -> Vec<f64> {
+        let mut gradients = vec![Matrix3::zero(); self.coupled_particles.len()];
+        for (i, j) in pairs {
+            let particle1 = self.coupled_particles[*i].get(particle_source);
+            let particle2 = self.coupled_particles[*j].get(particle_source);
+            if particle1.density == 0.0 || particle2.density == 0.0 {
+                continue;
+            }
+            let gradient = self.pair_gradient(particle1, particle2);
+            // ∇W and the relative velocity both change sign from one particle to the other
+            let shared = Matrix3::outer_product(particle2.vel - particle1.vel, gradient);
+            gradients[*i] += particle2.mass / particle2.density * shared;
+            gradients[*j] += particle1.mass / particle1.density * shared;
+        }
+
+        gradients
+            .iter()
+            .map(|gradient| {
+                let mut contraction = 0.0;
+                for a in 0..3 {
+                    for b in 0..3 {
+                        let strain_rate = 0.5 * (gradient.0[a][b] + gradient.0[b][a]);
+                        contraction += strain_rate * strain_rate;
+                    }
+                }
+                (2.0 * contraction).sqrt()
+            })
+            .collect()
+    }
+
+    /// The reach of both kernels for a smoothing length h.
+    fn support(&self, h: f64) -> f64 {
+        self.kernel.support(h).max(self.gradient_kernel.support(h))
+    }
+
+    /// Keeps the neighbor list's cutoff at or above the given one, rebuilding it when it falls
+    /// short or when adaptive smoothing lengths have shrunk to well within it.
+    fn update_neighbor_list(&mut self, positions: &[Point3], cutoff: f64) {
+        let stale = match &self.neighbor_list {
+            Some(list) => list.cutoff() < cutoff || list.cutoff() > 2.0 * cutoff,
+            None => true,
+        };
+        if stale {
+            // leave room for the smoothing lengths to grow before the next rebuild
+            let cutoff = if self.adaptive_smoothing.is_some() {
+                1.2 * cutoff
+            } else {
+                cutoff
+            };
+            self.neighbor_list = Some(NeighborList::new(cutoff, 0.1 * cutoff));
+        }
+        self.neighbor_list.as_mut().unwrap().update(positions);
+    }
+
+    /// Solve for the smoothing length of every coupled particle by Newton-Raphson iteration on
+    /// ρ(h) = m (η / h)^d, where ρ(h) is the summed density and η sets the neighbor count.
+    /// The neighbor list is widened, and the solve repeated, if it doesn't reach far enough.
+    fn solve_smoothing_lengths(
+        &mut self,
+        particle_source: &[Particle],
+        positions: &[Point3],
+        masses: &[f64],
+        neighbor_count: f64,
+        dimension: Dimension,
+    ) -> Vec<f64> {
+        let d = dimension.count();
+        let unit_volume = match dimension {
+            Dimension::One => 2.0,
+            Dimension::Two => PI,
+            Dimension::Three => 4.0 / 3.0 * PI,
+        };
+        // the kernel's support is proportional to h, so the neighbor count fixes η
+        let reach = self.kernel.support(1.0);
+        let eta = (neighbor_count / (unit_volume * reach.powi(d))).powf(1.0 / d as f64);
+        let largest_allowed = 10.0 * self.smoothing_radius;
+
+        let mut lengths: Vec<f64> = self
+            .coupled_particles
+            .iter()
+            .map(|reference| {
+                let particle = reference.get(particle_source);
+                if particle.smoothing_length > 0.0 {
+                    particle.smoothing_length
+                } else {
+                    self.smoothing_radius
+                }
+            })
+            .collect();
+
+        for _ in 0..4 {
+            let largest = lengths.iter().fold(0.0, |a: f64, b| a.max(*b));
+            self.update_neighbor_list(positions, self.support(largest));
+            let list = self.neighbor_list.as_ref().unwrap();
+            let reachable = list.cutoff() / self.support(1.0);
+            let mut neighbors = vec![Vec::new(); positions.len()];
+            for (i, j) in list.pairs() {
+                neighbors[*i].push(*j);
+                neighbors[*j].push(*i);
+            }
+
+            let mut covered = true;
+            for (i, length) in lengths.iter_mut().enumerate() {
+                let mut h = *length;
+                for _ in 0..MAX_SMOOTHING_ITERATIONS {
+                    let mut density = masses[i] * self.kernel.w(Vec3::zero(), h);
+                    let mut derivative = masses[i] * self.dw_dh(Vec3::zero(), h, d);
+                    let support = self.kernel.support(h);
+                    for j in &neighbors[i] {
+                        let radial = positions[i] - positions[*j];
+                        if radial.mag_squared() < support * support {
+                            density += masses[*j] * self.kernel.w(radial, h);
+                            derivative += masses[*j] * self.dw_dh(radial, h, d);
+                        }
+                    }
+                    let target = masses[i] * (eta / h).powi(d);
+                    let slope = derivative + d as f64 * target / h;
+                    let step = if slope > 0.0 {
+                        h - (density - target) / slope
+                    } else if density > target {
+                        0.5 * h
+                    } else {
+                        2.0 * h
+                    };
+                    let next = step.clamp(0.5 * h, 2.0 * h).min(largest_allowed);
+                    let converged = (next - h).abs() < SMOOTHING_TOLERANCE * h;
+                    h = next;
+                    if converged {
+                        break;
+                    }
+                }
+                covered &= h <= reachable;
+                *length = h;
+            }
+            if covered {
+                break;
+            }
+        }
+        lengths
+    }
+
+    /// ∂W/∂h, for a kernel of the form W = σ / h^d f(r / h).
+    fn dw_dh(&self, radial: Vec3, h: f64, d: i32) -> f64 {
+        -(d as f64 * self.kernel.w(radial, h) + radial.mag() * self.kernel.div_w(radial, h)) / h
+    }
+
+    /// The forces, and unless only the forces are wanted, the heating and XSPH's smoothing of
+    /// the velocities over a step.
+    fn interact(&mut self, particle_source: &mut [Particle], dt: f64, forces_only: bool) {
+        let positions: Vec<Point3> = self
+            .coupled_particles
+            .iter()
+            .map(|reference| reference.get(particle_source).pos)
+            .collect();
+        let masses: Vec<f64> = self
+            .coupled_particles
+            .iter()
+            .map(|reference| reference.get(particle_source).mass)
+            .collect();
+
+        let lengths = match self.adaptive_smoothing {
+            Some((neighbor_count, dimension)) => self.solve_smoothing_lengths(
+                particle_source,
+                &positions,
+                &masses,
+                neighbor_count,
+                dimension,
+            ),
+            None => {
+                let support = self.support(self.smoothing_radius);
+                self.update_neighbor_list(&positions, support);
+                vec![self.smoothing_radius; positions.len()]
+            }
+        };
+        // the list reaches past the kernels' support by its skin, and adaptive smoothing lengths
+        // leave it reaching as far as the largest of them, so only the pairs within reach of
+        // their own smoothing lengths are kept. Kernels such as the gaussian never quite reach
+        // zero, and the sums would otherwise depend on when the list was last built.
+        let pairs: Vec<(usize, usize)> = self
+            .neighbor_list
+            .as_ref()
+            .unwrap()
+            .pairs()
+            .iter()
+            .filter(|(i, j)| {
+                let reach = self.support(lengths[*i].max(lengths[*j]));
+                (positions[*i] - positions[*j]).mag_squared() < reach * reach
+            })
+            .copied()
+            .collect();
+        let pairs = &pairs;
+
+        // the mass each neighbor counts for in a particle's density: its own, or in the
+        // multiphase formulation the particle's
+        let multiphase = self.multiphase;
+        let counted = |i: usize, j: usize| if multiphase { masses[i] } else { masses[j] };
+
+        // density summation, including each particle's own contribution, with each particle's
+        // density taken over its own smoothing length
+        let mut densities: Vec<f64> = masses
+            .iter()
+            .zip(&lengths)
+            .map(|(mass, h)| mass * self.kernel.w(Vec3::zero(), *h))
+            .collect();
+        for (i, j) in pairs {
+            let radial = positions[*i] - positions[*j];
+            let w1 = self.kernel.w(radial, lengths[*i]);
+            let w2 = if lengths[*i] == lengths[*j] {
+                w1
+            } else {
+                self.kernel.w(radial, lengths[*j])
+            };
+            densities[*i] += counted(*i, *j) * w1;
+            densities[*j] += counted(*j, *i) * w2;
+        }
+
+        // the boundary sees particles with a material at their material's rest density
+        let rest_densities: Vec<Option<f64>> = match self.boundary {
+            Some(_) => self
+                .coupled_particles
+                .iter()
+                .map(|reference| {
+                    self.material_of(reference.get(particle_source))
+                        .map(|material| material.rest_density)
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        if let Some(boundary) = &mut self.boundary {
+            let kernels = (&*self.kernel, &*self.gradient_kernel);
+            boundary.prepare(
+                particle_source,
+                &positions,
+                &lengths,
+                &rest_densities,
+                self.smoothing_radius,
+                kernels,
+            );
+            boundary.add_densities(
+                particle_source,
+                &positions,
+                &lengths,
+                &*self.kernel,
+                &mut densities,
+            );
+        }
+
+        // the grad-h corrections Ω = 1 - ∂h/∂ρ Σ m ∂W/∂h, which are 1 for a fixed smoothing length
+        let mut corrections = vec![1.0; positions.len()];
+        if let Some((_, dimension)) = self.adaptive_smoothing {
+            let d = dimension.count();
+            let mut derivatives: Vec<f64> = masses
+                .iter()
+                .zip(&lengths)
+                .map(|(mass, h)| mass * self.dw_dh(Vec3::zero(), *h, d))
+                .collect();
+            for (i, j) in pairs {
+                let radial = positions[*i] - positions[*j];
+                derivatives[*i] += counted(*i, *j) * self.dw_dh(radial, lengths[*i], d);
+                derivatives[*j] += counted(*j, *i) * self.dw_dh(radial, lengths[*j], d);
+            }
+            for (index, correction) in corrections.iter_mut().enumerate() {
+                let omega =
+                    1.0 + lengths[index] / (d as f64 * densities[index]) * derivatives[index];
+                // guards against the rare particle whose neighbors sit right at the kernel's edge
+                if omega > 0.1 {
+                    *correction = omega;
+                }
+            }
+        }
+
+        let mut pressures = Vec::with_capacity(densities.len());
+        let mut sound_speeds = Vec::with_capacity(densities.len());
+        for (index, reference) in self.coupled_particles.iter().enumerate() {
+            let particle = reference.get_mut(particle_source);
+            particle.density = densities[index];
+            if self.adaptive_smoothing.is_some() {
+                particle.smoothing_length = lengths[index];
+            }
+            let state_equation = self.state_equation_of(particle);
+            pressures.push(state_equation.pressure(particle));
+            if self.artificial_viscosity.is_some() {
+                sound_speeds.push(state_equation.sound_speed(particle));
+            }
+        }
+
+        let shear_rates = if self.shear_dependent() {
+            self.shear_rates(particle_source, pairs)
+        } else {
+            vec![0.0; self.coupled_particles.len()]
+        };
+        let viscosities: Vec<f64> = self
+            .coupled_particles
+            .iter()
+            .zip(&shear_rates)
+            .map(|(reference, shear_rate)| {
+                self.viscosity_law_of(reference.get(particle_source))
+                    .map_or(0.0, |law| law.viscosity(*shear_rate))
+            })
+            .collect();
+
+        let switches = if self.balsara && self.artificial_viscosity.is_some() {
+            self.balsara_switches(particle_source, pairs, &sound_speeds)
+        } else {
+            vec![1.0; self.coupled_particles.len()]
+        };
+
+        // the rate of change of each particle's specific internal energy
+        let mut heating = vec![0.0; self.coupled_particles.len()];
+        for (i, j) in pairs {
+            let (ref1, ref2) = (self.coupled_particles[*i], self.coupled_particles[*j]);
+            let (particle1, particle2) = (ref1.get(particle_source), ref2.get(particle_source));
+            let mut force = self.symmetric_pressure_force(
+                particle1,
+                particle2,
+                (pressures[*i], pressures[*j]),
+                (corrections[*i], corrections[*j]),
+            );
+            let mut viscous_force = Vec3::zero();
+            if self.viscous() {
+                viscous_force = self.viscous_force(
+                    particle1,
+                    particle2,
+                    (
+                        sound_speeds.get(*i).copied().unwrap_or(0.0),
+                        sound_speeds.get(*j).copied().unwrap_or(0.0),
+                    ),
+                    (switches[*i], switches[*j]),
+                    (viscosities[*i], viscosities[*j]),
+                );
+                force += viscous_force;
+            }
+
+            if self.specific_heat.is_some() {
+                let relative_velocity = particle1.vel - particle2.vel;
+                // each particle does PdV work in proportion to its own pressure, which sums to
+                // exactly the work done by the symmetric pressure force
+                let radial = positions[*i] - positions[*j];
+                let compression1 =
+                    relative_velocity.dot(self.gradient_kernel.grad_w(radial, lengths[*i]));
+                let compression2 =
+                    relative_velocity.dot(self.gradient_kernel.grad_w(radial, lengths[*j]));
+                heating[*i] += counted(*i, *j) * pressures[*i]
+                    / (corrections[*i] * densities[*i].powi(2))
+                    * compression1;
+                heating[*j] += counted(*j, *i) * pressures[*j]
+                    / (corrections[*j] * densities[*j].powi(2))
+                    * compression2;
+
+                // the kinetic energy taken by viscosity is shared equally as heat
+                let dissipated = -relative_velocity.dot(viscous_force);
+                heating[*i] += 0.5 * dissipated / masses[*i];
+                heating[*j] += 0.5 * dissipated / masses[*j];
+
+                if self.conductivity.is_some() {
+                    let conduction = self.conduction(particle1, particle2);
+                    heating[*i] += conduction;
+                    heating[*j] -= masses[*i] / masses[*j] * conduction;
+                }
+            }
+
+            ref1.get_mut(particle_source).add_force(force);
+            ref2.get_mut(particle_source).add_force(-force);
+        }
+
+        if let Some(boundary) = &self.boundary {
+            let pressure_terms: Vec<f64> = (0..densities.len())
+                .map(|index| pressures[index] / (corrections[index] * densities[index].powi(2)))
+                .collect();
+            boundary.apply_forces(
+                particle_source,
+                &self.coupled_particles,
+                &lengths,
+                &pressure_terms,
+                &*self.gradient_kernel,
+                &mut heating,
+            );
+        }
+
+        if forces_only {
+            return;
+        }
+        if let Some(specific_heat) = self.specific_heat {
+            for (reference, rate) in self.coupled_particles.iter().zip(heating) {
+                let particle = reference.get_mut(particle_source);
+                particle.temperature = (particle.temperature + dt * rate / specific_heat).max(0.0);
+            }
+        }
+
+        if let Some(epsilon) = self.xsph {
+            let mut corrections = vec![Vec3::zero(); self.coupled_particles.len()];
+            for (i, j) in pairs {
+                let (particle1, particle2) = (
+                    self.coupled_particles[*i].get(particle_source),
+                    self.coupled_particles[*j].get(particle_source),
+                );
+                let radial = positions[*i] - positions[*j];
+                let w =
+                    0.5 * (self.kernel.w(radial, lengths[*i]) + self.kernel.w(radial, lengths[*j]));
+                let shared = epsilon * w / (0.5 * (densities[*i] + densities[*j]))
+                    * (particle2.vel - particle1.vel);
+                corrections[*i] += masses[*j] * shared;
+                corrections[*j] -= masses[*i] * shared;
+            }
+            for (reference, correction) in self.coupled_particles.iter().zip(corrections) {
+                reference.get_mut(particle_source).vel += correction;
+            }
+        }
+    }
 }
 
 impl Interaction for SphParameters {
-    fn handle(&mut self, _particle_source: &mut [Particle], _dt: f64) {
-        todo!()
+    fn handle(&mut self, particle_source: &mut [Particle], dt: f64) {
+        self.interact(particle_source, dt, false);
+    }
+
+    fn add_forces(&mut self, particle_source: &mut [Particle]) {
+        self.interact(particle_source, 0.0, true);
     }
 }
 
+/// The pressure and viscous forces alone, for use with
+/// [`crate::interaction::pair_wise::PairWiseForceParameters`]. The densities have to be kept up
+/// to date by something else, neither Balsara's switch nor XSPH are applied, and viscosity laws
+/// are taken at rest, since the shear rates aren't known.
 impl PairWiseForce for SphParameters {
-    fn force(&self, particle1: &Particle, particle2: &Particle) -> Option<Vec3> {
-        todo!()
+    fn force(&self, particle1: &Particle, particle2: &Particle, radial: Vec3) -> Option<Vec3> {
+        let h = self
+            .smoothing_length(particle1)
+            .max(self.smoothing_length(particle2));
+        if radial.mag() >= self.gradient_kernel.support(h) {
+            None
+        } else {
+            let mut force = self.pressure_force(particle1, particle2);
+            if self.viscous() && particle1.density != 0.0 && particle2.density != 0.0 {
+                let sound_speeds = (
+                    self.state_equation_of(particle1).sound_speed(particle1),
+                    self.state_equation_of(particle2).sound_speed(particle2),
+                );
+                let viscosities = (
+                    self.viscosity_law_of(particle1)
+                        .map_or(0.0, |law| law.viscosity(0.0)),
+                    self.viscosity_law_of(particle2)
+                        .map_or(0.0, |law| law.viscosity(0.0)),
+                );
+                force +=
+                    self.viscous_force(particle1, particle2, sound_speeds, (1.0, 1.0), viscosities);
+            }
+            Some(force)
+        }
     }
 }
 
-//---------------------------------------------------------------------------------------------------//
-// Builtin Kernels
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        algorithms::Random,
+        sph::{
+            kernels::CubicSplineKernel,
+            state_equations::{IdealGas, Isothermal, Tait},
+        },
+    };
 
-pub struct GaussianKernel;
-impl SphKernel for GaussianKernel {
-    fn w(&self, r: Point3, h: f64) -> f64 {
-        (h.powi(3) * PI.powi(3).sqrt()).powi(-1) * (-r.mag_squared() / h.powi(2)).exp()
+    /// A cube of particles of unit mass on a lattice, and references to them.
+    fn lattice(side: usize, spacing: f64) -> (Vec<Particle>, Vec<ParticleReference>) {
+        let mut particles = Vec::new();
+        for x in 0..side {
+            for y in 0..side {
+                for z in 0..side {
+                    let pos = spacing * Vec3::new(x as f64, y as f64, z as f64);
+                    particles.push(
+                        Particle::new()
+                            .id(particles.len() as u32)
+                            .pos(pos)
+                            .mass(1.0),
+                    );
+                }
+            }
+        }
+        let references = (0..particles.len())
+            .map(|index| ParticleReference::new(particles[index].id, index))
+            .collect();
+        (particles, references)
     }
 
-    fn grad_w(&self, r: Point3, h: f64) -> Vec3 {
-        -2. * (h.powi(5) * PI.powi(3).sqrt()).powi(-1) * (-r.mag_squared() / h.powi(2)).exp() * r
+    /// A clump of particles of assorted masses, jostled about.
+    fn clump() -> (Vec<Particle>, Vec<ParticleReference>) {
+        let mut random = Random::new(8);
+        let particles: Vec<Particle> = (0..300)
+            .map(|id| {
+                let mut uniform =
+                    || Vec3::new(random.uniform(), random.uniform(), random.uniform());
+                let (pos, vel) = (uniform(), uniform() - 0.5 * Vec3::new(1.0, 1.0, 1.0));
+                Particle::new()
+                    .id(id)
+                    .pos(pos)
+                    .vel(vel)
+                    .mass(0.002 + 0.002 * random.uniform())
+            })
+            .collect();
+        let references = (0..particles.len())
+            .map(|index| ParticleReference::new(particles[index].id, index))
+            .collect();
+        (particles, references)
     }
 
-    fn div_w(&self, r: Point3, h: f64) -> f64 {
-        todo!()
+    fn total_force(particle: &Particle) -> Vec3 {
+        particle
+            .forces
+            .iter()
+            .fold(Vec3::zero(), |total, force| total + *force)
     }
 
-    fn laplace_w(&self, r: Point3, h: f64) -> f64 {
-        todo!()
+    /// The rate at which the pressure forces do work on a gas of unequal masses, and the rate
+    /// at which its internal energy changes, which should cancel.
+    fn work_and_heating(multiphase: bool) -> (f64, f64) {
+        let mut random = Random::new(3);
+        let mut particles: Vec<Particle> = (0..200)
+            .map(|id| {
+                let mut uniform =
+                    || Vec3::new(random.uniform(), random.uniform(), random.uniform());
+                let (pos, vel) = (4.0 * uniform(), uniform() - 0.5 * Vec3::new(1.0, 1.0, 1.0));
+                Particle::new()
+                    .id(id)
+                    .pos(pos)
+                    .vel(vel)
+                    .mass(1.0 + 4.0 * random.uniform())
+                    .temperature(1.0 + random.uniform())
+            })
+            .collect();
+        let references: Vec<ParticleReference> = (0..particles.len())
+            .map(|index| ParticleReference::new(particles[index].id, index))
+            .collect();
+        let mut sph = SphParameters::new(0.6, IdealGas::new(5.0 / 3.0, 1.0))
+            .energy_equation(1.5)
+            .with_particles(&references);
+        if multiphase {
+            sph = sph.multiphase();
+        }
+
+        let (dt, temperatures): (f64, Vec<f64>) = (
+            1e-6,
+            particles
+                .iter()
+                .map(|particle| particle.temperature)
+                .collect(),
+        );
+        sph.handle(&mut particles, dt);
+        let work: f64 = particles
+            .iter()
+            .map(|particle| {
+                let force = particle
+                    .forces
+                    .iter()
+                    .fold(Vec3::zero(), |total, force| total + *force);
+                force.dot(particle.vel)
+            })
+            .sum();
+        let heating: f64 = particles
+            .iter()
+            .zip(temperatures)
+            .map(|(particle, temperature)| {
+                particle.mass * 1.5 * (particle.temperature - temperature) / dt
+            })
+            .sum();
+        (work, heating)
     }
-}
 
-pub struct Poly6Kernel;
-impl SphKernel for Poly6Kernel {
-    fn w(&self, r: Point3, h: f64) -> f64 {
-        315. * (64. * PI * h.powi(9)).powi(-1) * (h.powi(2) - r.mag_squared()).powi(3)
+    #[test]
+    fn pressure_work_heats_unequal_masses() {
+        for multiphase in [false, true] {
+            let (work, heating) = work_and_heating(multiphase);
+            assert!(work.abs() > 0.0);
+            assert!((work + heating).abs() < 1e-6 * work.abs());
+        }
     }
+    #[test]
+    fn density_summation_recovers_a_lattice_density() {
+        let (mut particles, references) = lattice(12, 0.1);
+        SphParameters::new(0.13, Tait::new(1000.0, 10.0))
+            .kernel(CubicSplineKernel::new(Dimension::Three))
+            .gradient_kernel(CubicSplineKernel::new(Dimension::Three))
+            .with_particles(&references)
+            .handle(&mut particles, 0.0);
 
-    fn grad_w(&self, r: Point3, h: f64) -> Vec3 {
-        todo!()
+        // far enough from the faces to have a full set of neighbors
+        let interior = |pos: Point3| {
+            [pos.x, pos.y, pos.z]
+                .iter()
+                .all(|x| (0.3..=0.8).contains(x))
+        };
+        let mut checked = 0;
+        for particle in particles.iter().filter(|particle| interior(particle.pos)) {
+            assert!((particle.density - 1000.0).abs() < 0.01 * 1000.0);
+            checked += 1;
+        }
+        assert!(checked > 0);
+        // and the faces, with fewer neighbors, come out thinner
+        assert!(particles[0].density < 0.5 * 1000.0);
     }
 
-    fn div_w(&self, r: Point3, h: f64) -> f64 {
-        todo!()
+    #[test]
+    fn pressure_and_viscous_forces_conserve_momentum() {
+        let (mut particles, references) = clump();
+        SphParameters::new(0.15, Tait::new(1.0, 10.0))
+            .artificial_viscosity(1.0, 2.0)
+            .laminar_viscosity(0.01)
+            .with_particles(&references)
+            .handle(&mut particles, 0.0);
+
+        let (mut force, mut torque, mut scale) = (Vec3::zero(), Vec3::zero(), 0.0);
+        for particle in &particles {
+            let total = total_force(particle);
+            force += total;
+            torque += particle.pos.cross(total);
+            scale += total.mag();
+        }
+        assert!(scale > 0.0);
+        assert!(force.mag() < 1e-12 * scale);
+        // the pressure forces are central, the viscous ones aren't quite, so only the linear
+        // momentum is exact
+        assert!(torque.mag() < 1e-2 * scale);
     }
 
-    fn laplace_w(&self, r: Point3, h: f64) -> f64 {
-        todo!()
+    #[test]
+    fn pair_wise_forces_match_the_interaction() {
+        let (mut particles, references) = clump();
+        let mut sph = SphParameters::new(0.15, Tait::new(1.0, 10.0)).with_particles(&references);
+        sph.handle(&mut particles, 0.0);
+
+        for (i, particle) in particles.iter().enumerate() {
+            let mut expected = Vec3::zero();
+            for (j, other) in particles.iter().enumerate() {
+                if i != j {
+                    if let Some(force) = sph.force(particle, other, other.pos - particle.pos) {
+                        expected += force;
+                    }
+                }
+            }
+            assert!((total_force(particle) - expected).mag() < 1e-10 * (1.0 + expected.mag()));
+        }
     }
-}
 
-pub struct SpikyKernel;
-impl SphKernel for SpikyKernel {
-    fn w(&self, r: Point3, h: f64) -> f64 {
-        //15. * (PI * h.powi(6)).powi(-1) * (h - r.mag()).powi(3)
-        todo!()
+    #[test]
+    fn adaptive_smoothing_holds_the_neighbor_count() {
+        let (mut particles, references) = clump();
+        // spread the clump out unevenly, so the smoothing lengths have to differ
+        for particle in particles.iter_mut() {
+            particle.pos = particle.pos * particle.pos.mag();
+        }
+        let neighbor_count = 50.0;
+        let mut sph = SphParameters::new(0.15, Tait::new(1.0, 10.0))
+            .kernel(CubicSplineKernel::new(Dimension::Three))
+            .gradient_kernel(CubicSplineKernel::new(Dimension::Three))
+            .adaptive_smoothing(neighbor_count, Dimension::Three)
+            .with_particles(&references);
+        sph.handle(&mut particles, 0.0);
+
+        let eta = (neighbor_count / (4.0 / 3.0 * PI * 8.0)).cbrt();
+        for particle in &particles {
+            let h = particle.smoothing_length;
+            let target = particle.mass * (eta / h).powi(3);
+            assert!((particle.density - target).abs() < 1e-3 * target);
+        }
+        let lengths: Vec<f64> = particles
+            .iter()
+            .map(|particle| particle.smoothing_length)
+            .collect();
+        let (shortest, longest) = lengths
+            .iter()
+            .fold((f64::MAX, 0.0_f64), |(a, b), h| (a.min(*h), b.max(*h)));
+        assert!(longest > 2.0 * shortest);
     }
 
-    fn grad_w(&self, r: Point3, h: f64) -> Vec3 {
-        let mag = r.mag();
-        -45. * (PI * h.powi(6)).powi(-1) * (h - mag).powi(2) * mag.powi(-1) * r
+    #[test]
+    fn grad_h_forces_are_the_internal_energy_gradient() {
+        let (mut particles, references) = clump();
+        for particle in particles.iter_mut() {
+            particle.entropy = 1.0 + particle.pos.x;
+        }
+        let adiabatic_index = 5.0 / 3.0;
+        let sph = || {
+            SphParameters::new(0.15, IdealGas::new(adiabatic_index, 1.0).from_entropy())
+                .kernel(CubicSplineKernel::new(Dimension::Three))
+                .gradient_kernel(CubicSplineKernel::new(Dimension::Three))
+                .adaptive_smoothing(50.0, Dimension::Three)
+                .with_particles(&references)
+        };
+        sph().handle(&mut particles, 0.0);
+        let lengths: Vec<f64> = particles
+            .iter()
+            .map(|particle| particle.smoothing_length)
+            .collect();
+
+        // the internal energy Σ m A ρ^(γ-1) / (γ - 1), with the smoothing lengths solved afresh
+        let internal_energy = |positions: &[Point3]| {
+            let mut moved: Vec<Particle> = particles
+                .iter()
+                .zip(positions)
+                .zip(&lengths)
+                .map(|((particle, pos), h)| {
+                    Particle::new()
+                        .id(particle.id)
+                        .pos(*pos)
+                        .mass(particle.mass)
+                        .entropy(particle.entropy)
+                        .smoothing_length(*h)
+                })
+                .collect();
+            sph().handle(&mut moved, 0.0);
+            moved
+                .iter()
+                .map(|particle| {
+                    particle.mass * particle.entropy * particle.density.powf(adiabatic_index - 1.0)
+                        / (adiabatic_index - 1.0)
+                })
+                .sum::<f64>()
+        };
+
+        let positions: Vec<Point3> = particles.iter().map(|particle| particle.pos).collect();
+        let step = 1e-4 * 0.15;
+        for index in [0, 40, 80, 120, 160] {
+            let force = total_force(&particles[index]);
+            for axis in [Vec3::x_hat(), Vec3::y_hat(), Vec3::z_hat()] {
+                let shifted = |sign: f64| {
+                    let mut moved = positions.clone();
+                    moved[index] += sign * step * axis;
+                    internal_energy(&moved)
+                };
+                let expected = -(shifted(1.0) - shifted(-1.0)) / (2.0 * step);
+                assert!(
+                    (force.dot(axis) - expected).abs() < 1e-3 * force.mag(),
+                    "{} against {}",
+                    force.dot(axis),
+                    expected
+                );
+            }
+        }
     }
 
-    fn div_w(&self, r: Point3, h: f64) -> f64 {
-        todo!()
+    #[test]
+    fn viscous_heating_balances_the_work_done() {
+        let (mut particles, references) = clump();
+        for particle in particles.iter_mut() {
+            particle.temperature = 1.0;
+        }
+        let dt = 1e-6;
+        SphParameters::new(0.15, IdealGas::new(5.0 / 3.0, 1.0))
+            .artificial_viscosity(1.0, 2.0)
+            .laminar_viscosity(0.01)
+            .energy_equation(1.5)
+            .with_particles(&references)
+            .handle(&mut particles, dt);
+
+        let work: f64 = particles
+            .iter()
+            .map(|particle| total_force(particle).dot(particle.vel))
+            .sum();
+        let heating: f64 = particles
+            .iter()
+            .map(|particle| particle.mass * 1.5 * (particle.temperature - 1.0) / dt)
+            .sum();
+        assert!(work.abs() > 0.0);
+        assert!((work + heating).abs() < 1e-6 * work.abs());
     }
 
-    fn laplace_w(&self, r: Point3, h: f64) -> f64 {
-        todo!()
+    /// The rate each particle's temperature changes by conduction alone, for particles at rest
+    /// on a lattice with the given temperatures.
+    fn conduction_rates(
+        temperature: impl Fn(Point3) -> f64,
+        insulated: impl Fn(Point3) -> bool,
+    ) -> (Vec<Particle>, Vec<f64>) {
+        let (mut particles, references) = lattice(14, 0.1);
+        for particle in particles.iter_mut() {
+            particle.temperature = temperature(particle.pos);
+            if insulated(particle.pos) {
+                particle.group = 1;
+            }
+        }
+        let temperatures: Vec<f64> = particles
+            .iter()
+            .map(|particle| particle.temperature)
+            .collect();
+        let dt = 1e-6;
+        SphParameters::new(0.13, Isothermal::new(1.0))
+            .kernel(CubicSplineKernel::new(Dimension::Three))
+            .gradient_kernel(CubicSplineKernel::new(Dimension::Three))
+            .energy_equation(2.0)
+            .heat_conduction(3.0)
+            .group_conductivity(1, 0.0)
+            .with_particles(&references)
+            .handle(&mut particles, dt);
+        let rates = particles
+            .iter()
+            .zip(temperatures)
+            .map(|(particle, temperature)| (particle.temperature - temperature) / dt)
+            .collect();
+        (particles, rates)
     }
-}
 
-pub struct ViscosityKernel;
-impl SphKernel for ViscosityKernel {
-    fn w(&self, r: Point3, h: f64) -> f64 {
-        todo!()
+    #[test]
+    fn conduction_diffuses_heat_and_conserves_it() {
+        // far enough from the faces that their neighbors have full sets of neighbors too
+        let interior = |pos: Point3| {
+            [pos.x, pos.y, pos.z]
+                .iter()
+                .all(|x| (0.55..=0.75).contains(x))
+        };
+
+        // a linear profile carries a steady flow of heat through the interior
+        let (particles, rates) = conduction_rates(|pos| 1.0 + pos.x, |_| false);
+        for (particle, rate) in particles.iter().zip(&rates) {
+            if interior(particle.pos) {
+                assert!(rate.abs() < 1e-9, "{}", rate);
+            }
+        }
+
+        // and a quadratic one heats at κ ∇²T / (ρ c)
+        let (particles, rates) = conduction_rates(|pos| 1.0 + pos.x * pos.x, |_| false);
+        let mut total = 0.0;
+        for (particle, rate) in particles.iter().zip(&rates) {
+            if interior(particle.pos) {
+                let expected = 3.0 * 2.0 / (particle.density * 2.0);
+                assert!(
+                    (rate - expected).abs() < 0.05 * expected,
+                    "{} {}",
+                    rate,
+                    expected
+                );
+            }
+            total += particle.mass * 2.0 * rate;
+        }
+        let scale: f64 = particles
+            .iter()
+            .zip(&rates)
+            .map(|(particle, rate)| (particle.mass * rate).abs())
+            .sum();
+        assert!(total.abs() < 1e-6 * scale);
     }
 
-    fn grad_w(&self, r: Point3, h: f64) -> Vec3 {
-        todo!()
+    #[test]
+    fn groups_without_conductivity_are_insulated() {
+        let insulated = |pos: Point3| pos.x > 0.55;
+        let (particles, rates) =
+            conduction_rates(|pos| if pos.x < 0.55 { 2.0 } else { 1.0 }, insulated);
+        for (particle, rate) in particles.iter().zip(&rates) {
+            if insulated(particle.pos) {
+                assert_eq!(*rate, 0.0);
+            }
+        }
+        // while the hot particles are left to share their heat among themselves
+        assert!(rates.iter().all(|rate| rate.abs() < 1e-9));
+
+        let (particles, rates) =
+            conduction_rates(|pos| if pos.x < 0.55 { 2.0 } else { 1.0 }, |_| false);
+        for (particle, rate) in particles.iter().zip(&rates) {
+            let beside_the_front = (particle.pos.x - 0.55).abs() < 0.1;
+            if beside_the_front {
+                assert!((particle.pos.x < 0.55) == (*rate < 0.0));
+            }
+        }
     }
 
-    fn div_w(&self, r: Point3, h: f64) -> f64 {
-        todo!()
+    #[test]
+    fn viscosity_only_takes_kinetic_energy() {
+        let (mut particles, references) = clump();
+        let mut sph = SphParameters::new(0.15, Tait::new(1.0, 10.0))
+            .artificial_viscosity(1.0, 2.0)
+            .laminar_viscosity(0.01)
+            .with_particles(&references);
+        sph.handle(&mut particles, 0.0);
+
+        let (mut approaching, mut receding) = (0, 0);
+        for (i, particle1) in particles.iter().enumerate() {
+            for particle2 in &particles[i + 1..] {
+                let radial = particle1.pos - particle2.pos;
+                if radial.mag() >= sph.support(0.15) {
+                    continue;
+                }
+                let relative_velocity = particle1.vel - particle2.vel;
+                let force =
+                    sph.viscous_force(particle1, particle2, (10.0, 10.0), (1.0, 1.0), (0.01, 0.01));
+                assert!(relative_velocity.dot(force) <= 0.0);
+
+                let artificial =
+                    sph.viscous_force(particle1, particle2, (10.0, 10.0), (1.0, 1.0), (0.0, 0.0));
+                if relative_velocity.dot(radial) < 0.0 {
+                    assert!(relative_velocity.dot(artificial) < 0.0);
+                    approaching += 1;
+                } else {
+                    // receding particles feel no artificial viscosity at all
+                    assert_eq!(artificial.mag(), 0.0);
+                    receding += 1;
+                }
+            }
+        }
+        assert!(approaching > 0 && receding > 0);
     }
 
-    fn laplace_w(&self, r: Point3, h: f64) -> f64 {
-        45. * (PI * h.powi(6)).powi(-1) * (h - r.mag())
+    #[test]
+    fn balsara_switch_separates_compression_from_rotation() {
+        let (mut particles, references) = lattice(10, 0.1);
+        let mut sph = SphParameters::new(0.13, Tait::new(1000.0, 10.0))
+            .kernel(CubicSplineKernel::new(Dimension::Three))
+            .gradient_kernel(CubicSplineKernel::new(Dimension::Three))
+            .artificial_viscosity(1.0, 2.0)
+            .balsara()
+            .with_particles(&references);
+        sph.handle(&mut particles, 0.0);
+        let pairs = sph.neighbor_list.as_ref().unwrap().pairs().to_vec();
+        let sound_speeds = vec![10.0; particles.len()];
+        let center = Point3::new(0.45, 0.45, 0.45);
+        let interior = |pos: Point3| (pos - center).mag() < 0.2;
+
+        for particle in particles.iter_mut() {
+            particle.vel = center - particle.pos;
+        }
+        let switches = sph.balsara_switches(&particles, &pairs, &sound_speeds);
+        for (particle, switch) in particles.iter().zip(&switches) {
+            if interior(particle.pos) {
+                assert!(*switch > 0.99, "{} under compression", switch);
+            }
+        }
+
+        let spin = Vec3::new(0.0, 0.0, 1.0);
+        for particle in particles.iter_mut() {
+            particle.vel = spin.cross(particle.pos - center);
+        }
+        let switches = sph.balsara_switches(&particles, &pairs, &sound_speeds);
+        for (particle, switch) in particles.iter().zip(&switches) {
+            if interior(particle.pos) {
+                assert!(*switch < 0.01, "{} under rotation", switch);
+            }
+        }
+    }
+
+    #[test]
+    fn adding_forces_alone_leaves_the_velocities_and_temperatures() {
+        let sph = |references: &[ParticleReference]| {
+            SphParameters::new(0.15, Tait::new(1.0, 10.0))
+                .artificial_viscosity(1.0, 2.0)
+                .energy_equation(1.5)
+                .xsph(0.5)
+                .with_particles(references)
+        };
+        let (mut stepped, references) = clump();
+        sph(&references).handle(&mut stepped, 0.01);
+        let (mut refreshed, references) = clump();
+        sph(&references).add_forces(&mut refreshed);
+
+        let (untouched, _) = clump();
+        for ((stepped, refreshed), untouched) in stepped.iter().zip(&refreshed).zip(&untouched) {
+            assert!((total_force(stepped) - total_force(refreshed)).mag() < 1e-12);
+            assert!((refreshed.vel - untouched.vel).mag() == 0.0);
+            assert!(refreshed.temperature == untouched.temperature);
+        }
+        // while a step smooths the velocities and heats the particles
+        assert!(stepped
+            .iter()
+            .zip(&untouched)
+            .any(|(stepped, untouched)| (stepped.vel - untouched.vel).mag() > 0.0));
+        assert!(stepped
+            .iter()
+            .zip(&untouched)
+            .any(|(stepped, untouched)| stepped.temperature != untouched.temperature));
+    }
+
+    #[test]
+    fn xsph_conserves_momentum_and_leaves_uniform_flow_alone() {
+        let (mut particles, references) = clump();
+        let momentum = |particles: &[Particle]| {
+            particles.iter().fold(Vec3::zero(), |total, particle| {
+                total + particle.mass * particle.vel
+            })
+        };
+        let before = momentum(&particles);
+        let speeds: f64 = particles.iter().map(|particle| particle.vel.mag()).sum();
+        SphParameters::new(0.15, Tait::new(1.0, 10.0))
+            .xsph(0.5)
+            .with_particles(&references)
+            .handle(&mut particles, 0.0);
+        assert!((momentum(&particles) - before).mag() < 1e-12 * before.mag().max(1.0));
+        // and the velocities are smoothed, not left as they were
+        let smoothed: f64 = particles.iter().map(|particle| particle.vel.mag()).sum();
+        assert!(smoothed < speeds);
+
+        let drift = Vec3::new(0.3, -0.2, 0.1);
+        for particle in particles.iter_mut() {
+            particle.vel = drift;
+        }
+        SphParameters::new(0.15, Tait::new(1.0, 10.0))
+            .xsph(0.5)
+            .with_particles(&references)
+            .handle(&mut particles, 0.0);
+        for particle in &particles {
+            assert!((particle.vel - drift).mag() < 1e-12);
+        }
     }
 }
 
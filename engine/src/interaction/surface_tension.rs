@@ -0,0 +1,578 @@
+//! Surface tension and adhesion for SPH fluids.
+//!
+//! Two models are offered. Akinci et al. (2013) pull neighboring fluid particles together with
+//! a cohesion spline and minimize the surface area by pulling along differences in surface
+//! normals, which keeps thin sheets and small droplets stable. The continuum surface force
+//! (CSF) model of Brackbill et al., with Morris' (2000) estimate of the curvature, instead treats
+//! each group as a color field and pushes the surface along its normal in proportion to its
+//! curvature.
+//! Either way, fluid can be made to stick to solid boundary particles with Akinci's adhesion term.
+//!
+//! Densities are not computed here. They are read from the particles, so this is meant to run
+//! after something that stores them, such as [`crate::interaction::sph::SphParameters`].
+
+//---------------------------------------------------------------------------------------------------//
+use std::collections::HashMap;
+
+use crate::{
+    collision::neighbor_list::NeighborList,
+    interaction::Interaction,
+    math::{Point3, Vec3, PI},
+    particle::{Particle, ParticleReference},
+    sph::{kernels::GaussianKernel, SphKernel},
+};
+
+//---------------------------------------------------------------------------------------------------//
+
+pub enum SurfaceTensionModel {
+    /// Cohesion and curvature forces, relative to the fluid's rest density. The cohesion grows
+    /// with the product of the masses and the curvature force only with the mass, so the balance
+    /// struck by a single γ depends on the units. Akinci's values assume water in SI units, where
+    /// the cohesion dominates.
+    Akinci { rest_density: f64 },
+    /// The continuum surface force. Normals shorter than the threshold (in inverse units of
+    /// length) are taken to be inside the fluid, where the curvature estimate is just noise.
+    Csf { normal_threshold: f64 },
+}
+
+pub struct SurfaceTension {
+    coupled_particles: Vec<ParticleReference>,
+    boundary_particles: Vec<ParticleReference>,
+    model: SurfaceTensionModel,
+    kernel: Box<dyn SphKernel>,
+    smoothing_radius: f64,
+
+    // group -> tension coefficient
+    tension: HashMap<u32, f64>,
+    // (fluid group, boundary group) -> adhesion coefficient
+    adhesion: HashMap<(u32, u32), f64>,
+
+    neighbor_list: Option<NeighborList>,
+}
+
+impl SurfaceTension {
+    /// Defaults to the gaussian kernel, to match [`crate::interaction::sph::SphParameters`].
+    pub fn new(smoothing_radius: f64, model: SurfaceTensionModel) -> SurfaceTension {
+        SurfaceTension {
+            coupled_particles: Vec::new(),
+            boundary_particles: Vec::new(),
+            model,
+            kernel: Box::new(GaussianKernel),
+            smoothing_radius,
+            tension: HashMap::new(),
+            adhesion: HashMap::new(),
+            neighbor_list: None,
+        }
+    }
+
+    /// The kernel whose gradient (and, for CSF, Laplacian) measures the surface. It also sets
+    /// the reach of the cohesion and adhesion splines.
+    pub fn kernel(mut self, kernel: impl SphKernel + 'static) -> SurfaceTension {
+        self.kernel = Box::new(kernel);
+        self.neighbor_list = None;
+        self
+    }
+
+    /// The tension between fluid particles of the given group. With the Akinci model this is the
+    /// dimensionless γ, and with CSF it is the physical coefficient σ. Particles of different
+    /// groups don't feel each other's tension, so immiscible fluids each keep their own surface.
+    /// Strong tension stirs up spurious currents near the surface, which take a good deal of
+    /// viscosity to settle.
+    pub fn tension(mut self, group: u32, coefficient: f64) -> SurfaceTension {
+        self.tension.insert(group, coefficient);
+        self
+    }
+
+    /// How strongly fluid of `fluid_group` is drawn to boundary particles of `boundary_group`.
+    /// Fluids with no adhesion to a boundary group only bead up on it.
+    pub fn adhesion(
+        mut self,
+        fluid_group: u32,
+        boundary_group: u32,
+        coefficient: f64,
+    ) -> SurfaceTension {
+        self.adhesion
+            .insert((fluid_group, boundary_group), coefficient);
+        self
+    }
+
+    pub fn with_particle(mut self, reference: ParticleReference) -> SurfaceTension {
+        self.coupled_particles.push(reference);
+        self
+    }
+
+    pub fn with_particles(mut self, references: &[ParticleReference]) -> SurfaceTension {
+        for reference in references {
+            self.coupled_particles.push(*reference);
+        }
+        self
+    }
+
+    /// Solid particles that the fluid can adhere to. Their masses stand in for Akinci's
+    /// boundary volume times rest density, so they should be about as heavy as a fluid particle.
+    pub fn with_boundary_particles(mut self, references: &[ParticleReference]) -> SurfaceTension {
+        for reference in references {
+            self.boundary_particles.push(*reference);
+        }
+        self
+    }
+
+    //--------------------------------------------------------------------//
+
+    /// Akinci's cohesion spline, normalized in three dimensions over a support c.
+    fn cohesion_spline(r: f64, c: f64) -> f64 {
+        let norm = 32.0 / (PI * c.powi(9));
+        if r <= 0.0 || r > c {
+            0.0
+        } else if 2.0 * r > c {
+            norm * (c - r).powi(3) * r.powi(3)
+        } else {
+            norm * (2.0 * (c - r).powi(3) * r.powi(3) - c.powi(6) / 64.0)
+        }
+    }
+
+    /// Akinci's adhesion spline, which only acts over the outer half of the support c.
+    fn adhesion_spline(r: f64, c: f64) -> f64 {
+        if 2.0 * r <= c || r > c {
+            0.0
+        } else {
+            0.007 / c.powf(3.25) * (-4.0 * r * r / c + 6.0 * r - 2.0 * c).powf(0.25)
+        }
+    }
+
+    fn support(&self) -> f64 {
+        self.kernel.support(self.smoothing_radius)
+    }
+
+    /// The surface normal of every coupled particle, Σ m_j / ρ_j ∇W over neighbors of the same
+    /// group. This points into the fluid and vanishes inside it.
+    fn normals(&self, particle_source: &[Particle], pairs: &[(usize, usize)]) -> Vec<Vec3> {
+        let mut normals = vec![Vec3::zero(); self.coupled_particles.len()];
+        for (i, j) in pairs {
+            let (particle1, particle2) = (
+                self.coupled_particles[*i].get(particle_source),
+                self.coupled_particles[*j].get(particle_source),
+            );
+            if particle1.group != particle2.group
+                || particle1.density == 0.0
+                || particle2.density == 0.0
+            {
+                continue;
+            }
+            let gradient = self
+                .kernel
+                .grad_w(particle1.pos - particle2.pos, self.smoothing_radius);
+            normals[*i] += particle2.mass / particle2.density * gradient;
+            normals[*j] -= particle1.mass / particle1.density * gradient;
+        }
+        normals
+    }
+
+    /// The curvature κ = -∇·n̂ of every coupled particle's surface, taken only over neighbors
+    /// whose normals are long enough to trust, and divided by the kernel's sum over those same
+    /// neighbors to make up for the ones missing beyond the surface. Particles inside the fluid
+    /// get no curvature.
+    fn curvatures(
+        &self,
+        particle_source: &[Particle],
+        pairs: &[(usize, usize)],
+        normals: &[Vec3],
+        normal_threshold: f64,
+    ) -> Vec<f64> {
+        let unit_normals: Vec<Option<Vec3>> = normals
+            .iter()
+            .map(|normal| (normal.mag() > normal_threshold).then(|| normal.norm()))
+            .collect();
+
+        let mut curvatures = vec![0.0; self.coupled_particles.len()];
+        // Σ m_j / ρ_j W over the trusted neighbors, which would be 1 with a full set of them
+        let mut coverage: Vec<f64> = self
+            .coupled_particles
+            .iter()
+            .zip(&unit_normals)
+            .map(|(reference, normal)| {
+                let particle = reference.get(particle_source);
+                match normal {
+                    Some(_) if particle.density != 0.0 => {
+                        particle.mass / particle.density
+                            * self.kernel.w(Vec3::zero(), self.smoothing_radius)
+                    }
+                    _ => 0.0,
+                }
+            })
+            .collect();
+        for (i, j) in pairs {
+            let (normal1, normal2) = match (unit_normals[*i], unit_normals[*j]) {
+                (Some(normal1), Some(normal2)) => (normal1, normal2),
+                _ => continue,
+            };
+            let (particle1, particle2) = (
+                self.coupled_particles[*i].get(particle_source),
+                self.coupled_particles[*j].get(particle_source),
+            );
+            if particle1.group != particle2.group
+                || particle1.density == 0.0
+                || particle2.density == 0.0
+            {
+                continue;
+            }
+            let gradient = self
+                .kernel
+                .grad_w(particle1.pos - particle2.pos, self.smoothing_radius);
+            // the divergence (n̂_j - n̂_i)·∇W is symmetric in i and j
+            let divergence = (normal2 - normal1).dot(gradient);
+            curvatures[*i] -= particle2.mass / particle2.density * divergence;
+            curvatures[*j] -= particle1.mass / particle1.density * divergence;
+
+            let w = self
+                .kernel
+                .w(particle1.pos - particle2.pos, self.smoothing_radius);
+            coverage[*i] += particle2.mass / particle2.density * w;
+            coverage[*j] += particle1.mass / particle1.density * w;
+        }
+
+        for (curvature, coverage) in curvatures.iter_mut().zip(coverage) {
+            if coverage > 0.0 {
+                *curvature /= coverage;
+            }
+        }
+        curvatures
+    }
+
+    fn apply_akinci(
+        &self,
+        particle_source: &mut [Particle],
+        pairs: &[(usize, usize)],
+        rest_density: f64,
+    ) {
+        let support = self.support();
+        // Akinci's normals point out of the fluid, and are scaled by the support so that the
+        // curvature force is dimensionless
+        let normals: Vec<Vec3> = self
+            .normals(particle_source, pairs)
+            .into_iter()
+            .map(|normal| -support * normal)
+            .collect();
+
+        for (i, j) in pairs {
+            let (ref1, ref2) = (self.coupled_particles[*i], self.coupled_particles[*j]);
+            let (particle1, particle2) = (ref1.get(particle_source), ref2.get(particle_source));
+            if particle1.group != particle2.group {
+                continue;
+            }
+            let gamma = match self.tension.get(&particle1.group) {
+                Some(gamma) => *gamma,
+                None => continue,
+            };
+            let density_sum = particle1.density + particle2.density;
+            if density_sum == 0.0 {
+                continue;
+            }
+
+            // boosts the forces where particles are missing neighbors, at the surface
+            let correction = 2.0 * rest_density / density_sum;
+            let radial = particle1.pos - particle2.pos;
+            let distance = radial.mag();
+            let cohesion = if distance > 0.0 {
+                -gamma * particle1.mass * particle2.mass * Self::cohesion_spline(distance, support)
+                    / distance
+                    * radial
+            } else {
+                Vec3::zero()
+            };
+            let curvature = -gamma * (normals[*i] - normals[*j]);
+            let (mass1, mass2) = (particle1.mass, particle2.mass);
+
+            ref1.get_mut(particle_source)
+                .add_force(correction * (cohesion + mass1 * curvature));
+            ref2.get_mut(particle_source)
+                .add_force(-correction * (cohesion + mass2 * curvature));
+        }
+    }
+
+    fn apply_csf(
+        &self,
+        particle_source: &mut [Particle],
+        pairs: &[(usize, usize)],
+        normal_threshold: f64,
+    ) {
+        let normals = self.normals(particle_source, pairs);
+        let curvatures = self.curvatures(particle_source, pairs, &normals, normal_threshold);
+
+        for (index, reference) in self.coupled_particles.iter().enumerate() {
+            let particle = reference.get_mut(particle_source);
+            let sigma = match self.tension.get(&particle.group) {
+                Some(sigma) => *sigma,
+                None => continue,
+            };
+            if curvatures[index] == 0.0 || particle.density == 0.0 {
+                continue;
+            }
+
+            // σ κ n is a force per unit volume, and n = ∇c is only large near the surface
+            let force = sigma * curvatures[index] * normals[index];
+            particle.add_force(particle.mass / particle.density * force);
+        }
+    }
+
+    fn apply_adhesion(&self, particle_source: &mut [Particle], pairs: &[(usize, usize)]) {
+        let fluid_count = self.coupled_particles.len();
+        let support = self.support();
+
+        for (i, j) in pairs {
+            // boundary particles are indexed after the fluid particles
+            let (fluid, boundary) = match (*i < fluid_count, *j < fluid_count) {
+                (true, false) => (
+                    self.coupled_particles[*i],
+                    self.boundary_particles[*j - fluid_count],
+                ),
+                (false, true) => (
+                    self.coupled_particles[*j],
+                    self.boundary_particles[*i - fluid_count],
+                ),
+                _ => continue,
+            };
+            let (particle, solid) = (fluid.get(particle_source), boundary.get(particle_source));
+            let beta = match self.adhesion.get(&(particle.group, solid.group)) {
+                Some(beta) => *beta,
+                None => continue,
+            };
+
+            let radial = particle.pos - solid.pos;
+            let distance = radial.mag();
+            if distance == 0.0 {
+                continue;
+            }
+            let force =
+                -beta * particle.mass * solid.mass * Self::adhesion_spline(distance, support)
+                    / distance
+                    * radial;
+
+            fluid.get_mut(particle_source).add_force(force);
+            boundary.get_mut(particle_source).add_force(-force);
+        }
+    }
+}
+
+impl Interaction for SurfaceTension {
+    fn handle(&mut self, particle_source: &mut [Particle], _dt: f64) {
+        let support = self.support();
+        let positions: Vec<Point3> = self
+            .coupled_particles
+            .iter()
+            .chain(&self.boundary_particles)
+            .map(|reference| reference.get(particle_source).pos)
+            .collect();
+        let list = self
+            .neighbor_list
+            .get_or_insert_with(|| NeighborList::new(support, 0.1 * support));
+        list.update(&positions);
+
+        let fluid_count = self.coupled_particles.len();
+        let mut fluid_pairs = Vec::new();
+        let mut boundary_pairs = Vec::new();
+        // the list reaches past the support by its skin
+        let within_support = list
+            .pairs()
+            .iter()
+            .filter(|(i, j)| (positions[*i] - positions[*j]).mag_squared() < support * support);
+        for (i, j) in within_support {
+            match (*i < fluid_count, *j < fluid_count) {
+                (true, true) => fluid_pairs.push((*i, *j)),
+                (false, false) => (),
+                _ => boundary_pairs.push((*i, *j)),
+            }
+        }
+
+        match self.model {
+            SurfaceTensionModel::Akinci { rest_density } => {
+                self.apply_akinci(particle_source, &fluid_pairs, rest_density)
+            }
+            SurfaceTensionModel::Csf { normal_threshold } => {
+                self.apply_csf(particle_source, &fluid_pairs, normal_threshold)
+            }
+        }
+
+        if !self.adhesion.is_empty() {
+            self.apply_adhesion(particle_source, &boundary_pairs);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{math::Dimension, sph::kernels::CubicSplineKernel};
+
+    const SPACING: f64 = 0.1;
+    const SMOOTHING: f64 = 0.13;
+
+    /// A ball of particles of unit mass on a lattice about the origin, alternating between
+    /// the given groups, with their densities summed over the cubic spline.
+    fn ball(radius: f64, groups: &[u32]) -> (Vec<Particle>, Vec<ParticleReference>) {
+        let steps = (radius / SPACING) as i32;
+        let mut particles = Vec::new();
+        for x in -steps..=steps {
+            for y in -steps..=steps {
+                for z in -steps..=steps {
+                    let pos = SPACING * Vec3::new(x as f64, y as f64, z as f64);
+                    if pos.mag() <= radius {
+                        let group = groups[particles.len() % groups.len()];
+                        let particle = Particle::new()
+                            .id(particles.len() as u32)
+                            .group(group)
+                            .pos(pos)
+                            .mass(1.0);
+                        particles.push(particle);
+                    }
+                }
+            }
+        }
+
+        let kernel = CubicSplineKernel::new(Dimension::Three);
+        let densities: Vec<f64> = particles
+            .iter()
+            .map(|particle| {
+                particles
+                    .iter()
+                    .filter(|other| other.group == particle.group)
+                    .map(|other| other.mass * kernel.w(particle.pos - other.pos, SMOOTHING))
+                    .sum()
+            })
+            .collect();
+        for (particle, density) in particles.iter_mut().zip(densities) {
+            particle.density = density;
+        }
+        let references = (0..particles.len())
+            .map(|index| ParticleReference::new(particles[index].id, index))
+            .collect();
+        (particles, references)
+    }
+
+    fn total_force(particle: &Particle) -> Vec3 {
+        particle
+            .forces
+            .iter()
+            .fold(Vec3::zero(), |total, force| total + *force)
+    }
+
+    fn akinci() -> SurfaceTension {
+        SurfaceTension::new(
+            SMOOTHING,
+            SurfaceTensionModel::Akinci {
+                rest_density: 1000.0,
+            },
+        )
+        .kernel(CubicSplineKernel::new(Dimension::Three))
+    }
+
+    #[test]
+    fn cohesion_pulls_a_ball_together_without_moving_it() {
+        let (mut particles, references) = ball(0.6, &[0]);
+        akinci()
+            .tension(0, 1.0)
+            .with_particles(&references)
+            .handle(&mut particles, 0.0);
+
+        let (mut net, mut inward, mut scale) = (Vec3::zero(), 0.0, 0.0);
+        for particle in &particles {
+            let force = total_force(particle);
+            net += force;
+            inward -= force.dot(particle.pos);
+            scale += force.mag();
+        }
+        assert!(scale > 0.0);
+        assert!(net.mag() < 1e-12 * scale);
+        assert!(inward > 0.0);
+    }
+
+    #[test]
+    fn csf_curvature_of_a_ball_is_twice_its_inverse_radius() {
+        let radius = 0.6;
+        let (particles, references) = ball(radius, &[0]);
+        let tension = SurfaceTension::new(
+            SMOOTHING,
+            SurfaceTensionModel::Csf {
+                normal_threshold: 0.3 / SMOOTHING,
+            },
+        )
+        .kernel(CubicSplineKernel::new(Dimension::Three))
+        .tension(0, 1.0)
+        .with_particles(&references);
+
+        let support = tension.support();
+        let mut pairs = Vec::new();
+        for i in 0..particles.len() {
+            for j in i + 1..particles.len() {
+                if (particles[i].pos - particles[j].pos).mag() < support {
+                    pairs.push((i, j));
+                }
+            }
+        }
+        let normals = tension.normals(&particles, &pairs);
+        let curvatures = tension.curvatures(&particles, &pairs, &normals, 0.3 / SMOOTHING);
+
+        // the normals point into the ball at the surface
+        let surface: Vec<usize> = (0..particles.len())
+            .filter(|index| particles[*index].pos.mag() > radius - SPACING)
+            .collect();
+        for index in &surface {
+            assert!(normals[*index].dot(particles[*index].pos) < 0.0);
+        }
+        let mean =
+            surface.iter().map(|index| curvatures[*index]).sum::<f64>() / surface.len() as f64;
+        assert!(
+            (mean - 2.0 / radius).abs() < 0.15 * 2.0 / radius,
+            "{}",
+            mean
+        );
+    }
+
+    #[test]
+    fn groups_only_feel_their_own_tension() {
+        let (mut particles, references) = ball(0.6, &[0, 1]);
+        akinci()
+            .tension(0, 1.0)
+            .with_particles(&references)
+            .handle(&mut particles, 0.0);
+        for particle in &particles {
+            if particle.group == 1 {
+                assert!(particle.forces.is_empty());
+            }
+        }
+        assert!(particles
+            .iter()
+            .any(|particle| particle.group == 0 && !particle.forces.is_empty()));
+    }
+
+    #[test]
+    fn adhesion_draws_fluid_to_its_boundary_group() {
+        let mut particles = vec![
+            Particle::new().id(0).group(0).pos(Vec3::zero()).mass(1.0),
+            Particle::new()
+                .id(1)
+                .group(2)
+                .pos(0.7 * akinci().support() * Vec3::x_hat())
+                .mass(1.0),
+            Particle::new()
+                .id(2)
+                .group(3)
+                .pos(-0.7 * akinci().support() * Vec3::x_hat())
+                .mass(1.0),
+        ];
+        particles[0].density = 1000.0;
+        akinci()
+            .adhesion(0, 2, 1.0)
+            .with_particle(ParticleReference::new(0, 0))
+            .with_boundary_particles(&[ParticleReference::new(1, 1), ParticleReference::new(2, 2)])
+            .handle(&mut particles, 0.0);
+
+        let (fluid, solid) = (total_force(&particles[0]), total_force(&particles[1]));
+        assert!(fluid.x > 0.0);
+        assert!((fluid + solid).mag() < 1e-15);
+        // no adhesion was set for the other boundary group
+        assert!(particles[2].forces.is_empty());
+    }
+}
+
+//---------------------------------------------------------------------------------------------------//
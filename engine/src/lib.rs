@@ -1,10 +1,14 @@
 pub mod algorithms;
+pub mod barostat;
 pub mod collision;
 pub mod constraint;
 pub mod interaction;
 pub mod math;
 pub mod particle;
+pub mod simulation_box;
+pub mod sph;
 pub mod system;
+pub mod thermostat;
 
 pub mod prelude {
     pub use crate::{
@@ -12,6 +16,7 @@ pub mod prelude {
         interaction::interactions as Interactions,
         math::{Matrix3, Vec3, PI},
         particle::Particle,
+        simulation_box::SimulationBox,
         system::System,
     };
 }
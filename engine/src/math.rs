@@ -24,6 +24,15 @@ pub struct Vec3 {
 #[derive(Copy, Clone)]
 pub struct Matrix3(pub [[f64; 3]; 3]);
 
+/// The number of spatial dimensions something works in, such as a kernel's normalization, a
+/// thermostat's degrees of freedom or a box's periodic axes.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Dimension {
+    One,
+    Two,
+    Three,
+}
+
 //---------------------------------------------------------------------------------------------------//
 // Associated functions and methods of Vec3.
 
@@ -117,6 +126,19 @@ impl Vec3 {
     }
 }
 
+//---------------------------------------------------------------------------------------------------//
+// Associated functions and methods of Dimension.
+
+impl Dimension {
+    pub fn count(self) -> i32 {
+        match self {
+            Dimension::One => 1,
+            Dimension::Two => 2,
+            Dimension::Three => 3,
+        }
+    }
+}
+
 //---------------------------------------------------------------------------------------------------//
 // Associated functions and methods of Matrix3.
 
@@ -125,6 +147,19 @@ impl Matrix3 {
         Matrix3::default()
     }
 
+    pub fn zero() -> Matrix3 {
+        Matrix3([[0.0; 3]; 3])
+    }
+
+    /// The outer product of two vectors, a * b^T
+    pub fn outer_product(a: Vec3, b: Vec3) -> Matrix3 {
+        Matrix3([
+            [a.x * b.x, a.x * b.y, a.x * b.z],
+            [a.y * b.x, a.y * b.y, a.y * b.z],
+            [a.z * b.x, a.z * b.y, a.z * b.z],
+        ])
+    }
+
     pub fn cross_product_matrix(vector: Vec3) -> Matrix3 {
         Matrix3([
             [0.0, -vector.z, vector.y],
@@ -348,4 +383,17 @@ impl core::ops::Add<Matrix3> for Matrix3 {
     }
 }
 
+impl core::ops::Sub<Matrix3> for Matrix3 {
+    type Output = Matrix3;
+    fn sub(self, rhs: Matrix3) -> Self::Output {
+        self + (-1.0 * rhs)
+    }
+}
+
+impl core::ops::AddAssign<Matrix3> for Matrix3 {
+    fn add_assign(&mut self, rhs: Matrix3) {
+        *self = *self + rhs;
+    }
+}
+
 //---------------------------------------------------------------------------------------------------//
@@ -22,6 +22,8 @@ pub struct Particle {
     pub density: f64,
     pub temperature: f64,
     pub entropy: f64,
+    pub smoothing_length: f64,
+    pub material: Option<u32>,
 
     // electromagnetic properties
     pub charge: f64,
@@ -84,6 +86,34 @@ impl Particle {
         self
     }
 
+    //--------------------------------------------------------------------//
+    // builder methods for continuum sampling
+
+    pub fn temperature(mut self, temperature: f64) -> Particle {
+        self.temperature = temperature;
+        self
+    }
+    pub fn entropy(mut self, entropy: f64) -> Particle {
+        self.entropy = entropy;
+        self
+    }
+    pub fn smoothing_length(mut self, smoothing_length: f64) -> Particle {
+        self.smoothing_length = smoothing_length;
+        self
+    }
+    pub fn material(mut self, material: u32) -> Particle {
+        self.material = Some(material);
+        self
+    }
+
+    //--------------------------------------------------------------------//
+    // builder methods for electromagnetic properties
+
+    pub fn charge(mut self, charge: f64) -> Particle {
+        self.charge = charge;
+        self
+    }
+
     //--------------------------------------------------------------------//
     // physics methods
 
@@ -99,6 +129,23 @@ impl Particle {
         self.pos += self.vel * dt;
     }
 
+    /// Accelerate by the forces for a time, the half steps of velocity Verlet, keeping them.
+    pub fn kick(&mut self, dt: f64) {
+        let mut total_force = Vec3::zero();
+
+        for force in &self.forces {
+            total_force += *force;
+        }
+
+        self.vel += total_force * self.inverse_mass * dt;
+    }
+
+    /// Move at the velocity for a time, remembering where the move started.
+    pub fn drift(&mut self, dt: f64) {
+        self.prev_pos = self.pos;
+        self.pos += self.vel * dt;
+    }
+
     pub fn add_force(&mut self, force: Vec3) {
         self.forces.push(force);
     }
@@ -0,0 +1,204 @@
+//! The periodic box a [`crate::system::System`] can be simulated in, for bulk gases and liquids
+//! without walls.
+//!
+//! The box is orthorhombic, and periodic along each of the axes it spans: particles that leave
+//! through one face come back in through the opposite one, and interactions that support it
+//! measure displacements by the minimum image convention, to the nearest periodic copy. That
+//! needs the box to be more than twice as wide as the interactions reach, which they check
+//! whenever they're handed the box.
+//!
+//! Pair-wise forces, distance and non-penetration constraints, and collisions found by a spatial
+//! hash all reach across the box's faces. The system hands them its box before each substep.
+
+//---------------------------------------------------------------------------------------------------//
+use crate::{
+    math::{Dimension, Point3, Vec3},
+    particle::Particle,
+};
+
+//---------------------------------------------------------------------------------------------------//
+
+#[derive(Copy, Clone, Debug)]
+pub struct SimulationBox {
+    pub lower: Point3,
+    pub upper: Point3,
+    pub dimension: Dimension,
+}
+
+impl SimulationBox {
+    /// A box spanning three dimensions between two opposite corners.
+    pub fn new(lower: Point3, upper: Point3) -> SimulationBox {
+        SimulationBox {
+            lower,
+            upper,
+            dimension: Dimension::Three,
+        }
+    }
+
+    /// Only the first axes are periodic, and the volume is their area or length.
+    pub fn dimension(mut self, dimension: Dimension) -> SimulationBox {
+        self.dimension = dimension;
+        self
+    }
+
+    pub fn lengths(&self) -> Vec3 {
+        self.upper - self.lower
+    }
+
+    pub fn center(&self) -> Point3 {
+        0.5 * (self.lower + self.upper)
+    }
+
+    /// The volume of the box, or its area or length in fewer dimensions.
+    pub fn volume(&self) -> f64 {
+        let lengths = self.lengths();
+        match self.dimension {
+            Dimension::One => lengths.x,
+            Dimension::Two => lengths.x * lengths.y,
+            Dimension::Three => lengths.x * lengths.y * lengths.z,
+        }
+    }
+
+    /// Whether the box is more than twice as wide as a reach along each of its periodic axes, so
+    /// that no more than one periodic copy of a point is ever within reach of another.
+    pub fn fits_reach(&self, reach: f64) -> bool {
+        let lengths = self.lengths();
+        [lengths.x, lengths.y, lengths.z][..self.dimension.count() as usize]
+            .iter()
+            .all(|length| *length > 2.0 * reach)
+    }
+
+    /// The displacement to the nearest periodic copy of the end point.
+    pub fn minimum_image(&self, displacement: Vec3) -> Vec3 {
+        let lengths = self.lengths();
+        let image = |component: f64, length: f64| component - length * (component / length).round();
+        let d = self.dimension.count();
+        Vec3::new(
+            image(displacement.x, lengths.x),
+            if d > 1 {
+                image(displacement.y, lengths.y)
+            } else {
+                displacement.y
+            },
+            if d > 2 {
+                image(displacement.z, lengths.z)
+            } else {
+                displacement.z
+            },
+        )
+    }
+
+    /// The periodic copy of a point that lies inside the box.
+    pub fn wrap(&self, point: Point3) -> Point3 {
+        self.lower + self.minimum_image(point - self.center()) + 0.5 * self.lengths()
+    }
+
+    /// Stretch the box about its center by a factor along each axis, taking a point along with it.
+    pub fn scale_point(&self, point: Point3, factors: Vec3) -> Point3 {
+        let center = self.center();
+        let offset = point - center;
+        center
+            + Vec3::new(
+                offset.x * factors.x,
+                offset.y * factors.y,
+                offset.z * factors.z,
+            )
+    }
+
+    /// Stretch the box about its center by a factor along each axis.
+    pub fn scale(&mut self, factors: Vec3) {
+        let (lower, upper) = (
+            self.scale_point(self.lower, factors),
+            self.scale_point(self.upper, factors),
+        );
+        self.lower = lower;
+        self.upper = upper;
+    }
+
+    /// The diagonal of the pressure tensor of the particles in the box,
+    /// P_a = (Σ m v_a² + W_a) / V, given the diagonal of the virial W = Σ r_ij ⊗ F_ij of the
+    /// forces between them.
+    pub fn pressure(&self, particles: &[Particle], virial: Vec3) -> Vec3 {
+        let kinetic = particles.iter().fold(Vec3::zero(), |total, particle| {
+            let vel = particle.vel;
+            total + particle.mass * Vec3::new(vel.x * vel.x, vel.y * vel.y, vel.z * vel.z)
+        });
+        (kinetic + virial) / self.volume()
+    }
+}
+
+/// The displacement between two points, to the nearest periodic copy of the second if there is
+/// a box.
+pub fn displacement(simulation_box: Option<&SimulationBox>, from: Point3, to: Point3) -> Vec3 {
+    match simulation_box {
+        Some(simulation_box) => simulation_box.minimum_image(to - from),
+        None => to - from,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::Random;
+
+    /// Whether two vectors differ by whole box lengths along every axis.
+    fn differ_by_periods(a: Vec3, b: Vec3, lengths: Vec3) -> bool {
+        let whole = |difference: f64, length: f64| {
+            let periods = difference / length;
+            (periods - periods.round()).abs() < 1e-9
+        };
+        whole(a.x - b.x, lengths.x) && whole(a.y - b.y, lengths.y) && whole(a.z - b.z, lengths.z)
+    }
+
+    #[test]
+    fn the_minimum_image_is_the_nearest_periodic_copy() {
+        let simulation_box =
+            SimulationBox::new(Vec3::new(-1.0, 0.0, 2.0), Vec3::new(9.0, 4.0, 8.0));
+        let lengths = simulation_box.lengths();
+        let image = simulation_box.minimum_image(Vec3::new(9.0, -3.0, 3.5));
+        assert!((image - Vec3::new(-1.0, 1.0, -2.5)).mag() < 1e-12);
+
+        let mut random = Random::new(2);
+        for _ in 0..1000 {
+            let displacement =
+                50.0 * Vec3::new(random.gaussian(), random.gaussian(), random.gaussian());
+            let image = simulation_box.minimum_image(displacement);
+            assert!(differ_by_periods(image, displacement, lengths));
+            assert!(image.x.abs() <= 0.5 * lengths.x);
+            assert!(image.y.abs() <= 0.5 * lengths.y);
+            assert!(image.z.abs() <= 0.5 * lengths.z);
+        }
+    }
+
+    #[test]
+    fn wrapping_brings_points_into_the_box() {
+        let simulation_box =
+            SimulationBox::new(Vec3::new(-1.0, 0.0, 2.0), Vec3::new(9.0, 4.0, 8.0));
+        let mut random = Random::new(3);
+        for _ in 0..1000 {
+            let point = 50.0 * Vec3::new(random.gaussian(), random.gaussian(), random.gaussian());
+            let wrapped = simulation_box.wrap(point);
+            assert!(differ_by_periods(wrapped, point, simulation_box.lengths()));
+            let (lower, upper) = (simulation_box.lower, simulation_box.upper);
+            assert!(wrapped.x >= lower.x && wrapped.x <= upper.x);
+            assert!(wrapped.y >= lower.y && wrapped.y <= upper.y);
+            assert!(wrapped.z >= lower.z && wrapped.z <= upper.z);
+        }
+        // a point inside stays where it is
+        let inside = Vec3::new(0.5, 3.0, 7.5);
+        assert!((simulation_box.wrap(inside) - inside).mag() < 1e-12);
+    }
+
+    #[test]
+    fn only_the_periodic_axes_are_imaged() {
+        let simulation_box =
+            SimulationBox::new(Vec3::zero(), Vec3::new(4.0, 5.0, 6.0)).dimension(Dimension::Two);
+        let image = simulation_box.minimum_image(Vec3::new(3.0, -4.0, 10.0));
+        assert!((image - Vec3::new(-1.0, 1.0, 10.0)).mag() < 1e-12);
+        assert!((simulation_box.volume() - 20.0).abs() < 1e-12);
+        assert!(simulation_box.fits_reach(1.9));
+        assert!(!simulation_box.fits_reach(2.1));
+    }
+}
+
+//---------------------------------------------------------------------------------------------------//
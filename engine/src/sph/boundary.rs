@@ -0,0 +1,627 @@
+//! Solid boundaries for SPH fluids.
+//!
+//! Boundaries can be sampled with particles (Akinci et al., 2012), each standing for the patch
+//! of solid around it. The fluid counts a boundary particle as fluid at the rest density, filling
+//! its patch, and pushes on it with its own pressure. Boundary particles can be fixed, or carry
+//! mass and be bound into a body, which the fluid then pushes back on.
+//!
+//! Boundaries can also be described by signed distance fields. There the kernel is integrated
+//! over the solid once, as a function of the distance to its surface (Harada et al.'s wall
+//! weight functions), which gives a smooth wall without sampling it. Fluid that still gets into
+//! a wall, such as fast spray, is kept out by the contact constraints of
+//! [`SphBoundary::contacts`], which the system projects along with its other constraints.
+//!
+//! Boundaries are free-slip unless given a viscosity, which drags the fluid along with the
+//! boundary's velocity.
+
+//---------------------------------------------------------------------------------------------------//
+use std::rc::Rc;
+
+use crate::{
+    collision::neighbor_list::NeighborList,
+    constraint::{constraints::WallContact, xpbd::XpbdParameters},
+    math::{Point3, Vec3, PI},
+    particle::{Particle, ParticleReference},
+    sph::{kernels::Dimension, SphKernel},
+};
+
+//---------------------------------------------------------------------------------------------------//
+
+/// The number of samples in the wall weight tables, across the kernel's support on either side
+/// of the wall.
+const TABLE_SIZE: usize = 256;
+
+/// The number of intervals in the integrals over each slice of the kernel parallel to a wall.
+const SLICE_INTERVALS: usize = 64;
+
+/// A solid described by the signed distance to its surface.
+pub trait SignedDistance {
+    /// The distance from a point to the surface, negative inside the solid.
+    fn distance(&self, point: Point3) -> f64;
+
+    /// The unit normal of the surface nearest to a point, pointing out of the solid. Defaults to
+    /// the gradient of the distance, by central differences.
+    fn normal(&self, point: Point3) -> Vec3 {
+        let step = 1e-6 * (1.0 + point.mag());
+        let difference =
+            |axis: Vec3| self.distance(point + step * axis) - self.distance(point - step * axis);
+        let gradient = Vec3::new(
+            difference(Vec3::x_hat()),
+            difference(Vec3::y_hat()),
+            difference(Vec3::z_hat()),
+        );
+        if gradient.mag_squared() > 0.0 {
+            gradient.norm()
+        } else {
+            Vec3::zero()
+        }
+    }
+}
+
+impl<F: Fn(Point3) -> f64> SignedDistance for F {
+    fn distance(&self, point: Point3) -> f64 {
+        self(point)
+    }
+}
+
+/// Everything behind a plane, such as the floor or a wall of a tank.
+pub struct HalfSpace {
+    point: Point3,
+    normal: Vec3,
+}
+
+impl HalfSpace {
+    /// A plane through the given point, with the solid on the opposite side to the normal.
+    pub fn new(point: Point3, normal: Vec3) -> HalfSpace {
+        HalfSpace {
+            point,
+            normal: normal.norm(),
+        }
+    }
+}
+
+impl SignedDistance for HalfSpace {
+    fn distance(&self, point: Point3) -> f64 {
+        (point - self.point).dot(self.normal)
+    }
+
+    fn normal(&self, _point: Point3) -> Vec3 {
+        self.normal
+    }
+}
+
+/// A solid ball, or a disk in two dimensions.
+pub struct Ball {
+    center: Point3,
+    radius: f64,
+}
+
+impl Ball {
+    pub fn new(center: Point3, radius: f64) -> Ball {
+        Ball { center, radius }
+    }
+}
+
+impl SignedDistance for Ball {
+    fn distance(&self, point: Point3) -> f64 {
+        (point - self.center).mag() - self.radius
+    }
+
+    fn normal(&self, point: Point3) -> Vec3 {
+        let radial = point - self.center;
+        if radial.mag_squared() > 0.0 {
+            radial.norm()
+        } else {
+            Vec3::zero()
+        }
+    }
+}
+
+//---------------------------------------------------------------------------------------------------//
+
+/// Kernel integrals over the solid beyond a flat wall, for unit smoothing length, tabulated by
+/// the distance q from the wall. Each entry holds the integral of W, the integral of W over the
+/// slice at distance q (the rate at which the first changes with q, for the gradient kernel),
+/// and the integral of W'(r) r / (r² + 0.01) that the wall's viscosity needs.
+struct WallTable {
+    reach: f64,
+    entries: Vec<[f64; 3]>,
+}
+
+impl WallTable {
+    fn new(
+        kernel: &dyn SphKernel,
+        gradient_kernel: &dyn SphKernel,
+        dimension: Dimension,
+    ) -> WallTable {
+        let reach = kernel.support(1.0).max(gradient_kernel.support(1.0));
+        let w = |r: f64| kernel.w(Vec3::new(r, 0.0, 0.0), 1.0);
+        let gradient_w = |r: f64| gradient_kernel.w(Vec3::new(r, 0.0, 0.0), 1.0);
+        let viscous =
+            |r: f64| gradient_kernel.div_w(Vec3::new(r, 0.0, 0.0), 1.0) * r / (r * r + 0.01);
+
+        // a function of the distance from the kernel's center, integrated over the slice of the
+        // kernel's support at height z
+        let slice = |f: &dyn Fn(f64) -> f64, z: f64| -> f64 {
+            let extent = (reach * reach - z * z).max(0.0).sqrt();
+            match dimension {
+                Dimension::One => f(z.abs()),
+                Dimension::Two => 2.0 * simpson(|x| f((z * z + x * x).sqrt()), extent),
+                Dimension::Three => {
+                    2.0 * PI * simpson(|rho| f((z * z + rho * rho).sqrt()) * rho, extent)
+                }
+            }
+        };
+
+        let spacing = 2.0 * reach / (TABLE_SIZE - 1) as f64;
+        let heights: Vec<f64> = (0..TABLE_SIZE)
+            .map(|index| -reach + index as f64 * spacing)
+            .collect();
+        let weights: Vec<f64> = heights.iter().map(|z| slice(&w, *z)).collect();
+        let gradients: Vec<f64> = heights.iter().map(|z| slice(&gradient_w, *z)).collect();
+        let viscosities: Vec<f64> = heights.iter().map(|z| slice(&viscous, *z)).collect();
+
+        // the solid is everything beyond q, so the volume integrals accumulate from the far edge
+        let mut entries = vec![[0.0; 3]; TABLE_SIZE];
+        for index in (0..TABLE_SIZE - 1).rev() {
+            let above = entries[index + 1];
+            entries[index] = [
+                above[0] + 0.5 * spacing * (weights[index] + weights[index + 1]),
+                0.0,
+                above[2] + 0.5 * spacing * (viscosities[index] + viscosities[index + 1]),
+            ];
+        }
+        for (entry, gradient) in entries.iter_mut().zip(gradients) {
+            entry[1] = gradient;
+        }
+
+        WallTable { reach, entries }
+    }
+
+    /// The entries for a particle at a distance from the wall, in units of its smoothing length.
+    fn lookup(&self, q: f64) -> [f64; 3] {
+        let position = ((q + self.reach) / (2.0 * self.reach) * (TABLE_SIZE - 1) as f64)
+            .clamp(0.0, (TABLE_SIZE - 1) as f64);
+        let index = (position as usize).min(TABLE_SIZE - 2);
+        let fraction = position - index as f64;
+        let (low, high) = (self.entries[index], self.entries[index + 1]);
+        [
+            low[0] + fraction * (high[0] - low[0]),
+            low[1] + fraction * (high[1] - low[1]),
+            low[2] + fraction * (high[2] - low[2]),
+        ]
+    }
+}
+
+/// ∫ f from 0 to the given extent by Simpson's rule.
+fn simpson(f: impl Fn(f64) -> f64, extent: f64) -> f64 {
+    if extent <= 0.0 {
+        return 0.0;
+    }
+    let step = extent / SLICE_INTERVALS as f64;
+    let interior: f64 = (1..SLICE_INTERVALS)
+        .map(|index| {
+            let weight = if index % 2 == 1 { 4.0 } else { 2.0 };
+            weight * f(index as f64 * step)
+        })
+        .sum();
+    step / 3.0 * (f(0.0) + interior + f(extent))
+}
+
+//---------------------------------------------------------------------------------------------------//
+
+/// The solids that contain or obstruct an SPH fluid, handed to
+/// [`crate::interaction::sph::SphParameters::boundary`].
+pub struct SphBoundary {
+    particles: Vec<ParticleReference>,
+    walls: Vec<Rc<dyn SignedDistance>>,
+    rest_density: f64,
+    dimension: Dimension,
+    viscosity: Option<f64>,
+
+    table: Option<WallTable>,
+    volumes: Vec<f64>,
+    fluid_rest_densities: Vec<f64>,
+    particle_contacts: Vec<(usize, usize)>,
+    // fluid particle, distance and normal
+    wall_contacts: Vec<(usize, f64, Vec3)>,
+    neighbor_list: Option<NeighborList>,
+}
+
+impl SphBoundary {
+    /// A boundary for a fluid of the given rest density, simulated in the given number of
+    /// dimensions, which the walls need to integrate the kernel over the solid. Fluid particles
+    /// with a [`crate::sph::materials::Material`] see the boundary at their material's rest
+    /// density instead.
+    pub fn new(rest_density: f64, dimension: Dimension) -> SphBoundary {
+        SphBoundary {
+            particles: Vec::new(),
+            walls: Vec::new(),
+            rest_density,
+            dimension,
+            viscosity: None,
+            table: None,
+            volumes: Vec::new(),
+            fluid_rest_densities: Vec::new(),
+            particle_contacts: Vec::new(),
+            wall_contacts: Vec::new(),
+            neighbor_list: None,
+        }
+    }
+
+    /// A solid described by its signed distance. Walls are static.
+    pub fn wall(mut self, wall: impl SignedDistance + 'static) -> SphBoundary {
+        self.walls.push(Rc::new(wall));
+        self
+    }
+
+    /// Drag the fluid along with the boundary by Morris' laminar viscosity, with a dynamic
+    /// viscosity μ, for a no-slip boundary.
+    pub fn no_slip(mut self, dynamic_viscosity: f64) -> SphBoundary {
+        self.viscosity = Some(dynamic_viscosity);
+        self
+    }
+
+    /// Boundary particles should be spaced about as closely as the fluid particles.
+    pub fn with_particle(mut self, reference: ParticleReference) -> SphBoundary {
+        self.particles.push(reference);
+        self
+    }
+
+    pub fn with_particles(mut self, references: &[ParticleReference]) -> SphBoundary {
+        for reference in references {
+            self.particles.push(*reference);
+        }
+        self
+    }
+
+    /// Contact constraints that keep each of the given fluid particles out of every wall, to be
+    /// added to the system. The walls' pressure holds back fluid that has any, but spray too
+    /// thin to build up pressure would otherwise pass straight through.
+    pub fn contacts(&self, fluid: &[ParticleReference]) -> Vec<XpbdParameters> {
+        let mut contacts = Vec::with_capacity(fluid.len() * self.walls.len());
+        for wall in &self.walls {
+            for reference in fluid {
+                contacts.push(WallContact::new(*reference, 0.0, wall.clone()));
+            }
+        }
+        contacts
+    }
+
+    //--------------------------------------------------------------------//
+    // called by the fluid each step
+
+    /// Find the fluid particles near the boundary, and the volume of each boundary particle from
+    /// how closely it is packed with the others, using the given smoothing length. The rest
+    /// densities of the fluid particles' materials are passed in, where they have one.
+    pub(crate) fn prepare(
+        &mut self,
+        particle_source: &[Particle],
+        fluid_positions: &[Point3],
+        fluid_lengths: &[f64],
+        fluid_rest_densities: &[Option<f64>],
+        boundary_length: f64,
+        kernels: (&dyn SphKernel, &dyn SphKernel),
+    ) {
+        self.fluid_rest_densities = fluid_rest_densities
+            .iter()
+            .map(|rest_density| rest_density.unwrap_or(self.rest_density))
+            .collect();
+        let (kernel, gradient_kernel) = kernels;
+        let reach = kernel.support(1.0).max(gradient_kernel.support(1.0));
+        if self.table.is_none() && !self.walls.is_empty() {
+            self.table = Some(WallTable::new(kernel, gradient_kernel, self.dimension));
+        }
+
+        self.wall_contacts.clear();
+        for (index, pos) in fluid_positions.iter().enumerate() {
+            for wall in &self.walls {
+                let distance = wall.distance(*pos);
+                if distance < reach * fluid_lengths[index] {
+                    self.wall_contacts
+                        .push((index, distance, wall.normal(*pos)));
+                }
+            }
+        }
+
+        self.particle_contacts.clear();
+        if self.particles.is_empty() {
+            return;
+        }
+
+        // the boundary particles are indexed after the fluid particles
+        let fluid_count = fluid_positions.len();
+        let mut positions = fluid_positions.to_vec();
+        for reference in &self.particles {
+            positions.push(reference.get(particle_source).pos);
+        }
+        let largest = fluid_lengths
+            .iter()
+            .fold(boundary_length, |largest, h| largest.max(*h));
+        let cutoff = reach * largest;
+        let stale = match &self.neighbor_list {
+            Some(list) => list.cutoff() < cutoff || list.cutoff() > 2.0 * cutoff,
+            None => true,
+        };
+        if stale {
+            self.neighbor_list = Some(NeighborList::new(1.2 * cutoff, 0.12 * cutoff));
+        }
+        let list = self.neighbor_list.as_mut().unwrap();
+        list.update(&positions);
+
+        // the list reaches past the kernel by its skin, so only the pairs within reach of the
+        // smoothing length they're measured with are kept
+        let within = |distance: f64, h: f64| distance < reach * h;
+        let mut weights = vec![kernel.w(Vec3::zero(), boundary_length); self.particles.len()];
+        for (i, j) in list.pairs() {
+            let distance = (positions[*i] - positions[*j]).mag();
+            match (*i < fluid_count, *j < fluid_count) {
+                (true, false) if within(distance, fluid_lengths[*i]) => {
+                    self.particle_contacts.push((*i, *j - fluid_count))
+                }
+                (false, true) if within(distance, fluid_lengths[*j]) => {
+                    self.particle_contacts.push((*j, *i - fluid_count))
+                }
+                (false, false) if within(distance, boundary_length) => {
+                    let w = kernel.w(positions[*i] - positions[*j], boundary_length);
+                    weights[*i - fluid_count] += w;
+                    weights[*j - fluid_count] += w;
+                }
+                _ => {}
+            }
+        }
+        self.volumes = weights.iter().map(|weight| 1.0 / weight).collect();
+    }
+
+    /// Add the boundary's share of the density of each fluid particle.
+    pub(crate) fn add_densities(
+        &self,
+        particle_source: &[Particle],
+        fluid_positions: &[Point3],
+        fluid_lengths: &[f64],
+        kernel: &dyn SphKernel,
+        densities: &mut [f64],
+    ) {
+        for (i, b) in &self.particle_contacts {
+            let radial = fluid_positions[*i] - self.particles[*b].get(particle_source).pos;
+            densities[*i] += self.fluid_rest_densities[*i]
+                * self.volumes[*b]
+                * kernel.w(radial, fluid_lengths[*i]);
+        }
+        if let Some(table) = &self.table {
+            for (i, distance, _) in &self.wall_contacts {
+                densities[*i] +=
+                    self.fluid_rest_densities[*i] * table.lookup(distance / fluid_lengths[*i])[0];
+            }
+        }
+    }
+
+    /// Push the fluid particles away from the boundary by their pressures, and drag them along
+    /// it if the boundary is no-slip. Boundaries only push, so a fluid under tension doesn't
+    /// stick to them. Boundary particles feel the opposite forces. The pressure terms p / (Ω ρ²) of the fluid
+    /// particles are passed in, and the rate at which each fluid particle's specific internal
+    /// energy changes is added to `heating`.
+    pub(crate) fn apply_forces(
+        &self,
+        particle_source: &mut [Particle],
+        fluid: &[ParticleReference],
+        fluid_lengths: &[f64],
+        pressure_terms: &[f64],
+        gradient_kernel: &dyn SphKernel,
+        heating: &mut [f64],
+    ) {
+        for (i, b) in &self.particle_contacts {
+            let (particle, boundary) = (
+                fluid[*i].get(particle_source),
+                self.particles[*b].get(particle_source),
+            );
+            let h = fluid_lengths[*i];
+            let radial = particle.pos - boundary.pos;
+            let relative_velocity = particle.vel - boundary.vel;
+            let gradient = gradient_kernel.grad_w(radial, h);
+            let volume = self.volumes[*b];
+            let pressure_term = pressure_terms[*i].max(0.0);
+
+            let rest_density = self.fluid_rest_densities[*i];
+            let mut force = -particle.mass * rest_density * volume * pressure_term * gradient;
+            heating[*i] += rest_density * volume * pressure_term * relative_velocity.dot(gradient);
+            if let Some(viscosity) = self.viscosity {
+                let drag = 2.0 * viscosity * particle.mass * volume * radial.dot(gradient)
+                    / (particle.density * (radial.mag_squared() + 0.01 * h * h))
+                    * relative_velocity;
+                heating[*i] -= relative_velocity.dot(drag) / particle.mass;
+                force += drag;
+            }
+
+            fluid[*i].get_mut(particle_source).add_force(force);
+            self.particles[*b]
+                .get_mut(particle_source)
+                .add_force(-force);
+        }
+
+        if let Some(table) = &self.table {
+            for (i, distance, normal) in &self.wall_contacts {
+                let particle = fluid[*i].get_mut(particle_source);
+                let h = fluid_lengths[*i];
+                let [_, slice, viscous] = table.lookup(distance / h);
+                let pressure_term = pressure_terms[*i].max(0.0);
+                let rest_density = self.fluid_rest_densities[*i];
+
+                // the integral of ∇W over the solid is -slice n / h
+                let mut force = particle.mass * rest_density * pressure_term * slice / h * *normal;
+                heating[*i] -= rest_density * pressure_term * slice / h * particle.vel.dot(*normal);
+                if let Some(viscosity) = self.viscosity {
+                    let drag = 2.0 * viscosity * particle.mass * viscous
+                        / (particle.density * h * h)
+                        * particle.vel;
+                    heating[*i] -= particle.vel.dot(drag) / particle.mass;
+                    force += drag;
+                }
+                particle.add_force(force);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        constraint::Constraint,
+        interaction::{sph::SphParameters, Interaction},
+        sph::{kernels::CubicSplineKernel, state_equations::Tait},
+    };
+
+    const SPACING: f64 = 0.1;
+    const SMOOTHING: f64 = 0.13;
+
+    /// A block of fluid particles of unit mass, a density of 1000, resting on the floor y = 0
+    /// half a spacing above it, and references to them.
+    fn block(side: usize) -> (Vec<Particle>, Vec<ParticleReference>) {
+        let mut particles = Vec::new();
+        for x in 0..side {
+            for y in 0..side {
+                for z in 0..side {
+                    let pos = SPACING * Vec3::new(x as f64, y as f64 + 0.5, z as f64);
+                    let particle = Particle::new()
+                        .id(particles.len() as u32)
+                        .pos(pos)
+                        .mass(1.0);
+                    particles.push(particle);
+                }
+            }
+        }
+        let references = (0..particles.len())
+            .map(|index| ParticleReference::new(particles[index].id, index))
+            .collect();
+        (particles, references)
+    }
+
+    fn fluid(boundary: SphBoundary, references: &[ParticleReference]) -> SphParameters {
+        SphParameters::new(SMOOTHING, Tait::new(1000.0, 10.0))
+            .kernel(CubicSplineKernel::new(Dimension::Three))
+            .gradient_kernel(CubicSplineKernel::new(Dimension::Three))
+            .boundary(boundary)
+            .with_particles(references)
+    }
+
+    fn total_force(particle: &Particle) -> Vec3 {
+        particle
+            .forces
+            .iter()
+            .fold(Vec3::zero(), |total, force| total + *force)
+    }
+
+    #[test]
+    fn wall_tables_integrate_the_kernel_over_the_solid() {
+        for dimension in [Dimension::One, Dimension::Two, Dimension::Three] {
+            let kernel = CubicSplineKernel::new(dimension);
+            let table = WallTable::new(&kernel, &kernel, dimension);
+            // buried in the wall, at its surface, and clear of it
+            assert!((table.lookup(-table.reach)[0] - 1.0).abs() < 1e-3);
+            assert!((table.lookup(0.0)[0] - 0.5).abs() < 1e-3);
+            assert_eq!(table.lookup(table.reach)[0], 0.0);
+
+            // and the slices are the rate at which the volume integral falls away
+            let step = 0.01;
+            for q in [-1.5, -0.6, 0.3, 1.1] {
+                let slope = (table.lookup(q + step)[0] - table.lookup(q - step)[0]) / (2.0 * step);
+                assert!(
+                    (slope + table.lookup(q)[1]).abs() < 1e-3,
+                    "{:?} at {}",
+                    dimension,
+                    q
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn walls_make_up_the_density_missing_at_the_floor() {
+        let (mut particles, references) = block(10);
+        let floor = SphBoundary::new(1000.0, Dimension::Three)
+            .wall(HalfSpace::new(Vec3::zero(), Vec3::new(0.0, 1.0, 0.0)));
+        fluid(floor, &references).handle(&mut particles, 0.0);
+
+        let mut checked = 0;
+        for particle in &particles {
+            let middle = [particle.pos.x, particle.pos.z]
+                .iter()
+                .all(|x| (0.3..=0.6).contains(x));
+            if middle && particle.pos.y < 0.5 {
+                assert!(
+                    (particle.density - 1000.0).abs() < 0.03 * 1000.0,
+                    "{}",
+                    particle.density
+                );
+                checked += 1;
+            }
+        }
+        assert!(checked > 0);
+        // the floor's pressure holds the bottom layer up
+        let bottom = particles
+            .iter()
+            .find(|particle| particle.pos.y < SPACING)
+            .unwrap();
+        assert!(total_force(bottom).y > 0.0);
+    }
+
+    #[test]
+    fn boundary_particles_feel_the_opposite_forces() {
+        let (mut particles, references) = block(6);
+        let fluid_count = particles.len();
+        for x in -2..8 {
+            for z in -2..8 {
+                let pos = SPACING * Vec3::new(x as f64, -0.5, z as f64);
+                let id = particles.len() as u32;
+                particles.push(Particle::new().id(id).pos(pos).mass(1.0));
+            }
+        }
+        for (index, particle) in particles.iter_mut().enumerate() {
+            particle.vel = Vec3::new(0.3, -0.2, 0.1) * (index % 3) as f64;
+        }
+        let boundary_references: Vec<ParticleReference> = (fluid_count..particles.len())
+            .map(|index| ParticleReference::new(particles[index].id, index))
+            .collect();
+        let boundary = SphBoundary::new(1000.0, Dimension::Three)
+            .no_slip(0.5)
+            .with_particles(&boundary_references);
+        fluid(boundary, &references).handle(&mut particles, 0.0);
+
+        let (mut net, mut scale) = (Vec3::zero(), 0.0);
+        for particle in &particles {
+            net += total_force(particle);
+            scale += total_force(particle).mag();
+        }
+        assert!(particles[fluid_count..]
+            .iter()
+            .any(|particle| !particle.forces.is_empty()));
+        assert!(net.mag() < 1e-12 * scale);
+    }
+
+    #[test]
+    fn contacts_put_fluid_back_on_the_wall_surface() {
+        let mut particles = vec![
+            Particle::new().id(0).pos_xyz(0.3, 0.2, 0.0).mass(1.0),
+            Particle::new().id(1).pos_xyz(2.0, 0.0, 0.0).mass(1.0),
+        ];
+        let references = [ParticleReference::new(0, 0), ParticleReference::new(1, 1)];
+        let boundary = SphBoundary::new(1000.0, Dimension::Three)
+            .wall(Ball::new(Vec3::zero(), 1.0))
+            .wall(HalfSpace::new(
+                Vec3::new(0.0, -1.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+            ));
+        let mut contacts = boundary.contacts(&references);
+        assert_eq!(contacts.len(), 4);
+
+        for contact in contacts.iter_mut() {
+            contact.project(&mut particles, 1.0 / 60.0, true);
+        }
+        assert!((particles[0].pos.mag() - 1.0).abs() < 1e-9);
+        assert!(particles[0].pos.x > 0.0 && particles[0].pos.y > 0.0);
+        assert!((particles[1].pos - Vec3::new(2.0, 0.0, 0.0)).mag() == 0.0);
+    }
+}
+
+//---------------------------------------------------------------------------------------------------//
@@ -0,0 +1,623 @@
+//! Smoothing kernels.
+//!
+//! The gaussian and the kernels of Müller et al. (poly6, spiky and viscosity) are normalized
+//! in three dimensions. The spline and Wendland kernels can be normalized in one, two or
+//! three dimensions, and are written as W(r, h) = σ / h^d f(r / h) for a shape function f.
+//! Throughout, `div_w` is the radial derivative dW/dr.
+
+//---------------------------------------------------------------------------------------------------//
+use crate::{
+    math::{Point3, Vec3, PI},
+    sph::SphKernel,
+};
+
+pub use crate::math::Dimension;
+
+//---------------------------------------------------------------------------------------------------//
+
+/// W, dW/dr and d²W/dr² of a kernel W = σ / h^d f(r / h), from f and its first two derivatives.
+fn scaled(sigma: f64, dimension: Dimension, h: f64, shape: [f64; 3]) -> [f64; 3] {
+    let norm = sigma / h.powi(dimension.count());
+    [
+        norm * shape[0],
+        norm * shape[1] / h,
+        norm * shape[2] / h.powi(2),
+    ]
+}
+
+/// The gradient of a radially symmetric kernel from its radial derivative.
+fn radial_gradient(r: Point3, dw_dr: f64) -> Vec3 {
+    let mag = r.mag();
+    if mag == 0.0 {
+        Vec3::zero()
+    } else {
+        (dw_dr / mag) * r
+    }
+}
+
+/// The Laplacian of a radially symmetric kernel, W'' + (d - 1) W' / r. At the origin the
+/// second term tends to (d - 1) W'' for kernels that are smooth there.
+fn radial_laplacian(r: f64, radial: [f64; 3], dimension: Dimension) -> f64 {
+    let d = dimension.count() as f64;
+    if r == 0.0 {
+        d * radial[2]
+    } else {
+        radial[2] + (d - 1.0) * radial[1] / r
+    }
+}
+
+//---------------------------------------------------------------------------------------------------//
+// kernels normalized in three dimensions
+
+pub struct GaussianKernel;
+impl SphKernel for GaussianKernel {
+    fn w(&self, r: Point3, h: f64) -> f64 {
+        (h.powi(3) * PI.powi(3).sqrt()).powi(-1) * (-r.mag_squared() / h.powi(2)).exp()
+    }
+
+    fn grad_w(&self, r: Point3, h: f64) -> Vec3 {
+        -2. * (h.powi(5) * PI.powi(3).sqrt()).powi(-1) * (-r.mag_squared() / h.powi(2)).exp() * r
+    }
+
+    fn div_w(&self, r: Point3, h: f64) -> f64 {
+        -2. * r.mag() / h.powi(2) * self.w(r, h)
+    }
+
+    fn laplace_w(&self, r: Point3, h: f64) -> f64 {
+        (4. * r.mag_squared() / h.powi(4) - 6. / h.powi(2)) * self.w(r, h)
+    }
+
+    /// The gaussian never reaches zero, but beyond 3h it is below 0.02% of its peak.
+    fn support(&self, h: f64) -> f64 {
+        3. * h
+    }
+}
+
+pub struct Poly6Kernel;
+impl SphKernel for Poly6Kernel {
+    fn w(&self, r: Point3, h: f64) -> f64 {
+        if r.mag_squared() >= h.powi(2) {
+            return 0.0;
+        }
+        315. * (64. * PI * h.powi(9)).powi(-1) * (h.powi(2) - r.mag_squared()).powi(3)
+    }
+
+    fn grad_w(&self, r: Point3, h: f64) -> Vec3 {
+        if r.mag_squared() >= h.powi(2) {
+            return Vec3::zero();
+        }
+        -945. * (32. * PI * h.powi(9)).powi(-1) * (h.powi(2) - r.mag_squared()).powi(2) * r
+    }
+
+    fn div_w(&self, r: Point3, h: f64) -> f64 {
+        let mag = r.mag();
+        if mag >= h {
+            return 0.0;
+        }
+        -945. * (32. * PI * h.powi(9)).powi(-1) * (h.powi(2) - mag.powi(2)).powi(2) * mag
+    }
+
+    fn laplace_w(&self, r: Point3, h: f64) -> f64 {
+        let r_squared = r.mag_squared();
+        if r_squared >= h.powi(2) {
+            return 0.0;
+        }
+        -945.
+            * (32. * PI * h.powi(9)).powi(-1)
+            * (h.powi(2) - r_squared)
+            * (3. * h.powi(2) - 7. * r_squared)
+    }
+}
+
+/// A kernel with a sharp peak, so that the pressure gradient doesn't vanish as particles clump.
+pub struct SpikyKernel;
+impl SphKernel for SpikyKernel {
+    fn w(&self, r: Point3, h: f64) -> f64 {
+        let mag = r.mag();
+        if mag >= h {
+            return 0.0;
+        }
+        15. * (PI * h.powi(6)).powi(-1) * (h - mag).powi(3)
+    }
+
+    fn grad_w(&self, r: Point3, h: f64) -> Vec3 {
+        let mag = r.mag();
+        if mag >= h || mag == 0.0 {
+            return Vec3::zero();
+        }
+        -45. * (PI * h.powi(6)).powi(-1) * (h - mag).powi(2) * mag.powi(-1) * r
+    }
+
+    fn div_w(&self, r: Point3, h: f64) -> f64 {
+        let mag = r.mag();
+        if mag >= h {
+            return 0.0;
+        }
+        -45. * (PI * h.powi(6)).powi(-1) * (h - mag).powi(2)
+    }
+
+    /// Diverges at the origin, where zero is returned instead.
+    fn laplace_w(&self, r: Point3, h: f64) -> f64 {
+        let mag = r.mag();
+        if mag >= h || mag == 0.0 {
+            return 0.0;
+        }
+        90. * (PI * h.powi(6)).powi(-1) * (h - mag) * (2. * mag - h) / mag
+    }
+}
+
+/// A kernel whose Laplacian is positive everywhere, for viscous forces that only ever damp.
+/// W itself diverges at the origin, so it is not suitable for density estimates.
+pub struct ViscosityKernel;
+impl SphKernel for ViscosityKernel {
+    fn w(&self, r: Point3, h: f64) -> f64 {
+        let mag = r.mag();
+        if mag >= h {
+            return 0.0;
+        }
+        15. * (2. * PI * h.powi(3)).powi(-1)
+            * (-mag.powi(3) / (2. * h.powi(3)) + mag.powi(2) / h.powi(2) + h / (2. * mag) - 1.)
+    }
+
+    fn grad_w(&self, r: Point3, h: f64) -> Vec3 {
+        let mag = r.mag();
+        if mag >= h || mag == 0.0 {
+            return Vec3::zero();
+        }
+        radial_gradient(r, self.div_w(r, h))
+    }
+
+    fn div_w(&self, r: Point3, h: f64) -> f64 {
+        let mag = r.mag();
+        if mag >= h {
+            return 0.0;
+        }
+        15. * (2. * PI * h.powi(3)).powi(-1)
+            * (-3. * mag.powi(2) / (2. * h.powi(3)) + 2. * mag / h.powi(2) - h / (2. * mag.powi(2)))
+    }
+
+    fn laplace_w(&self, r: Point3, h: f64) -> f64 {
+        let mag = r.mag();
+        if mag >= h {
+            return 0.0;
+        }
+        45. * (PI * h.powi(6)).powi(-1) * (h - mag)
+    }
+}
+
+//---------------------------------------------------------------------------------------------------//
+// spline kernels
+
+/// The M4 cubic B-spline of Monaghan & Lattanzio, with support 2h.
+pub struct CubicSplineKernel(Dimension);
+
+impl CubicSplineKernel {
+    pub fn new(dimension: Dimension) -> CubicSplineKernel {
+        CubicSplineKernel(dimension)
+    }
+
+    fn radial(&self, r: f64, h: f64) -> [f64; 3] {
+        let sigma = match self.0 {
+            Dimension::One => 2. / 3.,
+            Dimension::Two => 10. / (7. * PI),
+            Dimension::Three => 1. / PI,
+        };
+        let q = r / h;
+        let shape = if q < 1. {
+            [
+                1. - 1.5 * q.powi(2) + 0.75 * q.powi(3),
+                -3. * q + 2.25 * q.powi(2),
+                -3. + 4.5 * q,
+            ]
+        } else if q < 2. {
+            [
+                0.25 * (2. - q).powi(3),
+                -0.75 * (2. - q).powi(2),
+                1.5 * (2. - q),
+            ]
+        } else {
+            [0.0; 3]
+        };
+        scaled(sigma, self.0, h, shape)
+    }
+}
+
+impl SphKernel for CubicSplineKernel {
+    fn w(&self, r: Point3, h: f64) -> f64 {
+        self.radial(r.mag(), h)[0]
+    }
+
+    fn grad_w(&self, r: Point3, h: f64) -> Vec3 {
+        radial_gradient(r, self.radial(r.mag(), h)[1])
+    }
+
+    fn div_w(&self, r: Point3, h: f64) -> f64 {
+        self.radial(r.mag(), h)[1]
+    }
+
+    fn laplace_w(&self, r: Point3, h: f64) -> f64 {
+        radial_laplacian(r.mag(), self.radial(r.mag(), h), self.0)
+    }
+
+    fn support(&self, h: f64) -> f64 {
+        2. * h
+    }
+}
+
+/// The M6 quintic B-spline of Morris, with support 3h.
+pub struct QuinticKernel(Dimension);
+
+impl QuinticKernel {
+    pub fn new(dimension: Dimension) -> QuinticKernel {
+        QuinticKernel(dimension)
+    }
+
+    fn radial(&self, r: f64, h: f64) -> [f64; 3] {
+        let sigma = match self.0 {
+            Dimension::One => 1. / 120.,
+            Dimension::Two => 7. / (478. * PI),
+            Dimension::Three => 1. / (120. * PI),
+        };
+        let q = r / h;
+        let mut shape = [0.0; 3];
+        // each term switches on inside its own radius
+        for (radius, weight) in [(3., 1.), (2., -6.), (1., 15.)] {
+            if q < radius {
+                let x: f64 = radius - q;
+                shape[0] += weight * x.powi(5);
+                shape[1] += -5. * weight * x.powi(4);
+                shape[2] += 20. * weight * x.powi(3);
+            }
+        }
+        scaled(sigma, self.0, h, shape)
+    }
+}
+
+impl SphKernel for QuinticKernel {
+    fn w(&self, r: Point3, h: f64) -> f64 {
+        self.radial(r.mag(), h)[0]
+    }
+
+    fn grad_w(&self, r: Point3, h: f64) -> Vec3 {
+        radial_gradient(r, self.radial(r.mag(), h)[1])
+    }
+
+    fn div_w(&self, r: Point3, h: f64) -> f64 {
+        self.radial(r.mag(), h)[1]
+    }
+
+    fn laplace_w(&self, r: Point3, h: f64) -> f64 {
+        radial_laplacian(r.mag(), self.radial(r.mag(), h), self.0)
+    }
+
+    fn support(&self, h: f64) -> f64 {
+        3. * h
+    }
+}
+
+//---------------------------------------------------------------------------------------------------//
+// wendland kernels
+
+/// Wendland's functions are only positive definite up to a given dimension, so the 1d
+/// kernels use a different polynomial than the 2d and 3d ones. All have support 2h, and are
+/// written in terms of s = r / 2h.
+fn wendland_radial(
+    dimension: Dimension,
+    r: f64,
+    h: f64,
+    sigmas: [f64; 3],
+    shape: impl Fn(f64) -> [f64; 3],
+) -> [f64; 3] {
+    let sigma = match dimension {
+        Dimension::One => sigmas[0],
+        Dimension::Two => sigmas[1],
+        Dimension::Three => sigmas[2],
+    };
+    let s = r / (2. * h);
+    if s >= 1. {
+        return [0.0; 3];
+    }
+    let [f, df, d2f] = shape(s);
+    // convert from the normalization over the support radius, and from derivatives in s
+    scaled(
+        sigma / 2f64.powi(dimension.count()),
+        dimension,
+        h,
+        [f, df / 2., d2f / 4.],
+    )
+}
+
+/// The C2 Wendland kernel, with support 2h.
+pub struct WendlandC2Kernel(Dimension);
+
+impl WendlandC2Kernel {
+    pub fn new(dimension: Dimension) -> WendlandC2Kernel {
+        WendlandC2Kernel(dimension)
+    }
+
+    fn radial(&self, r: f64, h: f64) -> [f64; 3] {
+        let one_dimensional = self.0 == Dimension::One;
+        wendland_radial(self.0, r, h, [5. / 4., 7. / PI, 21. / (2. * PI)], |s| {
+            let t = 1. - s;
+            if one_dimensional {
+                [
+                    t.powi(3) * (1. + 3. * s),
+                    -12. * s * t.powi(2),
+                    12. * t * (3. * s - 1.),
+                ]
+            } else {
+                [
+                    t.powi(4) * (1. + 4. * s),
+                    -20. * s * t.powi(3),
+                    20. * t.powi(2) * (4. * s - 1.),
+                ]
+            }
+        })
+    }
+}
+
+impl SphKernel for WendlandC2Kernel {
+    fn w(&self, r: Point3, h: f64) -> f64 {
+        self.radial(r.mag(), h)[0]
+    }
+
+    fn grad_w(&self, r: Point3, h: f64) -> Vec3 {
+        radial_gradient(r, self.radial(r.mag(), h)[1])
+    }
+
+    fn div_w(&self, r: Point3, h: f64) -> f64 {
+        self.radial(r.mag(), h)[1]
+    }
+
+    fn laplace_w(&self, r: Point3, h: f64) -> f64 {
+        radial_laplacian(r.mag(), self.radial(r.mag(), h), self.0)
+    }
+
+    fn support(&self, h: f64) -> f64 {
+        2. * h
+    }
+}
+
+/// The C4 Wendland kernel, with support 2h.
+pub struct WendlandC4Kernel(Dimension);
+
+impl WendlandC4Kernel {
+    pub fn new(dimension: Dimension) -> WendlandC4Kernel {
+        WendlandC4Kernel(dimension)
+    }
+
+    fn radial(&self, r: f64, h: f64) -> [f64; 3] {
+        let one_dimensional = self.0 == Dimension::One;
+        wendland_radial(self.0, r, h, [3. / 2., 9. / PI, 495. / (32. * PI)], |s| {
+            let t = 1. - s;
+            if one_dimensional {
+                [
+                    t.powi(5) * (1. + 5. * s + 8. * s.powi(2)),
+                    -14. * s * t.powi(4) * (1. + 4. * s),
+                    -14. * t.powi(3) * (1. + 3. * s - 24. * s.powi(2)),
+                ]
+            } else {
+                [
+                    t.powi(6) * (1. + 6. * s + 35. / 3. * s.powi(2)),
+                    -56. / 3. * s * t.powi(5) * (1. + 5. * s),
+                    56. / 3. * t.powi(4) * (35. * s.powi(2) - 4. * s - 1.),
+                ]
+            }
+        })
+    }
+}
+
+impl SphKernel for WendlandC4Kernel {
+    fn w(&self, r: Point3, h: f64) -> f64 {
+        self.radial(r.mag(), h)[0]
+    }
+
+    fn grad_w(&self, r: Point3, h: f64) -> Vec3 {
+        radial_gradient(r, self.radial(r.mag(), h)[1])
+    }
+
+    fn div_w(&self, r: Point3, h: f64) -> f64 {
+        self.radial(r.mag(), h)[1]
+    }
+
+    fn laplace_w(&self, r: Point3, h: f64) -> f64 {
+        radial_laplacian(r.mag(), self.radial(r.mag(), h), self.0)
+    }
+
+    fn support(&self, h: f64) -> f64 {
+        2. * h
+    }
+}
+
+/// The C6 Wendland kernel, with support 2h.
+pub struct WendlandC6Kernel(Dimension);
+
+impl WendlandC6Kernel {
+    pub fn new(dimension: Dimension) -> WendlandC6Kernel {
+        WendlandC6Kernel(dimension)
+    }
+
+    fn radial(&self, r: f64, h: f64) -> [f64; 3] {
+        let one_dimensional = self.0 == Dimension::One;
+        wendland_radial(
+            self.0,
+            r,
+            h,
+            [55. / 32., 78. / (7. * PI), 1365. / (64. * PI)],
+            |s| {
+                let t = 1. - s;
+                if one_dimensional {
+                    [
+                        t.powi(7) * (1. + 7. * s + 19. * s.powi(2) + 21. * s.powi(3)),
+                        -6. * s * t.powi(6) * (3. + 18. * s + 35. * s.powi(2)),
+                        18. * t.powi(5) * (105. * s.powi(3) + 13. * s.powi(2) - 5. * s - 1.),
+                    ]
+                } else {
+                    [
+                        t.powi(8) * (1. + 8. * s + 25. * s.powi(2) + 32. * s.powi(3)),
+                        -22. * s * t.powi(7) * (1. + 7. * s + 16. * s.powi(2)),
+                        22. * t.powi(6) * (160. * s.powi(3) + 15. * s.powi(2) - 6. * s - 1.),
+                    ]
+                }
+            },
+        )
+    }
+}
+
+impl SphKernel for WendlandC6Kernel {
+    fn w(&self, r: Point3, h: f64) -> f64 {
+        self.radial(r.mag(), h)[0]
+    }
+
+    fn grad_w(&self, r: Point3, h: f64) -> Vec3 {
+        radial_gradient(r, self.radial(r.mag(), h)[1])
+    }
+
+    fn div_w(&self, r: Point3, h: f64) -> f64 {
+        self.radial(r.mag(), h)[1]
+    }
+
+    fn laplace_w(&self, r: Point3, h: f64) -> f64 {
+        radial_laplacian(r.mag(), self.radial(r.mag(), h), self.0)
+    }
+
+    fn support(&self, h: f64) -> f64 {
+        2. * h
+    }
+}
+
+//---------------------------------------------------------------------------------------------------//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const H: f64 = 1.3;
+
+    /// Every kernel with the dimension it's normalized over, and the radius from which it's
+    /// smooth.
+    fn kernels() -> Vec<(String, Box<dyn SphKernel>, Dimension, f64)> {
+        let support = 2.0 * H;
+        let mut kernels: Vec<(String, Box<dyn SphKernel>, Dimension, f64)> = vec![
+            (
+                "gaussian".into(),
+                Box::new(GaussianKernel),
+                Dimension::Three,
+                0.0,
+            ),
+            ("poly6".into(), Box::new(Poly6Kernel), Dimension::Three, 0.0),
+            // the spiky and viscosity kernels are singular at the origin
+            (
+                "spiky".into(),
+                Box::new(SpikyKernel),
+                Dimension::Three,
+                0.05 * support,
+            ),
+            (
+                "viscosity".into(),
+                Box::new(ViscosityKernel),
+                Dimension::Three,
+                0.05 * support,
+            ),
+        ];
+        for dimension in [Dimension::One, Dimension::Two, Dimension::Three] {
+            let d = dimension.count();
+            kernels.push((
+                format!("cubic spline {d}d"),
+                Box::new(CubicSplineKernel::new(dimension)),
+                dimension,
+                0.0,
+            ));
+            kernels.push((
+                format!("quintic {d}d"),
+                Box::new(QuinticKernel::new(dimension)),
+                dimension,
+                0.0,
+            ));
+            kernels.push((
+                format!("wendland c2 {d}d"),
+                Box::new(WendlandC2Kernel::new(dimension)),
+                dimension,
+                0.0,
+            ));
+            kernels.push((
+                format!("wendland c4 {d}d"),
+                Box::new(WendlandC4Kernel::new(dimension)),
+                dimension,
+                0.0,
+            ));
+            kernels.push((
+                format!("wendland c6 {d}d"),
+                Box::new(WendlandC6Kernel::new(dimension)),
+                dimension,
+                0.0,
+            ));
+        }
+        kernels
+    }
+
+    #[test]
+    fn kernels_integrate_to_one() {
+        for (name, kernel, dimension, _) in kernels() {
+            let steps = 20_000;
+            let dr = kernel.support(H) / steps as f64;
+            let surface = |r: f64| match dimension {
+                Dimension::One => 2.0,
+                Dimension::Two => 2.0 * PI * r,
+                Dimension::Three => 4.0 * PI * r * r,
+            };
+            let integral: f64 = (0..steps)
+                .map(|step| {
+                    let r = (step as f64 + 0.5) * dr;
+                    surface(r) * kernel.w(r * Vec3::x_hat(), H) * dr
+                })
+                .sum();
+            // the gaussian loses a little of its weight past its nominal support
+            let tolerance = if name == "gaussian" { 1e-3 } else { 1e-6 };
+            assert!(
+                (integral - 1.0).abs() < tolerance,
+                "{name}: ∫W = {integral}"
+            );
+        }
+    }
+
+    #[test]
+    fn derivatives_match_finite_differences() {
+        let direction = Vec3::new(0.48, -0.6, 0.64);
+        for (name, kernel, dimension, from) in kernels() {
+            let d = dimension.count() as f64;
+            let support = kernel.support(H);
+            let step = 1e-4 * support;
+            let w = |r: f64| kernel.w(r * direction, H);
+
+            let (mut first_error, mut first_scale) = (0.0_f64, 0.0_f64);
+            let (mut second_error, mut second_scale) = (0.0_f64, 0.0_f64);
+            for sample in 1..200 {
+                let r = from + (support - from) * sample as f64 / 200.0;
+                let numeric_first = (w(r + step) - w(r - step)) / (2.0 * step);
+                let numeric_second = (w(r + step) - 2.0 * w(r) + w(r - step)) / step.powi(2);
+                let numeric_laplace = numeric_second + (d - 1.0) * numeric_first / r;
+
+                let analytic_first = kernel.div_w(r * direction, H);
+                let gradient = kernel.grad_w(r * direction, H);
+                first_error = first_error
+                    .max((analytic_first - numeric_first).abs())
+                    .max((gradient - analytic_first * direction).mag());
+                first_scale = first_scale.max(analytic_first.abs());
+                second_error =
+                    second_error.max((kernel.laplace_w(r * direction, H) - numeric_laplace).abs());
+                second_scale = second_scale.max(numeric_laplace.abs());
+            }
+            assert!(
+                first_error / first_scale < 1e-4,
+                "{name}: inconsistent gradient"
+            );
+            assert!(
+                second_error / second_scale < 1e-3,
+                "{name}: inconsistent laplacian"
+            );
+        }
+    }
+}
+
+//---------------------------------------------------------------------------------------------------//
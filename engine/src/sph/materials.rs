@@ -0,0 +1,118 @@
+//! Materials, for fluids made of several phases or with viscosities that depend on how fast
+//! they are sheared.
+//!
+//! A [`Material`] gives the particles made of it their own rest density, state equation and
+//! viscosity law. The SPH interaction looks up a particle's material by `Particle::material`,
+//! or by its group if it has none.
+
+//---------------------------------------------------------------------------------------------------//
+use crate::sph::StateEquation;
+
+//---------------------------------------------------------------------------------------------------//
+
+/// How a fluid's viscosity depends on the rate γ̇ at which it is sheared.
+#[derive(Copy, Clone, Debug)]
+pub enum ViscosityLaw {
+    /// A constant dynamic viscosity μ, as for water or oil.
+    Newtonian(f64),
+    /// μ = K γ̇^(n - 1), which thins under shear for n < 1 (paints, blood) and thickens for
+    /// n > 1 (cornstarch). A shear-thinning fluid would be infinitely viscous at rest, so the
+    /// viscosity is capped.
+    PowerLaw {
+        consistency: f64,
+        index: f64,
+        max_viscosity: f64,
+    },
+    /// A Bingham plastic, such as mud, which only flows under stresses above its yield stress
+    /// τ0 and then with a plastic viscosity μp. Papanastasiou's regularization
+    /// μ = μp + τ0 (1 - exp(-m γ̇)) / γ̇ gives the unyielded material a large but finite
+    /// viscosity of μp + m τ0.
+    Bingham {
+        yield_stress: f64,
+        plastic_viscosity: f64,
+        regularization: f64,
+    },
+    /// Cross' law μ = μ∞ + (μ0 - μ∞) / (1 + (λ γ̇)^m), which thins from μ0 at rest to μ∞ under
+    /// fast shear, as polymer solutions do.
+    Cross {
+        zero_shear_viscosity: f64,
+        infinite_shear_viscosity: f64,
+        time_constant: f64,
+        exponent: f64,
+    },
+}
+
+impl ViscosityLaw {
+    /// The apparent viscosity at a shear rate γ̇ = √(2 D:D), for the strain rate tensor D.
+    pub fn viscosity(&self, shear_rate: f64) -> f64 {
+        match *self {
+            ViscosityLaw::Newtonian(viscosity) => viscosity,
+            ViscosityLaw::PowerLaw {
+                consistency,
+                index,
+                max_viscosity,
+            } => {
+                if shear_rate > 0.0 {
+                    (consistency * shear_rate.powf(index - 1.0)).min(max_viscosity)
+                } else if index < 1.0 {
+                    max_viscosity
+                } else {
+                    0.0
+                }
+            }
+            ViscosityLaw::Bingham {
+                yield_stress,
+                plastic_viscosity,
+                regularization,
+            } => {
+                // (1 - exp(-m γ̇)) / γ̇ tends to m as the shear rate vanishes
+                let plastic = if shear_rate > 0.0 {
+                    -(-regularization * shear_rate).exp_m1() / shear_rate
+                } else {
+                    regularization
+                };
+                plastic_viscosity + yield_stress * plastic
+            }
+            ViscosityLaw::Cross {
+                zero_shear_viscosity,
+                infinite_shear_viscosity,
+                time_constant,
+                exponent,
+            } => {
+                infinite_shear_viscosity
+                    + (zero_shear_viscosity - infinite_shear_viscosity)
+                        / (1.0 + (time_constant * shear_rate).powf(exponent))
+            }
+        }
+    }
+
+    pub fn is_newtonian(&self) -> bool {
+        matches!(self, ViscosityLaw::Newtonian(_))
+    }
+}
+
+/// A fluid's rest density, state equation and viscosity. Materials without a viscosity law are
+/// inviscid, apart from any artificial viscosity.
+pub struct Material {
+    pub(crate) rest_density: f64,
+    pub(crate) state_equation: Box<dyn StateEquation>,
+    pub(crate) viscosity: Option<ViscosityLaw>,
+}
+
+impl Material {
+    /// The rest density should match the state equation's.
+    pub fn new(rest_density: f64, state_equation: impl StateEquation + 'static) -> Material {
+        Material {
+            rest_density,
+            state_equation: Box::new(state_equation),
+            viscosity: None,
+        }
+    }
+
+    pub fn viscosity(mut self, law: ViscosityLaw) -> Material {
+        self.viscosity = Some(law);
+        self
+    }
+}
+
+//---------------------------------------------------------------------------------------------------//
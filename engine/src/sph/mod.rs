@@ -0,0 +1,60 @@
+//! Building blocks for smoothed-particle hydrodynamics: smoothing kernels, equations of state,
+//! materials, implicit pressure solvers and solid boundaries.
+//!
+//! The SPH interaction itself lives in [`crate::interaction::sph`].
+
+pub mod boundary;
+pub mod kernels;
+pub mod materials;
+pub mod pressure_solvers;
+pub mod state_equations;
+
+//---------------------------------------------------------------------------------------------------//
+use crate::{
+    math::{Point3, Vec3},
+    particle::Particle,
+};
+
+//---------------------------------------------------------------------------------------------------//
+
+/// A smoothing kernel W(r, h), where `r` is the displacement from the kernel's center.
+pub trait SphKernel {
+    fn w(&self, r: Point3, h: f64) -> f64;
+    fn grad_w(&self, r: Point3, h: f64) -> Vec3;
+    fn div_w(&self, r: Point3, h: f64) -> f64;
+    fn laplace_w(&self, r: Point3, h: f64) -> f64;
+
+    /// The distance beyond which the kernel can be treated as zero.
+    fn support(&self, h: f64) -> f64 {
+        h
+    }
+}
+
+/// Relates a particle's state (density, temperature, ...) to its pressure.
+pub trait StateEquation {
+    fn pressure(&self, particle: &Particle) -> f64;
+
+    /// The adiabatic sound speed, sqrt(dp/dρ) at constant entropy.
+    fn sound_speed(&self, particle: &Particle) -> f64;
+}
+
+/// Solves for the pressures that keep a fluid incompressible. The [`crate::system::System`] runs
+/// its pressure solvers after the interactions, so that the forces they leave on the particles
+/// can be accounted for, and before integrating. The pressures are applied as forces.
+pub trait PressureSolver {
+    fn solve(&mut self, particle_source: &mut [Particle], dt: f64);
+
+    /// How the most recent solve went.
+    fn report(&self) -> PressureSolveReport;
+}
+
+/// The outcome of a pressure solve. Density errors are compressions relative to the rest density.
+#[derive(Copy, Clone, Default, Debug)]
+pub struct PressureSolveReport {
+    pub iterations: u32,
+    pub average_density_error: f64,
+    pub max_density_error: f64,
+    pub converged: bool,
+}
+
+//---------------------------------------------------------------------------------------------------//
@@ -0,0 +1,302 @@
+//! Equations of state.
+//!
+//! Pressures are computed from `Particle::density`, and from `Particle::temperature` or
+//! `Particle::entropy` for the equations that depend on the thermal state as well.
+
+//---------------------------------------------------------------------------------------------------//
+use crate::{particle::Particle, sph::StateEquation};
+
+//---------------------------------------------------------------------------------------------------//
+
+/// Tait's equation for a weakly-compressible liquid such as water, p = B((ρ/ρ0)^γ - 1) with
+/// B = ρ0 c0² / γ. The density varies by roughly (v / c0)² for flow speeds v, so c0 is usually
+/// chosen around ten times the fastest expected flow.
+pub struct Tait {
+    rest_density: f64,
+    sound_speed: f64,
+    exponent: f64,
+    background_pressure: f64,
+    non_negative: bool,
+}
+
+impl Tait {
+    /// Defaults to the exponent of 7 used for water.
+    pub fn new(rest_density: f64, sound_speed: f64) -> Tait {
+        Tait {
+            rest_density,
+            sound_speed,
+            exponent: 7.0,
+            background_pressure: 0.0,
+            non_negative: false,
+        }
+    }
+
+    pub fn exponent(mut self, exponent: f64) -> Tait {
+        self.exponent = exponent;
+        self
+    }
+
+    /// A constant added to every pressure. It has no effect on the flow, other than to
+    /// keep the pressure positive as the fluid is stretched.
+    pub fn background_pressure(mut self, pressure: f64) -> Tait {
+        self.background_pressure = pressure;
+        self
+    }
+
+    /// Clamp negative pressures to zero, so that the fluid never pulls on itself.
+    /// This avoids clumping at free surfaces, at the cost of any tensile strength.
+    pub fn non_negative(mut self) -> Tait {
+        self.non_negative = true;
+        self
+    }
+
+    fn stiffness(&self) -> f64 {
+        self.rest_density * self.sound_speed.powi(2) / self.exponent
+    }
+}
+
+impl StateEquation for Tait {
+    fn pressure(&self, particle: &Particle) -> f64 {
+        let pressure = self.stiffness()
+            * ((particle.density / self.rest_density).powf(self.exponent) - 1.0)
+            + self.background_pressure;
+        if self.non_negative {
+            pressure.max(0.0)
+        } else {
+            pressure
+        }
+    }
+
+    fn sound_speed(&self, particle: &Particle) -> f64 {
+        self.sound_speed * (particle.density / self.rest_density).powf(0.5 * (self.exponent - 1.0))
+    }
+}
+
+//--------------------------------------------------------------------//
+
+/// An ideal gas, p = ρ R T, where R is the specific gas constant (k_B / μ m_H).
+pub struct IdealGas {
+    adiabatic_index: f64,
+    specific_gas_constant: f64,
+    from_entropy: bool,
+}
+
+impl IdealGas {
+    pub fn new(adiabatic_index: f64, specific_gas_constant: f64) -> IdealGas {
+        IdealGas {
+            adiabatic_index,
+            specific_gas_constant,
+            from_entropy: false,
+        }
+    }
+
+    /// Read the entropic function A = p / ρ^γ from `Particle::entropy` instead of using the
+    /// temperature, so that p = A ρ^γ. This is the natural variable for entropy-conserving SPH.
+    pub fn from_entropy(mut self) -> IdealGas {
+        self.from_entropy = true;
+        self
+    }
+}
+
+impl StateEquation for IdealGas {
+    fn pressure(&self, particle: &Particle) -> f64 {
+        if self.from_entropy {
+            particle.entropy * particle.density.powf(self.adiabatic_index)
+        } else {
+            particle.density * self.specific_gas_constant * particle.temperature
+        }
+    }
+
+    fn sound_speed(&self, particle: &Particle) -> f64 {
+        if particle.density <= 0.0 {
+            return 0.0;
+        }
+        (self.adiabatic_index * self.pressure(particle).max(0.0) / particle.density).sqrt()
+    }
+}
+
+//--------------------------------------------------------------------//
+
+/// A gas held at constant temperature, p = c² ρ.
+pub struct Isothermal {
+    sound_speed: f64,
+}
+
+impl Isothermal {
+    pub fn new(sound_speed: f64) -> Isothermal {
+        Isothermal { sound_speed }
+    }
+}
+
+impl StateEquation for Isothermal {
+    fn pressure(&self, particle: &Particle) -> f64 {
+        self.sound_speed.powi(2) * particle.density
+    }
+
+    fn sound_speed(&self, _particle: &Particle) -> f64 {
+        self.sound_speed
+    }
+}
+
+//--------------------------------------------------------------------//
+
+/// A polytrope, p = K ρ^(1 + 1/n) for a polytropic index n. An index of 1 gives the
+/// simplest model of a star or gas giant, and 1.5 a fully convective star.
+pub struct Polytropic {
+    constant: f64,
+    exponent: f64,
+}
+
+impl Polytropic {
+    pub fn new(constant: f64, polytropic_index: f64) -> Polytropic {
+        Polytropic {
+            constant,
+            exponent: 1.0 + 1.0 / polytropic_index,
+        }
+    }
+}
+
+impl StateEquation for Polytropic {
+    fn pressure(&self, particle: &Particle) -> f64 {
+        self.constant * particle.density.max(0.0).powf(self.exponent)
+    }
+
+    fn sound_speed(&self, particle: &Particle) -> f64 {
+        (self.exponent * self.constant * particle.density.max(0.0).powf(self.exponent - 1.0)).sqrt()
+    }
+}
+
+//--------------------------------------------------------------------//
+
+/// The stiffened gas of Harlow & Amsden, p = (γ - 1) ρ e - γ p∞, for liquids and solids
+/// under strong compression. The specific internal energy is e = c_v T.
+pub struct StiffenedGas {
+    adiabatic_index: f64,
+    stiffening_pressure: f64,
+    specific_heat: f64,
+}
+
+impl StiffenedGas {
+    pub fn new(adiabatic_index: f64, stiffening_pressure: f64, specific_heat: f64) -> StiffenedGas {
+        StiffenedGas {
+            adiabatic_index,
+            stiffening_pressure,
+            specific_heat,
+        }
+    }
+}
+
+impl StateEquation for StiffenedGas {
+    fn pressure(&self, particle: &Particle) -> f64 {
+        (self.adiabatic_index - 1.0) * particle.density * self.specific_heat * particle.temperature
+            - self.adiabatic_index * self.stiffening_pressure
+    }
+
+    fn sound_speed(&self, particle: &Particle) -> f64 {
+        if particle.density <= 0.0 {
+            return 0.0;
+        }
+        (self.adiabatic_index * (self.pressure(particle) + self.stiffening_pressure).max(0.0)
+            / particle.density)
+            .sqrt()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fluid(density: f64, temperature: f64) -> Particle {
+        let mut particle = Particle::new()
+            .mass(1.0)
+            .temperature(temperature)
+            .entropy(0.7);
+        particle.density = density;
+        particle
+    }
+
+    /// √(∂p/∂ρ) along an adiabat by central differences. Thermal equations of state are
+    /// given their specific heat, so that the temperature follows de = p/ρ² dρ.
+    fn adiabatic_sound_speed(
+        equation: &dyn StateEquation,
+        particle: &Particle,
+        specific_heat: Option<f64>,
+    ) -> f64 {
+        let step = 1e-6 * particle.density;
+        let pressure = equation.pressure(particle);
+        let shifted = |sign: f64| {
+            let mut shifted = fluid(particle.density + sign * step, particle.temperature);
+            if let Some(specific_heat) = specific_heat {
+                shifted.temperature +=
+                    sign * step * pressure / (particle.density.powi(2) * specific_heat);
+            }
+            equation.pressure(&shifted)
+        };
+        ((shifted(1.0) - shifted(-1.0)) / (2.0 * step)).sqrt()
+    }
+
+    fn assert_sound_speed(
+        equation: &dyn StateEquation,
+        particle: &Particle,
+        specific_heat: Option<f64>,
+    ) {
+        let expected = adiabatic_sound_speed(equation, particle, specific_heat);
+        let sound_speed = equation.sound_speed(particle);
+        assert!(
+            (sound_speed - expected).abs() < 1e-6 * expected,
+            "sound speed {} against {} from the pressure",
+            sound_speed,
+            expected
+        );
+    }
+
+    #[test]
+    fn sound_speeds_are_the_adiabatic_pressure_slope() {
+        let particle = fluid(1.3, 2.1);
+        assert_sound_speed(&Tait::new(1.0, 10.0), &particle, None);
+        assert_sound_speed(
+            &Tait::new(1.0, 10.0).exponent(3.0).background_pressure(5.0),
+            &particle,
+            None,
+        );
+        assert_sound_speed(&Isothermal::new(2.5), &particle, None);
+        assert_sound_speed(&Polytropic::new(0.4, 1.5), &particle, None);
+        assert_sound_speed(
+            &IdealGas::new(5.0 / 3.0, 0.8).from_entropy(),
+            &particle,
+            None,
+        );
+        // R = (γ - 1) c_v for an ideal gas.
+        assert_sound_speed(&IdealGas::new(1.4, 0.8), &particle, Some(0.8 / 0.4));
+        assert_sound_speed(&StiffenedGas::new(4.4, 6.0, 3.0), &particle, Some(3.0));
+    }
+
+    #[test]
+    fn tait_pressure_vanishes_at_rest() {
+        let tait = Tait::new(1000.0, 20.0);
+        assert_eq!(tait.pressure(&fluid(1000.0, 0.0)), 0.0);
+        assert_eq!(
+            tait.background_pressure(3.0).pressure(&fluid(1000.0, 0.0)),
+            3.0
+        );
+
+        let stretched = fluid(900.0, 0.0);
+        let tait = Tait::new(1000.0, 20.0);
+        assert!(tait.pressure(&stretched) < 0.0);
+        assert_eq!(tait.non_negative().pressure(&stretched), 0.0);
+        assert!((Tait::new(1000.0, 20.0).sound_speed(&fluid(1000.0, 0.0)) - 20.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn ideal_gas_entropy_and_temperature_agree() {
+        let (adiabatic_index, gas_constant) = (5.0 / 3.0, 0.8);
+        let mut particle = fluid(1.3, 2.1);
+        let pressure = IdealGas::new(adiabatic_index, gas_constant).pressure(&particle);
+        particle.entropy = pressure / particle.density.powf(adiabatic_index);
+        let from_entropy = IdealGas::new(adiabatic_index, gas_constant).from_entropy();
+        assert!((from_entropy.pressure(&particle) - pressure).abs() < 1e-12 * pressure);
+        assert!((pressure - 1.3 * 0.8 * 2.1).abs() < 1e-12);
+    }
+}
+
+//---------------------------------------------------------------------------------------------------//
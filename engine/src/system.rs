@@ -1,7 +1,11 @@
-use crate::constraint::Constraint;
+use crate::barostat::Barostat;
+use crate::constraint::{rattle::Rattle, xpbd::Xpbd, Constraint, ConstraintSolver};
 use crate::interaction::Interaction;
 use crate::math::Vec3;
 use crate::particle::{Particle, ParticleReference};
+use crate::simulation_box::SimulationBox;
+use crate::sph::PressureSolver;
+use crate::thermostat::Thermostat;
 
 //---------------------------------------------------------------------------------------------------//
 
@@ -15,9 +19,25 @@ pub struct System {
 
     pub particles: Vec<Particle>,
     pub interactions: Vec<Box<dyn Interaction>>,
+    pub pressure_solvers: Vec<Box<dyn PressureSolver>>,
     pub constraints: Vec<Box<dyn Constraint>>,
+    /// Each acts for half a substep on either side of every substep.
+    pub thermostats: Vec<Thermostat>,
+
+    /// A periodic box the particles are wrapped into after every substep.
+    pub simulation_box: Option<SimulationBox>,
+    /// Resizes the periodic box to hold the pressure.
+    pub barostat: Option<Barostat>,
+    /// XPBD by default. Under RATTLE the forces carry over from one step to the next.
+    pub constraint_solver: ConstraintSolver,
 
     pub id_counter: u32,
+
+    // whether the particles hold the forces at their current positions, which velocity Verlet
+    // starts each step from
+    forces_current: bool,
+    // the virial of the forces RATTLE held the rigid constraints with in the last substep
+    constraint_virial: Vec3,
 }
 
 //---------------------------------------------------------------------------------------------------//
@@ -39,6 +59,7 @@ impl System {
         let id = self.id_counter;
         self.particles.push(particle.id(id));
         self.id_counter += 1;
+        self.forces_current = false;
 
         ParticleReference::new(id, self.particles.len() - 1)
     }
@@ -53,14 +74,38 @@ impl System {
 
     pub fn add_interaction(&mut self, interaction: impl Interaction + 'static) -> usize {
         self.interactions.push(Box::new(interaction));
+        self.forces_current = false;
         self.interactions.len() - 1
     }
 
+    pub fn add_pressure_solver(&mut self, solver: impl PressureSolver + 'static) -> usize {
+        self.pressure_solvers.push(Box::new(solver));
+        self.forces_current = false;
+        self.pressure_solvers.len() - 1
+    }
+
     pub fn add_constraint(&mut self, constraint: impl Constraint + 'static) -> usize {
         self.constraints.push(Box::new(constraint));
         self.constraints.len() - 1
     }
 
+    pub fn add_thermostat(&mut self, thermostat: Thermostat) -> usize {
+        self.thermostats.push(thermostat);
+        self.thermostats.len() - 1
+    }
+
+    pub fn set_simulation_box(&mut self, simulation_box: SimulationBox) {
+        self.simulation_box = Some(simulation_box);
+    }
+
+    pub fn set_barostat(&mut self, barostat: Barostat) {
+        self.barostat = Some(barostat);
+    }
+
+    pub fn set_constraint_solver(&mut self, constraint_solver: ConstraintSolver) {
+        self.constraint_solver = constraint_solver;
+    }
+
     //--------------------------------------------------------------------//
     // methods for retrieving particle references
 
@@ -82,6 +127,34 @@ impl System {
         references
     }
 
+    //--------------------------------------------------------------------//
+    // pressure
+
+    /// The diagonal of the virial the interactions reported in the last substep, plus that of
+    /// the forces holding the rigid constraints under RATTLE. XPBD's projections aren't forces,
+    /// so they're left out.
+    pub fn virial(&self) -> Vec3 {
+        self.interactions
+            .iter()
+            .fold(self.constraint_virial, |total, interaction| {
+                total + interaction.virial()
+            })
+    }
+
+    /// The diagonal of the pressure tensor in the periodic box, if there is one.
+    pub fn pressure_tensor(&self) -> Option<Vec3> {
+        self.simulation_box
+            .map(|simulation_box| simulation_box.pressure(&self.particles, self.virial()))
+    }
+
+    /// The pressure in the periodic box, the mean of the pressures along its periodic axes.
+    pub fn pressure(&self) -> Option<f64> {
+        let simulation_box = self.simulation_box?;
+        let tensor = simulation_box.pressure(&self.particles, self.virial());
+        let d = simulation_box.dimension.count() as usize;
+        Some([tensor.x, tensor.y, tensor.z][..d].iter().sum::<f64>() / d as f64)
+    }
+
     //--------------------------------------------------------------------//
     // debugging
 
@@ -113,11 +186,18 @@ impl System {
     // time evolution
 
     pub fn static_constraint_pass(&mut self, iterations: u32) {
+        for constraint in &mut self.constraints {
+            if let Some(simulation_box) = &self.simulation_box {
+                constraint.set_simulation_box(simulation_box);
+            }
+            constraint.prepare(&self.particles);
+        }
         for _ in 0..iterations {
             for constraint in &mut self.constraints {
-                constraint.project(&mut self.particles, core::f64::MAX, true);
+                constraint.project(&mut self.particles, f64::MAX, true);
             }
         }
+        self.forces_current = false;
     }
 
     pub fn step_forward(&mut self, dt: f64) {
@@ -126,25 +206,368 @@ impl System {
         }
 
         let sub_dt = dt / (self.substeps as f64);
+        let constraint_solver = std::mem::take(&mut self.constraint_solver);
+        // velocity Verlet starts each step from the forces the last one ended with
+        if matches!(constraint_solver, ConstraintSolver::Rattle(_)) && !self.forces_current {
+            self.refresh_forces(sub_dt);
+        }
         for _ in 0..self.substeps {
-            for interaction in &mut self.interactions {
-                interaction.handle(&mut self.particles, sub_dt);
+            self.apply_thermostats(&constraint_solver, 0.5 * sub_dt);
+            match &constraint_solver {
+                ConstraintSolver::Xpbd => self.xpbd_substep(sub_dt),
+                ConstraintSolver::Rattle(rattle) => self.rattle_substep(rattle, sub_dt),
             }
 
-            for particle in &mut self.particles {
-                particle.integrate(sub_dt);
-                particle.forces.clear();
+            let virial = self.virial();
+            if let Some(simulation_box) = &mut self.simulation_box {
+                if let Some(barostat) = &mut self.barostat {
+                    barostat.apply(simulation_box, &mut self.particles, virial, sub_dt);
+                }
+
+                for particle in &mut self.particles {
+                    let wrapped = simulation_box.wrap(particle.pos);
+                    particle.prev_pos += wrapped - particle.pos;
+                    particle.pos = wrapped;
+                }
             }
+            self.apply_thermostats(&constraint_solver, 0.5 * sub_dt);
+        }
+        self.constraint_solver = constraint_solver;
+        self.time += dt;
+    }
 
-            for constraint in &mut self.constraints {
-                constraint.project(&mut self.particles, sub_dt, false);
+    fn apply_thermostats(&mut self, constraint_solver: &ConstraintSolver, dt: f64) {
+        let rigid = holonomic(&self.constraints);
+        for thermostat in &mut self.thermostats {
+            thermostat.apply(&mut self.particles, dt, |particles| {
+                if let ConstraintSolver::Rattle(rattle) = constraint_solver {
+                    rattle.project_velocities(&rigid, particles, None);
+                }
+            });
+        }
+    }
+
+    /// Only the forces at the current positions, without the rest of what the interactions do
+    /// over a step, so that refreshing them doesn't run that twice.
+    fn refresh_forces(&mut self, dt: f64) {
+        for particle in &mut self.particles {
+            particle.forces.clear();
+        }
+        for interaction in &mut self.interactions {
+            if let Some(simulation_box) = &self.simulation_box {
+                interaction.set_simulation_box(simulation_box);
             }
+            interaction.add_forces(&mut self.particles);
+        }
+
+        // the pressures a solver finds are forces too
+        for solver in &mut self.pressure_solvers {
+            solver.solve(&mut self.particles, dt);
+        }
+        self.forces_current = true;
+    }
 
-            for particle in &mut self.particles {
-                particle.update_vel(sub_dt);
+    fn evaluate_forces(&mut self, dt: f64) {
+        for interaction in &mut self.interactions {
+            if let Some(simulation_box) = &self.simulation_box {
+                interaction.set_simulation_box(simulation_box);
             }
+            interaction.handle(&mut self.particles, dt);
         }
-        self.time += dt;
+
+        for solver in &mut self.pressure_solvers {
+            solver.solve(&mut self.particles, dt);
+        }
+    }
+
+    fn xpbd_substep(&mut self, dt: f64) {
+        self.evaluate_forces(dt);
+
+        for particle in &mut self.particles {
+            particle.integrate(dt);
+            particle.forces.clear();
+        }
+        self.forces_current = false;
+        self.constraint_virial = Vec3::zero();
+
+        for constraint in &mut self.constraints {
+            if let Some(simulation_box) = &self.simulation_box {
+                constraint.set_simulation_box(simulation_box);
+            }
+            constraint.prepare(&self.particles);
+            constraint.project(&mut self.particles, dt, false);
+        }
+
+        for particle in &mut self.particles {
+            particle.update_vel(dt);
+        }
+        for constraint in &mut self.constraints {
+            constraint.correct_velocities(&mut self.particles, dt);
+        }
+    }
+
+    fn rattle_substep(&mut self, rattle: &Rattle, dt: f64) {
+        for constraint in &mut self.constraints {
+            if let Some(simulation_box) = &self.simulation_box {
+                constraint.set_simulation_box(simulation_box);
+            }
+        }
+        let rigid = holonomic(&self.constraints);
+        let start_gradients = rattle.gradients(&rigid, &self.particles);
+
+        for particle in &mut self.particles {
+            particle.kick(0.5 * dt);
+            particle.forces.clear();
+            particle.drift(dt);
+        }
+        rattle.project_positions(&rigid, &start_gradients, &mut self.particles);
+
+        // whatever RATTLE doesn't hold is projected as under XPBD
+        for constraint in &mut self.constraints {
+            if constraint.holonomic().is_none() {
+                constraint.prepare(&self.particles);
+                constraint.project(&mut self.particles, dt, false);
+            }
+        }
+        for particle in &mut self.particles {
+            particle.update_vel(dt);
+        }
+        for constraint in &mut self.constraints {
+            constraint.correct_velocities(&mut self.particles, dt);
+        }
+
+        self.evaluate_forces(dt);
+        self.forces_current = true;
+        for particle in &mut self.particles {
+            particle.kick(0.5 * dt);
+        }
+
+        // the last half kick's constraint forces, F = 2 Δp / dt, act at the same positions as the
+        // forces just evaluated
+        let impulses = rattle.project_velocities(
+            &holonomic(&self.constraints),
+            &mut self.particles,
+            self.simulation_box.as_ref(),
+        );
+        self.constraint_virial = 2.0 / dt * impulses;
+    }
+}
+
+fn holonomic(constraints: &[Box<dyn Constraint>]) -> Vec<&dyn Xpbd> {
+    constraints
+        .iter()
+        .filter_map(|constraint| constraint.holonomic())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        algorithms::Random,
+        constraint::constraints::Distance,
+        interaction::{
+            interactions::LennardJones,
+            simple::{SimpleForce, SimpleForceParameters},
+        },
+    };
+
+    /// Pulls every particle towards the origin, with a potential r² / 2.
+    struct Trap;
+
+    impl SimpleForce for Trap {
+        fn force(&self, particle: &Particle) -> Option<Vec3> {
+            Some(-1.0 * particle.pos)
+        }
+    }
+
+    /// A rigid dimer of unit masses a unit apart along x, in the middle of a periodic box,
+    /// spinning about its center at a unit rate.
+    fn spinning_dimer(constraint_solver: ConstraintSolver) -> System {
+        let mut system = System::new();
+        system.substeps = 1;
+        system.set_simulation_box(SimulationBox::new(
+            Vec3::zero(),
+            Vec3::new(10.0, 10.0, 10.0),
+        ));
+        system.set_constraint_solver(constraint_solver);
+        let a = system.add_particle(
+            Particle::new()
+                .mass(1.0)
+                .pos_xyz(4.5, 5.0, 5.0)
+                .vel_xyz(0.0, -0.5, 0.0),
+        );
+        let b = system.add_particle(
+            Particle::new()
+                .mass(1.0)
+                .pos_xyz(5.5, 5.0, 5.0)
+                .vel_xyz(0.0, 0.5, 0.0),
+        );
+        system.add_constraint(Distance::new([a, b], 1.0));
+        system
+    }
+
+    #[test]
+    fn particles_leaving_through_a_face_come_back_through_the_opposite_one() {
+        for constraint_solver in [
+            ConstraintSolver::Xpbd,
+            ConstraintSolver::Rattle(Rattle::new()),
+        ] {
+            let mut system = System::new();
+            system.set_simulation_box(SimulationBox::new(
+                Vec3::zero(),
+                Vec3::new(10.0, 10.0, 10.0),
+            ));
+            system.set_constraint_solver(constraint_solver);
+            system.add_particle(
+                Particle::new()
+                    .mass(1.0)
+                    .pos_xyz(9.5, 0.2, 5.0)
+                    .vel_xyz(2.0, -1.0, 0.0),
+            );
+            system.step_forward(0.5);
+
+            // wrapped, without the jump showing up in the velocity
+            let particle = &system.particles[0];
+            assert!((particle.pos - Vec3::new(0.5, 9.7, 5.0)).mag() < 1e-9);
+            assert!((particle.vel - Vec3::new(2.0, -1.0, 0.0)).mag() < 1e-9);
+            system.step_forward(0.5);
+            let particle = &system.particles[0];
+            assert!((particle.pos - Vec3::new(1.5, 9.2, 5.0)).mag() < 1e-9);
+        }
+    }
+
+    /// Four rigid triangles of unit sides, tumbling in a trap and bumping into each other. Returns
+    /// how far the energy strays over a run with steps of `dt`, and the furthest a side ends up
+    /// from its length.
+    fn tumbling_triangles(dt: f64) -> (f64, f64) {
+        let mut system = System::new();
+        system.substeps = 1;
+        system.set_constraint_solver(ConstraintSolver::Rattle(Rattle::new()));
+        let mut random = Random::new(5);
+        let mut sides = Vec::new();
+        for center in [
+            Vec3::new(1.5, 0.0, 0.0),
+            Vec3::new(-1.5, 0.0, 0.0),
+            Vec3::new(0.0, 1.5, 0.5),
+            Vec3::new(0.0, -1.5, -0.5),
+        ] {
+            let corners = [
+                Vec3::new(0.0, 0.5773502691896258, 0.0),
+                Vec3::new(-0.5, -0.2886751345948129, 0.0),
+                Vec3::new(0.5, -0.2886751345948129, 0.0),
+            ];
+            let references = corners.map(|corner| {
+                let vel = 0.5 * Vec3::new(random.gaussian(), random.gaussian(), random.gaussian());
+                system.add_particle(Particle::new().mass(1.0).pos(center + corner).vel(vel))
+            });
+            for (a, b) in [(0, 1), (1, 2), (2, 0)] {
+                system.add_constraint(Distance::new([references[a], references[b]], 1.0));
+                sides.push((references[a].index, references[b].index));
+            }
+        }
+        // the corners of a triangle are held apart, so their attraction is constant
+        let particles = system.all_particles();
+        system.add_interaction(
+            LennardJones::new(0.5, 0.9)
+                .build()
+                .with_particles(&particles),
+        );
+        system.add_interaction(SimpleForceParameters::new(Trap).with_particles(&particles));
+
+        let mut pairs = LennardJones::new(0.5, 0.9)
+            .build()
+            .with_particles(&particles);
+        let mut energy = |system: &System| {
+            let (kinetic, trap) = system
+                .particles
+                .iter()
+                .fold((0.0, 0.0), |(k, u), particle| {
+                    (
+                        k + 0.5 * particle.mass * particle.vel.mag_squared(),
+                        u + 0.5 * particle.pos.mag_squared(),
+                    )
+                });
+            kinetic + trap + pairs.potential_energy(&system.particles)
+        };
+        // the first step projects out the velocities that would stretch the sides
+        system.step_forward(dt);
+        let start = energy(&system);
+
+        let mut drift: f64 = 0.0;
+        for _ in 0..(5.0 / dt) as usize {
+            system.step_forward(dt);
+            drift = drift.max((energy(&system) - start).abs() / start.abs());
+        }
+        let stretch = sides.iter().fold(0.0_f64, |stretch, &(a, b)| {
+            let length = (system.particles[a].pos - system.particles[b].pos).mag();
+            stretch.max((length - 1.0).abs())
+        });
+        (drift, stretch)
+    }
+
+    #[test]
+    fn rattle_conserves_the_energy_of_rigid_molecules() {
+        let (coarse, coarse_stretch) = tumbling_triangles(0.0005);
+        let (fine, fine_stretch) = tumbling_triangles(0.00025);
+        assert!(coarse < 1e-4);
+        // velocity Verlet's error is second order in the step
+        assert!(fine < coarse / 3.0);
+        assert!(coarse_stretch < 1e-9 && fine_stretch < 1e-9);
+    }
+
+    #[test]
+    fn a_thermostat_acts_once_per_step_however_the_forces_are_found() {
+        let mut system = System::new();
+        system.substeps = 4;
+        system.set_constraint_solver(ConstraintSolver::Rattle(Rattle::new()));
+        let mut random = Random::new(6);
+        for _ in 0..20 {
+            let vel = Vec3::new(random.gaussian(), random.gaussian(), random.gaussian());
+            system.add_particle(Particle::new().mass(1.0).vel(vel));
+        }
+        let particles = system.all_particles();
+        // the target is the time the thermostat has acted for
+        system.add_thermostat(
+            Thermostat::berendsen(1.0, 0.1)
+                .schedule(|time| time)
+                .with_particles(&particles),
+        );
+
+        for step in 0..10 {
+            // each new interaction leaves the forces stale, so the next step refreshes them
+            if step % 3 == 0 {
+                system.add_interaction(SimpleForceParameters::new(Trap).with_particles(&particles));
+            }
+            system.step_forward(0.01);
+            let target = system.thermostats[0].target_temperature();
+            assert!((target - system.time).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn rattle_counts_the_constraint_forces_in_the_virial() {
+        let mut system = spinning_dimer(ConstraintSolver::Rattle(Rattle::new()));
+        system.step_forward(1e-3);
+        // the tension holding the particles on their circle is m v² / r = 0.5, pulling them
+        // together along x
+        let virial = system.virial();
+        assert!((virial.x + 0.5).abs() < 1e-3);
+        assert!(virial.y.abs() < 1e-3 && virial.z.abs() < 1e-12);
+
+        // a spinning molecule that isn't moving has no pressure: its kinetic energy is
+        // cancelled by the tension
+        for _ in 0..2000 {
+            system.step_forward(1e-3);
+            let tensor = system.pressure_tensor().unwrap();
+            let volume = system.simulation_box.unwrap().volume();
+            assert!((tensor.x + tensor.y + tensor.z).abs() * volume < 1e-5);
+        }
+
+        // XPBD's projections aren't counted
+        let mut system = spinning_dimer(ConstraintSolver::Xpbd);
+        system.step_forward(1e-3);
+        assert!(system.virial().mag() == 0.0);
     }
 }
 
@@ -0,0 +1,585 @@
+//! Thermostats, which hold a set of particles at a temperature, for molecular dynamics at
+//! constant temperature (the canonical, or NVT, ensemble).
+//!
+//! The kinetic temperature of N particles moving in d dimensions is T = Σ m v² / (N_f k), over
+//! N_f = d N degrees of freedom, with Boltzmann's constant k. Fixed particles, with no inverse
+//! mass, are left out. Thermostats change the velocities directly, as a stage of the system's
+//! step of their own: each acts for half a substep before the substep's integration and half
+//! after it, so that the integration between stays time reversible. Under RATTLE the velocities
+//! are projected back onto the rigid constraints after every thermostat.
+//!
+//! - Berendsen's weak coupling rescales the velocities so that the temperature relaxes towards
+//!   the target over a time τ. It's robust for equilibrating, but it suppresses the
+//!   fluctuations of the kinetic energy, so it doesn't sample the canonical ensemble.
+//! - Bussi, Donadio & Parrinello's stochastic velocity rescaling adds a random term to
+//!   Berendsen's, which makes the kinetic energy canonically distributed.
+//! - Nosé–Hoover chains (Martyna, Klein & Tuckerman) couple the particles to a chain of
+//!   thermostat variables, deterministically, with the temperature oscillating over a time
+//!   around τ. Chaining keeps small or stiff systems from being stuck out of equilibrium, as
+//!   a single Nosé–Hoover thermostat can leave them.
+//! - Langevin dynamics slows every particle by a friction γ and kicks it randomly, by the exact
+//!   update for Ornstein-Uhlenbeck noise. It acts on each particle on its own, so it doesn't
+//!   conserve momentum, but it equilibrates even particles that don't interact.
+//!
+//! The energy that a thermostat takes out of the particles is tallied, so that the particles'
+//! total energy plus the tally stays constant, which checks the integration. A Nosé–Hoover
+//! chain also has an energy of its own, [`Thermostat::energy`], which the particles' energy
+//! plus it conserves at a constant target. The random numbers
+//! come from a seeded generator, so that stochastic runs can be repeated exactly.
+
+//---------------------------------------------------------------------------------------------------//
+use crate::{
+    algorithms::Random,
+    math::Dimension,
+    particle::{Particle, ParticleReference},
+};
+
+//---------------------------------------------------------------------------------------------------//
+
+enum Coupling {
+    Berendsen {
+        time_constant: f64,
+    },
+    Bussi {
+        time_constant: f64,
+    },
+    NoseHoover {
+        time_constant: f64,
+        // the velocity of each thermostat variable along the chain
+        chain: Vec<f64>,
+        // and its position, which only enters the chain's energy
+        positions: Vec<f64>,
+        degrees_of_freedom: f64,
+    },
+    Langevin {
+        friction: f64,
+    },
+}
+
+pub struct Thermostat {
+    coupled_particles: Vec<ParticleReference>,
+    coupling: Coupling,
+    temperature: f64,
+    schedule: Option<Box<dyn Fn(f64) -> f64>>,
+    boltzmann_constant: f64,
+    dimension: Dimension,
+
+    random: Random,
+    time: f64,
+    exchanged_energy: f64,
+}
+
+impl Thermostat {
+    fn new(temperature: f64, coupling: Coupling) -> Thermostat {
+        Thermostat {
+            coupled_particles: Vec::new(),
+            coupling,
+            temperature,
+            schedule: None,
+            boltzmann_constant: 1.0,
+            dimension: Dimension::Three,
+            random: Random::new(0),
+            time: 0.0,
+            exchanged_energy: 0.0,
+        }
+    }
+
+    /// Berendsen's thermostat, relaxing the temperature over a time constant τ.
+    pub fn berendsen(temperature: f64, time_constant: f64) -> Thermostat {
+        Thermostat::new(temperature, Coupling::Berendsen { time_constant })
+    }
+
+    /// Bussi's stochastic velocity rescaling, relaxing the temperature over a time constant τ.
+    pub fn bussi(temperature: f64, time_constant: f64) -> Thermostat {
+        Thermostat::new(temperature, Coupling::Bussi { time_constant })
+    }
+
+    /// A chain of three Nosé–Hoover thermostats, whose masses are set by the time constant τ
+    /// of the temperature's oscillations. The masses are k T τ² at this temperature, which has
+    /// to be above zero, even when a schedule moves the target away from it.
+    pub fn nose_hoover(temperature: f64, time_constant: f64) -> Thermostat {
+        assert!(
+            temperature > 0.0,
+            "a nosé–hoover thermostat needs a temperature above zero"
+        );
+        Thermostat::new(
+            temperature,
+            Coupling::NoseHoover {
+                time_constant,
+                chain: vec![0.0; 3],
+                positions: vec![0.0; 3],
+                degrees_of_freedom: 0.0,
+            },
+        )
+    }
+
+    /// Langevin dynamics with a friction rate γ, so that velocities are forgotten over a
+    /// time 1 / γ.
+    pub fn langevin(temperature: f64, friction: f64) -> Thermostat {
+        Thermostat::new(temperature, Coupling::Langevin { friction })
+    }
+
+    /// The number of thermostats in a Nosé–Hoover chain. One gives the plain Nosé–Hoover
+    /// thermostat.
+    pub fn chain_length(mut self, length: usize) -> Thermostat {
+        if let Coupling::NoseHoover {
+            chain, positions, ..
+        } = &mut self.coupling
+        {
+            *chain = vec![0.0; length.max(1)];
+            *positions = vec![0.0; length.max(1)];
+        }
+        self
+    }
+
+    /// Defaults to 1, for temperatures measured in units of energy.
+    pub fn boltzmann_constant(mut self, boltzmann_constant: f64) -> Thermostat {
+        self.boltzmann_constant = boltzmann_constant;
+        self
+    }
+
+    /// The number of dimensions the particles move in, which sets their degrees of freedom.
+    /// Defaults to three.
+    pub fn dimension(mut self, dimension: Dimension) -> Thermostat {
+        self.dimension = dimension;
+        self
+    }
+
+    /// Seed the random numbers of the stochastic thermostats. Defaults to 0.
+    pub fn seed(mut self, seed: u64) -> Thermostat {
+        self.random = Random::new(seed);
+        self
+    }
+
+    /// Take the target temperature from a function of the time since the thermostat started,
+    /// to heat or cool the particles on a schedule.
+    pub fn schedule(mut self, schedule: impl Fn(f64) -> f64 + 'static) -> Thermostat {
+        self.schedule = Some(Box::new(schedule));
+        self
+    }
+
+    pub fn with_particle(mut self, reference: ParticleReference) -> Thermostat {
+        self.coupled_particles.push(reference);
+        self
+    }
+
+    pub fn with_particles(mut self, references: &[ParticleReference]) -> Thermostat {
+        for reference in references {
+            self.coupled_particles.push(*reference);
+        }
+        self
+    }
+
+    //--------------------------------------------------------------------//
+
+    /// The kinetic temperature of the coupled particles.
+    pub fn temperature(&self, particle_source: &[Particle]) -> f64 {
+        let (kinetic_energy, degrees_of_freedom) = self.kinetic_energy(particle_source);
+        if degrees_of_freedom > 0.0 {
+            2.0 * kinetic_energy / (degrees_of_freedom * self.boltzmann_constant)
+        } else {
+            0.0
+        }
+    }
+
+    /// The temperature the thermostat is currently driving the particles towards.
+    pub fn target_temperature(&self) -> f64 {
+        match &self.schedule {
+            Some(schedule) => schedule(self.time),
+            None => self.temperature,
+        }
+    }
+
+    /// The energy the thermostat has taken out of the particles so far.
+    pub fn exchanged_energy(&self) -> f64 {
+        self.exchanged_energy
+    }
+
+    /// The energy of a Nosé–Hoover chain, Σ Q_i ξ_i² / 2 + N_f k T η_1 + k T Σ η_i over the
+    /// rest of the chain, for thermostat variables at positions η_i moving at ξ_i. The
+    /// particles' energy plus this stays constant while the target does. The other thermostats
+    /// have no energy of their own.
+    pub fn energy(&self) -> f64 {
+        match &self.coupling {
+            Coupling::NoseHoover {
+                time_constant,
+                chain,
+                positions,
+                degrees_of_freedom,
+            } => {
+                let k = self.boltzmann_constant;
+                let masses = chain_masses(
+                    chain.len(),
+                    *degrees_of_freedom,
+                    k * self.temperature * time_constant.powi(2),
+                );
+                let thermal = k * self.target_temperature();
+                chain
+                    .iter()
+                    .zip(positions)
+                    .zip(masses)
+                    .enumerate()
+                    .map(|(index, ((velocity, position), mass))| {
+                        let freedom = if index == 0 { *degrees_of_freedom } else { 1.0 };
+                        0.5 * mass * velocity * velocity + freedom * thermal * position
+                    })
+                    .sum()
+            }
+            _ => 0.0,
+        }
+    }
+
+    //--------------------------------------------------------------------//
+
+    /// The kinetic energy of the particles that can move, and their degrees of freedom.
+    fn kinetic_energy(&self, particle_source: &[Particle]) -> (f64, f64) {
+        let d = self.dimension.count() as f64;
+        self.coupled_particles
+            .iter()
+            .map(|reference| reference.get(particle_source))
+            .filter(|particle| particle.inverse_mass > 0.0)
+            .fold((0.0, 0.0), |(energy, freedom), particle| {
+                (
+                    energy + 0.5 * particle.mass * particle.vel.mag_squared(),
+                    freedom + d,
+                )
+            })
+    }
+
+    //--------------------------------------------------------------------//
+
+    /// Act on the coupled particles for a time dt, then hand them to `constrain`, which puts
+    /// back whatever the constraints don't allow before the exchanged energy is tallied.
+    pub(crate) fn apply(
+        &mut self,
+        particle_source: &mut [Particle],
+        dt: f64,
+        constrain: impl FnOnce(&mut [Particle]),
+    ) {
+        if dt == 0.0 {
+            return;
+        }
+        self.time += dt;
+        let temperature = self.target_temperature();
+        let k = self.boltzmann_constant;
+        let (kinetic_energy, degrees_of_freedom) = self.kinetic_energy(particle_source);
+        if degrees_of_freedom == 0.0 {
+            return;
+        }
+        let target = 0.5 * degrees_of_freedom * k * temperature;
+
+        let particles = &self.coupled_particles;
+        match &mut self.coupling {
+            Coupling::Berendsen { time_constant } => {
+                // rescaling can't heat particles that are all at rest
+                if kinetic_energy > 0.0 {
+                    let ratio = 1.0 + dt / *time_constant * (target / kinetic_energy - 1.0);
+                    scale_velocities(particles, particle_source, ratio.max(0.0).sqrt());
+                }
+            }
+            Coupling::Bussi { time_constant } => {
+                if kinetic_energy > 0.0 {
+                    let decay = (-dt / *time_constant).exp();
+                    let resampled = resample_kinetic_energy(
+                        &mut self.random,
+                        kinetic_energy,
+                        target,
+                        degrees_of_freedom,
+                        decay,
+                    );
+                    let factor = (resampled / kinetic_energy).sqrt();
+                    scale_velocities(particles, particle_source, factor);
+                }
+            }
+            Coupling::NoseHoover {
+                time_constant,
+                chain,
+                positions,
+                degrees_of_freedom: chain_freedom,
+            } => {
+                *chain_freedom = degrees_of_freedom;
+                let masses = chain_masses(
+                    chain.len(),
+                    degrees_of_freedom,
+                    k * self.temperature * time_constant.powi(2),
+                );
+
+                // each thermostat is driven by the excess kinetic energy of what it's coupled to,
+                // and slowed by the next one along the chain
+                let update = |chain: &mut [f64], index: usize, kinetic_energy: f64, half: f64| {
+                    let force = if index == 0 {
+                        2.0 * (kinetic_energy - target) / masses[0]
+                    } else {
+                        (masses[index - 1] * chain[index - 1].powi(2) - k * temperature)
+                            / masses[index]
+                    };
+                    let drag = match chain.get(index + 1) {
+                        Some(next) => (-0.5 * half * next).exp(),
+                        None => 1.0,
+                    };
+                    chain[index] = drag * (drag * chain[index] + half * force);
+                };
+
+                // half a step down the chain, the particles' full step, and half a step back up
+                let half = 0.5 * dt;
+                for index in (0..chain.len()).rev() {
+                    update(chain, index, kinetic_energy, half);
+                }
+                for (position, velocity) in positions.iter_mut().zip(chain.iter()) {
+                    *position += velocity * dt;
+                }
+                let factor = (-chain[0] * dt).exp();
+                scale_velocities(particles, particle_source, factor);
+                let scaled = kinetic_energy * factor * factor;
+                for index in 0..chain.len() {
+                    update(chain, index, scaled, half);
+                }
+            }
+            Coupling::Langevin { friction } => {
+                let decay = (-*friction * dt).exp();
+                let d = self.dimension.count();
+                for reference in particles {
+                    let particle = reference.get_mut(particle_source);
+                    if particle.inverse_mass == 0.0 {
+                        continue;
+                    }
+                    let spread = ((1.0 - decay * decay) * k * temperature / particle.mass).sqrt();
+                    particle.vel *= decay;
+                    particle.vel.x += spread * self.random.gaussian();
+                    if d > 1 {
+                        particle.vel.y += spread * self.random.gaussian();
+                    }
+                    if d > 2 {
+                        particle.vel.z += spread * self.random.gaussian();
+                    }
+                }
+            }
+        }
+
+        constrain(particle_source);
+        self.exchanged_energy += kinetic_energy - self.kinetic_energy(particle_source).0;
+    }
+}
+
+//---------------------------------------------------------------------------------------------------//
+
+/// The masses along a Nosé–Hoover chain: the first is N_f k T τ², and the rest k T τ², at the
+/// thermostat's own temperature so that a scheduled target can reach zero.
+fn chain_masses(length: usize, degrees_of_freedom: f64, mass: f64) -> Vec<f64> {
+    (0..length)
+        .map(|index| {
+            if index == 0 {
+                degrees_of_freedom * mass
+            } else {
+                mass
+            }
+        })
+        .collect()
+}
+
+//---------------------------------------------------------------------------------------------------//
+
+fn scale_velocities(
+    coupled_particles: &[ParticleReference],
+    particle_source: &mut [Particle],
+    factor: f64,
+) {
+    for reference in coupled_particles {
+        let particle = reference.get_mut(particle_source);
+        if particle.inverse_mass > 0.0 {
+            particle.vel *= factor;
+        }
+    }
+}
+
+/// Bussi's draw of the next kinetic energy,
+/// K' = c K + (1 - c) K0 / N_f (R² + S) + 2 R √(c (1 - c) K K0 / N_f), for c = exp(-dt / τ),
+/// a gaussian R, and S a sum of N_f - 1 more squared gaussians.
+fn resample_kinetic_energy(
+    random: &mut Random,
+    kinetic_energy: f64,
+    target: f64,
+    degrees_of_freedom: f64,
+    decay: f64,
+) -> f64 {
+    let r = random.gaussian();
+    // a sum of n squared gaussians is chi-squared, or twice gamma distributed with shape n / 2
+    let s = if degrees_of_freedom > 1.0 {
+        2.0 * random.gamma(0.5 * (degrees_of_freedom - 1.0))
+    } else {
+        0.0
+    };
+    let share = target / degrees_of_freedom;
+    decay * kinetic_energy
+        + (1.0 - decay) * share * (r * r + s)
+        + 2.0 * r * (decay * (1.0 - decay) * kinetic_energy * share).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        constraint::{rattle::Rattle, ConstraintSolver},
+        interaction::simple::{SimpleForce, SimpleForceParameters},
+        math::Vec3,
+        system::System,
+    };
+
+    const DT: f64 = 0.01;
+
+    /// Free particles of masses 1, 2 and 3, moving at about a temperature.
+    fn gas(count: usize, temperature: f64) -> System {
+        let mut random = Random::new(3);
+        let mut system = System::new();
+        system.substeps = 1;
+        for index in 0..count {
+            let mass = 1.0 + (index % 3) as f64;
+            let spread = (temperature / mass).sqrt();
+            let velocity = Vec3::new(random.gaussian(), random.gaussian(), random.gaussian());
+            system.add_particle(
+                Particle::new()
+                    .mass(mass)
+                    .pos(4.0 * Vec3::new(random.gaussian(), random.gaussian(), random.gaussian()))
+                    .vel(spread * velocity),
+            );
+        }
+        system
+    }
+
+    fn kinetic_energy(system: &System) -> f64 {
+        system
+            .particles
+            .iter()
+            .map(|particle| 0.5 * particle.mass * particle.vel.mag_squared())
+            .sum()
+    }
+
+    /// Pulls every particle towards the origin, with a potential r² / 2.
+    struct Trap;
+
+    impl SimpleForce for Trap {
+        fn force(&self, particle: &Particle) -> Option<Vec3> {
+            Some(-1.0 * particle.pos)
+        }
+    }
+
+    #[test]
+    fn berendsen_relaxes_to_the_target() {
+        let mut system = gas(300, 3.0);
+        let thermostat = Thermostat::berendsen(1.0, 0.1).with_particles(&system.all_particles());
+        system.add_thermostat(thermostat);
+        assert!(system.thermostats[0].temperature(&system.particles) > 2.5);
+        let start = kinetic_energy(&system);
+
+        for _ in 0..200 {
+            system.step_forward(DT);
+        }
+        let thermostat = &system.thermostats[0];
+        assert!((thermostat.temperature(&system.particles) - 1.0).abs() < 1e-6);
+        // free particles only change their energy through the thermostat
+        let exchanged = thermostat.exchanged_energy();
+        assert!((kinetic_energy(&system) + exchanged - start).abs() < 1e-9 * start);
+    }
+
+    #[test]
+    fn bussi_samples_the_target_temperature_with_canonical_fluctuations() {
+        let mut system = gas(300, 3.0);
+        let thermostat = Thermostat::bussi(1.0, 0.1)
+            .seed(5)
+            .with_particles(&system.all_particles());
+        system.add_thermostat(thermostat);
+        for _ in 0..500 {
+            system.step_forward(DT);
+        }
+
+        let samples = 20000;
+        let (mut sum, mut sum_squared) = (0.0, 0.0);
+        for _ in 0..samples {
+            system.step_forward(DT);
+            let kinetic = kinetic_energy(&system);
+            sum += kinetic;
+            sum_squared += kinetic * kinetic;
+        }
+        let mean = sum / samples as f64;
+        let variance = sum_squared / samples as f64 - mean * mean;
+        let degrees_of_freedom = 900.0;
+        assert!((2.0 * mean / degrees_of_freedom - 1.0).abs() < 0.01);
+        // var(K) / <K>² = 2 / N_f in the canonical ensemble
+        let ratio = variance / (mean * mean) / (2.0 / degrees_of_freedom);
+        assert!((ratio - 1.0).abs() < 0.2);
+    }
+
+    #[test]
+    fn nose_hoover_conserves_its_extended_energy() {
+        // velocity Verlet, whose energy error is second order in the step
+        let mut system = gas(30, 2.0);
+        system.set_constraint_solver(ConstraintSolver::Rattle(Rattle::new()));
+        let particles = system.all_particles();
+        system.add_interaction(SimpleForceParameters::new(Trap).with_particles(&particles));
+        system.add_thermostat(Thermostat::nose_hoover(1.0, 0.5).with_particles(&particles));
+        let energy = |system: &System| {
+            let trap: f64 = system
+                .particles
+                .iter()
+                .map(|particle| 0.5 * particle.pos.mag_squared())
+                .sum();
+            kinetic_energy(system) + trap + system.thermostats[0].energy()
+        };
+        let start = energy(&system);
+
+        let (mut drift, mut temperature): (f64, f64) = (0.0, 0.0);
+        let steps = 20000;
+        for step in 0..steps {
+            system.step_forward(DT);
+            drift = drift.max((energy(&system) - start).abs());
+            if step >= steps / 2 {
+                temperature += system.thermostats[0].temperature(&system.particles);
+            }
+        }
+        // the chain has taken the particles' excess heat, and given it back to the extended
+        // energy's account
+        let temperature = temperature / (steps / 2) as f64;
+        assert!((temperature - 1.0).abs() < 0.2);
+        assert!(drift < 1e-3 * start);
+    }
+
+    #[test]
+    fn langevin_shares_the_energy_equally_between_degrees_of_freedom() {
+        let mut system = gas(300, 0.0);
+        let thermostat = Thermostat::langevin(2.0, 1.0)
+            .seed(9)
+            .with_particles(&system.all_particles());
+        system.add_thermostat(thermostat);
+        for _ in 0..1000 {
+            system.step_forward(DT);
+        }
+
+        // the mean kinetic energy along each axis, for each of the three masses
+        let mut shares = [Vec3::zero(); 3];
+        let samples = 2000;
+        for _ in 0..samples {
+            for _ in 0..10 {
+                system.step_forward(DT);
+            }
+            for (index, particle) in system.particles.iter().enumerate() {
+                let velocity = particle.vel;
+                let energy = 0.5
+                    * particle.mass
+                    * Vec3::new(
+                        velocity.x * velocity.x,
+                        velocity.y * velocity.y,
+                        velocity.z * velocity.z,
+                    );
+                shares[index % 3] += energy / (100 * samples) as f64;
+            }
+        }
+        // k T / 2 each
+        for share in shares {
+            for energy in [share.x, share.y, share.z] {
+                assert!((energy - 1.0).abs() < 0.03, "{}", energy);
+            }
+        }
+    }
+}
+
+//---------------------------------------------------------------------------------------------------//
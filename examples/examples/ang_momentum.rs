@@ -1,4 +1,8 @@
-use engine::prelude::*;
+use engine::{
+    collision::spatial_hash::SpatialHash,
+    constraint::{rattle::Rattle, ConstraintSolver},
+    prelude::*,
+};
 use rendering::particle_2d_renderer::Particle2DRenderer;
 
 fn main() {
@@ -23,23 +27,22 @@ fn main() {
     system.add_interaction(gravity);
 
     // add a non_penetrate constraint to all particles
-    let mut index: usize = 0;
-    for ref1 in &system.all_particles() {
-        for ref2 in &system.all_particles()[(index + 1)..] {
-            system.add_constraint(
-                Constraints::NonPenetrate::new([*ref1, *ref2], 2.0 * system.particle_radius)
-                    .compliance(0.00001)
-                    .dissipation(30.0)
-                    .as_force(),
-            );
-        }
-        index += 1;
-    }
+    let collision_distance = 2.0 * system.particle_radius;
+    system.add_constraint(
+        Constraints::Collisions::new(SpatialHash::new(collision_distance))
+            .compliance(0.00001)
+            .dissipation(30.0)
+            .as_force(),
+    );
 
     /* let mut repulsion = Fields::VanDerWaals::new(100.0, None, 0.0);
     repulsion.add_particles(&system.all_particles());
     system.add_field(repulsion); */
 
+    // `cargo run --example ang_momentum -- rattle` swaps XPBD for RATTLE, to compare the two
+    if std::env::args().any(|argument| argument == "rattle") {
+        system.set_constraint_solver(ConstraintSolver::Rattle(Rattle::new()));
+    }
     system.static_constraint_pass(1);
 
     window.run(system);
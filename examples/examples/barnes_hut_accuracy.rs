@@ -0,0 +1,78 @@
+//! Reports the accuracy of the Barnes–Hut tree gravity against direct summation on a random
+//! cluster, over opening angles and with and without quadrupole moments. The engine's tests check
+//! the same errors.
+
+use engine::prelude::*;
+use rand::{Rng, SeedableRng};
+
+const PARTICLE_COUNT: usize = 2000;
+const G: f64 = 1.0;
+
+fn cluster() -> System {
+    let mut system = System::new();
+    let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+
+    for _ in 0..PARTICLE_COUNT {
+        // centrally concentrated, so that the tree has to cope with a wide range of cell sizes
+        let radius: f64 = rng.gen_range(0.0_f64..1.0).powi(2) * 100.0;
+        let theta = rng.gen_range(0.0..(2.0 * PI));
+        let phi = rng.gen_range(-1.0_f64..1.0).acos();
+        system.add_particle(
+            Particle::new()
+                .pos(Vec3::new_spherical(radius, theta, phi))
+                .mass(rng.gen_range(1.0..10.0)),
+        );
+    }
+    system
+}
+
+fn forces(system: &mut System) -> Vec<Vec3> {
+    for interaction in &mut system.interactions {
+        interaction.handle(&mut system.particles, 0.0);
+    }
+    system
+        .particles
+        .iter()
+        .map(|particle| {
+            particle
+                .forces
+                .iter()
+                .fold(Vec3::zero(), |total, force| total + *force)
+        })
+        .collect()
+}
+
+fn rms_relative_error(approximate: &[Vec3], exact: &[Vec3]) -> f64 {
+    let sum: f64 = approximate
+        .iter()
+        .zip(exact)
+        .map(|(a, e)| (*a - *e).mag_squared() / e.mag_squared())
+        .sum();
+    (sum / exact.len() as f64).sqrt()
+}
+
+fn main() {
+    let mut direct = cluster();
+    let gravity = Interactions::Gravity::new(G).with_particles(&direct.all_particles());
+    direct.add_interaction(gravity);
+    let exact = forces(&mut direct);
+
+    for opening_angle in [0.8, 0.5, 0.3] {
+        for quadrupole in [false, true] {
+            let mut tree = cluster();
+            let mut gravity = Interactions::BarnesHutGravity::new(G)
+                .opening_angle(opening_angle)
+                .with_particles(&tree.all_particles());
+            if quadrupole {
+                gravity = gravity.quadrupole();
+            }
+            tree.add_interaction(gravity);
+
+            let error = rms_relative_error(&forces(&mut tree), &exact);
+            println!(
+                "theta = {:.1}, quadrupole = {:>5}: rms relative force error = {:.2e}",
+                opening_angle, quadrupole, error
+            );
+        }
+    }
+}
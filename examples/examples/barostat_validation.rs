@@ -0,0 +1,276 @@
+//! Checks periodic boxes and barostats on a Lennard-Jones fluid, in reduced units
+//! (ε = σ = m = k = 1). The virial pressure of a static lattice should match the derivative of
+//! its energy with the box, P_a = -(L_a / V) dU/dL_a. At a temperature of 2 the barostats should
+//! take a fluid to the density whose pressure they're set to, as measured at fixed volume, and
+//! the Martyna–Tobias–Klein barostat should conserve its energy without a thermostat. Made
+//! anisotropic, it should relax a strained crystal back to a cube. A fluid wouldn't do: it has no
+//! shape of its own, so its box would wander.
+
+use engine::{
+    barostat::Barostat,
+    interaction::{
+        pair_wise::{PairWiseForceParameters, Truncation},
+        Interaction,
+    },
+    prelude::*,
+    thermostat::Thermostat,
+};
+
+const SIDE: usize = 6;
+const TEMPERATURE: f64 = 2.0;
+const DENSITY: f64 = 0.5;
+const DT: f64 = 0.004;
+const EQUILIBRATION_STEPS: usize = 5000;
+const SAMPLE_STEPS: usize = 10000;
+
+fn lennard_jones() -> PairWiseForceParameters {
+    Interactions::LennardJones::new(1.0, 1.0)
+        .build()
+        .cutoff(2.5)
+        .truncation(Truncation::ForceShifted)
+}
+
+/// A cubic lattice of particles at rest, filling a periodic box, with the forces between them.
+fn lattice(spacing: Vec3) -> System {
+    let mut system = System::new();
+    system.substeps = 1;
+    for x in 0..SIDE {
+        for y in 0..SIDE {
+            for z in 0..SIDE {
+                system.add_particle(
+                    Particle::new()
+                        .pos_xyz(
+                            (x as f64 + 0.5) * spacing.x,
+                            (y as f64 + 0.5) * spacing.y,
+                            (z as f64 + 0.5) * spacing.z,
+                        )
+                        .mass(1.0),
+                );
+            }
+        }
+    }
+    system.set_simulation_box(SimulationBox::new(Vec3::zero(), SIDE as f64 * spacing));
+    let particles = system.all_particles();
+    system.add_interaction(lennard_jones().with_particles(&particles));
+    system
+}
+
+/// A face-centered cubic crystal of 4 n³ particles at rest, filling a periodic box stretched
+/// along x.
+fn crystal(cells: usize, lattice_constant: f64, stretch: f64) -> System {
+    let mut system = System::new();
+    system.substeps = 1;
+    let basis = [
+        [0.0, 0.0, 0.0],
+        [0.5, 0.5, 0.0],
+        [0.5, 0.0, 0.5],
+        [0.0, 0.5, 0.5],
+    ];
+    for x in 0..cells {
+        for y in 0..cells {
+            for z in 0..cells {
+                for [u, v, w] in basis {
+                    system.add_particle(
+                        Particle::new()
+                            .pos_xyz(
+                                (x as f64 + u + 0.25) * lattice_constant * stretch,
+                                (y as f64 + v + 0.25) * lattice_constant,
+                                (z as f64 + w + 0.25) * lattice_constant,
+                            )
+                            .mass(1.0),
+                    );
+                }
+            }
+        }
+    }
+    let side = cells as f64 * lattice_constant;
+    system.set_simulation_box(SimulationBox::new(
+        Vec3::zero(),
+        Vec3::new(stretch * side, side, side),
+    ));
+    let particles = system.all_particles();
+    system.add_interaction(lennard_jones().with_particles(&particles));
+    system
+}
+
+/// A lattice at a density.
+fn fluid(density: f64) -> System {
+    let spacing = density.powf(-1.0 / 3.0);
+    lattice(Vec3::new(spacing, spacing, spacing))
+}
+
+fn density(system: &System) -> f64 {
+    system.particles.len() as f64 / system.simulation_box.unwrap().volume()
+}
+
+fn potential_energy(system: &System) -> f64 {
+    let mut potential = lennard_jones().with_particles(&system.all_particles());
+    potential.set_simulation_box(&system.simulation_box.unwrap());
+    potential.potential_energy(&system.particles)
+}
+
+fn kinetic_energy(system: &System) -> f64 {
+    system
+        .particles
+        .iter()
+        .map(|particle| 0.5 * particle.mass * particle.vel.mag_squared())
+        .sum()
+}
+
+/// Steps the system under a fresh Langevin thermostat, observing it after every step.
+fn run(system: &mut System, temperature: f64, steps: usize, mut observe: impl FnMut(&System)) {
+    let thermostat = Thermostat::langevin(temperature, 1.0)
+        .seed(1)
+        .with_particles(&system.all_particles());
+    system.thermostats.clear();
+    system.add_thermostat(thermostat);
+    for _ in 0..steps {
+        system.step_forward(DT);
+        observe(system);
+    }
+}
+
+//---------------------------------------------------------------------------------------------------//
+
+/// The relative error of the virial pressure along x of a lattice, stretched along x, against
+/// the derivative of its energy.
+fn static_pressure_error() -> f64 {
+    let spacing = Vec3::new(1.15, 1.05, 1.1);
+    let mut system = lattice(spacing);
+    system.step_forward(1e-9);
+    let pressure = system.pressure_tensor().unwrap().x;
+
+    let step = 1e-5;
+    let stretched = |factor: f64| {
+        potential_energy(&lattice(Vec3::new(
+            factor * spacing.x,
+            spacing.y,
+            spacing.z,
+        )))
+    };
+    let simulation_box = system.simulation_box.unwrap();
+    // dU/dL_x L_x = dU/d(ln L_x)
+    let derivative = (stretched(1.0 + step) - stretched(1.0 - step)) / (2.0 * step);
+    let expected = -derivative / simulation_box.volume();
+    (pressure - expected).abs() / expected.abs()
+}
+
+fn main() {
+    let error = static_pressure_error();
+    println!("static lattice: virial pressure error {:.2e}", error);
+    assert!(
+        error < 1e-4,
+        "the virial pressure doesn't match the energy's derivative"
+    );
+
+    // the pressure at the reference density, in a box of fixed size
+    let mut system = fluid(DENSITY);
+    run(&mut system, TEMPERATURE, EQUILIBRATION_STEPS, |_| {});
+    let mut pressure = 0.0;
+    run(&mut system, TEMPERATURE, SAMPLE_STEPS, |system| {
+        pressure += system.pressure().unwrap() / SAMPLE_STEPS as f64;
+    });
+    println!(
+        "fixed volume: pressure {:.3} at density {:.3}",
+        pressure, DENSITY
+    );
+    let simulation_box = system.simulation_box.unwrap();
+    assert!(
+        system.particles.iter().all(|particle| {
+            let (lower, upper) = (simulation_box.lower, simulation_box.upper);
+            (lower.x..=upper.x).contains(&particle.pos.x)
+                && (lower.y..=upper.y).contains(&particle.pos.y)
+                && (lower.z..=upper.z).contains(&particle.pos.z)
+        }),
+        "particles have left the box"
+    );
+
+    // both barostats should take a denser fluid back to the reference density, within what the
+    // reference pressure's own noise allows
+    let barostats = [
+        ("berendsen", Barostat::berendsen(pressure, 1.0, 1.0)),
+        (
+            "mtk",
+            Barostat::martyna_tobias_klein(pressure, 1.0).temperature(TEMPERATURE),
+        ),
+    ];
+    for (name, barostat) in barostats {
+        let mut system = fluid(1.2 * DENSITY);
+        system.set_barostat(barostat);
+        run(&mut system, TEMPERATURE, EQUILIBRATION_STEPS, |_| {});
+        let (mut mean_pressure, mut mean_density) = (0.0, 0.0);
+        run(&mut system, TEMPERATURE, SAMPLE_STEPS, |system| {
+            mean_pressure += system.pressure().unwrap() / SAMPLE_STEPS as f64;
+            mean_density += density(system) / SAMPLE_STEPS as f64;
+        });
+        println!(
+            "{}: mean pressure {:.3}, mean density {:.4}",
+            name, mean_pressure, mean_density
+        );
+        assert!(
+            (mean_pressure - pressure).abs() < 0.03 * pressure,
+            "the {} barostat doesn't hold its pressure",
+            name
+        );
+        assert!(
+            (mean_density - DENSITY).abs() < 0.05 * DENSITY,
+            "the {} barostat doesn't reach the density of its pressure",
+            name
+        );
+    }
+
+    // without a thermostat, the fluid's energy plus the barostat's is conserved
+    let mut system = fluid(DENSITY);
+    system.set_barostat(Barostat::martyna_tobias_klein(pressure, 1.0).temperature(TEMPERATURE));
+    run(&mut system, TEMPERATURE, EQUILIBRATION_STEPS, |_| {});
+    system.thermostats.clear();
+    let energy = |system: &System| {
+        let barostat = system.barostat.as_ref().unwrap();
+        kinetic_energy(system)
+            + potential_energy(system)
+            + barostat.energy(&system.simulation_box.unwrap())
+    };
+    let start = energy(&system);
+    let mut drift: f64 = 0.0;
+    for _ in 0..SAMPLE_STEPS / 10 {
+        for _ in 0..10 {
+            system.step_forward(DT);
+        }
+        drift = drift.max((energy(&system) - start).abs());
+    }
+    let drift = drift / kinetic_energy(&system);
+    println!("mtk without a thermostat: energy drift {:.4}", drift);
+    assert!(drift < 0.02, "the mtk barostat doesn't conserve energy");
+
+    // a crystal strained along x relaxes back to a cube, with the same pressure along every axis
+    let mut system = crystal(5, 1.6, 1.1);
+    system.set_barostat(
+        Barostat::martyna_tobias_klein(1.0, 1.0)
+            .temperature(0.1)
+            .anisotropic(),
+    );
+    run(&mut system, 0.1, EQUILIBRATION_STEPS, |_| {});
+    let (mut pressures, mut lengths) = (Vec3::zero(), Vec3::zero());
+    run(&mut system, 0.1, SAMPLE_STEPS, |system| {
+        pressures += system.pressure_tensor().unwrap() / SAMPLE_STEPS as f64;
+        lengths += system.simulation_box.unwrap().lengths() / SAMPLE_STEPS as f64;
+    });
+    println!(
+        "anisotropic mtk: mean pressures {:.3}, {:.3}, {:.3}, box {:.3} x {:.3} x {:.3}",
+        pressures.x, pressures.y, pressures.z, lengths.x, lengths.y, lengths.z
+    );
+    for (pressure, length) in [
+        (pressures.x, lengths.x),
+        (pressures.y, lengths.y),
+        (pressures.z, lengths.z),
+    ] {
+        assert!(
+            (pressure - 1.0).abs() < 0.1,
+            "the anisotropic barostat doesn't hold the pressure along every axis"
+        );
+        assert!(
+            (length / lengths.y - 1.0).abs() < 0.01,
+            "the anisotropic barostat doesn't relax the crystal's strain"
+        );
+    }
+}
@@ -0,0 +1,433 @@
+//! Checks the bonded terms of molecular mechanics. For every style of bond, angle, dihedral and
+//! improper on a distorted molecule straddling a periodic box, the forces should be minus the
+//! derivative of the energy, and the virial pressure should match the derivative of the energy
+//! with the box, even for fene bonds stretched past their maximum length. The terms should take their known values at landmark geometries, a flexible
+//! chain held together by them should conserve its energy, and a pendulum hung from stiff
+//! harmonic bonds should swing like one hung from XPBD distance constraints.
+
+use std::f64::consts::PI;
+
+use engine::{
+    algorithms::Random,
+    interaction::{
+        bonded::{Angles, Bonds, Dihedrals, Impropers},
+        Interaction,
+    },
+    particle::ParticleReference,
+    prelude::*,
+};
+
+// the Ryckaert–Bellemans torsion of butane, in kJ/mol
+const BUTANE: [f64; 6] = [9.28, 12.16, -13.12, -3.06, 26.24, -31.5];
+
+/// A bonded term, with the energy it reports.
+trait Term: Interaction {
+    fn energy(&self, particles: &[Particle]) -> f64;
+}
+
+macro_rules! term {
+    ($($kind:ty),*) => {
+        $(impl Term for $kind {
+            fn energy(&self, particles: &[Particle]) -> f64 {
+                self.potential_energy(particles)
+            }
+        })*
+    };
+}
+
+term!(Bonds, Angles, Dihedrals, Impropers);
+
+type Build = fn([ParticleReference; 4]) -> Box<dyn Term>;
+
+fn terms() -> Vec<(&'static str, Build)> {
+    vec![
+        ("harmonic bond", |[a, b, c, _]| {
+            Box::new(
+                Bonds::new()
+                    .harmonic([a, b], 300.0, 1.5)
+                    .harmonic([b, c], 300.0, 1.5),
+            )
+        }),
+        ("fene bond", |[a, b, c, _]| {
+            Box::new(Bonds::new().fene([a, b], 30.0, 2.0).fene([b, c], 30.0, 2.0))
+        }),
+        ("overstretched fene bond", |[a, b, c, _]| {
+            Box::new(Bonds::new().fene([a, b], 30.0, 1.2).fene([b, c], 30.0, 1.2))
+        }),
+        ("harmonic angle", |[a, b, c, d]| {
+            Box::new(
+                Angles::new()
+                    .harmonic([a, b, c], 50.0, 1.9)
+                    .harmonic([b, c, d], 50.0, 1.9),
+            )
+        }),
+        ("cosine angle", |[a, b, c, _]| {
+            Box::new(Angles::new().cosine([a, b, c], 5.0))
+        }),
+        ("cosine squared angle", |[a, b, c, _]| {
+            Box::new(Angles::new().cosine_squared([a, b, c], 50.0, 1.9))
+        }),
+        ("periodic dihedral", |particles| {
+            Box::new(
+                Dihedrals::new()
+                    .periodic(particles, 2.0, 1, 0.0)
+                    .periodic(particles, 1.0, 3, 0.4),
+            )
+        }),
+        ("ryckaert-bellemans dihedral", |particles| {
+            Box::new(Dihedrals::new().ryckaert_bellemans(particles, BUTANE))
+        }),
+        ("harmonic improper", |particles| {
+            Box::new(Impropers::new().harmonic(particles, 40.0, 0.3))
+        }),
+        ("periodic improper", |particles| {
+            Box::new(Impropers::new().periodic(particles, 4.0, 2, PI))
+        }),
+    ]
+}
+
+/// A gauche zigzag of four particles, distorted at random and placed so that it crosses the
+/// faces of the box.
+fn molecule(random: &mut Random) -> Vec<Vec3> {
+    let zigzag = [
+        Vec3::new(-1.3, 1.0, 0.0),
+        Vec3::new(-0.7, -0.4, 0.0),
+        Vec3::new(0.7, 0.4, 0.0),
+        Vec3::new(1.1, 1.0, 1.2),
+    ];
+    zigzag
+        .iter()
+        .map(|pos| {
+            let jitter = Vec3::new(random.uniform(), random.uniform(), random.uniform());
+            let pos = *pos + 0.2 * (jitter - Vec3::new(0.5, 0.5, 0.5));
+            // wrap into the box [0, 5)³, splitting the molecule across its faces
+            Vec3::new(
+                pos.x.rem_euclid(5.0),
+                pos.y.rem_euclid(5.0),
+                pos.z.rem_euclid(5.0),
+            )
+        })
+        .collect()
+}
+
+fn cube() -> SimulationBox {
+    SimulationBox::new(Vec3::zero(), Vec3::new(5.0, 5.0, 5.0))
+}
+
+/// The energy of a term on the positions, the forces on them and the virial.
+fn evaluate(
+    build: Build,
+    positions: &[Vec3],
+    simulation_box: SimulationBox,
+) -> (f64, Vec<Vec3>, Vec3) {
+    let mut system = System::new();
+    let particles = system.add_particles(
+        positions
+            .iter()
+            .map(|pos| Particle::new().pos(*pos))
+            .collect(),
+    );
+    let mut term = build([particles[0], particles[1], particles[2], particles[3]]);
+    term.set_simulation_box(&simulation_box);
+    term.handle(&mut system.particles, 0.0);
+    let forces = system
+        .particles
+        .iter()
+        .map(|particle| {
+            particle
+                .forces
+                .iter()
+                .fold(Vec3::zero(), |total, force| total + *force)
+        })
+        .collect();
+    (term.energy(&system.particles), forces, term.virial())
+}
+
+/// The largest difference between the forces of a term and minus the derivative of its energy,
+/// relative to the largest force.
+fn consistency_error(build: Build, positions: &[Vec3]) -> f64 {
+    let (_, forces, _) = evaluate(build, positions, cube());
+    let largest_force = forces.iter().map(|force| force.mag()).fold(0.0, f64::max);
+    let step = 1e-6;
+    let mut largest_error: f64 = 0.0;
+    for particle in 0..positions.len() {
+        for axis in [
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        ] {
+            let displaced = |offset: f64| {
+                let mut displaced = positions.to_vec();
+                displaced[particle] += offset * axis;
+                evaluate(build, &displaced, cube()).0
+            };
+            let expected = -(displaced(step) - displaced(-step)) / (2.0 * step);
+            largest_error = largest_error.max((forces[particle].dot(axis) - expected).abs());
+        }
+    }
+    largest_error / largest_force
+}
+
+/// The difference between the virial pressure along each axis and the derivative of the energy
+/// as the box, and the molecule with it, are stretched along that axis, relative to the largest
+/// force.
+fn pressure_error(build: Build, positions: &[Vec3]) -> f64 {
+    let simulation_box = cube();
+    let (_, forces, virial) = evaluate(build, positions, simulation_box);
+    let largest_force = forces.iter().map(|force| force.mag()).fold(0.0, f64::max);
+    let step = 1e-6;
+    let mut largest_error: f64 = 0.0;
+    for (axis, measured) in [
+        (Vec3::new(1.0, 0.0, 0.0), virial.x),
+        (Vec3::new(0.0, 1.0, 0.0), virial.y),
+        (Vec3::new(0.0, 0.0, 1.0), virial.z),
+    ] {
+        let stretched = |factor: f64| {
+            let stretch = Vec3::new(1.0, 1.0, 1.0) + (factor - 1.0) * axis;
+            let mut stretched_box = simulation_box;
+            stretched_box.scale(stretch);
+            let stretched: Vec<Vec3> = positions
+                .iter()
+                .map(|pos| simulation_box.scale_point(*pos, stretch))
+                .collect();
+            evaluate(build, &stretched, stretched_box).0
+        };
+        // the virial along an axis is -dU/d(ln L)
+        let expected = -(stretched(1.0 + step) - stretched(1.0 - step)) / (2.0 * step);
+        largest_error = largest_error.max((measured - expected).abs());
+    }
+    largest_error / largest_force
+}
+
+/// The energy of a term on four particles, at a dihedral angle φ with right bond angles.
+fn at_dihedral(build: Build, angle: f64) -> f64 {
+    let positions = [
+        Vec3::new(1.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, 1.0),
+        Vec3::new(angle.cos(), angle.sin(), 1.0),
+    ]
+    .map(|pos| pos + Vec3::new(2.5, 2.5, 2.5));
+    evaluate(build, &positions, cube()).0
+}
+
+/// The largest change in the total energy of a flexible chain, set moving at random, relative
+/// to its mean kinetic energy.
+fn energy_drift() -> f64 {
+    let mut system = System::new();
+    system.substeps = 1;
+    let mut random = Random::new(3);
+    let beads = 8;
+    let particles = system.add_particles(
+        (0..beads)
+            .map(|index| {
+                let zigzag = if index % 2 == 0 { 0.0 } else { 0.8 };
+                Particle::new()
+                    .pos_xyz(1.2 * index as f64, zigzag, 0.3 * (index as f64).sin())
+                    .vel(Vec3::new(
+                        random.gaussian(),
+                        random.gaussian(),
+                        random.gaussian(),
+                    ))
+                    .mass(1.0)
+            })
+            .collect(),
+    );
+    let chain = || {
+        let (mut bonds, mut angles, mut dihedrals) =
+            (Bonds::new(), Angles::new(), Dihedrals::new());
+        for index in 0..beads - 1 {
+            bonds = bonds.harmonic([particles[index], particles[index + 1]], 400.0, 1.4);
+        }
+        for index in 0..beads - 2 {
+            angles = angles.harmonic(
+                [particles[index], particles[index + 1], particles[index + 2]],
+                60.0,
+                1.95,
+            );
+        }
+        for index in 0..beads - 3 {
+            dihedrals = dihedrals.ryckaert_bellemans(
+                [
+                    particles[index],
+                    particles[index + 1],
+                    particles[index + 2],
+                    particles[index + 3],
+                ],
+                BUTANE.map(|coefficient| 0.1 * coefficient),
+            );
+        }
+        (bonds, angles, dihedrals)
+    };
+    let (bonds, angles, dihedrals) = chain();
+    system.add_interaction(bonds);
+    system.add_interaction(angles);
+    system.add_interaction(dihedrals);
+    let (bonds, angles, dihedrals) = chain();
+
+    let kinetic = |system: &System| {
+        system
+            .particles
+            .iter()
+            .map(|particle| 0.5 * particle.mass * particle.vel.mag_squared())
+            .sum::<f64>()
+    };
+    let total = |system: &System| {
+        kinetic(system)
+            + bonds.potential_energy(&system.particles)
+            + angles.potential_energy(&system.particles)
+            + dihedrals.potential_energy(&system.particles)
+    };
+    // short enough to resolve the bond vibrations, since the velocities lag the positions by
+    // half a step
+    let dt = 1e-4;
+    system.step_forward(dt);
+    let start = total(&system);
+    let (mut drift, mut mean_kinetic): (f64, f64) = (0.0, 0.0);
+    let steps = 20000;
+    for _ in 0..steps {
+        system.step_forward(dt);
+        mean_kinetic += kinetic(&system) / steps as f64;
+        drift = drift.max((total(&system) - start).abs());
+    }
+    drift / mean_kinetic
+}
+
+/// The positions of a double pendulum swinging for a second under gravity, its links held by
+/// stiff harmonic bonds or by XPBD distance constraints.
+fn double_pendulum(stiffness: Option<f64>) -> Vec<Vec3> {
+    let mut system = System::new();
+    let pivot = system.add_particle(Particle::new().mass(0.0));
+    let first = system.add_particle(Particle::new().pos_xyz(1.0, 0.0, 0.0).mass(2.0));
+    let second = system.add_particle(Particle::new().pos_xyz(1.0, -1.0, 0.0).mass(1.0));
+    system
+        .add_interaction(Interactions::Falling::new(9.81).with_particles(&system.all_particles()));
+    match stiffness {
+        Some(stiffness) => {
+            system.add_interaction(
+                Bonds::new()
+                    .harmonic([pivot, first], stiffness, 1.0)
+                    .harmonic([first, second], stiffness, 1.0),
+            );
+        }
+        None => {
+            system.add_constraint(Constraints::Distance::new([pivot, first], 1.0));
+            system.add_constraint(Constraints::Distance::new([first, second], 1.0));
+        }
+    }
+    for _ in 0..1000 {
+        system.step_forward(1e-3);
+    }
+    system
+        .particles
+        .iter()
+        .map(|particle| particle.pos)
+        .collect()
+}
+
+fn main() {
+    let mut random = Random::new(11);
+    let molecules: Vec<Vec<Vec3>> = (0..3).map(|_| molecule(&mut random)).collect();
+    for (name, build) in terms() {
+        let consistency = molecules
+            .iter()
+            .map(|positions| consistency_error(build, positions))
+            .fold(0.0, f64::max);
+        let pressure = molecules
+            .iter()
+            .map(|positions| pressure_error(build, positions))
+            .fold(0.0, f64::max);
+        println!(
+            "{}: force error {:.2e}, virial error {:.2e}",
+            name, consistency, pressure
+        );
+        assert!(
+            consistency < 1e-6,
+            "the {} forces aren't the derivative of its energy",
+            name
+        );
+        assert!(
+            pressure < 1e-6,
+            "the {} virial doesn't match the energy's derivative",
+            name
+        );
+    }
+
+    // a single cosine torsion vanishes at trans and peaks at cis, and the butane torsion is
+    // zero at trans and ΣC_n(-1)^n at cis
+    let cosine: Build = |particles| Box::new(Dihedrals::new().periodic(particles, 2.0, 1, 0.0));
+    let butane: Build =
+        |particles| Box::new(Dihedrals::new().ryckaert_bellemans(particles, BUTANE));
+    let cis_butane: f64 = BUTANE
+        .iter()
+        .enumerate()
+        .map(|(n, coefficient)| coefficient * (-1.0_f64).powi(n as i32))
+        .sum();
+    let landmarks = [
+        ("cosine torsion at trans", at_dihedral(cosine, PI), 0.0),
+        ("cosine torsion at cis", at_dihedral(cosine, 0.0), 4.0),
+        (
+            "cosine torsion at a right angle",
+            at_dihedral(cosine, 0.5 * PI),
+            2.0,
+        ),
+        ("butane torsion at trans", at_dihedral(butane, PI), 0.0),
+        (
+            "butane torsion at cis",
+            at_dihedral(butane, 0.0),
+            cis_butane,
+        ),
+        (
+            "harmonic angle at its rest angle",
+            at_dihedral(
+                |[a, b, c, _]| Box::new(Angles::new().harmonic([a, b, c], 10.0, 0.5 * PI)),
+                1.0,
+            ),
+            0.0,
+        ),
+        (
+            "harmonic improper at its rest angle",
+            at_dihedral(
+                |particles| Box::new(Impropers::new().harmonic(particles, 10.0, -0.7)),
+                -0.7,
+            ),
+            0.0,
+        ),
+    ];
+    for (name, energy, expected) in landmarks {
+        println!("{}: energy {:.6} (expected {:.6})", name, energy, expected);
+        assert!((energy - expected).abs() < 1e-9, "the {} is off", name);
+    }
+
+    let drift = energy_drift();
+    println!(
+        "flexible chain: energy drift {:.2e} of its kinetic energy",
+        drift
+    );
+    assert!(
+        drift < 1e-2,
+        "the flexible chain doesn't conserve its energy"
+    );
+
+    // stiffer bonds stretch less under the swing, and converge on the constrained pendulum
+    let constrained = double_pendulum(None);
+    let differences: Vec<f64> = [1e4, 1e6]
+        .into_iter()
+        .map(|stiffness| {
+            let difference = constrained
+                .iter()
+                .zip(&double_pendulum(Some(stiffness)))
+                .map(|(constrained, bonded)| (*constrained - *bonded).mag())
+                .fold(0.0, f64::max);
+            println!(
+                "double pendulum on bonds of stiffness {:.0e}: {:.2e} from the constrained one",
+                stiffness, difference
+            );
+            difference
+        })
+        .collect();
+    assert!(
+        differences[1] < 1e-2 && differences[1] < differences[0],
+        "the pendulum on stiff bonds doesn't follow the constrained one"
+    );
+}
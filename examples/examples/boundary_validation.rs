@@ -0,0 +1,221 @@
+//! Checks SPH boundaries with a column of water settling in a tank, once walled in by signed
+//! distance fields and once by boundary particles. The pressure should grow with depth at the
+//! hydrostatic rate ρ0 g, no water should leak out, and the boundary particles should carry the
+//! water's weight.
+
+use engine::{
+    interaction::sph::SphParameters,
+    prelude::*,
+    sph::{
+        boundary::{HalfSpace, SphBoundary},
+        kernels::{CubicSplineKernel, Dimension},
+        state_equations::Tait,
+        StateEquation,
+    },
+};
+
+const WIDTH: usize = 20;
+const HEIGHT: usize = 20;
+const SPACING: f64 = 1.0;
+const GRAVITY: f64 = 1.0;
+const DT: f64 = 0.05;
+
+/// Averages over the last steps of the settled column.
+struct Settled {
+    rest_density: f64,
+    pressure_gradient: f64,
+    boundary_load: f64,
+    lowest: f64,
+    widest: f64,
+}
+
+fn settle(particle_walls: bool) -> Settled {
+    let mut system = System::new();
+    system.substeps = 20;
+    for column in 0..WIDTH {
+        for row in 0..HEIGHT {
+            system.add_particle(
+                Particle::new()
+                    .pos_xyz(
+                        (column as f64 + 0.5) * SPACING,
+                        (row as f64 + 0.5) * SPACING,
+                        0.0,
+                    )
+                    .mass(1.0),
+            );
+        }
+    }
+    let fluid_particles = system.all_particles();
+
+    let smoothing_radius = 1.3 * SPACING;
+    let probe = SphParameters::new(smoothing_radius, Tait::new(1.0, 1.0))
+        .kernel(CubicSplineKernel::new(Dimension::Two));
+    let mut lattice = Vec::new();
+    for column in -3..=3 {
+        for row in -3..=3 {
+            lattice.push(
+                Particle::new()
+                    .pos_xyz(column as f64 * SPACING, row as f64 * SPACING, 0.0)
+                    .mass(1.0),
+            );
+        }
+    }
+    let rest_density = probe.density(Vec3::zero(), &lattice);
+
+    // the floor is at y = 0 and the walls at x = 0 and x = WIDTH
+    let right = WIDTH as f64 * SPACING;
+    let mut boundary = SphBoundary::new(rest_density, Dimension::Two);
+    let mut boundary_particles = Vec::new();
+    if particle_walls {
+        // three layers of fixed particles, outside the tank
+        for layer in 0..3 {
+            let offset = (layer as f64 + 0.5) * SPACING;
+            let mut x = -offset;
+            while x <= right + offset {
+                boundary_particles.push(
+                    system.add_particle(
+                        Particle::new()
+                            .pos_xyz(x, -offset, 0.0)
+                            .mass(1.0)
+                            .inverse_mass(0.0),
+                    ),
+                );
+                x += SPACING;
+            }
+            let mut y = -offset + SPACING;
+            while y <= 2.0 * HEIGHT as f64 * SPACING {
+                for x in [-offset, right + offset] {
+                    boundary_particles.push(
+                        system.add_particle(
+                            Particle::new()
+                                .pos_xyz(x, y, 0.0)
+                                .mass(1.0)
+                                .inverse_mass(0.0),
+                        ),
+                    );
+                }
+                y += SPACING;
+            }
+        }
+        boundary = boundary.with_particles(&boundary_particles);
+    } else {
+        boundary = boundary
+            .wall(HalfSpace::new(Vec3::zero(), Vec3::new(0.0, 1.0, 0.0)))
+            .wall(HalfSpace::new(Vec3::zero(), Vec3::new(1.0, 0.0, 0.0)))
+            .wall(HalfSpace::new(
+                Vec3::new(right, 0.0, 0.0),
+                Vec3::new(-1.0, 0.0, 0.0),
+            ));
+        for contact in boundary.contacts(&fluid_particles) {
+            system.add_constraint(contact);
+        }
+    }
+
+    let sound_speed = 10.0 * (2.0 * GRAVITY * HEIGHT as f64 * SPACING).sqrt();
+    let state_equation = Tait::new(rest_density, sound_speed);
+    let fluid = SphParameters::new(smoothing_radius, Tait::new(rest_density, sound_speed))
+        .kernel(CubicSplineKernel::new(Dimension::Two))
+        .gradient_kernel(CubicSplineKernel::new(Dimension::Two))
+        .artificial_viscosity(0.1, 0.0)
+        .boundary(boundary)
+        .with_particles(&fluid_particles);
+    system.add_interaction(fluid);
+    let gravity = Interactions::Falling::new(GRAVITY).with_particles(&fluid_particles);
+    system.add_interaction(gravity);
+
+    for _ in 0..1000 {
+        system.step_forward(DT);
+    }
+
+    // the column keeps sloshing a little, so the pressures and forces are averaged over time
+    let steps = 100;
+    let mut settled = Settled {
+        rest_density,
+        pressure_gradient: 0.0,
+        boundary_load: 0.0,
+        lowest: f64::MAX,
+        widest: 0.0,
+    };
+    for _ in 0..steps {
+        for interaction in &mut system.interactions {
+            interaction.handle(&mut system.particles, 0.0);
+        }
+        let load = boundary_particles
+            .iter()
+            .flat_map(|reference| reference.get(&system.particles).forces.iter())
+            .fold(Vec3::zero(), |total, force| total + *force);
+        settled.boundary_load -= load.y / steps as f64;
+
+        // the least squares slope of pressure against height, away from the floor and surface
+        let samples: Vec<(f64, f64)> = fluid_particles
+            .iter()
+            .map(|reference| reference.get(&system.particles))
+            .filter(|particle| {
+                particle.pos.y > 2.0 * SPACING
+                    && particle.pos.y < (HEIGHT - 2) as f64 * SPACING
+                    && (particle.pos.x - 0.5 * right).abs() < 0.5 * right - 2.0 * SPACING
+            })
+            .map(|particle| (particle.pos.y, state_equation.pressure(particle)))
+            .collect();
+        let count = samples.len() as f64;
+        let mean_height = samples.iter().map(|sample| sample.0).sum::<f64>() / count;
+        let mean_pressure = samples.iter().map(|sample| sample.1).sum::<f64>() / count;
+        let covariance: f64 = samples
+            .iter()
+            .map(|(height, pressure)| (height - mean_height) * (pressure - mean_pressure))
+            .sum();
+        let variance: f64 = samples
+            .iter()
+            .map(|(height, _)| (height - mean_height).powi(2))
+            .sum();
+        settled.pressure_gradient -= covariance / variance / steps as f64;
+
+        for reference in &fluid_particles {
+            let pos = reference.get(&system.particles).pos;
+            settled.lowest = settled.lowest.min(pos.y);
+            settled.widest = settled.widest.max((pos.x - 0.5 * right).abs());
+        }
+
+        // the forces were only needed for measuring, the step works them out again
+        for particle in &mut system.particles {
+            particle.forces.clear();
+        }
+        system.step_forward(DT);
+    }
+    settled
+}
+
+fn main() {
+    let weight = (WIDTH * HEIGHT) as f64 * GRAVITY;
+    for (name, particle_walls) in [("walls", false), ("boundary particles", true)] {
+        let settled = settle(particle_walls);
+
+        let expected = settled.rest_density * GRAVITY;
+        println!(
+            "{}: pressure gradient {:.3}, expected {:.3}, lowest particle at {:.2}, widest at {:.2}",
+            name, settled.pressure_gradient, expected, settled.lowest, settled.widest
+        );
+        assert!(
+            (settled.pressure_gradient - expected).abs() < 0.1 * expected,
+            "the pressure doesn't grow hydrostatically with depth"
+        );
+        // with walls the outermost fluid settles against the surface itself, rather than half
+        // a spacing away from it, as it would next to boundary particles
+        let tolerance = 0.25 * SPACING;
+        assert!(
+            settled.lowest > -tolerance
+                && settled.widest < 0.5 * WIDTH as f64 * SPACING + tolerance,
+            "water leaked through the boundary"
+        );
+        if particle_walls {
+            println!(
+                "{}: load {:.2}, weight {:.2}",
+                name, settled.boundary_load, weight
+            );
+            assert!(
+                (settled.boundary_load - weight).abs() < 0.05 * weight,
+                "the boundary particles don't carry the water's weight"
+            );
+        }
+    }
+}
@@ -0,0 +1,62 @@
+//! Compares the spatial hash and the bounding volume hierarchy on a clustered scene,
+//! similar to star_collision.rs, where most of space is empty and a uniform grid degrades.
+
+use engine::{
+    collision::{bvh::Bvh, spatial_hash::SpatialHash, CollisionDetector},
+    prelude::*,
+};
+use rand::Rng;
+
+const PARTICLE_COUNT: u32 = 10000;
+const FRAMES: u32 = 10;
+
+fn main() {
+    let mut system = System::new();
+    let mut rng = rand::thread_rng();
+    let collision_distance = 2.0 * system.particle_radius;
+
+    // two dense clusters with a sparse halo of escaped particles
+    for i in 0..PARTICLE_COUNT {
+        let center = if i % 2 == 0 {
+            Vec3::new(-5000.0, 0.0, 0.0)
+        } else {
+            Vec3::new(5000.0, 2000.0, 0.0)
+        };
+        let spread = if i % 50 == 0 { 20000.0 } else { 300.0 };
+        let offset = Vec3::new(
+            rng.gen_range(-spread..spread),
+            rng.gen_range(-spread..spread),
+            rng.gen_range(-spread..spread),
+        );
+        system.add_particle(Particle::new().pos(center + offset));
+    }
+
+    let mut detectors: Vec<(&str, Box<dyn CollisionDetector>)> = vec![
+        (
+            "spatial hash",
+            Box::new(SpatialHash::new(collision_distance)),
+        ),
+        ("bvh", Box::new(Bvh::new(collision_distance))),
+    ];
+
+    let mut counts = Vec::new();
+    for (name, detector) in &mut detectors {
+        let start = std::time::Instant::now();
+        let mut pairs = 0;
+        for _ in 0..FRAMES {
+            pairs = detector.contact_pairs(&system.particles).len();
+        }
+        println!(
+            "{:>12}: {} contact pairs, {:.2?} per frame",
+            name,
+            pairs,
+            start.elapsed() / FRAMES
+        );
+        counts.push(pairs);
+    }
+
+    assert!(
+        counts.windows(2).all(|pair| pair[0] == pair[1]),
+        "broadphases disagree on the contact pairs"
+    );
+}
@@ -1,4 +1,4 @@
-use engine::prelude::*;
+use engine::{collision::spatial_hash::SpatialHash, prelude::*};
 use rendering::particle_2d_renderer::Particle2DRenderer;
 
 const CHAIN_LENGTH: f64 = 400.0;
@@ -27,11 +27,8 @@ fn main() {
     for (i, p1) in system.all_particles().iter().enumerate() {
         if (i as u32) < LINK_COUNT - 1 {
             system.add_constraint(
-                Constraints::Distance::new(
-                    [*p1, *&system.all_particles()[(i as usize) + 1]],
-                    2.0 * LINK_RADIUS,
-                )
-                .max_force(226000.0 / ((i + 1) as f64)),
+                Constraints::Distance::new([*p1, system.all_particles()[i + 1]], 2.0 * LINK_RADIUS)
+                    .max_force(226000.0 / ((i + 1) as f64)),
             );
         }
     }
@@ -39,16 +36,10 @@ fn main() {
     system.add_particle(Particle::new().pos_xyz(100.0, -250.0, 0.0).mass(0.0));
     system.add_particle(Particle::new().pos_xyz(60.0, -250.0, 0.0).mass(0.0));
 
-    let mut index: usize = 0;
-    for ref1 in &system.all_particles() {
-        for ref2 in &system.all_particles()[(index + 1)..] {
-            system.add_constraint(Constraints::NonPenetrate::new(
-                [*ref1, *ref2],
-                2.0 * system.particle_radius,
-            ));
-        }
-        index += 1;
-    }
+    let collision_distance = 2.0 * system.particle_radius;
+    system.add_constraint(Constraints::Collisions::new(SpatialHash::new(
+        collision_distance,
+    )));
 
     let gravity = Interactions::Falling::new(GRAVITY).with_particles(&system.all_particles());
 
@@ -0,0 +1,137 @@
+use engine::{
+    interaction::{
+        sph::SphParameters,
+        surface_tension::{SurfaceTension, SurfaceTensionModel},
+    },
+    prelude::*,
+    sph::{
+        kernels::{CubicSplineKernel, Dimension},
+        state_equations::Tait,
+    },
+};
+use rendering::particle_2d_renderer::Particle2DRenderer;
+
+const SPACING: f64 = 10.0;
+const SIDE: u32 = 12;
+const MASS: f64 = 1.0;
+const GRAVITY: f64 = 10.0;
+const SOUND_SPEED: f64 = 1000.0;
+
+// water clings to the ceiling, while oil drips off it
+const WATER: u32 = 1;
+const OIL: u32 = 2;
+const CEILING: u32 = 3;
+
+fn main() {
+    let mut system = System::new();
+    system.particle_radius = SPACING / 2.0;
+    system.substeps = 40;
+    let mut window = Particle2DRenderer::new(None);
+    window.scale.physics_dt = 1.0 / 30.0;
+    window
+        .style
+        .group_colors
+        .insert(WATER, rendering::colors::EARTH_BLUE);
+    window
+        .style
+        .group_colors
+        .insert(OIL, rendering::colors::FOREST_GREEN);
+
+    let bounds = [-500.0, 500.0, -500.0, 500.0];
+    let radius = system.particle_radius;
+
+    // a square of each fluid against the ceiling, which pulls itself into a drop
+    for (group, left) in [(WATER, -300.0), (OIL, 200.0)] {
+        for column in 0..SIDE {
+            for row in 0..SIDE {
+                system.add_particle(
+                    Particle::new()
+                        .pos_xyz(
+                            left + column as f64 * SPACING,
+                            bounds[3] - radius - row as f64 * SPACING,
+                            0.0,
+                        )
+                        .mass(MASS)
+                        .group(group),
+                );
+            }
+        }
+    }
+    let fluid_particles = system.all_particles();
+
+    // the ceiling is lined with fixed particles for the fluid to stick to, just above the wall
+    let mut ceiling_particles = Vec::new();
+    let mut x = bounds[0];
+    while x <= bounds[1] {
+        ceiling_particles.push(
+            system.add_particle(
+                Particle::new()
+                    .pos_xyz(x, bounds[3] + SPACING, 0.0)
+                    .mass(MASS)
+                    .inverse_mass(0.0)
+                    .group(CEILING),
+            ),
+        );
+        x += SPACING;
+    }
+
+    let smoothing_radius = 1.3 * SPACING;
+    let probe = SphParameters::new(smoothing_radius, Tait::new(1.0, SOUND_SPEED))
+        .kernel(CubicSplineKernel::new(Dimension::Two));
+    let center = system.particles[(SIDE / 2 * SIDE + SIDE / 2) as usize].pos;
+    let rest_density = probe.density(center, &system.particles);
+
+    // unlike the dam break, the fluid is allowed a little tension, which lets the drops hang
+    let fluid = SphParameters::new(smoothing_radius, Tait::new(rest_density, SOUND_SPEED))
+        .kernel(CubicSplineKernel::new(Dimension::Two))
+        .gradient_kernel(CubicSplineKernel::new(Dimension::Two))
+        .artificial_viscosity(1.0, 0.0)
+        .with_particles(&fluid_particles);
+    system.add_interaction(fluid);
+
+    let surface_tension = SurfaceTension::new(
+        smoothing_radius,
+        SurfaceTensionModel::Csf {
+            normal_threshold: 0.3 / smoothing_radius,
+        },
+    )
+    .kernel(CubicSplineKernel::new(Dimension::Two))
+    .tension(WATER, 1000.0)
+    .tension(OIL, 1000.0)
+    .adhesion(WATER, CEILING, 5e8)
+    .with_particles(&fluid_particles)
+    .with_boundary_particles(&ceiling_particles);
+    system.add_interaction(surface_tension);
+
+    let gravity = Interactions::Falling::new(GRAVITY).with_particles(&fluid_particles);
+    system.add_interaction(gravity);
+
+    for particle in &fluid_particles {
+        system.add_constraint(Constraints::ContactPlane::new(
+            *particle,
+            radius,
+            Vec3::new(bounds[0], 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+        ));
+        system.add_constraint(Constraints::ContactPlane::new(
+            *particle,
+            radius,
+            Vec3::new(bounds[1], 0.0, 0.0),
+            Vec3::new(-1.0, 0.0, 0.0),
+        ));
+        system.add_constraint(Constraints::ContactPlane::new(
+            *particle,
+            radius,
+            Vec3::new(0.0, bounds[2], 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        ));
+        system.add_constraint(Constraints::ContactPlane::new(
+            *particle,
+            radius,
+            Vec3::new(0.0, bounds[3], 0.0),
+            Vec3::new(0.0, -1.0, 0.0),
+        ));
+    }
+
+    window.run(system);
+}
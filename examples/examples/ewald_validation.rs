@@ -0,0 +1,187 @@
+//! Reports how long-range electrostatics does on a rock-salt crystal, with unit charges a unit
+//! distance apart (k = 1). Its energy per ion pair is -M, Madelung's constant for the NaCl
+//! lattice, which Ewald summation should find to many digits whatever the split between its two
+//! sums, and PPPM to its mesh's accuracy. On a disordered crystal, it compares the Ewald forces
+//! with the derivative of the energy and the PPPM forces with them, and the virial pressure of
+//! both with the derivative of the energy with the box. The engine's tests check the Madelung
+//! constant and the Ewald forces.
+
+use engine::{
+    algorithms::Random,
+    interaction::{ewald::Ewald, Interaction},
+    prelude::*,
+};
+
+const MADELUNG: f64 = 1.747_564_594_633;
+const SIDE: usize = 6;
+const CUTOFF: f64 = 2.5;
+
+/// Alternating charges on a cubic lattice, which is the rock-salt crystal.
+fn rock_salt() -> (Vec<Vec3>, Vec<f64>) {
+    let mut positions = Vec::new();
+    let mut charges = Vec::new();
+    for x in 0..SIDE {
+        for y in 0..SIDE {
+            for z in 0..SIDE {
+                positions.push(Vec3::new(x as f64 + 0.25, y as f64 + 0.25, z as f64 + 0.25));
+                charges.push(if (x + y + z) % 2 == 0 { 1.0 } else { -1.0 });
+            }
+        }
+    }
+    (positions, charges)
+}
+
+fn cube() -> SimulationBox {
+    let side = SIDE as f64;
+    SimulationBox::new(Vec3::zero(), Vec3::new(side, side, side))
+}
+
+fn summation() -> Ewald {
+    Ewald::summation(1.0, CUTOFF, cube()).accuracy(1e-10)
+}
+
+fn particle_mesh() -> Ewald {
+    Ewald::particle_mesh(1.0, CUTOFF, 64, cube())
+}
+
+/// The energy of the charges at the positions, the forces on them and the virial.
+fn evaluate(
+    solver: Ewald,
+    positions: &[Vec3],
+    charges: &[f64],
+    simulation_box: SimulationBox,
+) -> (f64, Vec<Vec3>, Vec3) {
+    let mut system = System::new();
+    for (pos, charge) in positions.iter().zip(charges) {
+        system.add_particle(Particle::new().pos(*pos).charge(*charge));
+    }
+    let mut solver = solver.with_particles(&system.all_particles());
+    solver.set_simulation_box(&simulation_box);
+    solver.handle(&mut system.particles, 0.0);
+    let forces = system
+        .particles
+        .iter()
+        .map(|particle| {
+            particle
+                .forces
+                .iter()
+                .fold(Vec3::zero(), |total, force| total + *force)
+        })
+        .collect();
+    (
+        solver.potential_energy(&system.particles),
+        forces,
+        solver.virial(),
+    )
+}
+
+/// The energy per ion pair, relative to Madelung's.
+fn madelung_error(solver: Ewald) -> f64 {
+    let (positions, charges) = rock_salt();
+    let (energy, _, _) = evaluate(solver, &positions, &charges, cube());
+    let per_pair = energy / (0.5 * positions.len() as f64);
+    (per_pair + MADELUNG).abs() / MADELUNG
+}
+
+/// The relative error of the virial pressure along x against the derivative of the energy as
+/// the box, and the charges with it, are stretched along x.
+fn pressure_error(solver: fn() -> Ewald, positions: &[Vec3], charges: &[f64]) -> f64 {
+    let simulation_box = cube();
+    let (_, _, virial) = evaluate(solver(), positions, charges, simulation_box);
+    let pressure = virial.x / simulation_box.volume();
+
+    let step = 1e-5;
+    let stretched = |factor: f64| {
+        let stretch = Vec3::new(factor, 1.0, 1.0);
+        let mut stretched_box = simulation_box;
+        stretched_box.scale(stretch);
+        let stretched: Vec<Vec3> = positions
+            .iter()
+            .map(|pos| simulation_box.scale_point(*pos, stretch))
+            .collect();
+        evaluate(solver(), &stretched, charges, stretched_box).0
+    };
+    // dU/dL_x L_x = dU/d(ln L_x)
+    let derivative = (stretched(1.0 + step) - stretched(1.0 - step)) / (2.0 * step);
+    let expected = -derivative / simulation_box.volume();
+    (pressure - expected).abs() / expected.abs()
+}
+
+fn main() {
+    // the crystal's energy, by each solver and over a range of splits wide enough that both
+    // sums have converged at the cutoff
+    let error = madelung_error(summation());
+    println!("ewald summation: madelung constant error {:.2e}", error);
+    for splitting in [1.6, 2.2, 2.8] {
+        let error = madelung_error(summation().splitting(splitting));
+        println!(
+            "ewald summation with α = {}: madelung constant error {:.2e}",
+            splitting, error
+        );
+    }
+    let error = madelung_error(particle_mesh());
+    println!("pppm: madelung constant error {:.2e}", error);
+
+    // forces on a disordered crystal
+    let (lattice, charges) = rock_salt();
+    let mut random = Random::new(1);
+    let positions: Vec<Vec3> = lattice
+        .iter()
+        .map(|pos| {
+            *pos + 0.2
+                * Vec3::new(
+                    random.uniform() - 0.5,
+                    random.uniform() - 0.5,
+                    random.uniform() - 0.5,
+                )
+        })
+        .collect();
+    let (_, forces, _) = evaluate(summation(), &positions, &charges, cube());
+    let largest_force = forces.iter().map(|force| force.mag()).fold(0.0, f64::max);
+    let step = 1e-5;
+    let mut largest_error: f64 = 0.0;
+    for particle in [0, 7, positions.len() / 2, positions.len() - 1] {
+        for axis in [
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        ] {
+            let displaced = |offset: f64| {
+                let mut displaced = positions.clone();
+                displaced[particle] += offset * axis;
+                evaluate(summation(), &displaced, &charges, cube()).0
+            };
+            let expected = -(displaced(step) - displaced(-step)) / (2.0 * step);
+            largest_error = largest_error.max((forces[particle].dot(axis) - expected).abs());
+        }
+    }
+    let error = largest_error / largest_force;
+    println!(
+        "ewald summation: force against the energy's derivative, error {:.2e}",
+        error
+    );
+
+    let (_, mesh_forces, _) = evaluate(particle_mesh(), &positions, &charges, cube());
+    let (difference, magnitude) = forces.iter().zip(&mesh_forces).fold(
+        (0.0, 0.0),
+        |(difference, magnitude), (exact, mesh)| {
+            (
+                difference + (*exact - *mesh).mag_squared(),
+                magnitude + exact.mag_squared(),
+            )
+        },
+    );
+    let error = (difference / magnitude).sqrt();
+    println!(
+        "pppm: rms force error against ewald summation {:.2e}",
+        error
+    );
+
+    for (name, solver) in [
+        ("ewald summation", summation as fn() -> Ewald),
+        ("pppm", particle_mesh),
+    ] {
+        let error = pressure_error(solver, &positions, &charges);
+        println!("{}: virial pressure error {:.2e}", name, error);
+    }
+}
@@ -1,4 +1,7 @@
-use engine::prelude::*;
+use engine::{
+    collision::spatial_hash::SpatialHash, interaction::pair_wise::Truncation, math::Dimension,
+    prelude::*, thermostat::Thermostat,
+};
 use rand::Rng;
 use rendering::particle_2d_renderer::Particle2DRenderer;
 
@@ -26,9 +29,18 @@ fn main() {
 
     let repulsion = Interactions::LennardJones::new(BOND_ENERGY, 2. * RADIUS)
         .build()
+        .cutoff(2.5 * 2. * RADIUS)
+        .truncation(Truncation::ForceShifted)
         .with_particles(&system.all_particles());
     system.add_interaction(repulsion);
 
+    // the particles start as a hot gas and are cooled until they freeze, over half a minute
+    let thermostat = Thermostat::langevin(BOND_ENERGY, 1.0)
+        .dimension(Dimension::Two)
+        .schedule(|time| BOND_ENERGY * (2.0 - time / 15.0).max(0.05))
+        .with_particles(&system.all_particles());
+    system.add_thermostat(thermostat);
+
     let gravity = Interactions::Falling::new(GRAVITY).with_particles(&system.all_particles());
     system.add_interaction(gravity);
 
@@ -65,18 +77,11 @@ fn main() {
         ));
     }
 
-    let mut index: usize = 0;
-    let mut constraints = Vec::new();
-    for ref1 in &system.all_particles() {
-        for ref2 in &system.all_particles()[(index + 1)..] {
-            constraints.push(Constraints::NonPenetrate::new([*ref1, *ref2], 2. * RADIUS));
-        }
-        index += 1;
-    }
+    let collision_distance = 2. * RADIUS;
+    let mut separation = Constraints::Collisions::new(SpatialHash::new(collision_distance));
     for _ in 0..100 {
-        for constraint in &mut constraints {
-            constraint.project(&mut system.particles, core::f64::MIN_POSITIVE, true);
-        }
+        separation.prepare(&system.particles);
+        separation.project(&mut system.particles, f64::MIN_POSITIVE, true);
         system.static_constraint_pass(1);
     }
 
@@ -1,4 +1,7 @@
-use engine::prelude::*;
+use engine::{
+    collision::spatial_hash::SpatialHash, interaction::pair_wise::Truncation, math::Dimension,
+    prelude::*, thermostat::Thermostat,
+};
 use rand::Rng;
 use rendering::particle_2d_renderer::Particle2DRenderer;
 
@@ -25,9 +28,17 @@ fn main() {
 
     let repulsion = Interactions::LennardJones::new(BOND_ENERGY, 2. * RADIUS)
         .build()
+        .cutoff(2.5 * 2. * RADIUS)
+        .truncation(Truncation::ForceShifted)
         .with_particles(&system.all_particles());
     system.add_interaction(repulsion);
 
+    // the gas is held at a temperature well above the depth of the attraction between particles
+    let thermostat = Thermostat::bussi(1.5 * BOND_ENERGY, 0.5)
+        .dimension(Dimension::Two)
+        .with_particles(&system.all_particles());
+    system.add_thermostat(thermostat);
+
     for particle in &system.all_particles() {
         system.add_constraint(
             Constraints::ContactPlane::new(
@@ -67,21 +78,11 @@ fn main() {
         );
     }
 
-    let mut index: usize = 0;
-    let mut constraints = Vec::new();
-    for ref1 in &system.all_particles() {
-        for ref2 in &system.all_particles()[(index + 1)..] {
-            constraints.push(Constraints::NonPenetrate::new(
-                [*ref1, *ref2],
-                2.0 * system.particle_radius,
-            ));
-        }
-        index += 1;
-    }
+    let collision_distance = 2.0 * system.particle_radius;
+    let mut separation = Constraints::Collisions::new(SpatialHash::new(collision_distance));
     for _ in 0..100 {
-        for constraint in &mut constraints {
-            constraint.project(&mut system.particles, core::f64::MIN_POSITIVE, true);
-        }
+        separation.prepare(&system.particles);
+        separation.project(&mut system.particles, f64::MIN_POSITIVE, true);
         system.static_constraint_pass(1);
     }
 
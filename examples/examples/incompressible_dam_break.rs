@@ -0,0 +1,89 @@
+use engine::{
+    prelude::*,
+    sph::{
+        kernels::{CubicSplineKernel, Dimension},
+        pressure_solvers::Iisph,
+        SphKernel,
+    },
+};
+use rendering::particle_2d_renderer::Particle2DRenderer;
+
+const SPACING: f64 = 10.0;
+const COLUMNS: u32 = 20;
+const ROWS: u32 = 30;
+const MASS: f64 = 1.0;
+const GRAVITY: f64 = 600.0;
+
+fn main() {
+    let mut system = System::new();
+    system.particle_radius = SPACING / 2.0;
+    // an implicit pressure solve isn't limited by the speed of sound, so the dam break
+    // needs a fraction of the substeps that its weakly-compressible counterpart does
+    system.substeps = 10;
+    let mut window = Particle2DRenderer::new(None);
+    window.scale.physics_dt = 1.0 / 30.0;
+    window
+        .style
+        .group_colors
+        .insert(1, rendering::colors::EARTH_BLUE);
+
+    let bounds = [-500.0, 500.0, -500.0, 500.0];
+    let radius = system.particle_radius;
+
+    // a column of fluid resting in the lower left corner
+    for column in 0..COLUMNS {
+        for row in 0..ROWS {
+            system.add_particle(
+                Particle::new()
+                    .pos_xyz(
+                        bounds[0] + radius + column as f64 * SPACING,
+                        bounds[2] + radius + row as f64 * SPACING,
+                        0.0,
+                    )
+                    .mass(MASS)
+                    .group(1),
+            );
+        }
+    }
+
+    // the rest density is whatever the kernel measures inside the initial lattice
+    let smoothing_radius = 1.3 * SPACING;
+    let kernel = CubicSplineKernel::new(Dimension::Two);
+    let center = system.particles[(COLUMNS / 2 * ROWS + ROWS / 2) as usize].pos;
+    let rest_density: f64 = system
+        .particles
+        .iter()
+        .map(|particle| particle.mass * kernel.w(center - particle.pos, smoothing_radius))
+        .sum();
+
+    let fluid = Iisph::new(rest_density, smoothing_radius)
+        .kernel(kernel)
+        .with_particles(&system.all_particles());
+    system.add_pressure_solver(fluid);
+
+    let gravity = Interactions::Falling::new(GRAVITY).with_particles(&system.all_particles());
+    system.add_interaction(gravity);
+
+    for particle in &system.all_particles() {
+        system.add_constraint(Constraints::ContactPlane::new(
+            *particle,
+            radius,
+            Vec3::new(bounds[0], 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+        ));
+        system.add_constraint(Constraints::ContactPlane::new(
+            *particle,
+            radius,
+            Vec3::new(bounds[1], 0.0, 0.0),
+            Vec3::new(-1.0, 0.0, 0.0),
+        ));
+        system.add_constraint(Constraints::ContactPlane::new(
+            *particle,
+            radius,
+            Vec3::new(0.0, bounds[2], 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        ));
+    }
+
+    window.run(system);
+}
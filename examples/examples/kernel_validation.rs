@@ -0,0 +1,174 @@
+//! Reports how every smoothing kernel does numerically: how close it integrates to one over its
+//! dimension, how well its derivatives agree with finite differences of W, and how accurate the
+//! SPH estimates of a constant and of a linear field's gradient are on a regular lattice. The
+//! engine's tests check the normalization and the derivatives.
+
+use engine::{
+    math::{Vec3, PI},
+    sph::{kernels::*, SphKernel},
+};
+
+const H: f64 = 1.3;
+
+fn normalization(kernel: &dyn SphKernel, dimension: Dimension) -> f64 {
+    let steps = 200_000;
+    let dr = kernel.support(H) / steps as f64;
+    let surface = match dimension {
+        Dimension::One => |_: f64| 2.0,
+        Dimension::Two => |r: f64| 2.0 * PI * r,
+        Dimension::Three => |r: f64| 4.0 * PI * r * r,
+    };
+
+    (0..steps)
+        .map(|step| {
+            let r = (step as f64 + 0.5) * dr;
+            surface(r) * kernel.w(r * Vec3::x_hat(), H) * dr
+        })
+        .sum()
+}
+
+/// The largest disagreement between the analytic derivatives and finite differences of W,
+/// relative to the largest derivative seen.
+fn derivative_errors(kernel: &dyn SphKernel, dimension: Dimension, from: f64) -> (f64, f64) {
+    let d = dimension.count() as f64;
+    let support = kernel.support(H);
+    let step = 1e-4 * support;
+    let direction = Vec3::new(0.48, -0.6, 0.64);
+
+    let (mut first_error, mut first_scale) = (0.0_f64, 0.0_f64);
+    let (mut second_error, mut second_scale) = (0.0_f64, 0.0_f64);
+    for sample in 1..200 {
+        let r = from + (support - from) * sample as f64 / 200.0;
+        let w = |r: f64| kernel.w(r * direction, H);
+        let numeric_first = (w(r + step) - w(r - step)) / (2.0 * step);
+        let numeric_second = (w(r + step) - 2.0 * w(r) + w(r - step)) / step.powi(2);
+        let numeric_laplace = numeric_second + (d - 1.0) * numeric_first / r;
+
+        let analytic_first = kernel.div_w(r * direction, H);
+        let gradient = kernel.grad_w(r * direction, H);
+        first_error = first_error
+            .max((analytic_first - numeric_first).abs())
+            .max((gradient - analytic_first * direction).mag());
+        first_scale = first_scale.max(analytic_first.abs());
+
+        second_error =
+            second_error.max((kernel.laplace_w(r * direction, H) - numeric_laplace).abs());
+        second_scale = second_scale.max(numeric_laplace.abs());
+    }
+    (first_error / first_scale, second_error / second_scale)
+}
+
+/// The estimate of the constant field 1, and the worst error in the estimate of the identity
+/// matrix from the gradient of the position field, at a particle in a regular lattice.
+fn lattice_consistency(kernel: &dyn SphKernel, dimension: Dimension) -> (f64, f64) {
+    let spacing = kernel.support(H) / 6.0;
+    let volume = spacing.powi(dimension.count());
+    let reach = 7;
+    let range = |active: bool| if active { -reach..=reach } else { 0..=0 };
+
+    let mut one = 0.0;
+    let mut identity = [[0.0; 3]; 3];
+    for i in range(true) {
+        for j in range(dimension != Dimension::One) {
+            for k in range(dimension == Dimension::Three) {
+                let offset = spacing * Vec3::new(i as f64, j as f64, k as f64);
+                one += volume * kernel.w(offset, H);
+
+                // the gradient is taken with respect to the central particle, at -offset
+                let gradient = kernel.grad_w(-offset, H);
+                let (offset, gradient) = (
+                    [offset.x, offset.y, offset.z],
+                    [gradient.x, gradient.y, gradient.z],
+                );
+                for a in 0..3 {
+                    for b in 0..3 {
+                        identity[a][b] += volume * offset[a] * gradient[b];
+                    }
+                }
+            }
+        }
+    }
+
+    let mut worst = 0.0_f64;
+    for (a, row) in identity.iter().enumerate().take(dimension.count() as usize) {
+        for (b, value) in row.iter().enumerate().take(dimension.count() as usize) {
+            let expected = if a == b { 1.0 } else { 0.0 };
+            worst = worst.max((value - expected).abs());
+        }
+    }
+    (one, worst)
+}
+
+fn main() {
+    let dimensions = [Dimension::One, Dimension::Two, Dimension::Three];
+    let mut kernels: Vec<(String, Box<dyn SphKernel>, Dimension)> = vec![
+        (
+            "gaussian".into(),
+            Box::new(GaussianKernel),
+            Dimension::Three,
+        ),
+        ("poly6".into(), Box::new(Poly6Kernel), Dimension::Three),
+        ("spiky".into(), Box::new(SpikyKernel), Dimension::Three),
+        (
+            "viscosity".into(),
+            Box::new(ViscosityKernel),
+            Dimension::Three,
+        ),
+    ];
+    for dimension in dimensions {
+        kernels.push((
+            format!("cubic spline {}d", dimension.count()),
+            Box::new(CubicSplineKernel::new(dimension)),
+            dimension,
+        ));
+        kernels.push((
+            format!("quintic {}d", dimension.count()),
+            Box::new(QuinticKernel::new(dimension)),
+            dimension,
+        ));
+        kernels.push((
+            format!("wendland c2 {}d", dimension.count()),
+            Box::new(WendlandC2Kernel::new(dimension)),
+            dimension,
+        ));
+        kernels.push((
+            format!("wendland c4 {}d", dimension.count()),
+            Box::new(WendlandC4Kernel::new(dimension)),
+            dimension,
+        ));
+        kernels.push((
+            format!("wendland c6 {}d", dimension.count()),
+            Box::new(WendlandC6Kernel::new(dimension)),
+            dimension,
+        ));
+    }
+
+    println!(
+        "{:<18} {:>12} {:>12} {:>12} {:>12} {:>12}",
+        "kernel", "∫W", "dW/dr err", "∇²W err", "Σ V W", "Σ V x∇W err"
+    );
+    for (name, kernel, dimension) in &kernels {
+        let kernel = kernel.as_ref();
+        let integral = normalization(kernel, *dimension);
+
+        // the spiky and viscosity kernels are singular at the origin
+        let singular = name == "spiky" || name == "viscosity";
+        let from = if singular {
+            0.1 * kernel.support(H)
+        } else {
+            0.0
+        };
+        let (first_error, second_error) = derivative_errors(kernel, *dimension, from);
+
+        print!(
+            "{:<18} {:>12.6} {:>12.2e} {:>12.2e}",
+            name, integral, first_error, second_error
+        );
+
+        if name != "viscosity" {
+            let (one, identity_error) = lattice_consistency(kernel, *dimension);
+            print!(" {:>12.6} {:>12.2e}", one, identity_error);
+        }
+        println!();
+    }
+}
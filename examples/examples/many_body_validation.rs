@@ -0,0 +1,477 @@
+//! Checks the many-body potentials, in electronvolts, ångströms and atomic mass units. For each
+//! of them, the forces on a disordered crystal should be minus the derivative of its energy, the
+//! virial pressure should match the derivative of the energy with the box, and a crystal should
+//! conserve its energy as it vibrates.
+//!
+//! Stillinger–Weber and Tersoff silicon should bind the diamond lattice by their published
+//! cohesive energies. The embedded-atom method is read from a setfl file written here from an
+//! analytic model of copper (after Johnson, 1988), whose embedding energy makes the energy of
+//! the fcc lattice follow Rose's universal equation of state exactly while only nearest
+//! neighbors interact. A second, made-up element checks that alloys find their cross terms.
+
+use engine::{
+    algorithms::Random,
+    interaction::{
+        many_body::{EmbeddedAtom, ManyBodyParameters},
+        Interaction,
+    },
+    prelude::*,
+};
+
+const COPPER_LATTICE: f64 = 3.615;
+const COPPER_MASS: f64 = 63.546;
+const COHESIVE_ENERGY: f64 = 3.54;
+// the stiffness of Rose's equation of state, from copper's bulk modulus
+const ROSE_STIFFNESS: f64 = 5.1;
+const DENSITY_DECAY: f64 = 5.85;
+const PAIR_DECAY: f64 = 8.0;
+const PAIR_ENERGY: f64 = 0.59;
+// the functions are tapered to zero between the first and second neighbor shells
+const TAPER_START: f64 = 3.0;
+const EAM_CUTOFF: f64 = 3.3;
+
+const SILICON_LATTICE: f64 = 5.431;
+const SILICON_MASS: f64 = 28.0855;
+
+/// The nearest neighbor distance of copper.
+fn equilibrium_distance() -> f64 {
+    COPPER_LATTICE / 2_f64.sqrt()
+}
+
+/// Rose's equation of state, the energy per atom at a nearest neighbor distance.
+fn rose(r: f64) -> f64 {
+    let strain = ROSE_STIFFNESS * (r / equilibrium_distance() - 1.0);
+    -COHESIVE_ENERGY * (1.0 + strain) * (-strain).exp()
+}
+
+/// One out to the first neighbor shell, falling smoothly to zero at the cutoff.
+fn taper(r: f64) -> f64 {
+    let x = ((r - TAPER_START) / (EAM_CUTOFF - TAPER_START)).clamp(0.0, 1.0);
+    1.0 - x * x * x * (10.0 - 15.0 * x + 6.0 * x * x)
+}
+
+fn untapered_pair_potential(r: f64) -> f64 {
+    PAIR_ENERGY * (-PAIR_DECAY * (r / equilibrium_distance() - 1.0)).exp()
+}
+
+fn pair_potential(r: f64) -> f64 {
+    untapered_pair_potential(r) * taper(r)
+}
+
+fn density(r: f64) -> f64 {
+    (-DENSITY_DECAY * (r / equilibrium_distance() - 1.0)).exp() * taper(r)
+}
+
+/// The embedding energy that puts an atom, among twelve nearest neighbors at the distance that
+/// gives its density, on Rose's curve.
+fn embedding(density: f64) -> f64 {
+    if density <= 0.0 {
+        return 0.0;
+    }
+    let r = equilibrium_distance() * (1.0 - (density / 12.0).ln() / DENSITY_DECAY);
+    rose(r) - 6.0 * untapered_pair_potential(r)
+}
+
+/// Tabulate a function at evenly spaced points from zero, five to a line.
+fn write_table(text: &mut String, function: impl Fn(f64) -> f64, count: usize, step: f64) {
+    for index in 0..count {
+        *text += &format!("{:.16e}", function(index as f64 * step));
+        *text += if index % 5 == 4 { "\n" } else { " " };
+    }
+    *text += "\n";
+}
+
+/// A setfl file for the copper model and a second element, with a weaker density, a lower
+/// embedding energy and stiffer pairs.
+fn setfl() -> String {
+    let (density_count, density_step) = (5000, 0.01);
+    let distance_count = 3000;
+    let distance_step = EAM_CUTOFF / (distance_count - 1) as f64;
+    let mut text = String::from(
+        "analytic copper after Johnson, and a made-up second element\n\
+         written by the many-body validation example\n\n2 Cu X\n",
+    );
+    text += &format!(
+        "{} {} {} {} {}\n",
+        density_count, density_step, distance_count, distance_step, EAM_CUTOFF
+    );
+
+    text += "29 63.546 3.615 fcc\n";
+    write_table(&mut text, embedding, density_count, density_step);
+    write_table(&mut text, density, distance_count, distance_step);
+    text += "0 40.0 3.8 fcc\n";
+    write_table(
+        &mut text,
+        |rho| 0.8 * embedding(rho),
+        density_count,
+        density_step,
+    );
+    write_table(
+        &mut text,
+        |r| 0.7 * density(r),
+        distance_count,
+        distance_step,
+    );
+    // r φ(r), for Cu-Cu, X-Cu and X-X
+    for scale in [1.0, 1.1, 1.3] {
+        write_table(
+            &mut text,
+            |r| scale * r * pair_potential(r),
+            distance_count,
+            distance_step,
+        );
+    }
+    text
+}
+
+/// Each check builds its own copies of a potential.
+type Potential = fn() -> ManyBodyParameters;
+
+fn copper() -> ManyBodyParameters {
+    EmbeddedAtom::from_setfl(&setfl()).unwrap().build()
+}
+
+fn stillinger_weber() -> ManyBodyParameters {
+    Interactions::StillingerWeber::silicon().build()
+}
+
+fn tersoff() -> ManyBodyParameters {
+    Interactions::Tersoff::silicon().build()
+}
+
+//---------------------------------------------------------------------------------------------------//
+
+/// The positions in a cubic crystal of cells³ unit cells, filling a periodic box.
+fn crystal(basis: &[[f64; 3]], cells: usize, lattice_constant: f64) -> (Vec<Vec3>, SimulationBox) {
+    let mut positions = Vec::new();
+    for x in 0..cells {
+        for y in 0..cells {
+            for z in 0..cells {
+                for [u, v, w] in basis {
+                    positions.push(
+                        lattice_constant
+                            * Vec3::new(
+                                x as f64 + u + 0.125,
+                                y as f64 + v + 0.125,
+                                z as f64 + w + 0.125,
+                            ),
+                    );
+                }
+            }
+        }
+    }
+    let side = cells as f64 * lattice_constant;
+    (
+        positions,
+        SimulationBox::new(Vec3::zero(), Vec3::new(side, side, side)),
+    )
+}
+
+fn fcc(cells: usize, lattice_constant: f64) -> (Vec<Vec3>, SimulationBox) {
+    let basis = [
+        [0.0, 0.0, 0.0],
+        [0.5, 0.5, 0.0],
+        [0.5, 0.0, 0.5],
+        [0.0, 0.5, 0.5],
+    ];
+    crystal(&basis, cells, lattice_constant)
+}
+
+fn diamond(cells: usize, lattice_constant: f64) -> (Vec<Vec3>, SimulationBox) {
+    let mut basis = Vec::new();
+    for [u, v, w] in [
+        [0.0, 0.0, 0.0],
+        [0.5, 0.5, 0.0],
+        [0.5, 0.0, 0.5],
+        [0.0, 0.5, 0.5],
+    ] {
+        basis.push([u, v, w]);
+        basis.push([u + 0.25, v + 0.25, w + 0.25]);
+    }
+    crystal(&basis, cells, lattice_constant)
+}
+
+fn jitter(positions: &[Vec3], amplitude: f64, random: &mut Random) -> Vec<Vec3> {
+    positions
+        .iter()
+        .map(|pos| {
+            *pos + amplitude
+                * Vec3::new(
+                    random.uniform() - 0.5,
+                    random.uniform() - 0.5,
+                    random.uniform() - 0.5,
+                )
+        })
+        .collect()
+}
+
+/// The potential energy of particles at the positions, the forces on them and the virial.
+fn evaluate(
+    potential: Potential,
+    positions: &[Vec3],
+    simulation_box: SimulationBox,
+    groups: u32,
+) -> (f64, Vec<Vec3>, Vec3) {
+    let mut system = System::new();
+    for (index, pos) in positions.iter().enumerate() {
+        system.add_particle(Particle::new().pos(*pos).group(index as u32 % groups));
+    }
+    let mut potential = potential().with_particles(&system.all_particles());
+    potential.set_simulation_box(&simulation_box);
+    potential.handle(&mut system.particles, 0.0);
+    let forces = system
+        .particles
+        .iter()
+        .map(|particle| {
+            particle
+                .forces
+                .iter()
+                .fold(Vec3::zero(), |total, force| total + *force)
+        })
+        .collect();
+    (
+        potential.potential_energy(&system.particles),
+        forces,
+        potential.virial(),
+    )
+}
+
+fn energy(
+    potential: Potential,
+    positions: &[Vec3],
+    simulation_box: SimulationBox,
+    groups: u32,
+) -> f64 {
+    evaluate(potential, positions, simulation_box, groups).0
+}
+
+//---------------------------------------------------------------------------------------------------//
+
+/// The largest difference between the forces on a few particles and minus the derivative of the
+/// energy, relative to the largest force.
+fn consistency_error(
+    potential: Potential,
+    positions: &[Vec3],
+    simulation_box: SimulationBox,
+    groups: u32,
+) -> f64 {
+    let (_, forces, _) = evaluate(potential, positions, simulation_box, groups);
+    let largest_force = forces.iter().map(|force| force.mag()).fold(0.0, f64::max);
+
+    let step = 1e-5;
+    let axes = [
+        Vec3::new(1.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        Vec3::new(0.0, 0.0, 1.0),
+    ];
+    let mut largest_error: f64 = 0.0;
+    for particle in [0, 5, positions.len() / 2, positions.len() - 1] {
+        for axis in axes {
+            let displaced = |offset: f64| {
+                let mut displaced = positions.to_vec();
+                displaced[particle] += offset * axis;
+                energy(potential, &displaced, simulation_box, groups)
+            };
+            let expected = -(displaced(step) - displaced(-step)) / (2.0 * step);
+            largest_error = largest_error.max((forces[particle].dot(axis) - expected).abs());
+        }
+    }
+    largest_error / largest_force
+}
+
+/// The relative error of the virial pressure along x against the derivative of the energy as
+/// the box, and the particles with it, are stretched along x.
+fn pressure_error(
+    potential: Potential,
+    positions: &[Vec3],
+    simulation_box: SimulationBox,
+    groups: u32,
+) -> f64 {
+    let (_, _, virial) = evaluate(potential, positions, simulation_box, groups);
+    let pressure = virial.x / simulation_box.volume();
+
+    let step = 1e-5;
+    let stretched = |factor: f64| {
+        let stretch = Vec3::new(factor, 1.0, 1.0);
+        let mut stretched_box = simulation_box;
+        stretched_box.scale(stretch);
+        let stretched: Vec<Vec3> = positions
+            .iter()
+            .map(|pos| simulation_box.scale_point(*pos, stretch))
+            .collect();
+        energy(potential, &stretched, stretched_box, groups)
+    };
+    // dU/dL_x L_x = dU/d(ln L_x)
+    let derivative = (stretched(1.0 + step) - stretched(1.0 - step)) / (2.0 * step);
+    let expected = -derivative / simulation_box.volume();
+    (pressure - expected).abs() / expected.abs()
+}
+
+/// The largest change in the total energy of a crystal, set vibrating at a temperature k T,
+/// relative to its mean kinetic energy.
+fn energy_drift(
+    potential: Potential,
+    positions: &[Vec3],
+    simulation_box: SimulationBox,
+    mass: f64,
+    temperature: f64,
+) -> f64 {
+    let mut system = System::new();
+    system.substeps = 1;
+    system.set_simulation_box(simulation_box);
+    let mut random = Random::new(7);
+    for pos in positions {
+        let speed = (temperature / mass).sqrt();
+        system.add_particle(
+            Particle::new()
+                .pos(*pos)
+                .vel(speed * Vec3::new(random.gaussian(), random.gaussian(), random.gaussian()))
+                .mass(mass),
+        );
+    }
+    let particles = system.all_particles();
+    system.add_interaction(potential().with_particles(&particles));
+    let mut energy_potential = potential().with_particles(&particles);
+
+    let kinetic = |system: &System| {
+        system
+            .particles
+            .iter()
+            .map(|particle| 0.5 * particle.mass * particle.vel.mag_squared())
+            .sum::<f64>()
+    };
+    let mut total = |system: &System| {
+        energy_potential.set_simulation_box(&system.simulation_box.unwrap());
+        kinetic(system) + energy_potential.potential_energy(&system.particles)
+    };
+    // a twentieth of a femtosecond, since the velocities lag the positions by half a step
+    let dt = 0.005;
+    system.step_forward(dt);
+    let start = total(&system);
+    let (mut drift, mut mean_kinetic): (f64, f64) = (0.0, 0.0);
+    let steps = 5000;
+    for _ in 0..steps {
+        system.step_forward(dt);
+        mean_kinetic += kinetic(&system) / steps as f64;
+        drift = drift.max((total(&system) - start).abs());
+    }
+    drift / mean_kinetic
+}
+
+//---------------------------------------------------------------------------------------------------//
+
+fn main() {
+    let mut random = Random::new(1);
+    let (copper_lattice, copper_box) = fcc(3, COPPER_LATTICE);
+    let (silicon_lattice, silicon_box) = diamond(3, SILICON_LATTICE);
+    let potentials: [(&str, Potential, &[Vec3], SimulationBox, u32); 4] = [
+        ("eam copper", copper, &copper_lattice, copper_box, 1),
+        ("eam alloy", copper, &copper_lattice, copper_box, 2),
+        (
+            "stillinger-weber",
+            stillinger_weber,
+            &silicon_lattice,
+            silicon_box,
+            1,
+        ),
+        ("tersoff", tersoff, &silicon_lattice, silicon_box, 1),
+    ];
+    for (name, potential, lattice, simulation_box, groups) in potentials {
+        let disordered = jitter(lattice, 0.3, &mut random);
+        let error = consistency_error(potential, &disordered, simulation_box, groups);
+        let pressure = pressure_error(potential, &disordered, simulation_box, groups);
+        println!(
+            "{}: force against the energy's derivative, error {:.2e}, virial pressure error {:.2e}",
+            name, error, pressure
+        );
+        assert!(
+            error < 1e-5,
+            "the {} forces aren't the derivative of its energy",
+            name
+        );
+        assert!(
+            pressure < 1e-4,
+            "the {} virial pressure doesn't match the energy's derivative",
+            name
+        );
+    }
+
+    // the copper lattice follows rose's equation of state, and rests at its lattice constant
+    let mut worst: f64 = 0.0;
+    for lattice_constant in [3.5, COPPER_LATTICE, 3.75] {
+        let (lattice, simulation_box) = fcc(3, lattice_constant);
+        let per_atom = energy(copper, &lattice, simulation_box, 1) / lattice.len() as f64;
+        worst = worst.max((per_atom - rose(lattice_constant / 2_f64.sqrt())).abs());
+    }
+    let (_, _, virial) = evaluate(copper, &copper_lattice, copper_box, 1);
+    let pressure = virial.x / copper_box.volume();
+    println!(
+        "eam copper: largest energy error against rose's curve {:.2e} eV, pressure at rest {:.2e} eV/Å³",
+        worst, pressure
+    );
+    assert!(
+        worst < 1e-5,
+        "the eam lattice doesn't follow its equation of state"
+    );
+    assert!(
+        pressure.abs() < 1e-5,
+        "the eam lattice isn't at rest at its lattice constant"
+    );
+
+    let cohesive = |potential: Potential, lattice_constant: f64| {
+        let (lattice, simulation_box) = diamond(3, lattice_constant);
+        energy(potential, &lattice, simulation_box, 1) / lattice.len() as f64
+    };
+    let stillinger_weber_energy = cohesive(stillinger_weber, SILICON_LATTICE);
+    let tersoff_energy = cohesive(tersoff, 5.432);
+    println!(
+        "diamond silicon: stillinger-weber {:.4} eV/atom, tersoff {:.4} eV/atom",
+        stillinger_weber_energy, tersoff_energy
+    );
+    assert!(
+        (stillinger_weber_energy + 4.3366).abs() < 1e-3,
+        "stillinger-weber doesn't bind silicon by its cohesive energy"
+    );
+    assert!(
+        (tersoff_energy + 4.63).abs() < 5e-3,
+        "tersoff doesn't bind silicon by its cohesive energy"
+    );
+
+    // a malformed file is reported rather than read
+    let truncated = setfl();
+    let truncated = &truncated[..truncated.len() / 2];
+    let error = EmbeddedAtom::from_setfl(truncated).err();
+    println!("truncated setfl: {}", error.clone().unwrap_or_default());
+    assert!(error.is_some(), "a truncated setfl file was read");
+
+    // crystals vibrating at around 600 K conserve their energy
+    let temperature = 0.05;
+    let runs: [(&str, Potential, &[Vec3], SimulationBox, f64); 3] = [
+        (
+            "eam copper",
+            copper,
+            &copper_lattice,
+            copper_box,
+            COPPER_MASS,
+        ),
+        (
+            "stillinger-weber",
+            stillinger_weber,
+            &silicon_lattice,
+            silicon_box,
+            SILICON_MASS,
+        ),
+        (
+            "tersoff",
+            tersoff,
+            &silicon_lattice,
+            silicon_box,
+            SILICON_MASS,
+        ),
+    ];
+    for (name, potential, lattice, simulation_box, mass) in runs {
+        let drift = energy_drift(potential, lattice, simulation_box, mass, temperature);
+        println!("{}: energy drift {:.2e}", name, drift);
+        assert!(drift < 0.01, "the {} crystal doesn't conserve energy", name);
+    }
+}
@@ -0,0 +1,326 @@
+//! Checks SPH materials. The viscous forces of each viscosity law are compared with the analytic
+//! divergence of the stress in a shear flow v = a y² x̂, and two layers of fluids a thousand
+//! times apart in density are settled in a tank with the multiphase formulation, where they
+//! should stay layered with hydrostatic pressures in each.
+
+use engine::{
+    interaction::sph::SphParameters,
+    prelude::*,
+    sph::{
+        boundary::{HalfSpace, SphBoundary},
+        kernels::{CubicSplineKernel, Dimension},
+        materials::{Material, ViscosityLaw},
+        state_equations::Tait,
+        SphKernel, StateEquation,
+    },
+};
+
+const SPACING: f64 = 1.0;
+const SMOOTHING_RADIUS: f64 = 1.3 * SPACING;
+
+/// The density the kernel measures inside a square lattice of unit masses.
+fn lattice_density() -> f64 {
+    let probe = SphParameters::new(SMOOTHING_RADIUS, Tait::new(1.0, 1.0))
+        .kernel(CubicSplineKernel::new(Dimension::Two));
+    let mut lattice = Vec::new();
+    for column in -3..=3 {
+        for row in -3..=3 {
+            lattice.push(
+                Particle::new()
+                    .pos_xyz(column as f64 * SPACING, row as f64 * SPACING, 0.0)
+                    .mass(1.0),
+            );
+        }
+    }
+    probe.density(Vec3::zero(), &lattice)
+}
+
+//---------------------------------------------------------------------------------------------------//
+// viscosity laws
+
+const SIDE: usize = 40;
+const CURVATURE: f64 = 0.02;
+
+/// The relative root mean square error of the viscous force densities away from the lattice's
+/// edges, against d/dy (μ(γ̇) γ̇) with γ̇ = 2 a y.
+fn viscosity_error(law: ViscosityLaw) -> f64 {
+    let rest_density = lattice_density();
+    let mut system = System::new();
+    for column in 0..SIDE {
+        for row in 0..SIDE {
+            let y = row as f64 * SPACING;
+            system.add_particle(
+                Particle::new()
+                    .pos_xyz(column as f64 * SPACING, y, 0.0)
+                    .vel_xyz(CURVATURE * y * y, 0.0, 0.0)
+                    .mass(1.0)
+                    .group(1),
+            );
+        }
+    }
+    let fluid = SphParameters::new(SMOOTHING_RADIUS, Tait::new(rest_density, 10.0))
+        .kernel(CubicSplineKernel::new(Dimension::Two))
+        .gradient_kernel(CubicSplineKernel::new(Dimension::Two))
+        .material(
+            1,
+            Material::new(rest_density, Tait::new(rest_density, 10.0)).viscosity(law),
+        )
+        .with_particles(&system.all_particles());
+    system.add_interaction(fluid);
+    for interaction in &mut system.interactions {
+        interaction.handle(&mut system.particles, 0.0);
+    }
+
+    let stress = |y: f64| {
+        let shear_rate = 2.0 * CURVATURE * y;
+        law.viscosity(shear_rate) * shear_rate
+    };
+    let margin = 8.0 * SPACING;
+    let far = (SIDE - 1) as f64 * SPACING - margin;
+    let (mut squared_error, mut squared_expected) = (0.0, 0.0);
+    for particle in &system.particles {
+        let (x, y) = (particle.pos.x, particle.pos.y);
+        if x < margin || x > far || y < margin || y > far {
+            continue;
+        }
+        let force = particle
+            .forces
+            .iter()
+            .fold(Vec3::zero(), |total, force| total + *force);
+        let force_density = particle.density / particle.mass * force.x;
+        let expected = (stress(y + 1e-4) - stress(y - 1e-4)) / 2e-4;
+        squared_error += (force_density - expected).powi(2);
+        squared_expected += expected.powi(2);
+    }
+    (squared_error / squared_expected).sqrt()
+}
+
+//---------------------------------------------------------------------------------------------------//
+// two layers
+
+const WIDTH: usize = 20;
+const LAYER: usize = 10;
+const GRAVITY: f64 = 1.0;
+const DENSITY_RATIO: f64 = 1000.0;
+const DT: f64 = 0.05;
+
+struct Layers {
+    heavy_gradient: f64,
+    light_gradient: f64,
+    // how far the phases reach into each other, above or below the interface
+    mixing: f64,
+}
+
+/// The kernel average of a phase's pressures around a particle. The pressures of single particles
+/// scatter with how they happened to pack as the layers settled, by several percent of the
+/// hydrostatic difference across a layer, which no amount of averaging over time removes.
+fn smoothed_pressure(particle: &Particle, phase: &[&Particle], state_equation: &Tait) -> f64 {
+    let kernel = CubicSplineKernel::new(Dimension::Two);
+    let (pressure, weight) = phase.iter().fold((0.0, 0.0), |(pressure, weight), other| {
+        let w = kernel.w(particle.pos - other.pos, SMOOTHING_RADIUS);
+        (pressure + w * state_equation.pressure(other), weight + w)
+    });
+    pressure / weight
+}
+
+/// The least squares slope of pressure against height.
+fn pressure_gradient(samples: &[(f64, f64)]) -> f64 {
+    let count = samples.len() as f64;
+    let mean_height = samples.iter().map(|sample| sample.0).sum::<f64>() / count;
+    let mean_pressure = samples.iter().map(|sample| sample.1).sum::<f64>() / count;
+    let covariance: f64 = samples
+        .iter()
+        .map(|(height, pressure)| (height - mean_height) * (pressure - mean_pressure))
+        .sum();
+    let variance: f64 = samples
+        .iter()
+        .map(|(height, _)| (height - mean_height).powi(2))
+        .sum();
+    -covariance / variance
+}
+
+fn settle_layers(rest_density: f64) -> Layers {
+    let mut system = System::new();
+    system.substeps = 20;
+    // the heavy phase below, the light phase above, both sampled at the same spacing
+    for column in 0..WIDTH {
+        for row in 0..2 * LAYER {
+            let (mass, group) = if row < LAYER {
+                (DENSITY_RATIO, 1)
+            } else {
+                (1.0, 2)
+            };
+            system.add_particle(
+                Particle::new()
+                    .pos_xyz(
+                        (column as f64 + 0.5) * SPACING,
+                        (row as f64 + 0.5) * SPACING,
+                        0.0,
+                    )
+                    .mass(mass)
+                    .group(group),
+            );
+        }
+    }
+    let fluid_particles = system.all_particles();
+
+    // both phases share a sound speed, fast enough for the heavy layer
+    let sound_speed = 10.0 * (2.0 * GRAVITY * LAYER as f64 * SPACING).sqrt();
+    let heavy_density = DENSITY_RATIO * rest_density;
+    let right = WIDTH as f64 * SPACING;
+    let boundary = SphBoundary::new(rest_density, Dimension::Two)
+        .wall(HalfSpace::new(Vec3::zero(), Vec3::new(0.0, 1.0, 0.0)))
+        .wall(HalfSpace::new(Vec3::zero(), Vec3::new(1.0, 0.0, 0.0)))
+        .wall(HalfSpace::new(
+            Vec3::new(right, 0.0, 0.0),
+            Vec3::new(-1.0, 0.0, 0.0),
+        ));
+    for contact in boundary.contacts(&fluid_particles) {
+        system.add_constraint(contact);
+    }
+    let fluid = SphParameters::new(SMOOTHING_RADIUS, Tait::new(rest_density, sound_speed))
+        .kernel(CubicSplineKernel::new(Dimension::Two))
+        .gradient_kernel(CubicSplineKernel::new(Dimension::Two))
+        .artificial_viscosity(0.1, 0.0)
+        .material(
+            1,
+            Material::new(heavy_density, Tait::new(heavy_density, sound_speed)),
+        )
+        .material(
+            2,
+            // the light phase has a free surface, where it shouldn't pull on itself
+            Material::new(
+                rest_density,
+                Tait::new(rest_density, sound_speed).non_negative(),
+            ),
+        )
+        // without it, the light particles at the interface would count the heavy ones' masses
+        // in their densities, and the layers would blow apart
+        .multiphase()
+        .boundary(boundary)
+        .with_particles(&fluid_particles);
+    system.add_interaction(fluid);
+    let gravity = Interactions::Falling::new(GRAVITY).with_particles(&fluid_particles);
+    system.add_interaction(gravity);
+
+    for _ in 0..1000 {
+        system.step_forward(DT);
+    }
+
+    // the layers keep sloshing a little, so the pressure gradients are averaged over time
+    let steps = 100;
+    let interface = LAYER as f64 * SPACING;
+    let phases = [
+        (1, Tait::new(heavy_density, sound_speed), 0.0, interface),
+        (
+            2,
+            Tait::new(rest_density, sound_speed),
+            interface,
+            2.0 * interface,
+        ),
+    ];
+    let mut gradients = [0.0; 2];
+    let mut mixing = f64::MIN;
+    for _ in 0..steps {
+        system.step_forward(DT);
+        for (gradient, (group, state_equation, bottom, top)) in gradients.iter_mut().zip(&phases) {
+            let phase: Vec<&Particle> = system
+                .particles
+                .iter()
+                .filter(|particle| particle.group == *group)
+                .collect();
+            // away from the walls, the floor, the interface and the surface
+            let samples: Vec<(f64, f64)> = phase
+                .iter()
+                .filter(|particle| {
+                    particle.pos.y > bottom + 2.0 * SPACING
+                        && particle.pos.y < top - 2.0 * SPACING
+                        && (particle.pos.x - 0.5 * right).abs() < 0.5 * right - 2.0 * SPACING
+                })
+                .map(|particle| {
+                    (
+                        particle.pos.y,
+                        smoothed_pressure(particle, &phase, state_equation),
+                    )
+                })
+                .collect();
+            *gradient += pressure_gradient(&samples) / steps as f64;
+        }
+
+        for particle in &system.particles {
+            let overlap = if particle.group == 1 {
+                particle.pos.y - interface
+            } else {
+                interface - particle.pos.y
+            };
+            mixing = mixing.max(overlap);
+        }
+    }
+    let [heavy_gradient, light_gradient] = gradients;
+
+    Layers {
+        heavy_gradient,
+        light_gradient,
+        mixing,
+    }
+}
+
+//---------------------------------------------------------------------------------------------------//
+
+fn main() {
+    let laws = [
+        ("newtonian", ViscosityLaw::Newtonian(1.0)),
+        (
+            "power law",
+            ViscosityLaw::PowerLaw {
+                consistency: 1.0,
+                index: 0.5,
+                max_viscosity: 100.0,
+            },
+        ),
+        (
+            "bingham",
+            ViscosityLaw::Bingham {
+                yield_stress: 0.5,
+                plastic_viscosity: 0.5,
+                regularization: 10.0,
+            },
+        ),
+        (
+            "cross",
+            ViscosityLaw::Cross {
+                zero_shear_viscosity: 2.0,
+                infinite_shear_viscosity: 0.2,
+                time_constant: 2.0,
+                exponent: 1.0,
+            },
+        ),
+    ];
+    for (name, law) in laws {
+        let error = viscosity_error(law);
+        println!("{}: viscous force error {:.3}", name, error);
+        assert!(
+            error < 0.1,
+            "the {} viscous forces don't match the stress",
+            name
+        );
+    }
+
+    let rest_density = lattice_density();
+    let layers = settle_layers(rest_density);
+    let heavy_expected = DENSITY_RATIO * rest_density * GRAVITY;
+    let light_expected = rest_density * GRAVITY;
+    println!(
+        "heavy pressure gradient {:.1}, expected {:.1}; light pressure gradient {:.3}, expected {:.3}; mixing {:.2}",
+        layers.heavy_gradient, heavy_expected, layers.light_gradient, light_expected, layers.mixing
+    );
+    assert!(
+        (layers.heavy_gradient - heavy_expected).abs() < 0.03 * heavy_expected,
+        "the heavy layer isn't hydrostatic"
+    );
+    assert!(
+        (layers.light_gradient - light_expected).abs() < 0.03 * light_expected,
+        "the light layer isn't hydrostatic"
+    );
+    assert!(layers.mixing < SPACING, "the layers have mixed");
+}
@@ -0,0 +1,119 @@
+//! Checks the particle-mesh gravity solver against the exact force law between two bodies,
+//! and against direct summation on a cluster, for both mass assignment schemes.
+
+use engine::{interaction::particle_mesh::MassAssignment, prelude::*};
+use rand::{Rng, SeedableRng};
+
+const PARTICLE_COUNT: usize = 4000;
+const G: f64 = 1.0;
+const GRID_SIZE: usize = 32;
+const CELL_SIZE: f64 = 10.0;
+
+fn cluster() -> System {
+    let mut system = System::new();
+    let mut rng = rand::rngs::StdRng::seed_from_u64(11);
+
+    for _ in 0..PARTICLE_COUNT {
+        // a uniform ball that spans most of the grid
+        let radius = rng.gen_range(0.0_f64..1.0).cbrt() * 100.0;
+        let theta = rng.gen_range(0.0..(2.0 * PI));
+        let phi = rng.gen_range(-1.0_f64..1.0).acos();
+        system.add_particle(
+            Particle::new()
+                .pos(Vec3::new_spherical(radius, theta, phi))
+                .mass(1.0),
+        );
+    }
+    system
+}
+
+fn forces(system: &mut System) -> Vec<Vec3> {
+    for interaction in &mut system.interactions {
+        interaction.handle(&mut system.particles, 0.0);
+    }
+    system
+        .particles
+        .iter()
+        .map(|particle| {
+            particle
+                .forces
+                .iter()
+                .fold(Vec3::zero(), |total, force| total + *force)
+        })
+        .collect()
+}
+
+fn force_law(assignment: MassAssignment) {
+    // the mesh softens forces within a few cells, but should be close to newtonian beyond that
+    for cells in [4.0, 8.0, 12.0] {
+        let separation = cells * CELL_SIZE;
+        let mut system = System::new();
+        system.add_particle(Particle::new().pos_xyz(3.3, 1.2, -2.1).mass(1000.0));
+        system.add_particle(
+            Particle::new()
+                .pos_xyz(3.3 + 0.6 * separation, 1.2 + 0.8 * separation, -2.1)
+                .mass(1.0),
+        );
+        let gravity = Interactions::ParticleMeshGravity::new(G, GRID_SIZE, CELL_SIZE)
+            .mass_assignment(assignment)
+            .build()
+            .with_particles(&system.all_particles());
+        system.add_interaction(gravity);
+
+        let force = forces(&mut system)[1];
+        let ratio = force.mag() / (G * 1000.0 / separation.powi(2));
+        println!(
+            "  separation of {} cells: force / newtonian = {:.4}",
+            cells, ratio
+        );
+        assert!((ratio - 1.0).abs() < 0.02, "the mesh force law is off");
+    }
+}
+
+fn main() {
+    // the mesh can not resolve structure below a few cells, so compare against softened direct forces
+    let mut direct = cluster();
+    let gravity = Interactions::BarnesHutGravity::new(G)
+        .opening_angle(0.0)
+        .softening(CELL_SIZE)
+        .with_particles(&direct.all_particles());
+    direct.add_interaction(gravity);
+    let exact = forces(&mut direct);
+    let mean_force = exact.iter().map(|force| force.mag()).sum::<f64>() / exact.len() as f64;
+
+    for (name, assignment) in [
+        ("cic", MassAssignment::CloudInCell),
+        ("tsc", MassAssignment::TriangularShapedCloud),
+    ] {
+        println!("{}:", name);
+        force_law(assignment);
+
+        let mut mesh = cluster();
+        let gravity = Interactions::ParticleMeshGravity::new(G, GRID_SIZE, CELL_SIZE)
+            .mass_assignment(assignment)
+            .build()
+            .with_particles(&mesh.all_particles());
+        mesh.add_interaction(gravity);
+
+        let start = std::time::Instant::now();
+        let approximate = forces(&mut mesh);
+        let elapsed = start.elapsed();
+
+        let error = (approximate
+            .iter()
+            .zip(&exact)
+            .map(|(a, e)| (*a - *e).mag_squared())
+            .sum::<f64>()
+            / exact.len() as f64)
+            .sqrt()
+            / mean_force;
+        println!(
+            "  cluster: rms force error relative to the mean force = {:.2e}, solved in {:.2?}",
+            error, elapsed
+        );
+        assert!(
+            error < 0.1,
+            "the mesh forces should agree with direct summation"
+        );
+    }
+}
@@ -0,0 +1,278 @@
+//! Checks periodic boundary conditions. A rigid dimer and a pair of colliding particles are
+//! placed across the faces of a periodic box, where their constraints should act on the nearest
+//! copies of each other, and both broadphases should find the same contacts across the faces. A bulk Lennard-Jones fluid, in reduced units (ε = σ = m = 1), should
+//! then have no walls to feel: its density should be the same across the box, its momentum
+//! should be conserved as particles wrap around, and the pair distribution should settle to
+//! one at long range.
+
+use engine::{
+    algorithms::Random,
+    collision::{bvh::Bvh, spatial_hash::SpatialHash, CollisionDetector},
+    interaction::pair_wise::Truncation,
+    prelude::*,
+};
+
+const LENGTH: f64 = 10.0;
+const DT: f64 = 0.005;
+
+fn periodic_box() -> SimulationBox {
+    SimulationBox::new(Vec3::zero(), Vec3::new(LENGTH, LENGTH, LENGTH))
+}
+
+//---------------------------------------------------------------------------------------------------//
+
+/// The largest error in the length of a spinning dimer that keeps crossing the box's corner.
+fn dimer_error() -> f64 {
+    let mut system = System::new();
+    system.set_simulation_box(periodic_box());
+    let first = system.add_particle(
+        Particle::new()
+            .pos_xyz(0.2, 0.2, 0.2)
+            .vel_xyz(-1.0, -0.5, 2.0)
+            .mass(1.0),
+    );
+    let second = system.add_particle(
+        Particle::new()
+            .pos_xyz(LENGTH - 0.3, 0.2, LENGTH - 0.2)
+            .vel_xyz(-1.0, 1.5, 0.0)
+            .mass(1.0),
+    );
+    let length = Vec3::new(0.5, 0.0, 0.4).mag();
+    system.add_constraint(Constraints::Distance::new([first, second], length));
+
+    let mut error: f64 = 0.0;
+    for _ in 0..2000 {
+        system.step_forward(DT);
+        let simulation_box = system.simulation_box.unwrap();
+        let separation = simulation_box
+            .minimum_image(system.particles[1].pos - system.particles[0].pos)
+            .mag();
+        error = error.max((separation - length).abs());
+    }
+    error
+}
+
+/// The closest two particles come as they collide head on across the box's face, relative to
+/// their collision distance, and their total momentum afterwards.
+fn collision_across_face(detector: impl CollisionDetector + 'static) -> (f64, Vec3) {
+    let collision_distance = detector.collision_distance();
+    let mut system = System::new();
+    system.set_simulation_box(periodic_box());
+    system.add_particle(
+        Particle::new()
+            .pos_xyz(1.0, 5.0, 5.0)
+            .vel_xyz(-2.0, 0.0, 0.0)
+            .mass(1.0),
+    );
+    system.add_particle(
+        Particle::new()
+            .pos_xyz(LENGTH - 1.0, 5.0, 5.0)
+            .vel_xyz(1.0, 0.0, 0.0)
+            .mass(2.0),
+    );
+    system.add_constraint(Constraints::Collisions::new(detector));
+
+    let mut closest = f64::MAX;
+    for _ in 0..200 {
+        system.step_forward(DT);
+        let simulation_box = system.simulation_box.unwrap();
+        let separation = simulation_box
+            .minimum_image(system.particles[1].pos - system.particles[0].pos)
+            .mag();
+        closest = closest.min(separation);
+    }
+    let momentum = system
+        .particles
+        .iter()
+        .fold(Vec3::zero(), |total, particle| {
+            total + particle.mass * particle.vel
+        });
+    (closest / collision_distance, momentum)
+}
+
+/// The contacts a broadphase finds in a cloud of particles scattered through the box, as sorted
+/// pairs of particle indices.
+fn contacts(mut detector: impl CollisionDetector) -> Vec<(usize, usize)> {
+    let simulation_box = periodic_box();
+    let mut random = Random::new(5);
+    let particles: Vec<Particle> = (0..400)
+        .map(|id| {
+            Particle::new()
+                .id(id)
+                .pos(LENGTH * Vec3::new(random.uniform(), random.uniform(), random.uniform()))
+        })
+        .collect();
+    detector.set_simulation_box(&simulation_box);
+    let mut pairs: Vec<(usize, usize)> = detector
+        .contact_pairs(&particles)
+        .into_iter()
+        .map(|(first, second, _)| (first.index.min(second.index), first.index.max(second.index)))
+        .collect();
+    pairs.sort();
+    pairs
+}
+
+//---------------------------------------------------------------------------------------------------//
+
+const SIDE: usize = 8;
+const BINS: usize = 10;
+
+/// A supercritical fluid filling the box, from a lattice with scattered velocities that add up
+/// to zero. Any colder, and it could separate into a liquid and its vapor.
+fn fluid() -> System {
+    let mut system = System::new();
+    system.substeps = 1;
+    system.set_simulation_box(periodic_box());
+    let spacing = LENGTH / SIDE as f64;
+    let mut velocities = Vec::new();
+    for index in 0..SIDE * SIDE * SIDE {
+        // a cheap deterministic spread of velocities
+        let phase = index as f64;
+        velocities.push(
+            2.5 * Vec3::new(
+                (1.3 * phase).sin(),
+                (2.1 * phase).cos(),
+                (0.7 * phase + 1.0).sin(),
+            ),
+        );
+    }
+    let drift = velocities
+        .iter()
+        .fold(Vec3::zero(), |total, vel| total + *vel)
+        / velocities.len() as f64;
+    for x in 0..SIDE {
+        for y in 0..SIDE {
+            for z in 0..SIDE {
+                let vel = velocities[(x * SIDE + y) * SIDE + z] - drift;
+                system.add_particle(
+                    Particle::new()
+                        .pos_xyz(
+                            (x as f64 + 0.5) * spacing,
+                            (y as f64 + 0.5) * spacing,
+                            (z as f64 + 0.5) * spacing,
+                        )
+                        .vel(vel)
+                        .mass(1.0),
+                );
+            }
+        }
+    }
+    let particles = system.all_particles();
+    system.add_interaction(
+        Interactions::LennardJones::new(1.0, 1.0)
+            .build()
+            .cutoff(2.5)
+            .truncation(Truncation::ForceShifted)
+            .with_particles(&particles),
+    );
+    system
+}
+
+fn main() {
+    let error = dimer_error();
+    println!(
+        "dimer across the corner: largest length error {:.2e}",
+        error
+    );
+    assert!(
+        error < 1e-6,
+        "the distance constraint doesn't reach across the box"
+    );
+
+    for (name, (closest, momentum)) in [
+        ("spatial hash", collision_across_face(SpatialHash::new(1.0))),
+        ("bvh", collision_across_face(Bvh::new(1.0))),
+    ] {
+        println!(
+            "{}: collision across the face, closest approach {:.3} of the collision distance, momentum {:.2e}",
+            name,
+            closest,
+            momentum.mag()
+        );
+        assert!(closest > 0.99, "the particles passed through each other");
+        assert!(
+            momentum.mag() < 1e-9,
+            "the collision didn't conserve momentum"
+        );
+    }
+
+    let (hashed, tree) = (contacts(SpatialHash::new(1.2)), contacts(Bvh::new(1.2)));
+    println!(
+        "random cloud: {} contacts from the spatial hash, {} from the bvh",
+        hashed.len(),
+        tree.len()
+    );
+    assert!(
+        hashed == tree,
+        "the broadphases disagree on the contacts across the box"
+    );
+
+    let mut system = fluid();
+    let count = system.particles.len();
+    let bin_width = LENGTH / BINS as f64;
+    let mut profile = [0.0; BINS];
+    let mut pair_counts = [0.0; BINS];
+    let mut largest_momentum: f64 = 0.0;
+    let samples = 800;
+    for step in 0..2000 + 10 * samples {
+        system.step_forward(DT);
+        let momentum = system
+            .particles
+            .iter()
+            .fold(Vec3::zero(), |total, particle| {
+                total + particle.mass * particle.vel
+            });
+        largest_momentum = largest_momentum.max(momentum.mag());
+
+        // sampled every 10 steps once the lattice has melted
+        if step >= 2000 && step % 10 == 0 {
+            let simulation_box = system.simulation_box.unwrap();
+            for particle in &system.particles {
+                profile[((particle.pos.x / bin_width) as usize).min(BINS - 1)] += 1.0;
+            }
+            // pairs counted in shells out to half the box
+            for (i, first) in system.particles.iter().enumerate() {
+                for second in &system.particles[i + 1..] {
+                    let r = simulation_box.minimum_image(second.pos - first.pos).mag();
+                    let shell = (2.0 * r / bin_width) as usize;
+                    if shell < BINS {
+                        pair_counts[shell] += 2.0;
+                    }
+                }
+            }
+        }
+    }
+
+    // the density in each slab relative to the mean
+    let expected = (count * samples) as f64 / BINS as f64;
+    let unevenness = profile
+        .iter()
+        .map(|slab| (slab / expected - 1.0).abs())
+        .fold(0.0, f64::max);
+    // g(r) averaged over the outer shells, from 3.5 to 5
+    let density = count as f64 / LENGTH.powi(3);
+    let outer: f64 = (7..BINS)
+        .map(|shell| {
+            let (inner, outer) = (
+                shell as f64 * 0.5 * bin_width,
+                (shell + 1) as f64 * 0.5 * bin_width,
+            );
+            let volume = 4.0 / 3.0 * PI * (outer.powi(3) - inner.powi(3));
+            pair_counts[shell] / (samples as f64 * count as f64 * density * volume)
+        })
+        .sum::<f64>()
+        / (BINS - 7) as f64;
+    println!(
+        "bulk fluid: slab density within {:.3} of the mean, g(r) at long range {:.3}, momentum {:.2e}",
+        unevenness, outer, largest_momentum
+    );
+    assert!(unevenness < 0.05, "the fluid isn't uniform across the box");
+    assert!(
+        (outer - 1.0).abs() < 0.02,
+        "the pair distribution doesn't settle at long range"
+    );
+    assert!(
+        largest_momentum < 1e-8,
+        "wrapping the particles didn't conserve momentum"
+    );
+}
@@ -1,4 +1,4 @@
-use engine::prelude::*;
+use engine::{collision::spatial_hash::SpatialHash, prelude::*};
 use rand::Rng;
 use rendering::particle_2d_renderer::Particle2DRenderer;
 
@@ -39,16 +39,10 @@ fn main() {
     system.add_interaction(gravity);
 
     // add a non_penetrate constraint to all particles
-    let mut index: usize = 0;
-    for ref1 in &system.all_particles() {
-        for ref2 in &system.all_particles()[(index + 1)..] {
-            system.add_constraint(Constraints::NonPenetrate::new(
-                [*ref1, *ref2],
-                2.0 * system.particle_radius,
-            ));
-        }
-        index += 1;
-    }
+    let collision_distance = 2.0 * system.particle_radius;
+    system.add_constraint(Constraints::Collisions::new(SpatialHash::new(
+        collision_distance,
+    )));
 
     // add a boundary constraint to all particles
     for part in &system.all_particles() {
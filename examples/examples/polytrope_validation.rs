@@ -0,0 +1,127 @@
+//! Checks adaptive smoothing lengths and kernel-softened self-gravity by relaxing a star of
+//! SPH particles into an n = 1 polytrope, and comparing it with the analytic Lane–Emden profile
+//! ρ(r) = ρc sin(πr/R) / (πr/R), with R = √(πK / 2G) and ρc = π²/3 times the mean density.
+
+use engine::{
+    interaction::{barnes_hut::BarnesHutGravity, sph::SphParameters},
+    prelude::*,
+    sph::{
+        kernels::{CubicSplineKernel, Dimension},
+        state_equations::Polytropic,
+    },
+};
+use rand::{Rng, SeedableRng};
+
+const PARTICLE_COUNT: usize = 1000;
+const NEIGHBOR_COUNT: f64 = 60.0;
+const G: f64 = 1.0;
+const MASS: f64 = 1.0;
+const RADIUS: f64 = 1.0;
+
+fn main() {
+    let mut system = System::new();
+    let mut rng = rand::rngs::StdRng::seed_from_u64(3);
+
+    // a uniform ball, which falls in on itself towards the polytrope
+    let particle_mass = MASS / PARTICLE_COUNT as f64;
+    while system.particles.len() < PARTICLE_COUNT {
+        let pos = Vec3::new(
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+        );
+        if pos.mag() < 1.0 {
+            system.add_particle(Particle::new().pos(RADIUS * pos).mass(particle_mass));
+        }
+    }
+
+    let mean_density = MASS / (4.0 / 3.0 * PI * RADIUS.powi(3));
+    let polytropic_constant = 2.0 * G * RADIUS.powi(2) / PI;
+    let star = SphParameters::new(
+        1.2 * (particle_mass / mean_density).cbrt(),
+        Polytropic::new(polytropic_constant, 1.0),
+    )
+    .kernel(CubicSplineKernel::new(Dimension::Three))
+    .gradient_kernel(CubicSplineKernel::new(Dimension::Three))
+    .artificial_viscosity(1.0, 2.0)
+    .adaptive_smoothing(NEIGHBOR_COUNT, Dimension::Three)
+    .with_particles(&system.all_particles());
+    system.add_interaction(star);
+
+    let gravity = BarnesHutGravity::new(G)
+        .quadrupole()
+        .kernel_softening()
+        .with_particles(&system.all_particles());
+    system.add_interaction(gravity);
+
+    // the oscillations of the collapse are damped out, so that the star settles
+    for _ in 0..400 {
+        system.step_forward(0.02);
+        for particle in &mut system.particles {
+            particle.vel *= 0.97;
+        }
+    }
+
+    // the number of neighbors within each particle's kernel, from the smoothing lengths solved for
+    let neighbors: Vec<usize> = system
+        .particles
+        .iter()
+        .map(|particle| {
+            let support = 2.0 * particle.smoothing_length;
+            system
+                .particles
+                .iter()
+                .filter(|other| (other.pos - particle.pos).mag() < support)
+                .count()
+        })
+        .collect();
+    let mean_neighbors = neighbors.iter().sum::<usize>() as f64 / PARTICLE_COUNT as f64;
+    println!("mean neighbor count {:.1}", mean_neighbors);
+    assert!(
+        (mean_neighbors - NEIGHBOR_COUNT).abs() < 0.1 * NEIGHBOR_COUNT,
+        "the smoothing lengths don't keep the neighbor count"
+    );
+
+    // the center of mass doesn't move, but the star may have been nudged off the origin
+    let center = system
+        .particles
+        .iter()
+        .fold(Vec3::zero(), |total, particle| {
+            total + particle.mass * particle.pos
+        })
+        / MASS;
+    let mut radii: Vec<f64> = system
+        .particles
+        .iter()
+        .map(|particle| (particle.pos - center).mag())
+        .collect();
+    radii.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+    // the enclosed mass of the polytrope is M (sin x - x cos x) / π, which is half at x = 1.905
+    let half_mass_radius = radii[PARTICLE_COUNT / 2];
+    let expected_half_mass_radius = 1.905 / PI * RADIUS;
+    println!(
+        "half mass radius {:.3}, expected {:.3}",
+        half_mass_radius, expected_half_mass_radius
+    );
+    assert!(
+        (half_mass_radius - expected_half_mass_radius).abs() < 0.1 * expected_half_mass_radius,
+        "the star hasn't settled into the polytrope's profile"
+    );
+
+    let central_density = system
+        .particles
+        .iter()
+        .filter(|particle| (particle.pos - center).mag() < 0.2 * RADIUS)
+        .map(|particle| particle.density)
+        .fold(0.0, f64::max);
+    let expected_central_density = PI.powi(2) / 3.0 * mean_density;
+    println!(
+        "central density {:.3}, expected {:.3}",
+        central_density, expected_central_density
+    );
+    assert!(
+        (central_density - expected_central_density).abs() < 0.15 * expected_central_density,
+        "the star's central density is off"
+    );
+}
@@ -0,0 +1,112 @@
+use engine::{
+    collision::spatial_hash::SpatialHash,
+    prelude::*,
+    sph::{kernels::Poly6Kernel, SphKernel},
+};
+use rendering::particle_2d_renderer::Particle2DRenderer;
+
+const SPACING: f64 = 10.0;
+const COLUMNS: u32 = 30;
+const ROWS: u32 = 20;
+const MASS: f64 = 1.0;
+const GRAVITY: f64 = 600.0;
+const CHAIN_LINKS: u32 = 30;
+
+fn main() {
+    let mut system = System::new();
+    system.particle_radius = SPACING / 2.0;
+    let mut window = Particle2DRenderer::new(None);
+    window.scale.physics_dt = 1.0 / 30.0;
+    window
+        .style
+        .group_colors
+        .insert(1, rendering::colors::EARTH_BLUE);
+    window.style.group_colors.insert(2, rendering::colors::RUST);
+
+    let bounds = [-500.0, 500.0, -500.0, 500.0];
+    let radius = system.particle_radius;
+
+    // a block of fluid that falls into the bottom of the box
+    for column in 0..COLUMNS {
+        for row in 0..ROWS {
+            system.add_particle(
+                Particle::new()
+                    .pos_xyz(
+                        bounds[0] + radius + column as f64 * SPACING,
+                        bounds[2] + 300.0 + row as f64 * SPACING,
+                        0.0,
+                    )
+                    .mass(MASS)
+                    .group(1),
+            );
+        }
+    }
+    let fluid_particles = system.particles_in_group(1);
+
+    // the rest density is whatever the kernel measures inside the initial lattice
+    let smoothing_radius = 2.0 * SPACING;
+    let center = system.particles[(COLUMNS / 2 * ROWS + ROWS / 2) as usize].pos;
+    let rest_density: f64 = system
+        .particles
+        .iter()
+        .map(|particle| particle.mass * Poly6Kernel.w(center - particle.pos, smoothing_radius))
+        .sum();
+
+    system.add_constraint(
+        Constraints::PositionBasedFluid::new(rest_density, smoothing_radius)
+            .tensile_correction(0.1, 4, 0.2)
+            .vorticity_confinement(1.0)
+            .with_particles(&fluid_particles),
+    );
+
+    // a heavy chain that swings down into the fluid
+    let mut previous = system.add_particle(
+        Particle::new()
+            .pos_xyz(0.0, bounds[2] + 400.0, 0.0)
+            .inverse_mass(0.0)
+            .group(2),
+    );
+    for link in 1..=CHAIN_LINKS {
+        let next = system.add_particle(
+            Particle::new()
+                .pos_xyz(link as f64 * SPACING, bounds[2] + 400.0, 0.0)
+                .mass(5.0 * MASS)
+                .group(2),
+        );
+        system.add_constraint(Constraints::Distance::new([previous, next], SPACING));
+        previous = next;
+    }
+
+    let gravity = Interactions::Falling::new(GRAVITY).with_particles(&system.all_particles());
+    system.add_interaction(gravity);
+
+    // contacts between the chain and the fluid, and between links of the chain. the fluid
+    // particles sit a spacing apart, so a full spacing would fight the density constraints
+    let collision_distance = 0.6 * SPACING;
+    system.add_constraint(Constraints::Collisions::new(SpatialHash::new(
+        collision_distance,
+    )));
+
+    for particle in &system.all_particles() {
+        system.add_constraint(Constraints::ContactPlane::new(
+            *particle,
+            radius,
+            Vec3::new(bounds[0], 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+        ));
+        system.add_constraint(Constraints::ContactPlane::new(
+            *particle,
+            radius,
+            Vec3::new(bounds[1], 0.0, 0.0),
+            Vec3::new(-1.0, 0.0, 0.0),
+        ));
+        system.add_constraint(Constraints::ContactPlane::new(
+            *particle,
+            radius,
+            Vec3::new(0.0, bounds[2], 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        ));
+    }
+
+    window.run(system);
+}
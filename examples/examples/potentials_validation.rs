@@ -0,0 +1,220 @@
+//! Checks the pair potentials. For each one, the force between two particles should be minus
+//! the derivative of the potential energy, and the energy should have its expected landmarks:
+//! Morse's well, the Weeks–Chandler–Andersen and soft-sphere cutoffs, Yukawa's approach to
+//! Coulomb's law at short range, and a tabulated Lennard-Jones potential matching the real one.
+//! A cluster bound by a Morse potential should then conserve its energy.
+
+use engine::{
+    interaction::{pair_wise::PairWiseForceParameters, Interaction},
+    prelude::*,
+};
+
+/// The 12-6 Lennard-Jones potential in reduced units.
+fn lennard_jones(r: f64) -> f64 {
+    4.0 * (r.powi(-12) - r.powi(-6))
+}
+
+/// Two unit charges, whose separation along x is set before each measurement.
+fn pair() -> System {
+    let mut system = System::new();
+    system.add_particle(Particle::new().mass(1.0).charge(1.0));
+    system.add_particle(Particle::new().mass(1.0).charge(1.0));
+    system
+}
+
+/// The potential energy of the pair at a distance, and the force on the second particle
+/// along x.
+fn measure(system: &mut System, potential: &mut PairWiseForceParameters, r: f64) -> (f64, f64) {
+    system.particles[1].pos = Vec3::new(r, 0.0, 0.0);
+    for particle in &mut system.particles {
+        particle.forces.clear();
+    }
+    potential.handle(&mut system.particles, 0.0);
+    let force = system.particles[1]
+        .forces
+        .iter()
+        .fold(Vec3::zero(), |total, force| total + *force);
+    (potential.potential_energy(&system.particles), force.x)
+}
+
+/// The largest difference between the force and minus the derivative of the energy, relative
+/// to the largest force, over a range of distances.
+fn consistency_error(potential: PairWiseForceParameters, start: f64, end: f64) -> f64 {
+    let mut system = pair();
+    let mut potential = potential.with_particles(&system.all_particles());
+    let step = 1e-6;
+    let (mut largest_error, mut largest_force): (f64, f64) = (0.0, 0.0);
+    for index in 0..=100 {
+        let r = start + (end - start) * index as f64 / 100.0;
+        let (_, force) = measure(&mut system, &mut potential, r);
+        let (above, _) = measure(&mut system, &mut potential, r + step);
+        let (below, _) = measure(&mut system, &mut potential, r - step);
+        let expected = -(above - below) / (2.0 * step);
+        largest_error = largest_error.max((force - expected).abs());
+        largest_force = largest_force.max(force.abs());
+    }
+    largest_error / largest_force
+}
+
+fn energy_at(potential: PairWiseForceParameters, r: f64) -> f64 {
+    let mut system = pair();
+    let mut potential = potential.with_particles(&system.all_particles());
+    measure(&mut system, &mut potential, r).0
+}
+
+//---------------------------------------------------------------------------------------------------//
+
+/// The largest change in the total energy of a small Morse cluster, relative to its
+/// kinetic energy.
+fn morse_cluster_drift() -> f64 {
+    let mut system = System::new();
+    system.substeps = 1;
+    for x in 0..3 {
+        for y in 0..3 {
+            for z in 0..3 {
+                let jitter = 0.05 * ((x * 9 + y * 3 + z) as f64).sin();
+                system.add_particle(
+                    Particle::new()
+                        .pos_xyz(x as f64 + jitter, y as f64 - jitter, z as f64)
+                        .vel_xyz(0.1 * jitter, -0.2 * jitter, 0.3 * jitter)
+                        .mass(1.0),
+                );
+            }
+        }
+    }
+    let potential =
+        || Interactions::Morse::new(1.0, 3.0, 1.0).with_particles(&system.all_particles());
+    let mut energy_potential = potential();
+    system.add_interaction(potential());
+
+    let kinetic = |system: &System| {
+        system
+            .particles
+            .iter()
+            .map(|particle| 0.5 * particle.mass * particle.vel.mag_squared())
+            .sum::<f64>()
+    };
+    let dt = 0.001;
+    // the first step settles the velocities the integrator works with
+    system.step_forward(dt);
+    let start = kinetic(&system) + energy_potential.potential_energy(&system.particles);
+    let (mut drift, mut mean_kinetic): (f64, f64) = (0.0, 0.0);
+    let steps = 20000;
+    for _ in 0..steps {
+        system.step_forward(dt);
+        let kinetic = kinetic(&system);
+        mean_kinetic += kinetic / steps as f64;
+        let energy = kinetic + energy_potential.potential_energy(&system.particles);
+        drift = drift.max((energy - start).abs());
+    }
+    drift / mean_kinetic
+}
+
+//---------------------------------------------------------------------------------------------------//
+
+fn main() {
+    let potentials = [
+        ("morse", Interactions::Morse::new(2.0, 1.5, 1.2), 0.8, 3.0),
+        (
+            "buckingham",
+            Interactions::Buckingham::new(1000.0, 0.3, 2.0),
+            1.2,
+            3.0,
+        ),
+        ("yukawa", Interactions::Yukawa::new(1.0, 2.0), 0.5, 5.0),
+        (
+            "weeks-chandler-andersen",
+            Interactions::WeeksChandlerAndersen::new(1.0, 1.0),
+            0.9,
+            1.5,
+        ),
+        (
+            "soft sphere",
+            Interactions::SoftSphere::new(10.0, 1.0),
+            0.2,
+            1.5,
+        ),
+        (
+            "tabulated",
+            Interactions::Tabulated::from_function(lennard_jones, 0.8, 2.5, 200),
+            0.9,
+            2.4,
+        ),
+    ];
+    for (name, potential, start, end) in potentials {
+        let error = consistency_error(potential, start, end);
+        println!(
+            "{}: force against the energy's derivative, error {:.2e}",
+            name, error
+        );
+        assert!(
+            error < 1e-5,
+            "the {} force isn't the derivative of its energy",
+            name
+        );
+    }
+
+    // landmarks of each potential
+    let morse_well = energy_at(Interactions::Morse::new(2.0, 1.5, 1.2), 1.2);
+    assert!(
+        (morse_well + 2.0).abs() < 1e-12,
+        "morse's well isn't at its depth"
+    );
+
+    let wca_cutoff = 2_f64.powf(1.0 / 6.0);
+    let (inside, outside) = (
+        energy_at(
+            Interactions::WeeksChandlerAndersen::new(1.0, 1.0),
+            wca_cutoff - 1e-6,
+        ),
+        energy_at(
+            Interactions::WeeksChandlerAndersen::new(1.0, 1.0),
+            wca_cutoff + 1e-6,
+        ),
+    );
+    assert!(
+        inside.abs() < 1e-9 && outside == 0.0,
+        "the weeks-chandler-andersen potential doesn't meet zero at its cutoff"
+    );
+    assert!(
+        (energy_at(Interactions::WeeksChandlerAndersen::new(1.0, 1.0), 1.0) - 1.0).abs() < 1e-12,
+        "the weeks-chandler-andersen potential isn't lennard-jones raised by its well depth"
+    );
+
+    assert!(
+        energy_at(Interactions::SoftSphere::new(10.0, 1.0), 1.2) == 0.0
+            && (energy_at(Interactions::SoftSphere::new(10.0, 1.0), 0.5) - 1.25).abs() < 1e-12,
+        "the soft spheres don't overlap harmonically"
+    );
+
+    let coulomb = energy_at(Interactions::ElectroStatic::new(1.0), 0.01);
+    let screened = energy_at(Interactions::Yukawa::new(1.0, 100.0), 0.01);
+    assert!(
+        (screened / coulomb - 1.0).abs() < 1e-3,
+        "the screened potential doesn't approach coulomb's at short range"
+    );
+
+    // the tabulated potential is shifted to meet zero at the end of its table
+    let shift = lennard_jones(2.5);
+    let mut worst: f64 = 0.0;
+    for index in 0..100 {
+        let r = 0.95 + 1.5 * index as f64 / 100.0;
+        let tabulated = energy_at(
+            Interactions::Tabulated::from_function(lennard_jones, 0.8, 2.5, 200),
+            r,
+        );
+        worst = worst.max((tabulated - (lennard_jones(r) - shift)).abs());
+    }
+    println!(
+        "tabulated lennard-jones: largest energy error {:.2e}",
+        worst
+    );
+    assert!(
+        worst < 1e-4,
+        "the tabulated potential doesn't follow its table"
+    );
+
+    let drift = morse_cluster_drift();
+    println!("morse cluster: energy drift {:.2e}", drift);
+    assert!(drift < 0.01, "the morse cluster doesn't conserve energy");
+}
@@ -0,0 +1,216 @@
+//! Compares the XPBD and RATTLE constraint solvers. The triple pendulum of triple_pendulum.rs
+//! should keep its energy under RATTLE, where XPBD bleeds it away, with the links held to their
+//! lengths by both. A rigid triangle spinning freely, whose three sides are coupled constraints,
+//! should keep its energy and angular momentum under RATTLE. The colliding pair of
+//! ang_momentum.rs, which has no rigid constraints, should keep its angular momentum under either.
+
+use engine::{
+    collision::spatial_hash::SpatialHash,
+    constraint::{rattle::Rattle, ConstraintSolver},
+    prelude::*,
+};
+
+fn solver(name: &str) -> ConstraintSolver {
+    match name {
+        "rattle" => ConstraintSolver::Rattle(Rattle::new()),
+        _ => ConstraintSolver::Xpbd,
+    }
+}
+
+fn kinetic_energy(system: &System) -> f64 {
+    system
+        .particles
+        .iter()
+        .map(|particle| 0.5 * particle.mass * particle.vel.mag_squared())
+        .sum()
+}
+
+fn angular_momentum(system: &System) -> Vec3 {
+    system
+        .particles
+        .iter()
+        .fold(Vec3::zero(), |total, particle| {
+            total + particle.pos.cross(particle.mass * particle.vel)
+        })
+}
+
+/// The largest relative change in the energy of the triple pendulum over half a minute, and
+/// the largest error in the lengths of its links.
+fn triple_pendulum(constraint_solver: ConstraintSolver) -> (f64, f64) {
+    let mut system = System::new();
+    system.set_constraint_solver(constraint_solver);
+    let gravity = 200.0;
+
+    let center = system.add_particle(Particle::new().mass(0.0));
+    let mass1 = system.add_particle(Particle::new().pos_xyz(0.0, 250.0, 0.0).mass(15.0));
+    let mass2 = system.add_particle(Particle::new().pos_xyz(100.0, 250.0, 0.0).mass(8.0));
+    let mass3 = system.add_particle(Particle::new().pos_xyz(200.0, 250.0, 0.0).mass(2.0));
+    let links = [
+        ([center, mass1], 250.0),
+        ([mass1, mass2], 100.0),
+        ([mass2, mass3], 100.0),
+    ];
+
+    system.add_interaction(
+        Interactions::Falling::new(gravity).with_particles(&system.all_particles()),
+    );
+    for (particles, length) in links {
+        system.add_constraint(Constraints::Distance::new(particles, length));
+    }
+
+    let energy = |system: &System| {
+        kinetic_energy(system)
+            + system
+                .particles
+                .iter()
+                .map(|particle| particle.mass * gravity * particle.pos.y)
+                .sum::<f64>()
+    };
+    // the energy it would take to lift the masses through the pendulum's length
+    let scale = 25.0 * gravity * 450.0;
+
+    let start = energy(&system);
+    let (mut drift, mut length_error): (f64, f64) = (0.0, 0.0);
+    for _ in 0..30 * 160 {
+        system.step_forward(1.0 / 160.0);
+        drift = drift.max((energy(&system) - start).abs() / scale);
+        for (particles, length) in links {
+            let distance = (particles[1].get(&system.particles).pos
+                - particles[0].get(&system.particles).pos)
+                .mag();
+            length_error = length_error.max((distance - length).abs() / length);
+        }
+    }
+    (drift, length_error)
+}
+
+/// The largest relative changes in the kinetic energy and the angular momentum of a rigid
+/// triangle spinning freely about an axis tilted from its own for ten turns.
+fn spinning_triangle(constraint_solver: ConstraintSolver) -> (f64, f64) {
+    let mut system = System::new();
+    system.set_constraint_solver(constraint_solver);
+
+    let corners = [
+        Vec3::new(1.0, 0.0, 0.0),
+        Vec3::new(-0.5, 0.8, 0.1),
+        Vec3::new(-0.4, -0.9, -0.1),
+    ];
+    let masses = [1.0, 2.0, 3.0];
+    let centre = corners
+        .iter()
+        .zip(masses)
+        .fold(Vec3::zero(), |total, (corner, mass)| total + mass * *corner)
+        / 6.0;
+    let spin = Vec3::new(0.3, 0.2, 2.0 * PI);
+    let particles: Vec<_> = corners
+        .iter()
+        .zip(masses)
+        .map(|(corner, mass)| {
+            let arm = *corner - centre;
+            system.add_particle(Particle::new().pos(arm).vel(spin.cross(arm)).mass(mass))
+        })
+        .collect();
+    for (first, second) in [(0, 1), (1, 2), (2, 0)] {
+        let length = (corners[second] - corners[first]).mag();
+        system.add_constraint(Constraints::Distance::new(
+            [particles[first], particles[second]],
+            length,
+        ));
+    }
+
+    let (energy, momentum) = (kinetic_energy(&system), angular_momentum(&system));
+    let (mut energy_drift, mut momentum_drift): (f64, f64) = (0.0, 0.0);
+    for _ in 0..600 {
+        system.step_forward(1.0 / 60.0);
+        energy_drift = energy_drift.max((kinetic_energy(&system) - energy).abs() / energy);
+        momentum_drift =
+            momentum_drift.max((angular_momentum(&system) - momentum).mag() / momentum.mag());
+    }
+    (energy_drift, momentum_drift)
+}
+
+/// The largest relative change in the angular momentum of the gravitating pair of
+/// ang_momentum.rs over a minute.
+fn colliding_pair(constraint_solver: ConstraintSolver) -> f64 {
+    let mut system = System::new();
+    system.set_constraint_solver(constraint_solver);
+    system.add_particle(
+        Particle::new()
+            .pos_xyz(100.0, 0.0, 0.0)
+            .vel_xyz(0.0, 5.0, 0.0)
+            .mass(50.0),
+    );
+    system.add_particle(
+        Particle::new()
+            .pos_xyz(-100.0, 0.0, 0.0)
+            .vel_xyz(0.0, 0.0, 0.0)
+            .mass(10.0),
+    );
+    system.add_interaction(
+        Interactions::Gravity::new(6000.0).with_particles(&system.all_particles()),
+    );
+    let collision_distance = 2.0 * system.particle_radius;
+    system.add_constraint(
+        Constraints::Collisions::new(SpatialHash::new(collision_distance))
+            .compliance(0.00001)
+            .dissipation(30.0)
+            .as_force(),
+    );
+
+    let momentum = angular_momentum(&system);
+    let mut drift: f64 = 0.0;
+    for _ in 0..60 * 240 {
+        system.step_forward(1.0 / 240.0);
+        drift = drift.max((angular_momentum(&system) - momentum).mag() / momentum.mag());
+    }
+    drift
+}
+
+fn main() {
+    let mut pendulum_drifts = Vec::new();
+    // xpbd makes a single pass through the links each substep, where rattle iterates them
+    for (name, tolerance) in [("xpbd", 1e-4), ("rattle", 1e-9)] {
+        let (drift, length_error) = triple_pendulum(solver(name));
+        println!(
+            "{}: triple pendulum energy drift {:.2e}, link length error {:.2e}",
+            name, drift, length_error
+        );
+        assert!(
+            length_error < tolerance,
+            "{} doesn't hold the pendulum's links",
+            name
+        );
+        pendulum_drifts.push(drift);
+    }
+    assert!(
+        pendulum_drifts[1] < 1e-3 && pendulum_drifts[1] < 0.1 * pendulum_drifts[0],
+        "rattle doesn't conserve the triple pendulum's energy"
+    );
+
+    for name in ["xpbd", "rattle"] {
+        let (energy_drift, momentum_drift) = spinning_triangle(solver(name));
+        println!(
+            "{}: spinning triangle energy drift {:.2e}, angular momentum drift {:.2e}",
+            name, energy_drift, momentum_drift
+        );
+        if name == "rattle" {
+            assert!(
+                energy_drift < 1e-3 && momentum_drift < 1e-8,
+                "rattle doesn't conserve the spinning triangle's energy and angular momentum"
+            );
+        }
+    }
+
+    for name in ["xpbd", "rattle"] {
+        let drift = colliding_pair(solver(name));
+        println!(
+            "{}: colliding pair angular momentum drift {:.2e}",
+            name, drift
+        );
+        assert!(
+            drift < 1e-6,
+            "{} doesn't conserve the pair's angular momentum",
+            name
+        );
+    }
+}
@@ -1,4 +1,14 @@
-use engine::prelude::*;
+//! Two stars, each an n = 1 polytrope of SPH particles held together by their own gravity,
+//! collide and merge. The smoothing lengths follow the gas as it is squeezed and flung out.
+
+use engine::{
+    interaction::sph::SphParameters,
+    prelude::*,
+    sph::{
+        kernels::{CubicSplineKernel, Dimension},
+        state_equations::Polytropic,
+    },
+};
 use rand::Rng;
 use rendering::particle_2d_renderer::Particle2DRenderer;
 
@@ -6,12 +16,13 @@ const STAR_PARTICLE_COUNT: u32 = 750;
 const STAR_MASS: f64 = 1.989e30;
 const STAR_DENSITY: f64 = 1410.0;
 const G: f64 = 6.674e-11;
+const NEIGHBOR_COUNT: f64 = 60.0;
 
 fn main() {
     // configure the system and rendering parameters
     let mut system = System::new();
     let mut window = Particle2DRenderer::new(None);
-    system.substeps = 1;
+    system.substeps = 2;
     window.style.stroke_size = 0.0;
     window.style.bg_color = rendering::colors::BLACK;
     window
@@ -47,13 +58,19 @@ fn main() {
         -0.00065 * star_radius,
     );
 
-    // create stars
-    for _ in 0..STAR_PARTICLE_COUNT {
+    // create stars, starting as uniform balls that settle into polytropes as they fall together
+    let mut count = 0;
+    while count < STAR_PARTICLE_COUNT {
         let rand = Vec3::new(
             rng.gen_range(-1.0..1.0),
             rng.gen_range(-1.0..1.0),
             rng.gen_range(-1.0..1.0),
         );
+        if rand.mag() >= 1.0 {
+            continue;
+        }
+        let rand = star_radius * rand;
+        count += 1;
 
         // star 1
         system.add_particle(
@@ -74,23 +91,25 @@ fn main() {
         );
     }
 
-    // create gravity
-    let gravity = Interactions::Gravity::new(G).with_particles(&system.all_particles());
-    system.add_interaction(gravity);
+    // the gas, with the polytropic constant that gives an n = 1 star of the same radius
+    let polytropic_constant = 2.0 * G * star_radius.powi(2) / engine::math::PI;
+    let gas = SphParameters::new(
+        1.2 * (particle_mass / STAR_DENSITY).cbrt(),
+        Polytropic::new(polytropic_constant, 1.0),
+    )
+    .kernel(CubicSplineKernel::new(Dimension::Three))
+    .gradient_kernel(CubicSplineKernel::new(Dimension::Three))
+    .artificial_viscosity(1.0, 2.0)
+    .adaptive_smoothing(NEIGHBOR_COUNT, Dimension::Three)
+    .with_particles(&system.all_particles());
+    system.add_interaction(gas);
 
-    // add a non_penetrate constraint to all particles
-    let mut index: usize = 0;
-    for ref1 in &system.all_particles() {
-        for ref2 in &system.all_particles()[(index + 1)..] {
-            system.add_constraint(Constraints::NonPenetrate::new(
-                [*ref1, *ref2],
-                2.0 * system.particle_radius,
-            ));
-        }
-        index += 1;
-    }
-
-    system.static_constraint_pass(50);
+    // create gravity, softened over the same smoothing lengths as the gas
+    let gravity = Interactions::BarnesHutGravity::new(G)
+        .quadrupole()
+        .kernel_softening()
+        .with_particles(&system.all_particles());
+    system.add_interaction(gravity);
 
     window.run(system);
 }
@@ -0,0 +1,150 @@
+//! A dam break in a tank walled in by signed distance fields, over a round obstacle, with a
+//! crate of boundary particles floating on the water.
+
+use engine::{
+    interaction::sph::SphParameters,
+    prelude::*,
+    sph::{
+        boundary::{Ball, HalfSpace, SphBoundary},
+        kernels::{CubicSplineKernel, Dimension},
+        state_equations::Tait,
+    },
+};
+use rendering::particle_2d_renderer::Particle2DRenderer;
+
+const SPACING: f64 = 10.0;
+const COLUMNS: u32 = 20;
+const ROWS: u32 = 30;
+const MASS: f64 = 1.0;
+const GRAVITY: f64 = 600.0;
+const SOUND_SPEED: f64 = 3000.0;
+
+// the crate is half as dense as the water
+const CRATE_SIDE: u32 = 5;
+const CRATE_MASS: f64 = 0.5 * MASS;
+
+fn main() {
+    let mut system = System::new();
+    system.particle_radius = SPACING / 2.0;
+    system.substeps = 40;
+    let mut window = Particle2DRenderer::new(None);
+    window.scale.physics_dt = 1.0 / 30.0;
+    window
+        .style
+        .group_colors
+        .insert(1, rendering::colors::EARTH_BLUE);
+    window.style.group_colors.insert(2, rendering::colors::RUST);
+
+    let bounds = [-500.0, 500.0, -500.0, 500.0];
+    let radius = system.particle_radius;
+
+    // a column of fluid resting in the lower left corner
+    for column in 0..COLUMNS {
+        for row in 0..ROWS {
+            system.add_particle(
+                Particle::new()
+                    .pos_xyz(
+                        bounds[0] + radius + column as f64 * SPACING,
+                        bounds[2] + radius + row as f64 * SPACING,
+                        0.0,
+                    )
+                    .mass(MASS)
+                    .group(1),
+            );
+        }
+    }
+    let fluid_particles = system.all_particles();
+
+    // a crate dropped onto the column, held together by distance constraints to its neighbors
+    let mut crate_particles = Vec::new();
+    for column in 0..CRATE_SIDE {
+        for row in 0..CRATE_SIDE {
+            crate_particles.push(
+                system.add_particle(
+                    Particle::new()
+                        .pos_xyz(
+                            bounds[0] + 50.0 + column as f64 * SPACING,
+                            bounds[2] + 400.0 + row as f64 * SPACING,
+                            0.0,
+                        )
+                        .mass(CRATE_MASS)
+                        .group(2),
+                ),
+            );
+        }
+    }
+    for (index, first) in crate_particles.iter().enumerate() {
+        for second in &crate_particles[index + 1..] {
+            let distance =
+                (first.get(&system.particles).pos - second.get(&system.particles).pos).mag();
+            if distance < 1.5 * SPACING {
+                system.add_constraint(Constraints::Distance::new([*first, *second], distance));
+            }
+        }
+    }
+
+    // the rest density is whatever the kernel measures inside the initial lattice
+    let smoothing_radius = 1.3 * SPACING;
+    let probe = SphParameters::new(smoothing_radius, Tait::new(1.0, SOUND_SPEED))
+        .kernel(CubicSplineKernel::new(Dimension::Two));
+    let center = system.particles[(COLUMNS / 2 * ROWS + ROWS / 2) as usize].pos;
+    let rest_density = probe.density(center, &system.particles);
+
+    let boundary = SphBoundary::new(rest_density, Dimension::Two)
+        .wall(HalfSpace::new(
+            Vec3::new(bounds[0], 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+        ))
+        .wall(HalfSpace::new(
+            Vec3::new(bounds[1], 0.0, 0.0),
+            Vec3::new(-1.0, 0.0, 0.0),
+        ))
+        .wall(HalfSpace::new(
+            Vec3::new(0.0, bounds[2], 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        ))
+        .wall(Ball::new(Vec3::new(100.0, bounds[2], 0.0), 120.0))
+        .with_particles(&crate_particles);
+    for contact in boundary.contacts(&fluid_particles) {
+        system.add_constraint(contact);
+    }
+
+    let fluid = SphParameters::new(
+        smoothing_radius,
+        // the fluid shouldn't stick to itself at its free surface
+        Tait::new(rest_density, SOUND_SPEED).non_negative(),
+    )
+    .kernel(CubicSplineKernel::new(Dimension::Two))
+    .gradient_kernel(CubicSplineKernel::new(Dimension::Two))
+    .artificial_viscosity(0.1, 0.0)
+    .boundary(boundary)
+    .with_particles(&fluid_particles);
+    system.add_interaction(fluid);
+
+    let gravity = Interactions::Falling::new(GRAVITY).with_particles(&system.all_particles());
+    system.add_interaction(gravity);
+
+    // the walls only hold back the water, so the crate needs its own contacts
+    for particle in &crate_particles {
+        system.add_constraint(Constraints::ContactPlane::new(
+            *particle,
+            radius,
+            Vec3::new(bounds[0], 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+        ));
+        system.add_constraint(Constraints::ContactPlane::new(
+            *particle,
+            radius,
+            Vec3::new(bounds[1], 0.0, 0.0),
+            Vec3::new(-1.0, 0.0, 0.0),
+        ));
+        system.add_constraint(Constraints::ContactPlane::new(
+            *particle,
+            radius,
+            Vec3::new(0.0, bounds[2], 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        ));
+    }
+
+    window.run(system);
+}
@@ -0,0 +1,140 @@
+//! Checks the SPH energy equation: that heat conduction between two halves of a rod at
+//! different temperatures follows the analytic solution, and that a gas cloud expanding into
+//! vacuum trades its thermal energy for kinetic energy while conserving the total.
+
+use engine::{
+    interaction::sph::SphParameters,
+    prelude::*,
+    sph::{
+        kernels::{CubicSplineKernel, Dimension},
+        state_equations::{IdealGas, Isothermal},
+    },
+};
+
+/// The complementary error function, to within 1.5e-7 (Abramowitz & Stegun 7.1.26).
+fn erfc(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.3275911 * x.abs());
+    let polynomial = t
+        * (0.254829592
+            + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    let erfc = polynomial * (-x * x).exp();
+    if x >= 0.0 {
+        erfc
+    } else {
+        2.0 - erfc
+    }
+}
+
+/// A rod of fixed particles, hot on the left and cold on the right. The largest difference
+/// from T = erfc(x / 2√(αt)) / 2, with the diffusivity α = k / ρ c_v.
+fn conduction_error() -> f64 {
+    let (count, spacing, conductivity, specific_heat) = (200, 1.0, 1.0, 1.0);
+    let mut system = System::new();
+    for index in 0..count {
+        let x = (index as f64 - count as f64 / 2.0 + 0.5) * spacing;
+        system.add_particle(
+            Particle::new()
+                .pos_xyz(x, 0.0, 0.0)
+                .mass(1.0)
+                .inverse_mass(0.0)
+                .temperature(if x < 0.0 { 1.0 } else { 0.0 }),
+        );
+    }
+    let rod = SphParameters::new(1.3 * spacing, Isothermal::new(1.0))
+        .kernel(CubicSplineKernel::new(Dimension::One))
+        .gradient_kernel(CubicSplineKernel::new(Dimension::One))
+        .energy_equation(specific_heat)
+        .heat_conduction(conductivity)
+        .with_particles(&system.all_particles());
+    system.add_interaction(rod);
+
+    let duration = 50.0;
+    for _ in 0..duration as u32 {
+        system.step_forward(1.0);
+    }
+
+    // away from the ends, where the rod is missing neighbors
+    let density = system.particles[count / 2].density;
+    let diffusion_length = 2.0 * (conductivity / (density * specific_heat) * duration).sqrt();
+    system
+        .particles
+        .iter()
+        .filter(|particle| particle.pos.x.abs() < 50.0)
+        .map(|particle| {
+            let expected = 0.5 * erfc(particle.pos.x / diffusion_length);
+            (particle.temperature - expected).abs()
+        })
+        .fold(0.0, f64::max)
+}
+
+/// A square of warm gas at rest expanding into vacuum, with artificial viscosity to catch the
+/// shocks. Returns the initial and final thermal and kinetic energies.
+fn expansion_energies() -> ([f64; 2], [f64; 2]) {
+    let (side, adiabatic_index, gas_constant) = (20, 5.0 / 3.0, 1.0);
+    let specific_heat = gas_constant / (adiabatic_index - 1.0);
+    let mut system = System::new();
+    system.substeps = 10;
+    for column in 0..side {
+        for row in 0..side {
+            system.add_particle(
+                Particle::new()
+                    .pos_xyz(column as f64, row as f64, 0.0)
+                    .mass(1.0)
+                    .temperature(1.0),
+            );
+        }
+    }
+    let gas = SphParameters::new(1.3, IdealGas::new(adiabatic_index, gas_constant))
+        .kernel(CubicSplineKernel::new(Dimension::Two))
+        .gradient_kernel(CubicSplineKernel::new(Dimension::Two))
+        .artificial_viscosity(1.0, 2.0)
+        .energy_equation(specific_heat)
+        .with_particles(&system.all_particles());
+    system.add_interaction(gas);
+
+    let energies = |system: &System| {
+        system
+            .particles
+            .iter()
+            .fold([0.0, 0.0], |[thermal, kinetic], particle| {
+                [
+                    thermal + particle.mass * specific_heat * particle.temperature,
+                    kinetic + 0.5 * particle.mass * particle.vel.mag_squared(),
+                ]
+            })
+    };
+
+    let initial = energies(&system);
+    for _ in 0..50 {
+        system.step_forward(0.1);
+    }
+    (initial, energies(&system))
+}
+
+fn main() {
+    let error = conduction_error();
+    println!("conduction: largest temperature error {:.2e}", error);
+    assert!(
+        error < 0.01,
+        "conduction doesn't match the analytic solution"
+    );
+
+    let (initial, last) = expansion_energies();
+    let (total, total_last) = (initial[0] + initial[1], last[0] + last[1]);
+    println!(
+        "expansion: thermal {:.3} -> {:.3}, kinetic {:.3} -> {:.3}, total drifted by {:.2e}",
+        initial[0],
+        last[0],
+        initial[1],
+        last[1],
+        (total_last - total).abs() / total
+    );
+    assert!(
+        last[0] < 0.8 * initial[0],
+        "the gas hasn't cooled as it expanded"
+    );
+    assert!(
+        (total_last - total).abs() < 0.01 * total,
+        "the energy equation doesn't conserve energy"
+    );
+}
@@ -0,0 +1,232 @@
+//! Checks the thermostats on a Lennard-Jones fluid held together by a harmonic trap, in reduced
+//! units (ε = σ = m = k = 1). Every thermostat should hold the mean kinetic temperature at the
+//! target. The canonical ones should also give the kinetic energy K its canonical
+//! fluctuations, var(K) / <K>² = 2 / N_f, and keep the total energy plus the energy they've
+//! exchanged constant. Seeded runs should repeat exactly, and a scheduled thermostat should
+//! follow its schedule.
+
+use engine::{
+    interaction::{
+        pair_wise::{PairWiseForceParameters, Truncation},
+        simple::{SimpleForce, SimpleForceParameters},
+    },
+    prelude::*,
+    thermostat::Thermostat,
+};
+
+const SIDE: usize = 6;
+const SPACING: f64 = 1.5;
+const TRAP_STIFFNESS: f64 = 0.02;
+const TEMPERATURE: f64 = 1.0;
+const DT: f64 = 0.002;
+const EQUILIBRATION_STEPS: usize = 10000;
+const SAMPLE_STEPS: usize = 100000;
+
+/// Pulls every particle towards the origin.
+struct Trap(f64);
+
+impl SimpleForce for Trap {
+    fn force(&self, particle: &Particle) -> Option<Vec3> {
+        Some(-self.0 * particle.pos)
+    }
+}
+
+fn lennard_jones() -> PairWiseForceParameters {
+    Interactions::LennardJones::new(1.0, 1.0)
+        .build()
+        .cutoff(2.5)
+        .truncation(Truncation::ForceShifted)
+}
+
+/// A cube of particles at rest, with the forces between them and the trap.
+fn fluid() -> System {
+    let mut system = System::new();
+    system.substeps = 1;
+    let offset = 0.5 * (SIDE - 1) as f64 * SPACING;
+    for x in 0..SIDE {
+        for y in 0..SIDE {
+            for z in 0..SIDE {
+                system.add_particle(
+                    Particle::new()
+                        .pos_xyz(
+                            x as f64 * SPACING - offset,
+                            y as f64 * SPACING - offset,
+                            z as f64 * SPACING - offset,
+                        )
+                        .mass(1.0),
+                );
+            }
+        }
+    }
+    let particles = system.all_particles();
+    system.add_interaction(lennard_jones().with_particles(&particles));
+    system.add_interaction(
+        SimpleForceParameters::new(Trap(TRAP_STIFFNESS)).with_particles(&particles),
+    );
+    system
+}
+
+fn kinetic_energy(system: &System) -> f64 {
+    system
+        .particles
+        .iter()
+        .map(|particle| 0.5 * particle.mass * particle.vel.mag_squared())
+        .sum()
+}
+
+struct Run {
+    mean_temperature: f64,
+    // var(K) / <K>² relative to the canonical 2 / N_f
+    fluctuation_ratio: f64,
+    // the spread of the total energy plus the exchanged energy, relative to the mean kinetic energy
+    energy_drift: f64,
+}
+
+/// Equilibrates the fluid with the thermostat, then samples it.
+fn run(thermostat: Thermostat) -> Run {
+    let mut system = fluid();
+    let thermostat = thermostat.with_particles(&system.all_particles());
+    let index = system.add_thermostat(thermostat);
+    let mut potential = lennard_jones().with_particles(&system.all_particles());
+    let total_energy = |system: &System, potential: &mut PairWiseForceParameters| {
+        let trap: f64 = system
+            .particles
+            .iter()
+            .map(|particle| 0.5 * TRAP_STIFFNESS * particle.pos.mag_squared())
+            .sum();
+        kinetic_energy(system) + potential.potential_energy(&system.particles) + trap
+    };
+
+    for _ in 0..EQUILIBRATION_STEPS {
+        system.step_forward(DT);
+    }
+
+    let degrees_of_freedom = 3.0 * system.particles.len() as f64;
+    let (mut sum, mut sum_squared) = (0.0, 0.0);
+    let start =
+        total_energy(&system, &mut potential) + system.thermostats[index].exchanged_energy();
+    let mut energy_drift: f64 = 0.0;
+    for _ in 0..SAMPLE_STEPS {
+        system.step_forward(DT);
+
+        let kinetic = kinetic_energy(&system);
+        sum += kinetic;
+        sum_squared += kinetic * kinetic;
+        let conserved =
+            total_energy(&system, &mut potential) + system.thermostats[index].exchanged_energy();
+        energy_drift = energy_drift.max((conserved - start).abs());
+    }
+    let mean = sum / SAMPLE_STEPS as f64;
+    let variance = sum_squared / SAMPLE_STEPS as f64 - mean * mean;
+    Run {
+        mean_temperature: 2.0 * mean / degrees_of_freedom,
+        fluctuation_ratio: variance / (mean * mean) / (2.0 / degrees_of_freedom),
+        energy_drift: energy_drift / mean,
+    }
+}
+
+fn main() {
+    let thermostats = [
+        ("berendsen", Thermostat::berendsen(TEMPERATURE, 0.1), false),
+        ("bussi", Thermostat::bussi(TEMPERATURE, 0.1).seed(1), true),
+        (
+            "nose-hoover chain",
+            Thermostat::nose_hoover(TEMPERATURE, 0.1),
+            true,
+        ),
+        (
+            "langevin",
+            Thermostat::langevin(TEMPERATURE, 1.0).seed(1),
+            true,
+        ),
+    ];
+    for (name, thermostat, canonical) in thermostats {
+        let run = run(thermostat);
+        println!(
+            "{}: mean temperature {:.3}, kinetic energy fluctuations {:.2} of canonical, energy drift {:.4}",
+            name, run.mean_temperature, run.fluctuation_ratio, run.energy_drift
+        );
+        assert!(
+            (run.mean_temperature - TEMPERATURE).abs() < 0.02 * TEMPERATURE,
+            "the {} thermostat doesn't hold the temperature",
+            name
+        );
+        if canonical {
+            assert!(
+                (run.fluctuation_ratio - 1.0).abs() < 0.25,
+                "the {} thermostat's kinetic energy fluctuations aren't canonical",
+                name
+            );
+            assert!(
+                run.energy_drift < 0.02,
+                "the {} thermostat doesn't account for the energy it exchanges",
+                name
+            );
+        } else {
+            assert!(
+                run.fluctuation_ratio < 0.5,
+                "the {} thermostat should suppress fluctuations",
+                name
+            );
+        }
+    }
+
+    // the same seed repeats a stochastic run exactly, and another seed doesn't
+    let final_positions = |seed: u64| {
+        let mut system = fluid();
+        let thermostat = Thermostat::langevin(TEMPERATURE, 1.0)
+            .seed(seed)
+            .with_particles(&system.all_particles());
+        system.add_thermostat(thermostat);
+        for _ in 0..100 {
+            system.step_forward(DT);
+        }
+        system
+            .particles
+            .iter()
+            .map(|particle| [particle.pos.x, particle.pos.y, particle.pos.z])
+            .collect::<Vec<[f64; 3]>>()
+    };
+    let (first, repeat, other) = (final_positions(7), final_positions(7), final_positions(8));
+    assert!(
+        first.iter().zip(&repeat).all(|(a, b)| a == b),
+        "a seeded run didn't repeat"
+    );
+    assert!(
+        first.iter().zip(&other).any(|(a, b)| a != b),
+        "different seeds gave the same run"
+    );
+
+    // heating on a schedule, from 0.5 to 1.5 over 20 time units
+    let mut system = fluid();
+    let thermostat = Thermostat::bussi(TEMPERATURE, 0.1)
+        .seed(2)
+        .schedule(|time| 0.5 + (time / 20.0).min(1.0))
+        .with_particles(&system.all_particles());
+    system.add_thermostat(thermostat);
+    // the temperature is averaged over windows of a time unit, and compared with the target
+    // averaged over the same window
+    let window = 500;
+    let mut tracking: f64 = 0.0;
+    for index in 0..30 {
+        let (mut temperature, mut target) = (0.0, 0.0);
+        for _ in 0..window {
+            system.step_forward(DT);
+            let thermostat = &system.thermostats[0];
+            temperature += thermostat.temperature(&system.particles) / window as f64;
+            target += thermostat.target_temperature() / window as f64;
+        }
+        // the fluid starts at rest
+        if index > 0 {
+            tracking = tracking.max((temperature - target).abs() / target);
+        }
+    }
+    println!(
+        "schedule: largest relative lag behind the target {:.3}",
+        tracking
+    );
+    assert!(
+        tracking < 0.1,
+        "the scheduled thermostat doesn't follow its schedule"
+    );
+}
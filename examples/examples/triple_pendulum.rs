@@ -1,4 +1,7 @@
-use engine::prelude::*;
+use engine::{
+    constraint::{rattle::Rattle, ConstraintSolver},
+    prelude::*,
+};
 use rendering::particle_2d_renderer::Particle2DRenderer;
 
 fn main() {
@@ -21,6 +24,10 @@ fn main() {
     system.add_constraint(dist1);
     system.add_constraint(dist2);
     system.add_constraint(dist3);
+    // `cargo run --example triple_pendulum -- rattle` swaps XPBD for RATTLE, to compare the two
+    if std::env::args().any(|argument| argument == "rattle") {
+        system.set_constraint_solver(ConstraintSolver::Rattle(Rattle::new()));
+    }
     system.static_constraint_pass(5);
 
     window.run(system);
@@ -163,12 +163,10 @@ impl Particle2DRenderer {
                     VirtualKeyCode::Minus => context.view.zoom_out(),
                     VirtualKeyCode::Return => context.view.reset(),
                     VirtualKeyCode::Space => system.running = !system.running,
-                    VirtualKeyCode::F => {
-                        if !system.running {
-                            system.running = true;
-                            system.step_forward(self.scale.physics_dt);
-                            system.running = false;
-                        }
+                    VirtualKeyCode::F if !system.running => {
+                        system.running = true;
+                        system.step_forward(self.scale.physics_dt);
+                        system.running = false;
                     }
                     VirtualKeyCode::R => todo!(),
                     VirtualKeyCode::S => todo!(),
@@ -123,12 +123,10 @@ impl Particle3DRenderer {
                     VirtualKeyCode::Return => renderer_state.camera.reset(),
                     VirtualKeyCode::Space => system.running = !system.running,
                     VirtualKeyCode::R => todo!(),
-                    VirtualKeyCode::S => {
-                        if !system.running {
-                            system.running = true;
-                            system.step_forward(self.scale.physics_dt);
-                            system.running = false;
-                        }
+                    VirtualKeyCode::S if !system.running => {
+                        system.running = true;
+                        system.step_forward(self.scale.physics_dt);
+                        system.running = false;
                     }
                     VirtualKeyCode::Q => *control_flow = ControlFlow::Exit,
                     _ => (),
@@ -11,7 +11,7 @@
 //!
 //! For example, if (0, 0) is in the top left of the window for your renderer, after getting the
 //! transformed coordinates (using [`View2D::map_to_view`]), you would need to draw them as so:
-//! ```rust
+//! ```rust,ignore
 //! draw_point((x + width / 2.0), (height / 2.0 - y));
 //! ```
 